@@ -0,0 +1,73 @@
+//! The work a frontend does on every frame, with many stages. Run with
+//! `cargo bench`.
+
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use staged_timer::{EventStream, Timer, TimerStage};
+
+/// Stage counts to measure with, from a short workout to a generated ladder
+/// far longer than anyone would run.
+const STAGE_COUNTS: [usize; 3] = [10, 1_000, 100_000];
+
+/// How long the frontends wait between frames.
+const FRAME: Duration = Duration::from_millis(50);
+
+fn timer(stages: usize) -> Timer {
+    Timer::new((0..stages)
+        .map(|i| TimerStage::new(format!("Stage {}", i + 1), Duration::from_secs(30)))
+        .collect())
+}
+
+fn advance(c: &mut Criterion) {
+    let mut group = c.benchmark_group("advance");
+    for stages in STAGE_COUNTS {
+        let timer = timer(stages);
+        group.bench_with_input(BenchmarkId::new("frame", stages), &timer, |b, timer| {
+            b.iter_batched(
+                || timer.clone(),
+                |mut timer| {
+                    timer.advance(black_box(FRAME));
+                    timer
+                },
+                BatchSize::LargeInput
+            );
+        });
+        // e.g. catching up after the computer was suspended
+        let total = Duration::from_secs(30) * stages as u32;
+        group.bench_with_input(BenchmarkId::new("all stages", stages), &timer, |b, timer| {
+            b.iter_batched(
+                || timer.clone(),
+                |mut timer| {
+                    timer.advance(black_box(total));
+                    timer
+                },
+                BatchSize::LargeInput
+            );
+        });
+    }
+    group.finish();
+}
+
+fn events(c: &mut Criterion) {
+    let mut group = c.benchmark_group("events");
+    for stages in STAGE_COUNTS {
+        // the first update after starting has passed over every stage
+        let mut timer = timer(stages);
+        timer.advance(Duration::from_secs(30) * stages as u32);
+        group.bench_with_input(BenchmarkId::new("all stages", stages), &timer, |b, timer| {
+            b.iter_batched(
+                || (timer.clone(), EventStream::new()),
+                |(timer, mut events)| {
+                    black_box(events.update(&timer));
+                    (timer, events)
+                },
+                BatchSize::LargeInput
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, advance, events);
+criterion_main!(benches);
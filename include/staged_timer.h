@@ -0,0 +1,105 @@
+/*
+ * C interface to the staged-timer engine, built with the `ffi` feature:
+ *
+ *     cargo rustc --lib --release --features ffi --crate-type cdylib
+ *
+ * Regenerate with `cbindgen --config cbindgen.toml --output include/staged_timer.h`
+ * after changing src/ffi.rs.
+ */
+
+#ifndef STAGED_TIMER_H
+#define STAGED_TIMER_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#define ST_EVENT_TICK 0
+#define ST_EVENT_STAGE_START 1
+#define ST_EVENT_STAGE_END 2
+#define ST_EVENT_WARNING 3
+#define ST_EVENT_PAUSED 4
+#define ST_EVENT_RESUMED 5
+#define ST_EVENT_FINISHED 6
+#define ST_EVENT_COUNTDOWN 7
+
+/* A timer and what C callers need of it. */
+typedef struct StTimer StTimer;
+
+/*
+ * Called with the `user_data` it was set with, one of the `ST_EVENT_` codes
+ * and the stage the event is about, or the running stage for events that
+ * aren't about one.
+ */
+typedef void (*StCallback)(void *user_data, uint32_t event, size_t stage);
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * Create a timer of `count` stages named `names`, UTF-8 and NUL terminated,
+ * lasting `seconds` each. Stages within `warning_s` of their end get an
+ * `ST_EVENT_WARNING`, 0 for none. Returns NULL if a name isn't valid UTF-8
+ * or a duration is negative or not a number.
+ */
+StTimer *st_timer_new(const char *const *names,
+                      const double *seconds,
+                      size_t count,
+                      uint32_t warning_s);
+
+/* Free a timer created with st_timer_new. Does nothing with NULL. */
+void st_timer_free(StTimer *timer);
+
+/*
+ * Call `callback` with `user_data` for every event from now on, or stop
+ * calling back with NULL. `user_data` is passed on as it is. The callback
+ * may query the timer, but must not advance, control or free it.
+ */
+void st_timer_set_callback(StTimer *timer, StCallback callback, void *user_data);
+
+/*
+ * Advance the timer by `ms` milliseconds that have passed, calling back with
+ * what happened. Returns how many times the running stage reached a whole
+ * second or its end.
+ */
+uint32_t st_timer_advance(StTimer *timer, uint64_t ms);
+
+/* Advance the timer by one second. Returns false once it has finished. */
+bool st_timer_tick(StTimer *timer);
+
+/* Pause the timer, or resume it with `paused` false. */
+void st_timer_set_paused(StTimer *timer, bool paused);
+
+/* Move on to the next stage without finishing the running one. */
+void st_timer_skip_stage(StTimer *timer);
+
+/*
+ * Position of the running stage, or the number of stages once the timer has
+ * finished.
+ */
+size_t st_timer_current_stage(const StTimer *timer);
+
+size_t st_timer_stage_count(const StTimer *timer);
+
+/*
+ * Name of the stage at `index`, NULL if there is none. The name lives as
+ * long as the timer.
+ */
+const char *st_timer_stage_name(const StTimer *timer, size_t index);
+
+/* Whole seconds the running stage has run for, 0 once the timer has finished. */
+uint32_t st_timer_elapsed_s(const StTimer *timer);
+
+/* Seconds left of the running stage, rounded up, 0 once the timer has finished. */
+uint32_t st_timer_remaining_s(const StTimer *timer);
+
+bool st_timer_is_paused(const StTimer *timer);
+
+bool st_timer_is_finished(const StTimer *timer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* STAGED_TIMER_H */
@@ -0,0 +1,1249 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    io::{self, IsTerminal},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    time::Duration
+};
+
+use chrono::{DateTime, Local, NaiveTime, TimeZone};
+use staged_timer::{compensation, CountdownCue, Exercise, TimeFormat, TimerStage};
+
+use crate::{
+    attach, audio, backup, calibration, config, detach, dilution, event_log, export, film,
+    generators, heart_rate, history, hooks, integrations, maintenance, mqtt, notify, observe,
+    overlay, preflight, presets, reciprocity, resume, run_daemon, schema, session, stage_specs,
+    ConfirmPolicy, Controls, DisplayOptions, PresetLoader, Round, RunOptions, SessionLog,
+    SoundOptions, StageSpec, StagesOnly, DEFAULT_GAUGE_TITLE
+};
+use crate::cli::{cli, given_run_args};
+use crate::config::{Completion, DisplayMode, GaugeLabel, Orientation};
+use crate::error::RunError;
+#[cfg(unix)]
+use crate::instances;
+use crate::theme::{Palette, Theme};
+use crate::units::Units;
+
+/// The next time the local wall clock shows `target`, i.e. tomorrow if that
+/// time has already passed today.
+fn next_time_of_day(target: NaiveTime) -> DateTime<Local> {
+    let now = Local::now();
+    let mut deadline = now.date_naive().and_time(target);
+    if deadline <= now.naive_local() {
+        deadline += chrono::Duration::days(1);
+    }
+
+    Local.from_local_datetime(&deadline)
+        .earliest()
+        .unwrap_or(now)
+}
+
+/// Number of seconds from now until `deadline`.
+fn seconds_until(deadline: DateTime<Local>) -> u32 {
+    // round up so the alarm never goes off before the deadline
+    let remaining_ms = (deadline - Local::now()).num_milliseconds().max(1);
+    ((remaining_ms + 999) / 1000).try_into().unwrap_or(u32::MAX)
+}
+
+/// Suggest durations for the stages of the preset `name` from the median
+/// of how long they took in its recorded runs, and save them with `apply`.
+/// Stages need `min_runs` runs for a suggestion.
+fn calibrate(name: &str, min_runs: u32, apply: bool) -> Result<(), String> {
+    let loaded = config::load(&presets::find(name)?)?;
+    let history = history::load()?;
+    let sessions: Vec<&history::SessionRecord> = history.iter()
+        .filter(|session| session.preset.as_deref() == Some(name) && !session.aborted)
+        .collect();
+    if sessions.is_empty() {
+        return Err(format!(
+            "No runs of preset \"{}\" have been recorded yet, they are \
+            recorded when it is run with `staged-timer run {}`",
+            name, name
+        ));
+    }
+
+    let names: Vec<String> = loaded.stages.iter()
+        .enumerate()
+        .map(|(i, stage)| stage.name.clone().unwrap_or_else(|| format!("Stage {}", i + 1)))
+        .collect();
+    let times = calibration::actual_times(&names, &sessions);
+    let format = |seconds: u32| TimeFormat::Smart.format(seconds, seconds);
+    println!("{} recorded runs of preset \"{}\":", sessions.len(), name);
+    let periods: Vec<Option<Duration>> = loaded.stages.iter()
+        .zip(names.iter().zip(&times))
+        .map(|(stage, (stage_name, times))| {
+            if stage.period.is_zero() {
+                return None;
+            }
+            let planned_s = stage.period.as_secs_f64().round() as u32;
+            let runs = times.len();
+            match calibration::median(times).filter(|_| runs >= min_runs as usize) {
+                Some(median_s) if median_s != planned_s => {
+                    println!(
+                        "  {}: {} -> {} (median of {} runs)",
+                        stage_name, format(planned_s), format(median_s), runs
+                    );
+                    Some(Duration::from_secs(median_s.into()))
+                },
+                Some(_) => {
+                    println!("  {}: {} as planned", stage_name, format(planned_s));
+                    None
+                },
+                None => {
+                    println!("  {}: {} ({} runs)", stage_name, format(planned_s), runs);
+                    None
+                }
+            }
+        })
+        .collect();
+
+    if !periods.iter().any(Option::is_some) {
+        println!("There is nothing to change.");
+    } else if apply {
+        let path = presets::set_times(name, &periods)?;
+        println!("Saved the new durations to {}", path.display());
+    } else {
+        println!("Run `staged-timer calibrate {} --apply` to save them.", name);
+    }
+    Ok(())
+}
+
+/// Units given with --units, or else in the settings.
+fn selected_units(arg_match: &clap::ArgMatches, settings: &config::Settings) -> Units {
+    arg_match.get_one::<String>("units")
+        .and_then(|units| Units::from_name(units))
+        .or(settings.units)
+        .unwrap_or(Units::Metric)
+}
+
+/// The channels listed as `notifiers` in the settings, or else those the
+/// `sound`, `notify` and `speak` settings ask for, along with those asked
+/// for on the command line. Without sound the bell isn't rung either.
+fn notify_channels(
+    arg_match: &clap::ArgMatches,
+    settings: &config::Settings,
+    sound: bool
+) -> Vec<notify::Channel> {
+    use notify::Channel;
+    let mut channels = settings.notifiers.clone().unwrap_or_else(|| {
+        [
+            (Channel::Sound, true),
+            (Channel::Desktop, settings.notify.unwrap_or(false)),
+            (Channel::Speech, settings.speak.unwrap_or(false))
+        ].into_iter()
+            .filter_map(|(channel, wanted)| wanted.then_some(channel))
+            .collect()
+    });
+    let rings = channels.iter().any(|channel| matches!(channel, Channel::Bell | Channel::Sound));
+    let asked = [
+        (Channel::Sound, arg_match.is_present("sound") && !rings),
+        (Channel::Desktop, arg_match.is_present("notify")),
+        (Channel::Speech, arg_match.is_present("speak"))
+    ];
+    for (channel, wanted) in asked {
+        if wanted && !channels.contains(&channel) {
+            channels.push(channel);
+        }
+    }
+    channels.retain(|channel| sound || !matches!(channel, Channel::Bell | Channel::Sound));
+    channels
+}
+
+/// The channels of the `[alerts]` section of the settings. Without sound
+/// the bell isn't rung for any of them.
+fn notify_routes(settings: &config::Settings, sound: bool) -> notify::Routes {
+    let mut routes = settings.alerts.clone();
+    for route in [&mut routes.stage_end, &mut routes.warning, &mut routes.finished].into_iter().flatten() {
+        route.retain(|channel| sound || !matches!(channel, notify::Channel::Bell | notify::Channel::Sound));
+    }
+    routes
+}
+
+/// The options of a run of `stages` that every kind of run takes from the
+/// command line and the settings. Runs of presets and config files have
+/// more, quick timers, alarms and exposures only these.
+fn run_options(
+    arg_match: &clap::ArgMatches,
+    settings: &config::Settings,
+    stages: Vec<StageSpec>
+) -> Result<RunOptions, String> {
+    // quick timers, alarms and exposures take the options of their run
+    // themselves
+    let run_match = match arg_match.subcommand() {
+        Some(("quick" | "at" | "reciprocity", sub_match)) => sub_match,
+        _ => arg_match
+    };
+    let headless = run_match.is_present("headless");
+    let statusbar = run_match.is_present("statusbar");
+    let wait = run_match.is_present("wait") || headless || statusbar;
+    let session_name = run_match.get_one::<String>("session_name").cloned();
+
+    let confirm = ConfirmPolicy {
+        quit: settings.confirm.quit.unwrap_or(false),
+        skip: settings.confirm.skip.unwrap_or(false),
+        jump: settings.confirm.jump.unwrap_or(true)
+    };
+    let adjust_step = Duration::from_secs(
+        arg_match.get_one::<u32>("adjust_step").copied()
+            .or(settings.adjust_step)
+            .unwrap_or(10)
+            .into()
+    );
+    let idle_pause = arg_match.get_one::<u32>("idle_pause").copied()
+        .or(settings.idle_pause)
+        .map(|window_s| Duration::from_secs(window_s.into()));
+    let input_devices: Vec<PathBuf> = run_match.get_many::<PathBuf>("input_device")
+        .map(|devices| devices.cloned().collect())
+        .or_else(|| settings.input_devices.clone())
+        .unwrap_or_default();
+    // a socket of its own for every session
+    let control_socket = match run_match.get_one::<PathBuf>("control_socket") {
+        Some(path) => Some(path.clone()),
+        None if run_match.is_present("control_socket") => {
+            let name = session_name.as_deref().unwrap_or(detach::DEFAULT_NAME);
+            Some(session::runtime_dir()?.join(format!("{}.sock", session::file_name(name))))
+        },
+        None => None
+    };
+    let control_port = run_match.get_one::<u16>("control_port").copied();
+    #[cfg(not(unix))]
+    if control_socket.is_some() || control_port.is_some() {
+        return Err(String::from("--control-socket and --control-port are only available on Unix"));
+    }
+    let control_access = config::Access {
+        address: run_match.get_one::<IpAddr>("control_address").copied()
+            .or(settings.control.address)
+            .unwrap_or(config::Access::default().address),
+        clients: settings.control.clients.clone(),
+        others: settings.control.others.unwrap_or(config::Permission::Read),
+        tokens: settings.control.tokens.clone(),
+        tls: match (
+            run_match.get_one::<PathBuf>("control_cert").or(settings.control.cert.as_ref()),
+            run_match.get_one::<PathBuf>("control_key").or(settings.control.key.as_ref())
+        ) {
+            (Some(cert), Some(key)) => Some(config::TlsOptions { cert: cert.clone(), key: key.clone() }),
+            (None, None) => None,
+            _ => return Err(String::from(
+                "TLS needs both a certificate and its key, set cert and key under [control]"
+            ))
+        }
+    };
+    let time_format = settings.time_format.unwrap_or(TimeFormat::Smart);
+    let theme = run_match.get_one::<String>("theme")
+        .and_then(|name| Theme::named(name))
+        .or(settings.theme)
+        .unwrap_or_default();
+    let display_mode = run_match.get_one::<String>("display")
+        .and_then(|name| DisplayMode::from_name(name))
+        .or(settings.display)
+        .unwrap_or(DisplayMode::Gauges);
+    let orientation = run_match.get_one::<String>("orientation")
+        .and_then(|name| Orientation::from_name(name))
+        .or(settings.orientation)
+        .unwrap_or(Orientation::Vertical);
+    let sparkline = run_match.is_present("sparkline") || settings.sparkline.unwrap_or(false);
+    let accessible = run_match.is_present("accessible") || settings.accessible.unwrap_or(false);
+    let gradient = run_match.is_present("gradient") || settings.gradient.unwrap_or(false);
+    let palette = if accessible { Palette::None } else { Palette::detect() };
+    let gauge_title = run_match.get_one::<String>("gauge_title").cloned()
+        .or_else(|| settings.gauge_title.clone())
+        .unwrap_or_else(|| String::from(DEFAULT_GAUGE_TITLE));
+    let gauge_label = run_match.get_one::<String>("label")
+        .and_then(|name| GaugeLabel::from_name(name))
+        .or(settings.gauge_label)
+        .unwrap_or_default();
+    let upcoming = arg_match.get_one::<u32>("upcoming").copied()
+        .or(settings.upcoming_stages);
+    let pause_budget_s = run_match.get_one::<u32>("pause_budget").copied()
+        .or(settings.pause_budget);
+    let tenths = arg_match.is_present("tenths") || settings.tenths.unwrap_or(false);
+    let overtime = arg_match.is_present("overtime") || settings.overtime.unwrap_or(false);
+    let manual_advance = arg_match.is_present("manual_advance")
+        || settings.manual_advance.unwrap_or(false);
+    let keep_output = arg_match.is_present("keep_output")
+        || settings.keep_output.unwrap_or(false);
+    let allow_sleep = run_match.is_present("allow_sleep")
+        || settings.allow_sleep.unwrap_or(false);
+    // a dry run only shows how the stages go, it tells nobody and records
+    // nothing
+    let dry_run = arg_match.is_present("dry_run")
+        .then(|| *arg_match.get_one::<f64>("speed").unwrap());
+    if dry_run.is_some_and(|speed| !speed.is_finite() || speed <= 0.0) {
+        return Err(String::from("The speed of a dry run must be above 0."));
+    }
+    let checkpoint_interval = arg_match.get_one::<u32>("checkpoint_interval").copied()
+        .or(settings.checkpoint_interval)
+        .filter(|_| dry_run.is_none())
+        .map(|interval_s| Duration::from_secs(interval_s.into()));
+    let sound_asked = if run_match.is_present("sound") || run_match.is_present("no_sound") {
+        Some(!run_match.is_present("no_sound"))
+    } else {
+        settings.sound
+    };
+    // quick timers and alarms ring unless told not to, other runs only when
+    // asked to, which listing the bell or sound as a notifier does as well
+    let listed = [
+        &settings.notifiers,
+        &settings.alerts.stage_end,
+        &settings.alerts.warning,
+        &settings.alerts.finished
+    ];
+    let rings_by_default = matches!(arg_match.subcommand_name(), Some("quick" | "at"))
+        || listed.into_iter().flatten().flatten()
+            .any(|channel| matches!(channel, notify::Channel::Bell | notify::Channel::Sound));
+    let sound = SoundOptions {
+        enabled: dry_run.is_none() && sound_asked.unwrap_or(rings_by_default)
+    };
+    // the audio files of stages are played unless sound was turned off
+    let audio_player = audio::Player::find(settings.audio_player.as_deref())
+        .filter(|_| dry_run.is_none() && sound_asked != Some(false));
+    let mut notify = notify::NotifyOptions {
+        channels: notify_channels(run_match, settings, sound.enabled),
+        message: run_match.get_one::<String>("notify_message").cloned()
+            .or_else(|| settings.notify_message.clone())
+            .unwrap_or_else(|| String::from(notify::DEFAULT_MESSAGE)),
+        alert_file: settings.alert_sound.clone(),
+        webhook_url: settings.webhook_url.clone(),
+        push_url: settings.push_url.clone(),
+        routes: notify_routes(settings, sound.enabled)
+    };
+    if dry_run.is_some() {
+        notify.channels.clear();
+        notify.routes = notify::Routes::default();
+    }
+    // checking I/O reports rather than uses it
+    let check_io = arg_match.is_present("check_io");
+    let notifier_warnings = notify::Notifiers::find(&notify).1;
+    if !check_io {
+        for warning in notifier_warnings.iter() {
+            eprintln!("Warning: {}", warning);
+        }
+    }
+
+    let overlay_options = overlay::OverlayOptions {
+        file: arg_match.get_one::<PathBuf>("overlay_file").cloned(),
+        port: arg_match.get_one::<u16>("overlay_port").copied(),
+        format: arg_match.get_one::<String>("overlay_format").cloned()
+            .unwrap_or_else(|| String::from(overlay::DEFAULT_FORMAT)),
+        serve: arg_match.get_one::<SocketAddr>("serve").copied(),
+        serve_token: settings.serve.token.clone(),
+        serve_tls: match (
+            arg_match.get_one::<PathBuf>("serve_cert").or(settings.serve.cert.as_ref()),
+            arg_match.get_one::<PathBuf>("serve_key").or(settings.serve.key.as_ref())
+        ) {
+            (Some(cert), Some(key)) => Some(config::TlsOptions { cert: cert.clone(), key: key.clone() }),
+            (None, None) => None,
+            _ => return Err(String::from(
+                "HTTPS needs both a certificate and its key, set cert and key under [serve]"
+            ))
+        }
+    };
+    let overlay = (overlay_options.file.is_some() || overlay_options.port.is_some()
+        || overlay_options.serve.is_some())
+        .then(|| overlay::Overlay::start(&overlay_options, time_format))
+        .transpose()?;
+
+    let mqtt_options = run_match.get_one::<String>("mqtt").or(settings.mqtt_broker.as_ref())
+        .map(|broker| mqtt::MqttOptions {
+            broker: broker.clone(),
+            topic: run_match.get_one::<String>("mqtt_topic").cloned()
+                .or_else(|| settings.mqtt_topic.clone())
+                .unwrap_or_else(|| String::from(mqtt::DEFAULT_TOPIC)),
+            username: settings.mqtt_username.clone(),
+            password: settings.mqtt_password.clone()
+        });
+    let mqtt = mqtt_options.as_ref()
+        .filter(|_| !check_io && dry_run.is_none())
+        .map(mqtt::Mqtt::connect)
+        .transpose()?;
+
+    let event_log = arg_match.get_one::<PathBuf>("log").cloned();
+    for message in maintenance::tidy(&settings.retention, event_log.as_deref()) {
+        eprintln!("Warning: {}", message);
+    }
+    if let Some(Err(message)) = event_log.as_deref().map(event_log::EventLog::open) {
+        return Err(message);
+    }
+
+    let hooks = match dry_run {
+        Some(_) => hooks::Hooks::off(),
+        None => hooks::Hooks::new(
+            arg_match.get_one::<String>("on-stage-start").cloned()
+                .or_else(|| settings.on_stage_start.clone()),
+            arg_match.get_one::<String>("on-stage-end").cloned()
+                .or_else(|| settings.on_stage_end.clone())
+        )
+    };
+
+    let cli_completion = arg_match.get_one::<String>("on_complete")
+        .map(|mode| match mode.as_str() {
+            "summary" => Completion::Summary,
+            "presets" => Completion::Presets,
+            "again" => Completion::Again,
+            _ => Completion::Exit
+        });
+    let default_completion = cli_completion.clone()
+        .or_else(|| settings.on_complete.clone())
+        .unwrap_or(Completion::Exit);
+
+    Ok(RunOptions {
+        stages,
+        controls: Controls {
+            confirm,
+            adjust_step,
+            input_devices,
+            control_socket,
+            control_port,
+            control_access,
+            idle_pause,
+            heart_rate: None
+        },
+        display: DisplayOptions {
+            warning_threshold: settings.warn.unwrap_or(0),
+            time_format,
+            tenths,
+            keep_output,
+            headless,
+            statusbar,
+            theme,
+            mode: display_mode,
+            orientation,
+            gauge_title,
+            gauge_label,
+            upcoming,
+            pause_budget_s,
+            sparkline,
+            palette,
+            accessible,
+            gradient,
+            dry_run
+        },
+        sound,
+        select_stages: false,
+        edit_stages: false,
+        wait,
+        allow_sleep,
+        session_name,
+        audio_player,
+        notify,
+        hooks,
+        overlay,
+        mqtt,
+        log: SessionLog::default(),
+        completion: default_completion.clone(),
+        round: None,
+        splits: Vec::new(),
+        presets: PresetLoader {
+            exercises: HashMap::new(),
+            override_warn: false,
+            completion: default_completion,
+            stage_colors: settings.stage_colors.clone(),
+            compensation: None,
+            overtime,
+            manual_advance,
+            countdown: Vec::new()
+        },
+        watched_config: None,
+        checkpoint_interval,
+        resumed: None
+    })
+}
+
+/// The options of the run the command line asks for, or `None` if it asked
+/// for a command that is done already. Errors are left to `main` to print.
+pub fn parse_cl_args() -> Result<Option<RunOptions>, RunError> {
+    // without any options there is nothing to run yet, so stages are made up
+    // in the editor
+    let mut arg_match = if std::env::args_os().len() > 1 {
+        cli().get_matches()
+    } else {
+        cli().get_matches_from(["staged-timer", "--edit"])
+    };
+
+    if arg_match.is_present("schema") {
+        print!("{}", schema::JSON_SCHEMA);
+        return Ok(None);
+    }
+
+    // `validate` and `export` parse the options like `run` does, but stop
+    // once they have the stages
+    let mut stages_only = None;
+    if let Some((command @ ("run" | "validate" | "export"), run_match)) = arg_match.subcommand() {
+        stages_only = match command {
+            "validate" => Some(StagesOnly::Validate),
+            "export" => Some(StagesOnly::Export),
+            _ => None
+        };
+        let mut args = vec![OsString::from("staged-timer")];
+        let mut options: Vec<OsString> = run_match.get_raw("options").into_iter().flatten()
+            .map(OsStr::to_os_string)
+            .collect();
+        match run_match.get_one::<String>("preset") {
+            // without a preset, the options start right away
+            Some(option) if option.starts_with('-') => options.insert(0, OsString::from(option)),
+            Some(preset) => {
+                args.extend([OsString::from("--preset"), OsString::from(preset)]);
+                // the preset's own options come first so those given to `run`
+                // win
+                let loaded = presets::find(preset).and_then(|path| config::load(&path))?;
+                args.extend(loaded.options.into_iter().map(OsString::from));
+            },
+            None => {}
+        }
+        // run options before the preset are taken by the subcommand instead
+        // of being left in its trailing options
+        args.extend(given_run_args(run_match));
+        args.extend(options);
+        // like without a subcommand, the stages are made up in the editor
+        // if there is nothing else to go on
+        if args.len() == 1 && stages_only.is_none() {
+            args.push(OsString::from("--edit"));
+        }
+        arg_match = cli().get_matches_from(args);
+        // neither has stages of its own to check
+        if stages_only.is_some() && (arg_match.subcommand().is_some() || arg_match.is_present("resume")) {
+            return Err(RunError::Message(String::from(
+                "Only the stages of a preset or options can be checked or exported."
+            )));
+        }
+    }
+
+    // `save-preset` and `list-presets` are what `presets save` and `presets
+    // list` were called before
+    let presets_command = match arg_match.subcommand() {
+        Some(("presets", presets_match)) => presets_match.subcommand(),
+        Some(("save-preset", save_match)) => Some(("save", save_match)),
+        Some(("list-presets", list_match)) => Some(("list", list_match)),
+        _ => None
+    };
+
+    if let Some(("save", save_match)) = presets_command {
+        let preset = save_match.get_one::<String>("preset").unwrap();
+        let overwrite = save_match.is_present("force");
+        let saved = match save_match.get_one::<PathBuf>("config") {
+            Some(path) => presets::save_config(preset, path, overwrite),
+            None => {
+                let names = save_match.get_many::<String>("name")
+                    .into_iter()
+                    .flatten()
+                    .cloned()
+                    .map(Some)
+                    .chain(std::iter::repeat(None));
+                let stages: Vec<(Option<String>, Duration)> = names
+                    .zip(save_match.get_many::<Duration>("time").into_iter().flatten().copied())
+                    .collect();
+                presets::save_stages(preset, &stages, overwrite)
+            }
+        };
+        println!("Saved preset \"{}\" to {}", preset, saved?.display());
+        return Ok(None);
+    }
+
+    if let Some(("daemon", daemon_match)) = arg_match.subcommand() {
+        // errors have nowhere to go without a terminal
+        let name = daemon_match.get_one::<String>("name").unwrap();
+        return run_daemon(name).map(|()| None).map_err(|_| RunError::Quiet);
+    }
+
+    if let Some(("list", _)) = presets_command {
+        let presets = presets::list()?;
+        if presets.is_empty() {
+            println!("No presets have been saved yet.");
+        }
+        for (name, path) in presets {
+            match config::load(&path) {
+                Ok(loaded) => {
+                    let total: Duration = loaded.stages.iter()
+                        .map(|stage| stage.period)
+                        .sum();
+                    let total_s = u32::try_from(total.as_secs_f64().ceil() as u64)
+                        .unwrap_or(u32::MAX);
+                    println!(
+                        "{}: {} stages, {}",
+                        name,
+                        loaded.stages.len(),
+                        TimeFormat::Smart.format(total_s, total_s)
+                    );
+                },
+                Err(message) => println!("{}: {}", name, message)
+            }
+        }
+        return Ok(None);
+    }
+
+    if let Some(("attach", attach_match)) = arg_match.subcommand() {
+        if attach_match.is_present("observe") {
+            let name = attach_match.get_one::<String>("name")
+                .or(attach_match.get_one::<String>("session_name"))
+                .map(String::as_str)
+                .unwrap_or(observe::DEFAULT_NAME);
+            if let observe::Observed::Gone = observe::observe(name)? {
+                println!("The timer is no longer running");
+            }
+            return Ok(None);
+        }
+    }
+
+    if let Some((command @ ("status" | "pause" | "unpause"), instance_match)) = arg_match.subcommand() {
+        #[cfg(unix)]
+        {
+            // the commands of the socket of the timer
+            let request = match command {
+                "unpause" => "resume",
+                command => command
+            };
+            let target = instance_match.get_one::<String>("instance").map(String::as_str);
+            let answer = instances::find(target).and_then(|instance| instances::send(&instance, request))?;
+            if command == "status" {
+                println!("{}", answer);
+            }
+            return Ok(None);
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = instance_match;
+            return Err(RunError::Message(format!("`staged-timer {}` is only available on Unix", command)));
+        }
+    }
+
+    if let Some(("edit", edit_match)) = presets_command {
+        presets::edit(edit_match.get_one::<String>("preset").unwrap())?;
+        return Ok(None);
+    }
+
+    if let Some(("copy", copy_match)) = presets_command {
+        let preset = copy_match.get_one::<String>("preset").unwrap();
+        let set: Vec<String> = copy_match.get_many::<String>("set")
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        let copied = presets::copy(
+            copy_match.get_one::<String>("source").unwrap(),
+            preset,
+            &set,
+            copy_match.is_present("force")
+        )?;
+        println!("Saved preset \"{}\" to {}", preset, copied.display());
+        return Ok(None);
+    }
+
+    if let Some(("remove", remove_match)) = presets_command {
+        let preset = remove_match.get_one::<String>("preset").unwrap();
+        let path = presets::remove(preset)?;
+        println!("Removed preset \"{}\" from {}", preset, path.display());
+        return Ok(None);
+    }
+
+    if let Some(("config", config_match)) = arg_match.subcommand() {
+        if let Some(("edit", _)) = config_match.subcommand() {
+            config::edit_settings()?;
+            return Ok(None);
+        }
+    }
+
+    if let Some(("backup", backup_match)) = arg_match.subcommand() {
+        // both subcommands take an archive, one of them is required
+        let (command, command_match) = backup_match.subcommand().unwrap();
+        let archive = command_match.get_one::<PathBuf>("archive").unwrap();
+        let force = command_match.is_present("force");
+        let files = |count: usize| format!("{} file{}", count, if count == 1 { "" } else { "s" });
+        let done = if command == "import" {
+            backup::import(archive, force)
+                .map(|count| format!("Imported {} from {}", files(count), archive.display()))
+        } else {
+            backup::export(archive, force)
+                .map(|count| format!("Exported {} to {}", files(count), archive.display()))
+        };
+        println!("{}", done?);
+        return Ok(None);
+    }
+
+    if let Some(("roll", roll_match)) = arg_match.subcommand() {
+        let settings = config::load_settings()?;
+        film::print_log(
+            roll_match.get_one::<String>("roll_id").map(String::as_str),
+            selected_units(roll_match, &settings)
+        )?;
+        return Ok(None);
+    }
+
+    if let Some(("calibrate", calibrate_match)) = arg_match.subcommand() {
+        calibrate(
+            calibrate_match.get_one::<String>("preset").unwrap(),
+            *calibrate_match.get_one::<u32>("min_runs").unwrap(),
+            calibrate_match.is_present("apply")
+        )?;
+        return Ok(None);
+    }
+
+    if let Some(("history", history_match)) = arg_match.subcommand() {
+        let printed = if history_match.is_present("json") {
+            history::numbered().map(|sessions| {
+                for (id, session) in &sessions {
+                    println!("{}", schema::line(&schema::Session::of(session, Some(*id))));
+                }
+            })
+        } else {
+            history::print_sessions()
+        };
+        printed?;
+        return Ok(None);
+    }
+
+    if let Some(("dilute", dilute_match)) = arg_match.subcommand() {
+        let settings = config::load_settings()?;
+        let units = selected_units(dilute_match, &settings);
+
+        let ratio_str = dilute_match.get_one::<String>("ratio").unwrap();
+        let volume_str = dilute_match.get_one::<String>("volume").unwrap();
+        let ratio = dilution::parse_ratio(ratio_str)?;
+        let volume_ml = dilution::parse_volume(volume_str, units)?;
+
+        let (concentrate_ml, water_ml) = dilution::split(ratio, volume_ml);
+        let mut note = format!(
+            "{} for {}: {} concentrate, {} water",
+            ratio_str,
+            units.format_volume(volume_ml),
+            units.format_volume(concentrate_ml),
+            units.format_volume(water_ml)
+        );
+        if let Some(target) = dilute_match.get_one::<f64>("temperature") {
+            let target_c = units.to_celsius(*target);
+            let stock_c = dilute_match.get_one::<f64>("stock_temperature")
+                .map(|stock| units.to_celsius(*stock))
+                .unwrap_or(20.0);
+            if let Some(water_c) = dilution::water_temperature(
+                (concentrate_ml, water_ml), stock_c, target_c
+            ) {
+                note.push_str(&format!(
+                    "\nWater at {} brings the solution to {} with the \
+                    concentrate at {}",
+                    units.format_temperature(water_c),
+                    units.format_temperature(target_c),
+                    units.format_temperature(stock_c)
+                ));
+            }
+        }
+        println!("{}", note);
+
+        if dilute_match.is_present("attach") {
+            history::add_pending_note(&note)?;
+            println!("The note will be attached to the next recorded run.");
+        }
+        return Ok(None);
+    }
+
+    // quick timers, alarms and exposures take the options of their run
+    // themselves
+    let run_match = match arg_match.subcommand() {
+        Some(("quick" | "at" | "reciprocity", sub_match)) => sub_match,
+        _ => &arg_match
+    };
+
+    // headless runs and status bars are as unattended as silent runs
+    let headless = run_match.is_present("headless");
+    let statusbar = run_match.is_present("statusbar");
+    let wait = run_match.is_present("wait") || headless || statusbar;
+    let session_name = run_match.get_one::<String>("session_name").cloned();
+    if !wait && io::stdin().is_terminal() && io::stdout().is_terminal() {
+        if let Err(message) = config::first_run_wizard() {
+            eprintln!("{}", message);
+        }
+    }
+
+    let settings = config::load_settings()?;
+
+    if let Some(("attach", attach_match)) = arg_match.subcommand() {
+        let name = attach_match.get_one::<String>("name")
+            .or(attach_match.get_one::<String>("session_name"))
+            .map(String::as_str)
+            .unwrap_or(detach::DEFAULT_NAME);
+        return Ok(attach(name, &settings)?);
+    }
+
+    if arg_match.is_present("resume") {
+        let name = session_name.as_deref().unwrap_or(detach::DEFAULT_NAME);
+        let mut options = resume(name, &settings)?;
+        if let Some(interval_s) = arg_match.get_one::<u32>("checkpoint_interval") {
+            options.checkpoint_interval = Some(Duration::from_secs((*interval_s).into()));
+        }
+        return Ok(Some(options));
+    }
+
+    if let Some(("quick", quick_match)) = arg_match.subcommand() {
+        let mut options = run_options(&arg_match, &settings, vec![StageSpec {
+            stage: TimerStage::new(
+                quick_match.get_one::<String>("name").unwrap().clone(),
+                *quick_match.get_one::<Duration>("time").unwrap()
+            ),
+            enabled: true
+        }])?;
+        // it goes into overtime or waits like the stages of presets do
+        let stage = &mut options.stages[0].stage;
+        stage.overtime = options.presets.overtime;
+        stage.manual_advance = options.presets.manual_advance;
+        return Ok(Some(options));
+    }
+
+    if let Some(("reciprocity", reciprocity_match)) = arg_match.subcommand() {
+        let metered_s = *reciprocity_match.get_one::<u32>("metered_time").unwrap();
+        let (film, exponent) = match reciprocity_match.get_one::<String>("film") {
+            Some(key) => reciprocity::film_exponent(key).unwrap(),
+            None => ("Custom film", *reciprocity_match.get_one::<f64>("exponent").unwrap())
+        };
+        if !(1.0..=3.0).contains(&exponent) {
+            return Err(RunError::Message(String::from("The reciprocity exponent must be between 1 and 3.")));
+        }
+
+        let corrected_s = reciprocity::corrected_s(metered_s, exponent);
+        let time_format = settings.time_format.unwrap_or(TimeFormat::Smart);
+        println!(
+            "{} ({}): metered {}, corrected {}",
+            film,
+            exponent,
+            time_format.format(metered_s, metered_s),
+            time_format.format(corrected_s, corrected_s)
+        );
+        if !reciprocity_match.is_present("start") {
+            return Ok(None);
+        }
+
+        return run_options(&arg_match, &settings, vec![StageSpec {
+            stage: TimerStage::new(
+                String::from("Exposure"),
+                Duration::from_secs(corrected_s.into())
+            ),
+            enabled: true
+        }]).map(Some).map_err(RunError::from);
+    }
+
+    if let Some(("at", at_match)) = arg_match.subcommand() {
+        let deadline = next_time_of_day(*at_match.get_one::<NaiveTime>("clock_time").unwrap());
+        return run_options(&arg_match, &settings, vec![StageSpec {
+            stage: TimerStage {
+                // ends at the time of day rather than after as long as that
+                // was away, unless it is sped up for a dry run
+                ends_at: (!arg_match.is_present("dry_run")).then(|| deadline.into()),
+                ..TimerStage::new(
+                    at_match.get_one::<String>("name").unwrap().clone(),
+                    Duration::from_secs(seconds_until(deadline).into())
+                )
+            },
+            enabled: true
+        }]).map(Some).map_err(RunError::from);
+    }
+
+    let options = run_options(&arg_match, &settings, Vec::new())?;
+    let mut confirm = options.controls.confirm.clone();
+    let (overtime, manual_advance) = (options.presets.overtime, options.presets.manual_advance);
+    let default_completion = options.completion.clone();
+    let cli_completion = arg_match.is_present("on_complete").then(|| default_completion.clone());
+    // checking I/O reports rather than uses it
+    let check_io = arg_match.is_present("check_io");
+
+    let mut stages: Vec<config::ConfiguredStage> = Vec::new();
+    let mut cues: Vec<config::CueRule> = Vec::new();
+    let mut exercises: HashMap<String, Exercise> = HashMap::new();
+    let mut config_completion = None;
+    for path in arg_match.get_many::<PathBuf>("exercises").into_iter().flatten() {
+        let library = config::load_exercises(path)?;
+        exercises.extend(library.into_iter().map(|e| (e.name.clone(), e)));
+    }
+    // presets run later only have these libraries and their own exercises
+    let libraries = exercises.clone();
+
+    // only runs of nothing but a single preset count as runs of it
+    let stage_sources = ["config", "time", "work", "ladder", "intervals", "pomodoro", "split_grade", "from_session"];
+    let preset = match arg_match.get_many::<String>("preset") {
+        Some(mut names) if !stage_sources.iter().any(|id| arg_match.is_present(id)) => {
+            names.next().filter(|_| names.next().is_none()).cloned()
+        },
+        _ => None
+    };
+    let preset_paths: Vec<PathBuf> = arg_match.get_many::<String>("preset")
+        .into_iter()
+        .flatten()
+        .map(|preset| presets::find(preset))
+        .collect::<Result<_, String>>()?;
+    let config_paths: Vec<&PathBuf> = preset_paths.iter()
+        .chain(arg_match.get_many::<PathBuf>("config").into_iter().flatten())
+        .collect();
+    // stages of a single file and nothing else follow edits to it
+    let generated = stage_sources.iter().any(|id| *id != "config" && arg_match.is_present(id));
+    let watched_config = match config_paths[..] {
+        [path] if !generated => Some(path.clone()),
+        _ => None
+    };
+    for path in config_paths {
+        let loaded = config::load(path)?;
+        stages.extend(loaded.stages);
+        cues.extend(loaded.cues);
+        confirm.quit = loaded.confirm.quit.unwrap_or(confirm.quit);
+        confirm.skip = loaded.confirm.skip.unwrap_or(confirm.skip);
+        confirm.jump = loaded.confirm.jump.unwrap_or(confirm.jump);
+        config_completion = loaded.on_complete.or(config_completion);
+        exercises.extend(
+            loaded.exercises.into_iter().map(|e| (e.name.clone(), e))
+        );
+    }
+
+    if let Some(id) = arg_match.get_one::<u64>("from_session") {
+        let session = usize::try_from(*id).map_err(|e| e.to_string())
+            .and_then(history::session)?;
+        stages.extend(generators::from_session(&session));
+    }
+
+    let stage_names = arg_match.get_one::<String>("stage_names").cloned()
+        .or_else(|| settings.stage_names.clone())
+        .unwrap_or_else(|| String::from(generators::DEFAULT_STAGE_NAMES));
+
+    if let Some(work_s) = arg_match.get_one::<u32>("work") {
+        stages.extend(generators::work_rest(
+            *work_s,
+            *arg_match.get_one::<(u32, u32)>("work_rest_ratio").unwrap(),
+            *arg_match.get_one::<u32>("rounds").unwrap(),
+            &stage_names
+        ));
+    }
+
+    if let Some(steps) = arg_match.get_many::<u32>("ladder") {
+        stages.extend(generators::ladder(
+            &steps.copied().collect::<Vec<u32>>(),
+            arg_match.is_present("pyramid"),
+            arg_match.get_one::<u32>("ladder_rest").copied().unwrap_or(0),
+            &stage_names
+        ));
+    }
+
+    if let Some(spec) = arg_match.get_one::<String>("intervals") {
+        stages.extend(generators::intervals(spec, &stage_names)?);
+    }
+
+    if let Some(spec) = arg_match.get_one::<String>("pomodoro") {
+        stages.extend(generators::pomodoro(spec, &stage_names)?);
+        // focus and breaks go on until the user calls it a day
+        config_completion = config_completion.or(Some(Completion::Again));
+    }
+
+    if let Some(spec) = arg_match.get_one::<String>("split_grade") {
+        let enlarger_on = arg_match.get_one::<String>("enlarger_on").or(settings.enlarger_on.as_ref());
+        let enlarger_off = arg_match.get_one::<String>("enlarger_off").or(settings.enlarger_off.as_ref());
+        let exposures = generators::split_grade(
+            spec,
+            (enlarger_on.map(String::as_str), enlarger_off.map(String::as_str))
+        );
+        stages.extend(exposures?);
+    }
+
+    let input_names: Vec<String> = arg_match.get_many::<String>("name")
+        .map(|names| names.cloned().collect())
+        .unwrap_or_default();
+    let input_times: Vec<Duration> = arg_match.get_many::<Duration>("time")
+        .map(|times| times.cloned().collect())
+        .unwrap_or_default();
+    let input_notes: Vec<String> = arg_match.get_many::<String>("stage_note")
+        .map(|notes| notes.cloned().collect())
+        .unwrap_or_default();
+    let warn = arg_match.get_one::<u32>("warn").copied()
+        .or(settings.warn)
+        .unwrap_or(0);
+
+    if input_names.len() > input_times.len() {
+        return Err(RunError::Message(format!(
+            "Cannot match timer stage names with their durations. \
+            {} names and {} durations were provided.",
+            input_names.len(), input_times.len()
+        )));
+    }
+
+    if input_notes.len() > input_times.len() {
+        return Err(RunError::Message(format!(
+            "Cannot match stage notes with their stages. {} notes and {} \
+            durations were provided.",
+            input_notes.len(), input_times.len()
+        )));
+    }
+
+    if !input_names.is_empty() && input_names.len() < input_times.len() {
+        eprintln!(
+            "Warning: only {} of {} stages were named, the remaining stages \
+            are named automatically.",
+            input_names.len(), input_times.len()
+        );
+    }
+
+    let input_notes = input_notes.into_iter()
+        .map(Some)
+        .chain(std::iter::repeat(None));
+    let input_stages = input_names.into_iter()
+        .map(Some)
+        .chain(std::iter::repeat(None))
+        .zip(input_times)
+        .zip(input_notes)
+        .map(|((name, period), note)| config::ConfiguredStage {
+            name,
+            period,
+            note,
+            ..config::ConfiguredStage::default()
+        })
+        .collect();
+    stages.extend(generators::repeat(
+        input_stages,
+        arg_match.get_one::<u32>("repeat").copied().unwrap_or(1),
+        &stage_names
+    ).map_err(|message| format!("--repeat: {}", message))?);
+
+    let select_stages = arg_match.is_present("select");
+    let edit_stages = arg_match.is_present("edit");
+    if edit_stages && wait {
+        return Err(RunError::Message(String::from(
+            "--edit needs the terminal and can't be combined with --wait, --headless or --statusbar."
+        )));
+    }
+    if !edit_stages
+        && (stages.is_empty() || (!select_stages && !stages.iter().any(|s| s.enabled)))
+    {
+        return Err(RunError::Message(String::from("No enabled timer stages were provided.")));
+    }
+
+    let roll = arg_match.get_one::<String>("roll").cloned();
+    if let Some(roll) = roll.as_ref() {
+        let rolls = film::load_rolls()?;
+        if !rolls.iter().any(|registered| registered.id == *roll) {
+            return Err(RunError::Message(format!(
+                "Roll \"{}\" is not registered. Add it to {} first.",
+                roll,
+                film::registry_path().unwrap_or_default().display()
+            )));
+        }
+    }
+
+    let mut batches: Vec<String> = arg_match.get_many::<String>("batch")
+        .map(|batches| batches.cloned().collect())
+        .unwrap_or_default();
+    for batch in stages.iter().filter(|s| s.enabled).filter_map(|s| s.batch.as_ref()) {
+        if !batches.contains(batch) {
+            batches.push(batch.clone());
+        }
+    }
+
+    let missing_player = options.sound.enabled && options.audio_player.is_none();
+    let wants_audio = options.notify.alert_file.is_some() || stages.iter().any(|stage| stage.audio.is_some());
+    // --check-io reports these with the rest
+    let wants_audio = wants_audio && !check_io;
+    if let (true, Err(message)) = (options.sound.enabled && wants_audio, integrations::AUDIO.require()) {
+        eprintln!("Warning: {}", message);
+    } else if missing_player && wants_audio && stages.iter().any(|stage| stage.audio.is_some()) {
+        eprintln!(
+            "Warning: no audio player was found, stage audio will not be \
+            played. Set audio_player in the settings to choose one."
+        );
+    }
+
+    let heart_rate_command = arg_match.get_one::<String>("heart_rate_command").cloned()
+        .or(settings.heart_rate_command.clone());
+    let rest_below = arg_match.get_one::<u16>("rest_below").copied()
+        .or(settings.rest_below);
+    let heart_rate = match (heart_rate_command, rest_below) {
+        (Some(command), Some(below_bpm)) => Some(heart_rate::HeartRateOptions {
+            command,
+            below_bpm,
+            max_extension_s: arg_match.get_one::<u32>("max_rest_extension").copied()
+                .or(settings.max_rest_extension)
+                .unwrap_or(0)
+        }),
+        (None, None) => None,
+        (Some(_), None) => return Err(RunError::Message(String::from(
+            "The heart rate is only read for --rest-below, set it or rest_below in the settings."
+        ))),
+        (None, Some(_)) => return Err(RunError::Message(String::from(
+            "--rest-below needs a --heart-rate-command to read the heart rate from."
+        )))
+    };
+
+    // development times at the temperature of the developer
+    let units = selected_units(&arg_match, &settings);
+    let developer_c = arg_match.get_one::<f64>("temperature")
+        .map(|temperature| units.to_celsius(*temperature));
+    let compensation = developer_c.map(|temperature_c| {
+        let curve = settings.temperature_curve.as_deref()
+            .unwrap_or(compensation::STANDARD_CURVE);
+        compensation::factor(curve, temperature_c).ok_or_else(|| format!(
+            "Cannot compensate for {}, the temperature curve goes from {} to {}.",
+            units.format_temperature(temperature_c),
+            units.format_temperature(curve[0].0),
+            units.format_temperature(curve[curve.len() - 1].0)
+        ))
+    }).transpose()?;
+    let mut notes: Vec<String> = arg_match.get_many::<String>("note")
+        .map(|notes| notes.cloned().collect())
+        .unwrap_or_default();
+    if let (Some(temperature_c), Some(factor)) = (developer_c, compensation) {
+        config::compensate(&mut stages, factor);
+        notes.push(format!(
+            "Developer at {}, times × {:.2}", units.format_temperature(temperature_c), factor
+        ));
+    }
+
+    let countdown: Vec<CountdownCue> = arg_match.get_many::<CountdownCue>("countdown")
+        .map(|cues| cues.copied().collect())
+        .unwrap_or_default();
+    config::default_countdown(&mut stages, &countdown);
+
+    // an explicit --warn applies to every stage
+    let override_warn = arg_match.is_present("warn");
+    let stages = stage_specs(
+        stages,
+        &cues,
+        &exercises,
+        &settings.stage_colors,
+        override_warn,
+        overtime,
+        manual_advance
+    )?;
+
+    if let Some(stages_only) = stages_only {
+        let enabled: Vec<&TimerStage> = stages.iter()
+            .filter(|spec| spec.enabled)
+            .map(|spec| &spec.stage)
+            .collect();
+        match stages_only {
+            StagesOnly::Validate => {
+                let total: Duration = enabled.iter().map(|stage| stage.period).sum();
+                let total_s = u32::try_from(total.as_secs_f64().ceil() as u64).unwrap_or(u32::MAX);
+                println!(
+                    "The stages are fine: {} stages, {}",
+                    enabled.len(),
+                    TimeFormat::Smart.format(total_s, total_s)
+                );
+            },
+            StagesOnly::Export => {
+                let format = arg_match.get_one::<String>("export_format")
+                    .and_then(|format| export::ExportFormat::from_name(format))
+                    .unwrap_or(export::ExportFormat::Json);
+                let exported = export::export(
+                    stages.iter().map(|spec| (&spec.stage, spec.enabled)),
+                    format
+                );
+                println!("{}", exported?.trim_end());
+            }
+        }
+        return Ok(None);
+    }
+
+    let presets = PresetLoader {
+        exercises: libraries,
+        override_warn,
+        completion: default_completion.clone(),
+        stage_colors: settings.stage_colors.clone(),
+        compensation,
+        overtime,
+        manual_advance,
+        countdown
+    };
+    let splits: Vec<(String, Vec<StageSpec>)> = arg_match.get_many::<String>("split")
+        .into_iter()
+        .flatten()
+        .map(|name| presets.load(name, Completion::Exit).map(|(stages, _)| (name.clone(), stages)))
+        .collect::<Result<_, String>>()?;
+
+    // sounds, hosts, commands and devices the run uses, instead of running
+    if check_io {
+        let all_stages: Vec<&TimerStage> = stages.iter()
+            .chain(splits.iter().flat_map(|(_, stages)| stages))
+            .map(|spec| &spec.stage)
+            .collect();
+        let notify = &options.notify;
+        let mut checks: Vec<preflight::Check> = notify::Notifiers::find(notify).1.into_iter()
+            .map(|warning| preflight::Check::failed("Notifier", warning))
+            .collect();
+
+        // nothing is played with sound off
+        let alert_file = notify.alert_file.as_deref()
+            .filter(|_| notify.all_channels().contains(&notify::Channel::Sound));
+        let mut seen = HashSet::new();
+        let stage_audio: Vec<&Path> = all_stages.iter()
+            .filter_map(|stage| stage.audio.as_deref())
+            .filter(|audio| seen.insert(*audio))
+            .collect();
+        if options.sound.enabled && (alert_file.is_some() || !stage_audio.is_empty()) {
+            checks.push(match (integrations::AUDIO.require(), &options.audio_player) {
+                (Err(message), _) => preflight::Check::failed("Audio", message),
+                (Ok(()), Some(player)) => preflight::command("Audio player", &player.command_line()),
+                (Ok(()), None) => preflight::Check::failed("Audio player", String::from(
+                    "none is installed, set audio_player in the settings"
+                ))
+            });
+            checks.extend(alert_file.map(|file| preflight::sound_file("Alert sound", file)));
+            checks.extend(stage_audio.iter().map(|file| preflight::sound_file("Stage audio", file)));
+        }
+
+        let urls = [("Webhook", &notify.webhook_url), ("Push notifications", &notify.push_url)];
+        for (what, url) in urls {
+            checks.extend(url.as_deref().map(|url| preflight::url_host(what, url)));
+        }
+        if let Some(broker) = run_match.get_one::<String>("mqtt").or(settings.mqtt_broker.as_ref()) {
+            checks.push(match integrations::MQTT.require() {
+                Ok(()) => preflight::host("MQTT broker", broker, mqtt::DEFAULT_PORT),
+                Err(message) => preflight::Check::failed("MQTT broker", message)
+            });
+        }
+
+        checks.extend(options.hooks.commands().map(|(setting, command)| preflight::command(setting, command)));
+        for stage in all_stages.iter() {
+            let commands = [("on_start", &stage.on_start), ("on_end", &stage.on_end)];
+            for (setting, command) in commands {
+                checks.extend(command.as_deref().map(|command| preflight::command(
+                    &format!("{} of {}", setting, stage.name), command
+                )));
+            }
+        }
+        checks.extend(settings.sensor_command.as_deref()
+            .map(|command| preflight::command("sensor_command", command)));
+        checks.extend(options.controls.input_devices.iter().map(|device| preflight::device(device)));
+
+        // the report tells what failed
+        let passed = preflight::report(&checks, &mut io::stdout()).unwrap_or(false);
+        return if passed { Ok(None) } else { Err(RunError::Quiet) };
+    }
+
+    Ok(Some(RunOptions {
+        stages,
+        controls: Controls { confirm, heart_rate, ..options.controls },
+        display: DisplayOptions { warning_threshold: warn, ..options.display },
+        select_stages,
+        edit_stages,
+        log: SessionLog {
+            roll,
+            // scaled stages don't tell how long the preset takes
+            preset: preset.filter(|_| compensation.is_none()),
+            batches,
+            notes,
+            environment: history::Environment {
+                temperature_c: arg_match.get_one::<f64>("ambient_temperature")
+                    .map(|temperature| {
+                        selected_units(&arg_match, &settings).to_celsius(*temperature)
+                    }),
+                humidity_pct: arg_match.get_one::<f64>("humidity").copied()
+            },
+            sensor_command: settings.sensor_command,
+            tolerance_s: arg_match.get_one::<u32>("tolerance").copied()
+                .or(settings.deviation_tolerance),
+            event_log: arg_match.get_one::<PathBuf>("log").cloned(),
+            summary_json: arg_match.get_one::<PathBuf>("summary_json").cloned()
+        },
+        completion: arg_match.get_one::<String>("then").cloned()
+            .map(Completion::Preset)
+            .or(cli_completion)
+            .or(config_completion)
+            .unwrap_or(default_completion),
+        round: arg_match.get_one::<Round>("loop").copied(),
+        splits,
+        presets,
+        // choices of --select and --edit would be lost on a reload
+        watched_config: watched_config.filter(|_| !select_stages && !edit_stages),
+        ..options
+    }))
+}
@@ -0,0 +1,141 @@
+use std::path::Path;
+#[cfg(feature = "audio")]
+use std::{env, process::{Child, Command, Stdio}};
+
+/// Command line players tried in order when none is configured. Each must
+/// play the file given as last argument and exit when done.
+#[cfg(feature = "audio")]
+const PLAYER_CANDIDATES: &[&[&str]] = &[
+    &["afplay"],
+    &["paplay"],
+    &["mpv", "--no-video", "--really-quiet"],
+    &["ffplay", "-nodisp", "-autoexit", "-loglevel", "quiet"],
+    &["aplay", "-q"]
+];
+
+#[cfg(feature = "audio")]
+fn in_path(program: &str) -> bool {
+    env::var_os("PATH").is_some_and(|paths| {
+        env::split_paths(&paths).any(|dir| {
+            dir.join(program).is_file()
+            || dir.join(format!("{}.exe", program)).is_file()
+        })
+    })
+}
+
+/// The external program used to play audio files, with its arguments.
+#[cfg(feature = "audio")]
+#[derive(Clone)]
+pub struct Player {
+    command: Vec<String>
+}
+
+#[cfg(feature = "audio")]
+impl Player {
+    /// Use the configured player command if there is one, otherwise the
+    /// first known player that is installed.
+    pub fn find(configured: Option<&str>) -> Option<Player> {
+        if let Some(command) = configured {
+            let command: Vec<String> = command.split_whitespace()
+                .map(String::from)
+                .collect();
+            return (!command.is_empty()).then_some(Player { command });
+        }
+
+        PLAYER_CANDIDATES.iter()
+            .find(|candidate| in_path(candidate[0]))
+            .map(|candidate| Player {
+                command: candidate.iter().map(|s| s.to_string()).collect()
+            })
+    }
+
+    /// The command files are played with, without the file.
+    pub fn command_line(&self) -> String {
+        self.command.join(" ")
+    }
+
+    pub fn play(&self, file: &Path) -> Result<Playback, String> {
+        Command::new(&self.command[0])
+            .args(&self.command[1..])
+            .arg(file)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map(|child| Playback { child })
+            .map_err(|e| format!("Could not start {}: {}", self.command[0], e))
+    }
+}
+
+/// A running playback. It is stopped when dropped.
+#[cfg(feature = "audio")]
+pub struct Playback {
+    child: Child
+}
+
+#[cfg(feature = "audio")]
+impl Playback {
+    #[cfg(unix)]
+    fn signal(&self, signal: libc::c_int) {
+        if let Ok(pid) = libc::pid_t::try_from(self.child.id()) {
+            // SAFETY: sending a signal to our own child process has no
+            // memory safety implications
+            unsafe { libc::kill(pid, signal); }
+        }
+    }
+
+    /// Suspend playback. Only supported on Unix, elsewhere the audio keeps
+    /// playing.
+    pub fn pause(&self) {
+        #[cfg(unix)]
+        self.signal(libc::SIGSTOP);
+    }
+
+    pub fn resume(&self) {
+        #[cfg(unix)]
+        self.signal(libc::SIGCONT);
+    }
+}
+
+#[cfg(feature = "audio")]
+impl Drop for Playback {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Stands in for the player in builds without audio, where there is none.
+#[cfg(not(feature = "audio"))]
+#[derive(Clone)]
+pub enum Player {}
+
+#[cfg(not(feature = "audio"))]
+impl Player {
+    pub fn find(_configured: Option<&str>) -> Option<Player> {
+        None
+    }
+
+    pub fn command_line(&self) -> String {
+        match *self {}
+    }
+
+    pub fn play(&self, _file: &Path) -> Result<Playback, String> {
+        match *self {}
+    }
+}
+
+/// Stands in for a playback in builds without audio.
+#[cfg(not(feature = "audio"))]
+pub enum Playback {}
+
+#[cfg(not(feature = "audio"))]
+impl Playback {
+    pub fn pause(&self) {
+        match *self {}
+    }
+
+    pub fn resume(&self) {
+        match *self {}
+    }
+}
@@ -0,0 +1,195 @@
+use std::{fs, io, path::{Component, Path, PathBuf}};
+
+use chrono::{Local, SecondsFormat};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, history};
+
+/// Version of the archive format, so archives of a newer one are turned
+/// down instead of half understood.
+const FORMAT: u32 = 1;
+
+/// Files of the config directory that are backed up, besides the presets.
+const CONFIG_FILES: [&str; 3] = ["settings.toml", "rolls.toml", "chemicals.toml"];
+
+/// Files of the data directory that are backed up, besides the backups of
+/// the history.
+const DATA_FILES: [&str; 2] = ["history.toml", "pending_notes.toml"];
+
+/// Everything `backup export` bundles, as one TOML file.
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    format: u32,
+    /// Local time the archive was made at, in RFC 3339 format
+    exported: String,
+    #[serde(default, rename = "file")]
+    files: Vec<ArchivedFile>
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchivedFile {
+    /// Where the file goes, under `config/` or `data/`
+    path: String,
+    content: String
+}
+
+/// The directory the archived paths starting with `root` go to.
+fn root_dir(root: &str) -> Option<PathBuf> {
+    match root {
+        "config" => config::config_dir(),
+        "data" => history::data_dir(),
+        _ => None
+    }
+}
+
+/// Whether `name` in the presets directory is a preset.
+fn is_preset(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    !name.starts_with('.')
+        && matches!((components.next(), components.next()), (Some(Component::Normal(_)), None))
+}
+
+/// Whether `name` in the data directory is a backup of the history.
+fn is_history_backup(name: &str) -> bool {
+    name.strip_prefix("history.toml.").is_some_and(|n| n.parse::<u32>().is_ok())
+}
+
+/// Whether the archived `path` is one that [`backed_up`] lists where the
+/// file exists: a file of [`CONFIG_FILES`] or [`DATA_FILES`], a preset or a
+/// backup of the history.
+fn is_backed_up(path: &str) -> bool {
+    match path.split_once('/') {
+        Some(("config", name)) => CONFIG_FILES.contains(&name)
+            || name.strip_prefix("presets/").is_some_and(is_preset),
+        Some(("data", name)) => DATA_FILES.contains(&name) || is_history_backup(name),
+        _ => false
+    }
+}
+
+/// Where the archived file `path` goes on this machine, if it is one that
+/// is backed up at all.
+fn target(path: &str) -> Result<PathBuf, String> {
+    let invalid = || format!("The archive has a file at \"{}\", which isn't backed up", path);
+    if !is_backed_up(path) {
+        return Err(invalid());
+    }
+    let (root, rest) = path.split_once('/').ok_or_else(invalid)?;
+    let relative = Path::new(rest);
+    if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(invalid());
+    }
+    let dir = root_dir(root).ok_or_else(invalid)?;
+    Ok(dir.join(relative))
+}
+
+/// Names of the files directly in `dir` that `keep` holds for, sorted.
+fn files_in(dir: &Path, keep: impl Fn(&str) -> bool) -> Result<Vec<String>, String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Could not read {}: {}", dir.display(), e))
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| keep(name))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Paths of the files to back up, as they are archived.
+fn backed_up() -> Result<Vec<String>, String> {
+    let mut paths: Vec<String> = CONFIG_FILES.iter().map(|name| format!("config/{}", name)).collect();
+    if let Some(dir) = config::config_dir() {
+        let presets = files_in(&dir.join("presets"), is_preset)?;
+        paths.extend(presets.into_iter().map(|name| format!("config/presets/{}", name)));
+    }
+    paths.extend(DATA_FILES.iter().map(|name| format!("data/{}", name)));
+    if let Some(dir) = history::data_dir() {
+        let backups = files_in(&dir, is_history_backup)?;
+        paths.extend(backups.into_iter().map(|name| format!("data/{}", name)));
+    }
+    Ok(paths)
+}
+
+/// Bundle the presets, settings, registries and history into one archive at
+/// `path`, replacing a file there only if `overwrite` is set. Returns how
+/// many files went into it.
+pub fn export(path: &Path, overwrite: bool) -> Result<usize, String> {
+    if path.exists() && !overwrite {
+        return Err(format!(
+            "{} already exists, use --force to replace it", path.display()
+        ));
+    }
+    let mut files = Vec::new();
+    for archived in backed_up()? {
+        let source = target(&archived)?;
+        match fs::read_to_string(&source) {
+            Ok(content) => files.push(ArchivedFile { path: archived, content }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(format!("Could not read {}: {}", source.display(), e))
+        }
+    }
+    let count = files.len();
+    let archive = Archive {
+        format: FORMAT,
+        exported: Local::now().to_rfc3339_opts(SecondsFormat::Secs, false),
+        files
+    };
+    let content = toml::to_string(&archive).map_err(|e| format!(
+        "Could not write the archive: {}", e
+    ))?;
+    fs::write(path, content).map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+    Ok(count)
+}
+
+/// Unpack an archive made by [`export`] at `path`. Files that are already
+/// there and differ are only replaced if `overwrite` is set, otherwise
+/// nothing is unpacked. Returns how many files were written.
+pub fn import(path: &Path, overwrite: bool) -> Result<usize, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!(
+        "Could not read {}: {}", path.display(), e
+    ))?;
+    let archive: Archive = toml::from_str(&content).map_err(|e| format!(
+        "{} is not an archive made by `staged-timer backup export`: {}", path.display(), e
+    ))?;
+    if archive.format > FORMAT {
+        return Err(format!(
+            "{} was made by a newer version of staged-timer, which this one can't read",
+            path.display()
+        ));
+    }
+
+    let mut unpacked = Vec::new();
+    let mut conflicts = Vec::new();
+    for file in &archive.files {
+        let destination = target(&file.path)?;
+        match fs::read_to_string(&destination) {
+            Ok(existing) if existing == file.content => continue,
+            Ok(_) => conflicts.push(destination.display().to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(format!("Could not read {}: {}", destination.display(), e))
+        }
+        unpacked.push((destination, &file.content));
+    }
+    if !conflicts.is_empty() && !overwrite {
+        return Err(format!(
+            "These files are different here already, use --force to replace them:\n{}",
+            conflicts.join("\n")
+        ));
+    }
+
+    for (destination, content) in &unpacked {
+        if let Some(dir) = destination.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!(
+                "Could not create directory {}: {}", dir.display(), e
+            ))?;
+        }
+        fs::write(destination, content).map_err(|e| format!(
+            "Could not write {}: {}", destination.display(), e
+        ))?;
+    }
+    Ok(unpacked.len())
+}
@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+
+use crate::history::SessionRecord;
+
+/// How long each of the stages named `names` really took in `sessions`.
+/// Stages are matched by name and position among the stages of that name,
+/// so a second "Rinse" is only compared with second rinses. Aborted stages
+/// are left out.
+pub fn actual_times(names: &[String], sessions: &[&SessionRecord]) -> Vec<Vec<u32>> {
+    let mut times = vec![Vec::new(); names.len()];
+    for session in sessions {
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        let recorded: HashMap<(&str, usize), u32> = session.stages.iter()
+            .filter_map(|stage| {
+                let occurrence = seen.entry(stage.name.as_str()).or_default();
+                *occurrence += 1;
+                let actual_s = stage.actual_s.filter(|_| !stage.aborted)?;
+                Some(((stage.name.as_str(), *occurrence), actual_s))
+            })
+            .collect();
+
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        for (i, name) in names.iter().enumerate() {
+            let occurrence = seen.entry(name.as_str()).or_default();
+            *occurrence += 1;
+            if let Some(actual_s) = recorded.get(&(name.as_str(), *occurrence)) {
+                times[i].push(*actual_s);
+            }
+        }
+    }
+    times
+}
+
+/// The median of `times`, rounded to whole seconds, `None` if there are
+/// none.
+pub fn median(times: &[u32]) -> Option<u32> {
+    let mut sorted = times.to_vec();
+    sorted.sort_unstable();
+    let middle = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        len if len % 2 == 1 => Some(sorted[middle]),
+        _ => Some((u64::from(sorted[middle - 1]) + u64::from(sorted[middle])).div_ceil(2) as u32)
+    }
+}
@@ -0,0 +1,125 @@
+use std::{fs, io, path::PathBuf};
+use chrono::{Local, NaiveDate};
+use serde::Deserialize;
+
+use crate::{config::config_dir, history};
+
+/// A batch of mixed chemistry that wears out with age and use.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigBatch {
+    id: String,
+    chemical: String,
+    mixed: toml::value::Datetime,
+    /// Days the batch keeps after mixing
+    life_days: Option<u32>,
+    /// Number of rolls the batch can develop
+    capacity_rolls: Option<u32>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchRegistryFile {
+    #[serde(default, rename = "batch")]
+    batches: Vec<ConfigBatch>
+}
+
+pub struct Batch {
+    pub id: String,
+    pub chemical: String,
+    pub mixed: NaiveDate,
+    pub life_days: Option<u32>,
+    pub capacity_rolls: Option<u32>
+}
+
+pub fn registry_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("chemicals.toml"))
+}
+
+/// Load the chemical batches of the form
+///
+/// ```toml
+/// [[batch]]
+/// id = "D76-03"
+/// chemical = "D-76 stock"
+/// mixed = 2024-03-01
+/// life_days = 90 # optional
+/// capacity_rolls = 10 # optional
+/// ```
+///
+/// A missing file has no batches.
+pub fn load_batches() -> Result<Vec<Batch>, String> {
+    let path = match registry_path() {
+        Some(path) => path,
+        None => return Ok(Vec::new())
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!(
+            "Could not read chemical batches {}: {}", path.display(), e
+        ))
+    };
+    let registry: BatchRegistryFile = toml::from_str(&content).map_err(|e| format!(
+        "Could not parse chemical batches {}: {}", path.display(), e
+    ))?;
+
+    registry.batches.into_iter().map(|batch| {
+        let mixed = batch.mixed.date
+            .filter(|_| batch.mixed.time.is_none())
+            .and_then(|date| NaiveDate::from_ymd_opt(
+                i32::from(date.year), u32::from(date.month), u32::from(date.day)
+            ))
+            .ok_or_else(|| format!(
+                "{}: Batch \"{}\" must have a plain date as mix date, e.g. 2024-03-01",
+                path.display(), batch.id
+            ))?;
+        Ok(Batch {
+            id: batch.id,
+            chemical: batch.chemical,
+            mixed,
+            life_days: batch.life_days,
+            capacity_rolls: batch.capacity_rolls
+        })
+    }).collect()
+}
+
+/// Check the batches with the given IDs for age and use. Returns a warning
+/// for each batch past its usable life or capacity.
+pub fn check(ids: &[String]) -> Result<Vec<String>, String> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let batches = load_batches()?;
+    let history = history::load()?;
+    let today = Local::now().date_naive();
+
+    let mut warnings = Vec::new();
+    for id in ids {
+        let batch = batches.iter().find(|batch| batch.id == *id).ok_or_else(|| format!(
+            "Chemical batch \"{}\" is not registered. Add it to {} first.",
+            id,
+            registry_path().unwrap_or_default().display()
+        ))?;
+
+        let age_days = (today - batch.mixed).num_days();
+        if let Some(life_days) = batch.life_days.filter(|life| age_days > i64::from(*life)) {
+            warnings.push(format!(
+                "{} ({}) was mixed {} days ago and only keeps for {} days.",
+                batch.chemical, batch.id, age_days, life_days
+            ));
+        }
+
+        let used = history.iter()
+            .filter(|session| session.batches.contains(id))
+            .count();
+        if let Some(capacity) = batch.capacity_rolls.filter(|capacity| used >= *capacity as usize) {
+            warnings.push(format!(
+                "{} ({}) has already developed {} of {} rolls.",
+                batch.chemical, batch.id, used, capacity
+            ));
+        }
+    }
+
+    Ok(warnings)
+}
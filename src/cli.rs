@@ -0,0 +1,1298 @@
+use std::{
+    ffi::{OsStr, OsString},
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    time::Duration
+};
+
+use chrono::NaiveTime;
+use clap::{Arg, App, builder::TypedValueParser};
+use staged_timer::{parse_duration, parse_time, CountdownCue};
+
+use crate::{config, generators, integrations, reciprocity, theme, Round};
+
+/// Parses durations of stages, which may have fractions of a second.
+#[derive(Clone)]
+struct TimeValueParser {}
+
+impl TypedValueParser for TimeValueParser {
+    type Value = Duration;
+
+    fn parse_ref(
+        &self,
+        _: &clap::Command,
+        _: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<Self::Value, clap::Error>
+    {
+        let time_str = value.to_str().ok_or_else(|| clap::Error::raw(
+            clap::ErrorKind::InvalidUtf8,
+            "Could not convert input string to unicode"
+        ))?;
+
+        parse_duration(time_str).map_err(|message| clap::Error::raw(
+            clap::ErrorKind::InvalidValue,
+            format!("{}\n", message)
+        ))
+    }
+}
+
+/// Parses durations in whole seconds, like thresholds and generated stages.
+#[derive(Clone)]
+struct SecondsValueParser {}
+
+impl TypedValueParser for SecondsValueParser {
+    type Value = u32;
+
+    fn parse_ref(
+        &self,
+        _: &clap::Command,
+        _: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<Self::Value, clap::Error>
+    {
+        let time_str = value.to_str().ok_or_else(|| clap::Error::raw(
+            clap::ErrorKind::InvalidUtf8,
+            "Could not convert input string to unicode"
+        ))?;
+
+        parse_time(time_str).map_err(|message| clap::Error::raw(
+            clap::ErrorKind::InvalidValue,
+            format!("{}\n", message)
+        ))
+    }
+}
+
+/// Parses countdown cues like `2:00` or `50%`.
+#[derive(Clone)]
+struct CountdownValueParser {}
+
+impl TypedValueParser for CountdownValueParser {
+    type Value = CountdownCue;
+
+    fn parse_ref(
+        &self,
+        _: &clap::Command,
+        _: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<Self::Value, clap::Error>
+    {
+        let cue_str = value.to_str().ok_or_else(|| clap::Error::raw(
+            clap::ErrorKind::InvalidUtf8,
+            "Could not convert input string to unicode"
+        ))?;
+
+        config::parse_countdown(cue_str).map_err(|message| clap::Error::raw(
+            clap::ErrorKind::InvalidValue,
+            format!("{}\n", message)
+        ))
+    }
+}
+
+/// Parses ratios like `2:1` into their two non-zero parts.
+#[derive(Clone)]
+struct RatioValueParser {}
+
+impl TypedValueParser for RatioValueParser {
+    type Value = (u32, u32);
+
+    fn parse_ref(
+        &self,
+        _: &clap::Command,
+        _: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<Self::Value, clap::Error>
+    {
+        let ratio_str = value.to_str().ok_or_else(|| clap::Error::raw(
+            clap::ErrorKind::InvalidUtf8,
+            "Could not convert input string to unicode"
+        ))?;
+
+        ratio_str.split_once(':')
+            .and_then(|(a, b)| Some((a.parse::<u32>().ok()?, b.parse::<u32>().ok()?)))
+            .filter(|(a, b)| *a > 0 && *b > 0)
+            .ok_or_else(|| clap::Error::raw(
+                clap::ErrorKind::InvalidValue,
+                format!("Could not parse ratio {}, expected e.g. 2:1\n", ratio_str)
+            ))
+    }
+}
+
+/// Parses how often `--loop` runs the stages, a number or `forever`.
+#[derive(Clone)]
+struct LoopValueParser {}
+
+impl TypedValueParser for LoopValueParser {
+    type Value = Round;
+
+    fn parse_ref(
+        &self,
+        _: &clap::Command,
+        _: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<Self::Value, clap::Error>
+    {
+        let rounds_str = value.to_str().ok_or_else(|| clap::Error::raw(
+            clap::ErrorKind::InvalidUtf8,
+            "Could not convert input string to unicode"
+        ))?;
+
+        let of = match rounds_str {
+            "forever" => None,
+            rounds => Some(rounds.parse::<u32>().ok().filter(|rounds| *rounds > 0).ok_or_else(|| {
+                clap::Error::raw(
+                    clap::ErrorKind::InvalidValue,
+                    format!("Could not parse rounds {}, expected a number or forever\n", rounds)
+                )
+            })?)
+        };
+        Ok(Round { number: 1, of })
+    }
+}
+
+#[derive(Clone)]
+struct WallClockValueParser {}
+
+impl TypedValueParser for WallClockValueParser {
+    type Value = NaiveTime;
+
+    fn parse_ref(
+        &self,
+        _: &clap::Command,
+        _: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<Self::Value, clap::Error>
+    {
+        let time_str = value.to_str().ok_or_else(|| clap::Error::raw(
+            clap::ErrorKind::InvalidUtf8,
+            "Could not convert input string to unicode"
+        ))?;
+
+        NaiveTime::parse_from_str(time_str, "%H:%M:%S")
+            .or_else(|_| NaiveTime::parse_from_str(time_str, "%H:%M"))
+            .map_err(|_| clap::Error::raw(
+                clap::ErrorKind::InvalidValue,
+                format!("Could not parse wall clock time {}\n", time_str)
+            ))
+    }
+}
+
+/// `--instance` of the commands sent to a running timer.
+fn instance_arg() -> Arg<'static> {
+    Arg::with_name("instance")
+        .help("Name or PID of the timer, needed if several are running")
+        .long("instance")
+        .value_name("NAME|PID")
+        .takes_value(true)
+}
+
+pub fn cli() -> App<'static> {
+    App::new("Staged Timer")
+        .version("0.1.0")
+        .long_version(integrations::long_version())
+        .author("Jan Hettenkofer")
+        .about(
+            "Configurable multi-stage timer for film development or workouts"
+        )
+        // options given later win, e.g. over the default options of a preset
+        .args_override_self(true)
+        .arg(Arg::with_name("name")
+            .help("Name of the timer stage. Stages without a name are called \
+            \"Stage 1\", \"Stage 2\", etc.")
+            .long("name")
+            .short('n')
+            .value_name("TIMER_NAME")
+            .takes_value(true)
+            .action(clap::ArgAction::Append)
+        )
+        .arg(Arg::with_name("time")
+            .help(
+                "Duration of the timer stage. Specify in seconds or \
+                [[hrs:]min:]sec, e.g. 1:32:14, or with units like 7.5min or \
+                1h30m.")
+            .long("time")
+            .short('t')
+            .value_name("TIME")
+            .takes_value(true)
+            .value_parser(TimeValueParser{})
+            .action(clap::ArgAction::Append)
+            .required_unless_present_any(
+                ["config", "preset", "work", "ladder", "intervals", "pomodoro", "split_grade", "from_session", "resume", "schema", "edit"]
+            )
+        )
+        .arg(Arg::with_name("config")
+            .help("Load timer stages from a TOML file. May be given several \
+            times to run the stages of each file in order. Stages given \
+            with --time run after those loaded from files. A single file \
+            is reloaded in the TUI when it changes.")
+            .long("config")
+            .short('c')
+            .value_name("FILE")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .action(clap::ArgAction::Append)
+        )
+        .arg(Arg::with_name("preset")
+            .help("Load the timer stages of a preset saved with `staged-timer \
+            presets save`. Loaded before any --config files.")
+            .long("preset")
+            .short('p')
+            .value_name("PRESET")
+            .takes_value(true)
+            .action(clap::ArgAction::Append)
+        )
+        .arg(Arg::with_name("from_session")
+            .help("Run the stages of a session from the history for as long \
+            as they really took, e.g. to plan with one's actual pace. \
+            `staged-timer history` lists the sessions with their IDs.")
+            .long("from-session")
+            .value_name("ID")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(u64).range(1..))
+        )
+        .arg(Arg::with_name("work")
+            .help("Generate alternating work and rest stages, each work stage \
+            lasting <TIME>. Use with --rounds and --work-rest-ratio.")
+            .long("work")
+            .value_name("TIME")
+            .takes_value(true)
+            .value_parser(SecondsValueParser{})
+            .requires_all(&["rounds", "work_rest_ratio"])
+        )
+        .arg(Arg::with_name("work_rest_ratio")
+            .help("Ratio of work to rest time for generated stages, e.g. 2:1 \
+            for rests half as long as the work before them")
+            .long("work-rest-ratio")
+            .value_name("WORK:REST")
+            .takes_value(true)
+            .value_parser(RatioValueParser{})
+            .requires("work")
+        )
+        .arg(Arg::with_name("rounds")
+            .help("Number of generated work stages")
+            .long("rounds")
+            .value_name("ROUNDS")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(u32).range(1..))
+            .requires("work")
+        )
+        .arg(Arg::with_name("repeat")
+            .help("Run the stages given with --name and --time this many \
+            times, e.g. a work and rest pair for interval training")
+            .long("repeat")
+            .value_name("ROUNDS")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(u32).range(1..))
+            .requires("time")
+        )
+        .arg(Arg::with_name("stage_names")
+            .help("How the stages of --work, --ladder, --pomodoro, --repeat \
+            and repeated groups of --intervals are named. {phase} is replaced with what the \
+            stage is, e.g. Work or Rest, {round} with the round it is in and \
+            {rounds} with how many there are. Defaults to \"{phase} \
+            {round}/{rounds}\".")
+            .long("stage-names")
+            .value_name("TEMPLATE")
+            .takes_value(true)
+        )
+        .arg(Arg::with_name("ladder")
+            .help("Generate an interval ladder from a comma separated list of \
+            durations, e.g. 1:00,2:00,3:00")
+            .long("ladder")
+            .value_name("TIMES")
+            .takes_value(true)
+            .value_parser(SecondsValueParser{})
+            .value_delimiter(',')
+            .action(clap::ArgAction::Append)
+        )
+        .arg(Arg::with_name("pyramid")
+            .help("Run the --ladder intervals back down after reaching the top, \
+            e.g. 1-2-3-2-1")
+            .long("pyramid")
+            .requires("ladder")
+        )
+        .arg(Arg::with_name("ladder_rest")
+            .help("Rest between --ladder intervals")
+            .long("ladder-rest")
+            .value_name("TIME")
+            .takes_value(true)
+            .value_parser(SecondsValueParser{})
+            .requires("ladder")
+        )
+        .arg(Arg::with_name("intervals")
+            .help("Stages in a shorthand of NAME=TIME separated by commas, with \
+            groups in parentheses repeated by xROUNDS, e.g. \
+            \"warmup=5:00, (work=0:40, rest=0:20)x10, cooldown=3:00\"")
+            .long("intervals")
+            .value_name("SPEC")
+            .takes_value(true)
+        )
+        .arg(Arg::with_name("pomodoro")
+            .help("Generate Pomodoro stages from ROUNDSxFOCUS/BREAK+LONG_BREAK \
+            in minutes, e.g. 4x25/5+15, which is what --pomodoro alone runs. \
+            The stages run again and again until the timer is quit, unless \
+            --on-complete or --then say otherwise.")
+            .long("pomodoro")
+            .value_name("SPEC")
+            .takes_value(true)
+            .min_values(0)
+            .default_missing_value(generators::DEFAULT_POMODORO)
+        )
+        .arg(Arg::with_name("split_grade")
+            .help("Generate the stages of a split-grade print from \
+            BASE@GRADE+STOPS, e.g. 16@3.5+1/3: the base exposure split \
+            between the grade 00 and grade 5 filters for the contrast grade, \
+            from 0 to 5 and 2.5 if left out, and made longer or shorter by \
+            the stops, if given. Each exposure waits for ENTER once its \
+            filter is in.")
+            .long("split-grade")
+            .value_name("SPEC")
+            .takes_value(true)
+        )
+        .arg(Arg::with_name("enlarger_on")
+            .help("Shell command switching the enlarger on as an exposure of \
+            --split-grade starts")
+            .long("enlarger-on")
+            .value_name("CMD")
+            .takes_value(true)
+        )
+        .arg(Arg::with_name("enlarger_off")
+            .help("Shell command switching the enlarger off as an exposure \
+            of --split-grade ends")
+            .long("enlarger-off")
+            .value_name("CMD")
+            .takes_value(true)
+        )
+        .arg(Arg::with_name("heart_rate_command")
+            .help("Command printing the heart rate in beats per minute on \
+            every line, e.g. a script reading a chest strap, for \
+            --rest-below")
+            .long("heart-rate-command")
+            .value_name("CMD")
+            .takes_value(true)
+        )
+        .arg(Arg::with_name("rest_below")
+            .help("End rest stages as soon as the heart rate is below <BPM>. \
+            Rests are those generated by --work and --ladder and config \
+            stages with `rest = true`.")
+            .long("rest-below")
+            .value_name("BPM")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(u16).range(20..=250))
+        )
+        .arg(Arg::with_name("max_rest_extension")
+            .help("Keep a rest going for up to <TIME> past its end while the \
+            heart rate is still above --rest-below")
+            .long("max-rest-extension")
+            .value_name("TIME")
+            .takes_value(true)
+            .value_parser(SecondsValueParser{})
+        )
+        .arg(Arg::with_name("exercises")
+            .help("Load an exercise library that stages in config files can \
+            refer to. The instructions of the running stage's exercise are \
+            shown next to the timer.")
+            .long("exercises")
+            .value_name("FILE")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .action(clap::ArgAction::Append)
+        )
+        .arg(Arg::with_name("select")
+            .help("Show the list of stages before starting so individual \
+            stages can be switched on or off")
+            .long("select")
+            .short('s')
+        )
+        .arg(Arg::with_name("edit")
+            .help("Add, rename, reorder and re-time stages in the terminal \
+            before starting. Also what happens without any options.")
+            .long("edit")
+            .short('e')
+        )
+        .arg(Arg::with_name("roll")
+            .help("Log this run as the development of a film roll from the \
+            roll registry, see `staged-timer roll`")
+            .long("roll")
+            .value_name("ROLL_ID")
+            .takes_value(true)
+        )
+        .arg(Arg::with_name("batch")
+            .help("ID of a chemical batch used in this run. Batches past their \
+            usable life or capacity are reported before starting. May be \
+            given several times.")
+            .long("batch")
+            .value_name("BATCH_ID")
+            .takes_value(true)
+            .action(clap::ArgAction::Append)
+        )
+        .arg(Arg::with_name("stage_note")
+            .help("Note shown while a stage runs, e.g. a dilution. Given in \
+            the same order as the stages' durations.")
+            .long("stage-note")
+            .value_name("TEXT")
+            .takes_value(true)
+            .action(clap::ArgAction::Append)
+        )
+        .arg(Arg::with_name("note")
+            .help("Record a note with this run in the history. May be given \
+            several times.")
+            .long("note")
+            .value_name("TEXT")
+            .takes_value(true)
+            .action(clap::ArgAction::Append)
+        )
+        .arg(Arg::with_name("log")
+            .help("Append every event of the run, with when it happened, to \
+            <FILE>: CSV if it ends in .csv, lines of JSON otherwise")
+            .long("log")
+            .value_name("FILE")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(PathBuf))
+        )
+        .arg(Arg::with_name("summary_json")
+            .help("Write how the run went to <FILE> as JSON once it is over: \
+            the planned and actual time of each stage and of the whole run, \
+            and how often and how long it was paused")
+            .long("summary-json")
+            .value_name("FILE")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(PathBuf))
+        )
+        .arg(Arg::with_name("ambient_temperature")
+            .help("Record the room temperature with this run, in °C or °F \
+            depending on --units")
+            .long("ambient-temp")
+            .value_name("TEMP")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(f64))
+        )
+        .arg(Arg::with_name("humidity")
+            .help("Record the relative humidity in percent with this run")
+            .long("humidity")
+            .value_name("PERCENT")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(f64))
+        )
+        .arg(Arg::with_name("temperature")
+            .help("Temperature of the developer, in °C or °F depending on \
+            --units. Development times are scaled to it with the \
+            temperature_curve from the settings or the usual chart for black \
+            and white developers.")
+            .long("temperature")
+            .value_name("TEMP")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(f64))
+        )
+        .arg(Arg::with_name("check_io")
+            .help("Check the sound files, hosts, hooks and input devices the \
+            run would use and print a checklist instead of running the timer")
+            .long("check-io")
+        )
+        .arg(Arg::with_name("dry_run")
+            .help("Go through the stages faster than real time, see --speed, \
+            without sounds, notifications, hooks or MQTT and without recording \
+            the run, e.g. to see the order and warnings of a long preset")
+            .long("dry-run")
+            .conflicts_with("resume")
+        )
+        .arg(Arg::with_name("speed")
+            .help("How many times faster than real time a dry run goes")
+            .long("speed")
+            .value_name("FACTOR")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(f64))
+            .default_value("60")
+            .requires("dry_run")
+        )
+        .arg(Arg::with_name("export_format")
+            .help("What `staged-timer export` prints the stages as, json or \
+            toml")
+            .long("format")
+            .value_name("FORMAT")
+            .takes_value(true)
+            .value_parser(["json", "toml"])
+            .default_value("json")
+        )
+        .arg(Arg::with_name("on-stage-start")
+            .help("Shell command run whenever a stage starts, with the stage \
+            in STAGED_TIMER_STAGE and STAGED_TIMER_STAGE_INDEX")
+            .long("on-stage-start")
+            .value_name("CMD")
+            .takes_value(true)
+        )
+        .arg(Arg::with_name("on-stage-end")
+            .help("Shell command run whenever a stage ends")
+            .long("on-stage-end")
+            .value_name("CMD")
+            .takes_value(true)
+        )
+        .arg(Arg::with_name("tenths")
+            .help("Count down in tenths of a second")
+            .long("tenths")
+        )
+        .arg(Arg::with_name("overtime")
+            .help("Keep counting past the end of a stage until ENTER is \
+            pressed instead of moving on, for stages that don't say")
+            .long("overtime")
+        )
+        .arg(Arg::with_name("manual_advance")
+            .help("Wait for ENTER at the end of a stage before starting the \
+            next one, for stages that don't say")
+            .long("manual-advance")
+        )
+        .args(run_args())
+        .arg(Arg::with_name("upcoming")
+            .help("Only show gauges for the running stage and this many \
+            stages after it, the rest are summed up in one line")
+            .long("upcoming")
+            .value_name("N")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(u32))
+        )
+        .arg(Arg::with_name("keep_output")
+            .help("Print a summary of the run once the timer is closed, so \
+            it stays in the terminal's scrollback")
+            .long("keep-output")
+        )
+        .arg(Arg::with_name("adjust_step")
+            .help("How much time + and - add to or take off the running \
+            stage, 10 seconds if not given")
+            .long("adjust-step")
+            .value_name("TIME")
+            .takes_value(true)
+            .value_parser(SecondsValueParser{})
+        )
+        .arg(Arg::with_name("idle_pause")
+            .help("Pause the timer when a stage that waits for ENTER, because \
+            it counts up, runs over or waits to move on, goes <TIME> without a \
+            key press, and ring every <TIME> until there is one")
+            .long("idle-pause")
+            .value_name("TIME")
+            .takes_value(true)
+            .value_parser(SecondsValueParser{})
+        )
+        .arg(Arg::with_name("checkpoint_interval")
+            .help("Save the run this often so it can be resumed with \
+            --resume if the timer crashes or the terminal is closed. It is \
+            always saved when the timer is quit before it is done.")
+            .long("checkpoint-interval")
+            .value_name("TIME")
+            .takes_value(true)
+            .value_parser(SecondsValueParser{})
+        )
+        .arg(Arg::with_name("resume")
+            .help("Carry on with the last run that was quit or closed before \
+            it was done, or with the one named by --session-name")
+            .long("resume")
+            .conflicts_with_all(&["time", "config", "preset", "work", "ladder", "intervals", "pomodoro", "split_grade", "from_session"])
+        )
+        .arg(Arg::with_name("on_complete")
+            .help("What to do once all stages are done: exit, show a summary \
+            until a key is pressed, go back to the list of presets or run the \
+            same stages again")
+            .long("on-complete")
+            .value_name("ACTION")
+            .takes_value(true)
+            .value_parser(["exit", "summary", "presets", "again"])
+        )
+        .arg(Arg::with_name("loop")
+            .help("Start over with the first stage once all stages are done, \
+            until they have run <ROUNDS> times or, with forever, until the \
+            timer is quit. The round is shown above the stages.")
+            .long("loop")
+            .value_name("ROUNDS")
+            .takes_value(true)
+            .value_parser(LoopValueParser{})
+        )
+        .arg(Arg::with_name("then")
+            .help("Run this preset once all stages are done")
+            .long("then")
+            .value_name("PRESET")
+            .takes_value(true)
+            .conflicts_with("on_complete")
+        )
+        .arg(Arg::with_name("split")
+            .help("Run the stages of this preset as well, side by side with \
+            the others in a timer of its own that starts with SPACE. TAB \
+            moves the keys on to the next timer. May be given more than once")
+            .long("split")
+            .value_name("PRESET")
+            .takes_value(true)
+            .action(clap::ArgAction::Append)
+            .conflicts_with_all(&["wait", "headless", "statusbar", "edit"])
+        )
+        .arg(Arg::with_name("tolerance")
+            .help("Warn about stages that took more than <TIME> longer or \
+            shorter than planned, e.g. because the timer was paused")
+            .long("tolerance")
+            .value_name("TIME")
+            .takes_value(true)
+            .value_parser(SecondsValueParser{})
+        )
+        .arg(Arg::with_name("schema")
+            .help("Print the JSON Schema of the JSON written by --headless \
+            and `history --json`")
+            .long("schema")
+        )
+        .arg(Arg::with_name("overlay_file")
+            .help("Keep a line on the running stage and its time left in \
+            <FILE>, e.g. for a text source in streaming software. The file \
+            is replaced in one go whenever the line changes, so it is never \
+            read half written.")
+            .long("overlay-file")
+            .alias("status-file")
+            .value_name("FILE")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(PathBuf))
+        )
+        .arg(Arg::with_name("overlay_port")
+            .help("Serve the same line as --overlay-file over HTTP on \
+            localhost:<PORT>")
+            .long("overlay-port")
+            .value_name("PORT")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(u16).range(1..))
+        )
+        .arg(Arg::with_name("serve")
+            .help("Serve the status of the timer over HTTP on <ADDRESS>, e.g. \
+            0.0.0.0:8080: as JSON at /status.json and as a page following it \
+            at /, to watch the timer from a phone or another room")
+            .long("serve")
+            .value_name("ADDRESS")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(SocketAddr))
+        )
+        .arg(Arg::with_name("serve_cert")
+            .help("Serve --serve over HTTPS with this certificate, a PEM file. \
+            Needs --serve-key as well.")
+            .long("serve-cert")
+            .value_name("FILE")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .requires("serve_key")
+        )
+        .arg(Arg::with_name("serve_key")
+            .help("Private key of --serve-cert, a PEM file")
+            .long("serve-key")
+            .value_name("FILE")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .requires("serve_cert")
+        )
+        .arg(Arg::with_name("overlay_format")
+            .help("Text of the overlay. {stage} and {remaining} are replaced \
+            with the running stage and its time left, {index} and {count} \
+            with its position and {next} with the stage after it.")
+            .long("overlay-format")
+            .alias("status-format")
+            .value_name("TEMPLATE")
+            .takes_value(true)
+        )
+        .arg(Arg::with_name("warn")
+            .help("Highlight the countdown bar when <REMAINING_TIME> is left \
+            on the timer")
+            .long("warn")
+            .short('w')
+            .value_name("REMAINING_TIME")
+            .takes_value(true)
+            .value_parser(SecondsValueParser{})
+        )
+        .arg(Arg::with_name("countdown")
+            .help("Announce the time left at these points of every stage \
+            without countdown cues of its own, as times left or percentages \
+            of the stage, e.g. 50%,2:00,0:30")
+            .long("countdown")
+            .value_name("TIMES")
+            .takes_value(true)
+            .value_parser(CountdownValueParser{})
+            .value_delimiter(',')
+            .action(clap::ArgAction::Append)
+        )
+        .subcommand(App::new("quick")
+            .about("Start a single-stage timer that rings the terminal bell \
+            when it runs out")
+            .args(run_args())
+            .arg(Arg::with_name("time")
+                .help("Duration of the timer. Specify in seconds or \
+                [[hrs:]min:]sec, e.g. 5:00, or with units like 7.5min.")
+                .value_name("TIME")
+                .value_parser(TimeValueParser{})
+                .required(true)
+            )
+            .arg(Arg::with_name("name")
+                .help("Name of the timer")
+                .value_name("TIMER_NAME")
+                .default_value("Timer")
+            )
+        )
+        .subcommand(App::new("at")
+            .about("Count down to a wall clock time and ring the terminal bell \
+            when it is reached")
+            .args(run_args())
+            .arg(Arg::with_name("clock_time")
+                .help("Local time to count down to, as hrs:min[:sec], e.g. \
+                17:45. Times that have already passed today refer to \
+                tomorrow. It goes off at that time even if the system sleeps \
+                or the clock is set in the meantime, and pausing doesn't put \
+                it off.")
+                .value_name("CLOCK_TIME")
+                .value_parser(WallClockValueParser{})
+                .required(true)
+            )
+            .arg(Arg::with_name("name")
+                .help("Name of the timer")
+                .value_name("TIMER_NAME")
+                .default_value("Alarm")
+            )
+        )
+        .subcommand(App::new("reciprocity")
+            .about("Correct a metered exposure time for reciprocity failure \
+            and optionally time the exposure")
+            .args(run_args())
+            .arg(Arg::with_name("metered_time")
+                .help("Metered exposure time. Specify in seconds or \
+                [[hrs:]min:]sec, e.g. 0:30, or with units like 7.5min.")
+                .value_name("METERED_TIME")
+                .value_parser(SecondsValueParser{})
+                .required(true)
+            )
+            .arg(Arg::with_name("film")
+                .help("Film to correct the time for")
+                .long("film")
+                .short('f')
+                .value_name("FILM")
+                .takes_value(true)
+                .value_parser(clap::builder::PossibleValuesParser::new(
+                    reciprocity::FILMS.iter().map(|(key, _, _)| *key)
+                ))
+                .required_unless_present("exponent")
+            )
+            .arg(Arg::with_name("exponent")
+                .help("Reciprocity exponent of a film that is not built in, \
+                the corrected time is the metered time to the power of it")
+                .long("exponent")
+                .value_name("EXPONENT")
+                .takes_value(true)
+                .value_parser(clap::value_parser!(f64))
+                .conflicts_with("film")
+            )
+            .arg(Arg::with_name("start")
+                .help("Start a timer for the corrected exposure")
+                .long("start")
+            )
+        )
+        .subcommand(App::new("dilute")
+            .about("Work out the amounts of concentrate and water for a \
+            working solution")
+            .arg(units_arg())
+            .arg(Arg::with_name("ratio")
+                .help("Dilution as parts concentrate + parts water, e.g. 1+31")
+                .value_name("RATIO")
+                .required(true)
+            )
+            .arg(Arg::with_name("volume")
+                .help("Volume of working solution, e.g. 500ml, 1l or 16oz")
+                .long("volume")
+                .short('v')
+                .value_name("VOLUME")
+                .takes_value(true)
+                .required(true)
+            )
+            .arg(Arg::with_name("temperature")
+                .help("Temperature the working solution should have, in °C \
+                or °F depending on --units. Works out how warm the water has \
+                to be.")
+                .long("temperature")
+                .value_name("TEMP")
+                .takes_value(true)
+                .value_parser(clap::value_parser!(f64))
+            )
+            .arg(Arg::with_name("stock_temperature")
+                .help("Temperature of the concentrate, 20 °C if not given")
+                .long("stock-temperature")
+                .value_name("TEMP")
+                .takes_value(true)
+                .value_parser(clap::value_parser!(f64))
+                .requires("temperature")
+            )
+            .arg(Arg::with_name("attach")
+                .help("Attach the result as a note to the next run recorded \
+                in the history")
+                .long("attach")
+            )
+        )
+        .subcommand(App::new("attach")
+            .about("Bring back a timer detached with D, or show how it went if \
+            it is done")
+            .arg(Arg::with_name("name")
+                .help("Session name of the timer")
+                .value_name("NAME")
+            )
+            .arg(Arg::with_name("observe")
+                .help("Only show the timer running in another terminal: keys \
+                don't pause or stop it, ESC, Q or CTRL+C stop watching")
+                .long("observe")
+            )
+            .arg(Arg::with_name("session_name")
+                .help("Session name of the timer, like NAME")
+                .long("session-name")
+                .value_name("NAME")
+                .takes_value(true)
+                .conflicts_with("name")
+            )
+        )
+        .subcommand(App::new("status")
+            .about("Print the status of a running timer")
+            .arg(instance_arg())
+        )
+        .subcommand(App::new("pause")
+            .about("Pause a running timer")
+            .arg(instance_arg())
+        )
+        .subcommand(App::new("unpause")
+            .about("Carry on with a running timer that is paused")
+            .arg(instance_arg())
+        )
+        .subcommand(App::new("config")
+            .about("Manage the settings applied to every run")
+            .subcommand_required(true)
+            .subcommand(App::new("edit")
+                .about("Open the settings file in $EDITOR and check it when \
+                the editor is closed")
+            )
+        )
+        .subcommand(App::new("backup")
+            .about("Move presets, settings and the history to another machine")
+            .subcommand_required(true)
+            .subcommand(App::new("export")
+                .about("Bundle the presets, settings, roll and chemical \
+                registries and the history into one archive")
+                .arg(Arg::with_name("archive")
+                    .help("File to write the archive to")
+                    .value_name("ARCHIVE")
+                    .required(true)
+                    .value_parser(clap::value_parser!(PathBuf))
+                )
+                .arg(Arg::with_name("force")
+                    .help("Replace an existing file")
+                    .long("force")
+                    .short('f')
+                )
+            )
+            .subcommand(App::new("import")
+                .about("Unpack an archive made with `backup export`")
+                .arg(Arg::with_name("archive")
+                    .help("Archive to unpack")
+                    .value_name("ARCHIVE")
+                    .required(true)
+                    .value_parser(clap::value_parser!(PathBuf))
+                )
+                .arg(Arg::with_name("force")
+                    .help("Replace files that are different here")
+                    .long("force")
+                    .short('f')
+                )
+            )
+        )
+        .subcommand(App::new("roll")
+            .about("List the rolls in the roll registry, or show the \
+            development log of one of them")
+            .arg(units_arg())
+            .arg(Arg::with_name("roll_id")
+                .help("ID of the roll to show")
+                .value_name("ROLL_ID")
+            )
+        )
+        .subcommand(App::new("calibrate")
+            .about("Suggest stage durations for a preset from how long its \
+            stages really took in the recorded runs of it")
+            .arg(Arg::with_name("preset")
+                .help("Name of the preset")
+                .value_name("PRESET")
+                .required(true)
+            )
+            .arg(Arg::with_name("apply")
+                .help("Save the suggested durations to the preset")
+                .long("apply")
+            )
+            .arg(Arg::with_name("min_runs")
+                .help("Only suggest a duration for stages with at least this \
+                many recorded runs")
+                .long("min-runs")
+                .value_name("RUNS")
+                .takes_value(true)
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .default_value("3")
+            )
+        )
+        .subcommand(App::new("history")
+            .about("List the sessions recorded in the history with their IDs")
+            .arg(Arg::with_name("json")
+                .help("Print the sessions as JSON, one per line")
+                .long("json")
+            )
+        )
+        .subcommand(stages_command("run")
+            .about("Run the timer, with the stages of a saved preset if one \
+            is given: short for `staged-timer --preset PRESET [OPTIONS]`")
+        )
+        .subcommand(stages_command("validate")
+            .about("Check the stages a run would have, from a preset, config \
+            files or any other options, and report what is wrong with them \
+            instead of running the timer")
+        )
+        .subcommand(stages_command("export")
+            .about("Print the stages a run would have, with presets \
+            included, stages generated and --temperature applied, as JSON or \
+            with --format toml as a config file")
+        )
+        .subcommand(App::new("presets")
+            .about("Manage the saved presets")
+            .alias("preset")
+            .subcommand_required(true)
+            .subcommand(App::new("list")
+                .about("List the saved presets")
+            )
+            .subcommand(save_preset_command("save"))
+            .subcommand(App::new("edit")
+                .about("Open the preset in $EDITOR, check it when the editor \
+                is closed and show how its stages changed before keeping it")
+                .arg(Arg::with_name("preset")
+                    .help("Name of the preset")
+                    .value_name("PRESET")
+                    .required(true)
+                )
+            )
+            .subcommand(App::new("copy")
+                .about("Save a preset that runs the stages of another one, \
+                following changes to them, with settings of its own")
+                .arg(Arg::with_name("source")
+                    .help("Name of the preset to take the stages from")
+                    .value_name("SOURCE")
+                    .required(true)
+                )
+                .arg(Arg::with_name("preset")
+                    .help("Name of the new preset")
+                    .value_name("PRESET")
+                    .required(true)
+                )
+                .arg(Arg::with_name("set")
+                    .help("Change a setting of the new preset: scale (the \
+                    durations by a factor), repeat, on_complete or options, \
+                    e.g. scale=1.25")
+                    .long("set")
+                    .value_name("KEY=VALUE")
+                    .takes_value(true)
+                    .action(clap::ArgAction::Append)
+                )
+                .arg(Arg::with_name("force")
+                    .help("Replace an existing preset of the same name")
+                    .long("force")
+                    .short('f')
+                )
+            )
+            .subcommand(App::new("remove")
+                .about("Delete a saved preset")
+                .arg(Arg::with_name("preset")
+                    .help("Name of the preset")
+                    .value_name("PRESET")
+                    .required(true)
+                )
+            )
+        )
+        // the names from before `presets`
+        .subcommand(save_preset_command("save-preset").hide(true))
+        .subcommand(App::new("list-presets")
+            .about("List the saved presets")
+            .hide(true)
+        )
+        .subcommand(App::new("daemon")
+            .about("Run a detached timer in the background")
+            .hide(true)
+            .arg(Arg::with_name("name")
+                .value_name("NAME")
+                .required(true)
+            )
+        )
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
+}
+
+/// `presets save`, or `save-preset` as it was called before, as `name`.
+fn save_preset_command(name: &'static str) -> App<'static> {
+    App::new(name)
+        .about("Save stages under a name so they can be run again with \
+        `staged-timer run NAME`")
+        .arg(Arg::with_name("preset")
+            .help("Name of the preset")
+            .value_name("PRESET")
+            .required(true)
+        )
+        .arg(Arg::with_name("name")
+            .help("Name of a stage")
+            .long("name")
+            .short('n')
+            .value_name("TIMER_NAME")
+            .takes_value(true)
+            .action(clap::ArgAction::Append)
+        )
+        .arg(Arg::with_name("time")
+            .help("Duration of a stage")
+            .long("time")
+            .short('t')
+            .value_name("TIME")
+            .takes_value(true)
+            .value_parser(TimeValueParser{})
+            .action(clap::ArgAction::Append)
+            .required_unless_present("config")
+        )
+        .arg(Arg::with_name("config")
+            .help("Save a copy of this config file instead")
+            .long("config")
+            .short('c')
+            .value_name("FILE")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .conflicts_with_all(&["name", "time"])
+        )
+        .arg(Arg::with_name("force")
+            .help("Replace an existing preset of the same name")
+            .long("force")
+            .short('f')
+        )
+}
+
+/// A subcommand that takes its stages like a run does: from the preset
+/// `PRESET`, if one is given, and the options after it.
+fn stages_command(name: &'static str) -> App<'static> {
+    App::new(name)
+        // everything after the preset is passed on, flags included
+        .trailing_var_arg(true)
+        .allow_hyphen_values(true)
+        .args(run_args())
+        .arg(Arg::with_name("preset")
+            .help("Name of a saved preset")
+            .value_name("PRESET")
+        )
+        .arg(Arg::with_name("options")
+            .help("Further options as for running without a subcommand, \
+            e.g. more stages")
+            .value_name("OPTIONS")
+            .multiple_values(true)
+            .allow_hyphen_values(true)
+        )
+}
+
+/// Options of a run that the subcommands starting one take as well.
+fn run_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::with_name("mqtt")
+            .help("Publish the running stage, its time left and the state \
+            of the timer to the MQTT broker at <BROKER>, HOST or HOST:PORT, \
+            e.g. for home automation to dim the lights during a stage")
+            .long("mqtt")
+            .value_name("BROKER")
+            .takes_value(true),
+        Arg::with_name("mqtt_topic")
+            .help("Publish under <TOPIC>/stage, <TOPIC>/remaining, \
+            <TOPIC>/state and <TOPIC>/event [default: staged-timer]")
+            .long("mqtt-topic")
+            .value_name("TOPIC")
+            .takes_value(true),
+        Arg::with_name("theme")
+            .help("Colours to draw the timer in, instead of the theme from \
+            the settings")
+            .long("theme")
+            .value_name("THEME")
+            .takes_value(true)
+            .value_parser(theme::NAMES),
+        Arg::with_name("display")
+            .help("Show the time left on the running stage in large digits \
+            (big), a gauge for every stage (gauges) or a line for every stage \
+            (compact)")
+            .long("display")
+            .value_name("MODE")
+            .takes_value(true)
+            .value_parser(["gauges", "big", "compact"]),
+        Arg::with_name("orientation")
+            .help("Stack the stages' gauges (vertical) or put them side by \
+            side as bars filling up (horizontal)")
+            .long("orientation")
+            .value_name("ORIENTATION")
+            .takes_value(true)
+            .value_parser(["vertical", "horizontal"]),
+        Arg::with_name("accessible")
+            .help("Mark the running stage and its warnings with text instead \
+            of colour and draw the gauges in ASCII only. Without colour, as \
+            with NO_COLOR set, the marks are shown as well.")
+            .long("accessible"),
+        Arg::with_name("sparkline")
+            .help("Show how long each stage so far was paused and ran over \
+            in a strip below the stages")
+            .long("sparkline"),
+        Arg::with_name("gradient")
+            .help("Let the gauge of the running stage go from green through \
+            yellow to red as its time runs out, or through the gradient of \
+            the theme, instead of switching to the warning colour")
+            .long("gradient"),
+        Arg::with_name("gauge_title")
+            .help("Title of every stage's gauge. {name} is replaced with the \
+            name of the stage, {remaining}, {elapsed} and {total} with its \
+            times and {percent} with how much of it is done.")
+            .long("gauge-title")
+            .value_name("TEMPLATE")
+            .takes_value(true),
+        Arg::with_name("label")
+            .help("What to write inside every stage's gauge: how much of it \
+            is done (percent), the time left (remaining), the time so far \
+            (elapsed) or nothing (none)")
+            .long("label")
+            .value_name("LABEL")
+            .takes_value(true)
+            .value_parser(["percent", "remaining", "elapsed", "none"]),
+        Arg::with_name("allow_sleep")
+            .help("Let the system go to sleep while the timer runs instead of \
+            keeping it awake")
+            .long("allow-sleep"),
+        Arg::with_name("input_device")
+            .help("Control the timer with a foot pedal or button read from \
+            its event file, e.g. /dev/input/by-id/usb-…-event-kbd. Its SPACE \
+            or first button pauses, ENTER or the second finishes the stage \
+            and N or the third skips it. May be given several times.")
+            .long("input-device")
+            .value_name("PATH")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .action(clap::ArgAction::Append),
+        Arg::with_name("control_socket")
+            .help("Take commands from other programs on a Unix socket, one \
+            per line: pause, resume, toggle, skip, skip-group, finish, \
+            add-time [-]TIME and status, which answers with JSON. Without \
+            <PATH> the socket is named after the session in the runtime \
+            directory, e.g. $XDG_RUNTIME_DIR/staged-timer/default.sock.")
+            .long("control-socket")
+            .value_name("PATH")
+            .takes_value(true)
+            .min_values(0)
+            .value_parser(clap::value_parser!(PathBuf)),
+        Arg::with_name("control_port")
+            .help("Take the same commands as --control-socket on <PORT>, \
+            of this machine only unless --control-address says otherwise. \
+            Who may do what is set in the [control] section of the \
+            settings.")
+            .long("control-port")
+            .value_name("PORT")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(u16).range(1..)),
+        Arg::with_name("control_address")
+            .help("Address --control-port listens on, e.g. 0.0.0.0 for every \
+            network the machine is on [default: 127.0.0.1]")
+            .long("control-address")
+            .value_name("ADDRESS")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(IpAddr)),
+        Arg::with_name("control_cert")
+            .help("Take connections to --control-port over TLS with this \
+            certificate, a PEM file. Needs --control-key as well.")
+            .long("control-cert")
+            .value_name("FILE")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .requires("control_key"),
+        Arg::with_name("control_key")
+            .help("Private key of --control-cert, a PEM file")
+            .long("control-key")
+            .value_name("FILE")
+            .takes_value(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .requires("control_cert"),
+        Arg::with_name("pause_budget")
+            .help("Warn once the timer has been paused for longer than <TIME> \
+            in all, and mark the run as over its pause budget in the history")
+            .long("pause-budget")
+            .value_name("TIME")
+            .takes_value(true)
+            .value_parser(SecondsValueParser{}),
+        Arg::with_name("wait")
+            .help("Run without any output and exit once all stages have \
+            completed, e.g. for `staged-timer quick 3:00 --wait && ...`")
+            .long("wait"),
+        Arg::with_name("headless")
+            .help("Run without the TUI and print what happens as JSON, one \
+            event per line: tick, stage_start, stage_end, warning, paused, \
+            resumed, finished and how the run went in session")
+            .long("headless"),
+        Arg::with_name("statusbar")
+            .help("Run without the TUI and print a line of JSON for a status \
+            bar like waybar whenever the running stage or its time changes: \
+            text, tooltip, class and percentage")
+            .long("statusbar")
+            .conflicts_with("headless"),
+        Arg::with_name("session_name")
+            .help("Name this run so that accidentally starting a second run \
+            with the same name asks for confirmation first, and so `staged-timer \
+            status`, `pause`, `unpause` and `attach --observe` can pick it by \
+            name")
+            .long("session-name")
+            .value_name("NAME")
+            .takes_value(true),
+        units_arg(),
+        Arg::with_name("sound")
+            .help("Announce the end of every stage and the warning threshold \
+            with the terminal bell or the alert sound from the settings. Only \
+            quick timers and alarms do so by default.")
+            .long("sound")
+            .overrides_with("no_sound"),
+        Arg::with_name("no_sound")
+            .help("Don't ring the bell or play any audio")
+            .long("no-sound")
+            .overrides_with("sound"),
+        Arg::with_name("notify")
+            .help("Show a desktop notification whenever a stage ends")
+            .long("notify"),
+        Arg::with_name("speak")
+            .help("Read out loud which stage ended and which one follows")
+            .long("speak"),
+        Arg::with_name("notify_message")
+            .help("What notifications and --speak say when a stage ends. \
+            {finished} and {next} are replaced with the names of the stages \
+            and {time} with the duration of the next one.")
+            .long("notify-message")
+            .value_name("TEMPLATE")
+            .takes_value(true)
+    ]
+}
+
+/// `--units`, which the subcommands giving temperatures or volumes take as
+/// well as runs.
+fn units_arg() -> Arg<'static> {
+    Arg::with_name("units")
+        .help("Give temperatures and volumes in metric or imperial units")
+        .long("units")
+        .value_name("UNITS")
+        .takes_value(true)
+        .value_parser(["metric", "imperial"])
+}
+
+/// The run options a subcommand took in `matches`, as arguments to parse
+/// again.
+pub fn given_run_args(matches: &clap::ArgMatches) -> Vec<OsString> {
+    let mut args = Vec::new();
+    for arg in run_args() {
+        let long = match arg.get_long() {
+            Some(long) if matches.value_source(arg.get_id()) == Some(clap::ValueSource::CommandLine) => long,
+            _ => continue
+        };
+        let values: Vec<&OsStr> = matches.get_raw(arg.get_id()).into_iter().flatten().collect();
+        if values.is_empty() {
+            args.push(OsString::from(format!("--{}", long)));
+        }
+        for value in values {
+            let mut option = OsString::from(format!("--{}=", long));
+            option.push(value);
+            args.push(option);
+        }
+    }
+    args
+}
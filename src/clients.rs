@@ -0,0 +1,87 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant}
+};
+
+/// How many clients a server serves at once, each on a thread of its own.
+/// Connections past that are closed right away.
+const MAX_CLIENTS: usize = 16;
+
+/// How long an address waits between having tokens checked, so tokens
+/// can't be guessed quickly.
+const AUTH_DELAY: Duration = Duration::from_secs(1);
+
+/// When each address may have its next token checked. Shared by all
+/// connections of a server, so opening many at once doesn't speed up
+/// guessing.
+#[derive(Default)]
+pub struct AuthThrottle {
+    next: Mutex<HashMap<IpAddr, Instant>>
+}
+
+impl AuthThrottle {
+    /// Wait for the turn of `client` to have a token checked.
+    pub fn wait(&self, client: IpAddr) {
+        let now = Instant::now();
+        let turn = {
+            let mut next = self.next.lock().unwrap_or_else(PoisonError::into_inner);
+            next.retain(|_, turn| *turn > now);
+            let turn = next.get(&client).map_or(now, |turn| (*turn).max(now));
+            next.insert(client, turn + AUTH_DELAY);
+            turn
+        };
+        thread::sleep(turn - now);
+    }
+
+    /// Wait for the turn of `client` to have a token checked if it sent a
+    /// wrong one lately, and go right ahead if it didn't.
+    #[cfg(feature = "http")]
+    pub fn retry(&self, client: IpAddr) {
+        let now = Instant::now();
+        let turn = {
+            let mut next = self.next.lock().unwrap_or_else(PoisonError::into_inner);
+            next.retain(|_, turn| *turn > now);
+            match next.get(&client) {
+                Some(turn) => {
+                    let turn = *turn;
+                    next.insert(client, turn + AUTH_DELAY);
+                    turn
+                },
+                None => now
+            }
+        };
+        thread::sleep(turn - now);
+    }
+
+    /// Hold back the next token of `client`, which sent a wrong one.
+    #[cfg(feature = "http")]
+    pub fn fail(&self, client: IpAddr) {
+        let now = Instant::now();
+        let mut next = self.next.lock().unwrap_or_else(PoisonError::into_inner);
+        let turn = next.entry(client).or_insert(now);
+        *turn = (*turn).max(now + AUTH_DELAY);
+    }
+}
+
+/// One of the [`MAX_CLIENTS`] connections a server serves at once, given
+/// back when dropped.
+pub struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    /// A slot counted in `open`, unless all are taken.
+    pub fn take(open: &Arc<AtomicUsize>) -> Option<Slot> {
+        open.fetch_update(Ordering::AcqRel, Ordering::Acquire, |taken| {
+            (taken < MAX_CLIENTS).then_some(taken + 1)
+        }).ok()?;
+        Some(Slot(Arc::clone(open)))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use crate::timer::Checkpoint;
+
+/// Factors development times are multiplied by at temperatures in °C, the
+/// usual chart for black and white developers: about 8% shorter for every
+/// degree above 20 °C and as much longer below.
+pub const STANDARD_CURVE: &[(f64, f64)] = &[
+    (16.0, 1.40),
+    (17.0, 1.28),
+    (18.0, 1.18),
+    (19.0, 1.09),
+    (20.0, 1.00),
+    (21.0, 0.92),
+    (22.0, 0.85),
+    (23.0, 0.78),
+    (24.0, 0.72),
+    (25.0, 0.66),
+    (26.0, 0.61)
+];
+
+/// The factor of `curve` at `temperature_c`, interpolated between the two
+/// points around it. `None` outside the curve, where development is too
+/// unpredictable to guess.
+pub fn factor(curve: &[(f64, f64)], temperature_c: f64) -> Option<f64> {
+    curve.windows(2)
+        .find(|pair| pair[0].0 <= temperature_c && temperature_c <= pair[1].0)
+        .map(|pair| {
+            let ((low_c, low), (high_c, high)) = (pair[0], pair[1]);
+            low + (high - low) * (temperature_c - low_c) / (high_c - low_c)
+        })
+}
+
+/// `period` scaled by `factor` to whole seconds, at least one. Checkpoints
+/// keep their place relative to the length of the stage, those that no
+/// longer fall within it are dropped.
+pub fn scale(period: Duration, checkpoints: &mut Vec<Checkpoint>, factor: f64) -> Duration {
+    let scaled = Duration::from_secs((period.as_secs_f64() * factor).round().max(1.0) as u64);
+    // those in percent of the stage move along by themselves
+    for checkpoint in checkpoints.iter_mut().filter(|checkpoint| checkpoint.percent.is_none()) {
+        checkpoint.at_s = (f64::from(checkpoint.at_s) * factor).round() as u32;
+    }
+    checkpoints.retain(|checkpoint| {
+        let at_s = checkpoint.position_s(scaled);
+        at_s > 0 && u64::from(at_s) < scaled.as_secs()
+    });
+    scaled
+}
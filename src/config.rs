@@ -0,0 +1,1812 @@
+use std::{
+    collections::HashMap, env, fmt, fs, io, io::Write, net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf}, process::Command, time::Duration
+};
+use serde::{Deserialize, Serialize};
+
+use staged_timer::{
+    compensation, parse_duration, Agitation, Checkpoint, CountdownCue, Exercise, Phase, TimeFormat
+};
+
+use crate::{
+    generators, notify::{Channel, Routes}, presets, theme::{self, Theme}, units::Units
+};
+
+/// A stage duration as written in a config file: either a number of seconds
+/// or a `[[hrs:]min:]sec` or `7.5min` string, just like on the command
+/// line. Stage durations may have fractions of a second.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConfigTime {
+    Seconds(u32),
+    Fractional(f64),
+    Text(String)
+}
+
+impl ConfigTime {
+    fn duration(self, path: &Path) -> Result<Duration, String> {
+        match self {
+            ConfigTime::Seconds(sec) => Ok(Duration::from_secs(sec.into())),
+            ConfigTime::Fractional(sec) if (0.0..=f64::from(u32::MAX)).contains(&sec) => {
+                Ok(Duration::from_millis((sec * 1000.0).round() as u64))
+            },
+            ConfigTime::Fractional(sec) => Err(format!(
+                "{}: Time {} is out of range", path.display(), sec
+            )),
+            ConfigTime::Text(time_str) => parse_duration(&time_str)
+                .map_err(|e| format!("{}: {}", path.display(), e))
+        }
+    }
+
+    /// The time as a countdown cue, or a percentage of the stage left like
+    /// `"50%"`.
+    fn countdown(self, path: &Path) -> Result<CountdownCue, String> {
+        match self {
+            ConfigTime::Text(text) if text.trim_end().ends_with('%') => {
+                parse_countdown(&text).map_err(|e| format!("{}: {}", path.display(), e))
+            },
+            time => Ok(CountdownCue::Remaining(time.seconds(path)?))
+        }
+    }
+
+    /// The time in whole seconds, for thresholds and the like.
+    fn seconds(self, path: &Path) -> Result<u32, String> {
+        let duration = self.duration(path)?;
+        if duration.subsec_nanos() != 0 {
+            return Err(format!(
+                "{}: Expected whole seconds instead of {}s",
+                path.display(), duration.as_secs_f64()
+            ));
+        }
+        Ok(duration.as_secs() as u32)
+    }
+}
+
+#[derive(Deserialize)]
+struct ConfigStage {
+    name: Option<String>,
+    /// Required unless the stages of a preset are included
+    time: Option<ConfigTime>,
+    /// Preset whose stages take the place of this one
+    include: Option<String>,
+    #[serde(default = "stage_enabled_default")]
+    enabled: bool,
+    zone: Option<u8>,
+    #[serde(default)]
+    minute_chime: bool,
+    #[serde(default, rename = "checkpoint")]
+    checkpoints: Vec<ConfigCheckpoint>,
+    #[serde(default)]
+    countdown: Vec<ConfigTime>,
+    agitation: Option<ConfigAgitation>,
+    after: Option<ConfigPhase>,
+    exercise: Option<String>,
+    note: Option<String>,
+    group: Option<String>,
+    audio: Option<PathBuf>,
+    pace: Option<f64>,
+    #[serde(default)]
+    rest: bool,
+    #[serde(default)]
+    tags: Vec<String>,
+    batch: Option<String>,
+    warn: Option<ConfigTime>,
+    color: Option<String>,
+    on_start: Option<String>,
+    on_end: Option<String>,
+    #[serde(default)]
+    runs_while_paused: bool,
+    overtime: Option<bool>,
+    manual_advance: Option<bool>,
+    compensate: Option<bool>,
+    #[serde(default)]
+    each_side: bool,
+    switch: Option<ConfigTime>
+}
+
+fn stage_enabled_default() -> bool { true }
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigAgitation {
+    initial: Option<ConfigTime>,
+    every: Option<ConfigTime>,
+    #[serde(rename = "for")]
+    length: Option<ConfigTime>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigPhase {
+    name: String,
+    time: ConfigTime
+}
+
+#[derive(Deserialize)]
+struct ConfigCheckpoint {
+    /// Time since the start of the stage, a percentage of it like `"50%"`
+    /// or `"halfway"`
+    at: ConfigTime,
+    message: String
+}
+
+/// A stage as loaded from a config file. Stages without a name have `None`
+/// so they can be numbered according to their position in the complete
+/// sequence.
+#[derive(Clone)]
+pub struct ConfiguredStage {
+    pub name: Option<String>,
+    pub period: Duration,
+    pub enabled: bool,
+    pub zone: Option<u8>,
+    pub minute_chime: bool,
+    pub checkpoints: Vec<Checkpoint>,
+    /// When to announce the time left, the cues given on the command line
+    /// apply if there are none
+    pub countdown: Vec<CountdownCue>,
+    pub agitation: Option<Agitation>,
+    /// Part at the end of the stage, included in `period`
+    pub after: Option<Phase>,
+    /// Name of an exercise from the exercise library
+    pub exercise: Option<String>,
+    /// Free text shown while the stage runs
+    pub note: Option<String>,
+    /// Section the stage belongs to, shown as a header above it
+    pub group: Option<String>,
+    pub audio: Option<PathBuf>,
+    /// Cycles per minute of the pacing guide
+    pub pace_cpm: Option<f64>,
+    /// Rests may end early or go on longer depending on the heart rate
+    pub rest: bool,
+    /// Tags that cue rules can refer to
+    pub tags: Vec<String>,
+    /// ID of the chemical batch the stage uses
+    pub batch: Option<String>,
+    /// Warning threshold of this stage, overriding the one from the settings
+    pub warn_s: Option<u32>,
+    pub color: Option<[u8; 3]>,
+    /// Shell commands run when the stage starts and ends
+    pub on_start: Option<String>,
+    pub on_end: Option<String>,
+    /// Whether the stage's clock keeps running while the timer is paused
+    pub runs_while_paused: bool,
+    /// Whether the stage has no set duration and counts up until finished
+    pub count_up: bool,
+    /// Whether the stage keeps counting past its end until finished, the
+    /// global setting applies if not given
+    pub overtime: Option<bool>,
+    /// Whether the timer waits at the end of the stage until told to go on,
+    /// the global setting applies if not given
+    pub manual_advance: Option<bool>,
+    /// Whether `--temperature` scales the stage, see
+    /// [`compensate`]
+    pub compensate: Option<bool>
+}
+
+impl Default for ConfiguredStage {
+    fn default() -> Self {
+        ConfiguredStage {
+            name: None,
+            period: Duration::ZERO,
+            enabled: true,
+            zone: None,
+            minute_chime: false,
+            checkpoints: Vec::new(),
+            countdown: Vec::new(),
+            agitation: None,
+            after: None,
+            exercise: None,
+            note: None,
+            group: None,
+            audio: None,
+            pace_cpm: None,
+            rest: false,
+            tags: Vec::new(),
+            batch: None,
+            warn_s: None,
+            color: None,
+            on_start: None,
+            on_end: None,
+            runs_while_paused: false,
+            count_up: false,
+            overtime: None,
+            manual_advance: None,
+            compensate: None
+        }
+    }
+}
+
+/// Scale the stages to be compensated by `factor`, see
+/// [`compensation::scale`]. These are the stages with `compensate = true`,
+/// or all but those with `compensate = false` if there are none. Stages
+/// counting up aren't scaled.
+pub fn compensate(stages: &mut [ConfiguredStage], factor: f64) {
+    let any_marked = stages.iter().any(|stage| stage.compensate == Some(true));
+    for stage in stages.iter_mut()
+        .filter(|stage| stage.compensate.unwrap_or(!any_marked) && !stage.period.is_zero())
+    {
+        stage.period = compensation::scale(stage.period, &mut stage.checkpoints, factor);
+    }
+}
+
+/// A countdown cue as given on the command line: a time left like `2:00`
+/// or a percentage of the stage left like `50%`.
+pub fn parse_countdown(text: &str) -> Result<CountdownCue, String> {
+    let text = text.trim();
+    match text.strip_suffix('%') {
+        Some(percent) => match percent.trim().parse::<u8>() {
+            Ok(percent) if (1..100).contains(&percent) => Ok(CountdownCue::Percent(percent)),
+            _ => Err(format!("Countdown {} is not a percentage from 1% to 99%", text))
+        },
+        None => {
+            let remaining = parse_duration(text)?;
+            if remaining.is_zero() || remaining.subsec_nanos() != 0 {
+                return Err(format!("Countdown {} is not a whole number of seconds left", text));
+            }
+            Ok(CountdownCue::Remaining(remaining.as_secs() as u32))
+        }
+    }
+}
+
+/// Give the stages without countdown cues of their own `countdown`.
+pub fn default_countdown(stages: &mut [ConfiguredStage], countdown: &[CountdownCue]) {
+    for stage in stages.iter_mut().filter(|stage| stage.countdown.is_empty()) {
+        stage.countdown = countdown.to_vec();
+    }
+}
+
+/// Which runtime actions ask for confirmation before they take effect.
+/// Settings left out keep the value from earlier config files or the
+/// default.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfirmConfig {
+    pub quit: Option<bool>,
+    /// Skipping to the next stage with N
+    pub skip: Option<bool>,
+    /// Jumping to a stage by clicking it, asked unless turned off
+    pub jump: Option<bool>
+}
+
+/// The `[control]` section of the settings: who may do what over the
+/// control port.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ControlConfig {
+    /// Address the control port listens on, localhost unless set
+    pub address: Option<IpAddr>,
+    /// Permissions of clients by their address
+    #[serde(default)]
+    pub clients: HashMap<IpAddr, Permission>,
+    /// Permission of clients that aren't listed
+    pub others: Option<Permission>,
+    /// Permissions clients get with `auth TOKEN`, by token
+    #[serde(default)]
+    pub tokens: HashMap<String, Permission>,
+    /// Certificate and private key to take connections over TLS with
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>
+}
+
+/// The `[serve]` section of the settings: who may follow the timer on the
+/// `--serve` address, and over what.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ServeConfig {
+    /// Token clients have to send, as `Authorization: Bearer TOKEN` or
+    /// `?token=TOKEN`, to get the status
+    pub token: Option<String>,
+    /// Certificate and private key to serve over HTTPS with
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>
+}
+
+/// What a client of the control socket may do. Each permission includes the
+/// ones before it.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    /// Nothing but sending a token
+    None,
+    /// Asking for the status
+    Read,
+    /// Pausing, skipping and changing the timer as well
+    Control
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Permission::None => "none",
+            Permission::Read => "read",
+            Permission::Control => "control"
+        })
+    }
+}
+
+/// Who may do what over the control port, and whether they connect over
+/// TLS. The Unix socket can only be opened by its owner, who may do
+/// everything.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Access {
+    /// Address the port listens on
+    pub address: IpAddr,
+    /// What clients may do by the address they connect from
+    pub clients: HashMap<IpAddr, Permission>,
+    /// What clients that aren't listed may do, on this machine as well
+    pub others: Permission,
+    /// What clients may do once they sent `auth TOKEN`, by token. Never
+    /// saved with a run, the settings are read again for them.
+    #[serde(skip)]
+    pub tokens: HashMap<String, Permission>,
+    /// Certificate and key to take connections over TLS with instead of in
+    /// plain text
+    #[serde(default)]
+    pub tls: Option<TlsOptions>
+}
+
+impl Default for Access {
+    fn default() -> Access {
+        Access {
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            clients: HashMap::new(),
+            others: Permission::Read,
+            tokens: HashMap::new(),
+            tls: None
+        }
+    }
+}
+
+/// Certificate and private key to serve over TLS with, both PEM files.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TlsOptions {
+    /// The certificate, followed by any intermediate ones
+    pub cert: PathBuf,
+    pub key: PathBuf
+}
+
+/// The `[retention]` section of the settings: how much of the data the
+/// timer collects is kept. Everything is kept unless set.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Most runs kept in the history, older ones are moved to a backup
+    pub sessions: Option<usize>,
+    /// How many backups of the history and of an event log are kept
+    pub backups: Option<u32>,
+    /// Days after which checkpoints of runs that weren't resumed are deleted
+    pub checkpoint_days: Option<u32>,
+    /// Size in KiB past which an event log is moved to a backup
+    pub event_log_kib: Option<u64>
+}
+
+/// The `[theme]` section of the settings: a built-in theme, any of whose
+/// colours can be replaced, and colours of stages by tag or name.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ThemeConfig {
+    name: Option<String>,
+    active: Option<String>,
+    inactive: Option<String>,
+    warning: Option<String>,
+    background: Option<String>,
+    critical: Option<String>,
+    /// Seconds before the end of the running stage from which it blinks
+    blink: Option<ConfigTime>,
+    /// Seconds before the end from which it is drawn in `critical`
+    critical_from: Option<ConfigTime>,
+    /// Colours of the running stage with all, half and none of its time
+    /// left, for `gradient`
+    gradient: Option<Vec<String>>,
+    #[serde(default)]
+    stages: HashMap<String, String>
+}
+
+/// What happens once all stages are done.
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Completion {
+    /// Exit after showing the finished timer for a moment
+    Exit,
+    /// Show a summary of the run until a key is pressed
+    Summary,
+    /// Go back to choosing a preset to run next
+    Presets,
+    /// Run the same stages again, until the timer is quit
+    Again,
+    /// Run this preset next
+    Preset(String)
+}
+
+/// How the running timer is drawn.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayMode {
+    /// A gauge for every stage
+    Gauges,
+    /// The time left on the running stage in large digits above a strip of
+    /// smaller gauges
+    Big,
+    /// A line for every stage
+    Compact
+}
+
+impl DisplayMode {
+    pub fn from_name(name: &str) -> Option<DisplayMode> {
+        match name {
+            "gauges" => Some(DisplayMode::Gauges),
+            "big" => Some(DisplayMode::Big),
+            "compact" => Some(DisplayMode::Compact),
+            _ => None
+        }
+    }
+}
+
+/// Which way the stages' gauges are arranged.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    /// Stacked, each a bar filling up to the right
+    Vertical,
+    /// Side by side, each a bar filling up from the bottom
+    Horizontal
+}
+
+impl Orientation {
+    pub fn from_name(name: &str) -> Option<Orientation> {
+        match name {
+            "vertical" => Some(Orientation::Vertical),
+            "horizontal" => Some(Orientation::Horizontal),
+            _ => None
+        }
+    }
+}
+
+/// What is written inside the stages' gauges.
+#[derive(Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GaugeLabel {
+    /// How much of the stage is done
+    Percent,
+    /// What the stage's clock shows, usually the time left
+    Remaining,
+    Elapsed,
+    #[default]
+    None
+}
+
+impl GaugeLabel {
+    pub fn from_name(name: &str) -> Option<GaugeLabel> {
+        match name {
+            "percent" => Some(GaugeLabel::Percent),
+            "remaining" => Some(GaugeLabel::Remaining),
+            "elapsed" => Some(GaugeLabel::Elapsed),
+            "none" => Some(GaugeLabel::None),
+            _ => None
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigCue {
+    tag: String,
+    /// Times or percentages of the stage before its end
+    remaining: Vec<ConfigTime>,
+    message: Option<String>
+}
+
+/// A cue attached to every stage with the given tag, at each of the given
+/// times or percentages of the stage before its end.
+pub struct CueRule {
+    pub tag: String,
+    pub remaining: Vec<CountdownCue>,
+    pub message: Option<String>
+}
+
+impl CueRule {
+    /// The checkpoints this rule adds to a stage. Times that do not fall
+    /// within the stage are left out.
+    pub fn checkpoints(&self, stage: &ConfiguredStage) -> Vec<Checkpoint> {
+        if !stage.tags.contains(&self.tag) {
+            return Vec::new();
+        }
+        self.remaining.iter()
+            .filter_map(|cue| match *cue {
+                CountdownCue::Remaining(remaining_s) => {
+                    let remaining = Duration::from_secs(remaining_s.into());
+                    (remaining_s > 0 && remaining < stage.period).then(|| Checkpoint {
+                        at_s: (stage.period - remaining).as_secs() as u32,
+                        percent: None,
+                        message: self.message.clone().unwrap_or_else(|| format!(
+                            "{} remaining", TimeFormat::Smart.format(remaining_s, remaining_s)
+                        ))
+                    })
+                },
+                // percentages keep their place when the stage changes length
+                CountdownCue::Percent(percent) => (!stage.count_up).then(|| Checkpoint {
+                    at_s: 0,
+                    percent: Some(100 - percent),
+                    message: self.message.clone()
+                        .unwrap_or_else(|| format!("{}% remaining", percent))
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct ConfigExercise {
+    name: String,
+    #[serde(default)]
+    instructions: String,
+    diagram: Option<String>,
+    link: Option<String>
+}
+
+impl From<ConfigExercise> for Exercise {
+    fn from(exercise: ConfigExercise) -> Self {
+        Exercise {
+            name: exercise.name,
+            instructions: exercise.instructions,
+            diagram: exercise.diagram,
+            link: exercise.link
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default, rename = "stage")]
+    stages: Vec<ConfigStage>,
+    #[serde(default)]
+    confirm: ConfirmConfig,
+    #[serde(default, rename = "exercise")]
+    exercises: Vec<ConfigExercise>,
+    #[serde(default, rename = "cue")]
+    cues: Vec<ConfigCue>,
+    /// How many times to run the stages of the file
+    repeat: Option<u32>,
+    /// How the repeated stages are named
+    stage_names: Option<String>,
+    on_complete: Option<Completion>,
+    /// Command line options the file is run with as a preset
+    #[serde(default)]
+    options: Vec<String>,
+    /// Preset the stages come from, with the rest of this file in place of
+    /// its settings, see `staged-timer presets copy`
+    parent: Option<String>,
+    /// Factor the durations of the stages are scaled by
+    scale: Option<f64>
+}
+
+/// How many presets deep `parent` and `include` may go, more are taken for
+/// a loop.
+const MAX_DEPTH: usize = 16;
+
+/// Everything read from a single config file.
+pub struct LoadedConfig {
+    pub stages: Vec<ConfiguredStage>,
+    pub confirm: ConfirmConfig,
+    pub exercises: Vec<Exercise>,
+    pub cues: Vec<CueRule>,
+    pub on_complete: Option<Completion>,
+    /// Options `staged-timer run` puts before those given to it
+    pub options: Vec<String>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExerciseLibraryFile {
+    #[serde(default, rename = "exercise")]
+    exercises: Vec<ConfigExercise>
+}
+
+/// `stage` once for each side with `each_side`, left and then right, with
+/// the time to `switch` sides at the end of the left one.
+fn sides(
+    stage: ConfiguredStage,
+    each_side: bool,
+    switch: Option<ConfigTime>,
+    path: &Path
+) -> Result<Vec<ConfiguredStage>, String> {
+    let name = stage.name.clone().or_else(|| stage.exercise.clone());
+    if !each_side {
+        if switch.is_some() {
+            return Err(format!(
+                "{}: \"{}\" has a switch but isn't run on each side, set each_side = true",
+                path.display(), name.unwrap_or_default()
+            ));
+        }
+        return Ok(vec![stage]);
+    }
+    let name = name.ok_or_else(|| format!(
+        "{}: Stages run on each side need a name to tell the sides apart", path.display()
+    ))?;
+    let mut left = ConfiguredStage { name: Some(format!("{} (left)", name)), ..stage.clone() };
+    let switch = switch.map(|switch| switch.duration(path)).transpose()?;
+    if let Some(switch) = switch.filter(|switch| !switch.is_zero()) {
+        if left.after.is_some() {
+            return Err(format!(
+                "{}: \"{}\" can't have a part after it as well as a switch", path.display(), name
+            ));
+        }
+        left.period += switch;
+        left.after = Some(Phase { name: String::from("Switch sides"), period: switch });
+    }
+    let right = ConfiguredStage { name: Some(format!("{} (right)", name)), ..stage };
+    Ok(vec![left, right])
+}
+
+/// Load a TOML config file of the form
+///
+/// ```toml
+/// repeat = 8 # optional, run the stages of this file this many times
+/// stage_names = "Round {round} – {phase}" # optional, how repeated stages are named
+/// on_complete = "summary" # optional, or "exit", "presets", "again", { preset = "fix" }
+/// options = ["--theme", "light", "--manual-advance"] # optional, default options of `run`
+/// scale = 1.25 # optional, make all stages this much longer or shorter
+/// parent = "c41" # optional, take the stages from this preset instead
+///
+/// [[stage]]
+/// include = "wash-cycle" # the stages of this preset in place of one
+/// group = "Washing" # optional, put all of them in this section
+///
+/// [confirm]
+/// quit = true
+/// skip = true
+///
+/// [[stage]]
+/// name = "Developer"
+/// time = "7:30" # or "open" to count up until ENTER is pressed
+/// enabled = true # optional, disabled stages are skipped unless selected
+/// zone = 3 # optional training intensity zone from 1 to 5
+/// minute_chime = true # optional, ring the bell every full minute
+/// note = "1+9 with water" # optional, shown while the stage runs
+/// group = "Development" # optional, section shown as a header above the stage
+///
+/// audio = "coach/developer.mp3" # optional, played when the stage starts
+/// pace = 6 # optional, show a pacing guide with this many cycles per minute
+/// rest = true # optional, lasts until the heart rate is down if it is read
+/// tags = ["develop"] # optional, for cue rules
+/// batch = "D76-03" # optional, chemical batch checked before starting
+/// warn = "1:00" # optional, highlight the stage when this much time is left
+/// countdown = ["50%", "2:00", "0:30"] # optional, announce the time left then
+/// color = "#5b9bd5" # optional, colour of the running stage's gauge
+/// on_start = "smartplug on" # optional, shell command run when it starts
+/// on_end = "smartplug off" # optional, shell command run when it ends
+/// runs_while_paused = true # optional, keep counting while paused
+/// overtime = true # optional, count on past the end until ENTER is pressed
+/// manual_advance = true # optional, wait for ENTER before the next stage
+/// compensate = true # optional, which stages --temperature scales
+///
+/// # optional, agitate for 30s at the start, then for 10s every minute
+/// agitation = { initial = 30, every = "1:00", for = 10 }
+///
+/// # optional, a part after the time of the stage, shown on the same gauge
+/// after = { name = "Switch sides", time = 5 }
+///
+/// # optional, run the stage on the left and then the right side, with 5s
+/// # to switch sides in between
+/// each_side = true
+/// switch = 5
+///
+/// [[stage.checkpoint]] # optional, any number of them
+/// at = "4:00" # time since the start of the stage, "50%" of it or "halfway"
+/// message = "Check temperature"
+///
+/// [[cue]] # optional, applies to all stages with the tag, also generated ones
+/// tag = "develop"
+/// remaining = ["0:30", "0:10"] # or percentages of the stage like "25%"
+/// message = "Prepare stop bath" # optional, defaults to the time remaining
+///
+/// [[exercise]] # optional, see `load_exercises`
+/// ```
+///
+/// Files ending in `.yaml` or `.yml` are read as YAML with the same
+/// structure.
+pub fn load(path: &Path) -> Result<LoadedConfig, String> {
+    load_included(path, 0)
+}
+
+/// `load` for the TOML `content` of a config file that is to be saved at
+/// `path`, e.g. a preset that is only written once it loads.
+pub fn load_toml(content: &str, path: &Path) -> Result<LoadedConfig, String> {
+    let config = toml::from_str(content).map_err(|e| format!(
+        "Could not parse config file {}: {}", path.display(), e
+    ))?;
+    load_config(config, path, 0)
+}
+
+/// `load` for a config file included `depth` presets deep.
+fn load_included(path: &Path, depth: usize) -> Result<LoadedConfig, String> {
+    load_config(read_config(path)?, path, depth)
+}
+
+/// The stages and settings of `config`, read from `path` `depth` presets
+/// deep.
+fn load_config(config: ConfigFile, path: &Path, depth: usize) -> Result<LoadedConfig, String> {
+    let config = inherit(config, path, depth)?;
+
+    // included presets bring the exercises and cues of their stages along
+    let mut included_exercises = Vec::new();
+    let mut included_cues = Vec::new();
+    let mut stages: Vec<ConfiguredStage> = config.stages.into_iter().map(|stage| {
+        if let Some(include) = stage.include {
+            if stage.name.is_some() || stage.time.is_some() {
+                return Err(format!(
+                    "{}: The stages of \"{}\" are included as they are, \
+                    without a name or time of their own",
+                    path.display(), include
+                ));
+            }
+            if depth == MAX_DEPTH {
+                return Err(format!("{}: Presets include each other in a loop", path.display()));
+            }
+            let included_path = presets::find(&include)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            let mut included = load_included(&included_path, depth + 1)?;
+            included_exercises.extend(included.exercises);
+            included_cues.extend(included.cues);
+            // a group on the include makes a section of the included stages
+            if let Some(group) = stage.group {
+                for included_stage in included.stages.iter_mut() {
+                    included_stage.group = Some(group.clone());
+                }
+            }
+            return Ok(included.stages);
+        }
+        let (each_side, switch) = (stage.each_side, stage.switch);
+        if let Some(zone) = stage.zone.filter(|zone| !(1..=5).contains(zone)) {
+            return Err(format!(
+                "{}: Zone {} is out of range, expected 1 to 5",
+                path.display(), zone
+            ));
+        }
+
+        // "open" stages count up until they are finished
+        let (period, count_up) = match stage.time {
+            Some(ConfigTime::Text(time)) if time == "open" => (Duration::ZERO, true),
+            Some(time) => (time.duration(path)?, false),
+            None => return Err(format!(
+                "{}: \"{}\" needs a time, or an include of a preset in its place",
+                path.display(), stage.name.unwrap_or_default()
+            ))
+        };
+        // the part at the end comes on top of the time of the stage
+        let after = stage.after.map(|after| {
+            let after = Phase { name: after.name, period: after.time.duration(path)? };
+            if count_up || after.period.is_zero() {
+                return Err(format!(
+                    "{}: \"{}\" needs a time and a stage that doesn't count up",
+                    path.display(), after.name
+                ));
+            }
+            Ok(after)
+        }).transpose()?;
+        let period = period + after.as_ref().map_or(Duration::ZERO, |after| after.period);
+        let checkpoints = stage.checkpoints.into_iter().map(|checkpoint| {
+            let (at_s, percent) = match checkpoint.at {
+                ConfigTime::Text(at) if at == "halfway" => (0, Some(50)),
+                ConfigTime::Text(at) if at.trim_end().ends_with('%') => {
+                    match at.trim_end().trim_end_matches('%').trim().parse::<u8>() {
+                        Ok(percent) if (1..100).contains(&percent) => (0, Some(percent)),
+                        _ => return Err(format!(
+                            "{}: Checkpoint at {} is not a percentage from 1% to 99%",
+                            path.display(), at
+                        ))
+                    }
+                },
+                at => (at.seconds(path)?, None)
+            };
+            let outside = match percent {
+                Some(_) => count_up,
+                None => at_s == 0 || !count_up && Duration::from_secs(at_s.into()) >= period
+            };
+            if outside {
+                return Err(format!(
+                    "{}: Checkpoint \"{}\" is not within its stage",
+                    path.display(), checkpoint.message
+                ));
+            }
+            Ok(Checkpoint { at_s, percent, message: checkpoint.message })
+        }).collect::<Result<_, String>>()?;
+        let countdown = stage.countdown.into_iter().map(|cue| {
+            let cue = cue.countdown(path)?;
+            if let CountdownCue::Remaining(remaining_s) = cue {
+                if !count_up && Duration::from_secs(remaining_s.into()) >= period {
+                    return Err(format!(
+                        "{}: Countdown at {} left is not within its stage",
+                        path.display(), TimeFormat::Smart.format(remaining_s, remaining_s)
+                    ));
+                }
+            }
+            Ok(cue)
+        }).collect::<Result<_, String>>()?;
+
+        let agitation = stage.agitation.map(|agitation| {
+            let seconds = |time: Option<ConfigTime>| {
+                time.map(|time| time.seconds(path)).transpose().map(Option::unwrap_or_default)
+            };
+            let agitation = Agitation {
+                initial_s: seconds(agitation.initial)?,
+                every_s: seconds(agitation.every)?,
+                for_s: seconds(agitation.length)?
+            };
+            let repeats = agitation.every_s > 0 || agitation.for_s > 0;
+            if repeats && !(0 < agitation.for_s && agitation.for_s < agitation.every_s) {
+                return Err(format!(
+                    "{}: Agitation needs to be shorter than the time between \
+                    agitations, e.g. every = \"1:00\" and for = 10",
+                    path.display()
+                ));
+            }
+            Ok(agitation)
+        }).transpose()?;
+
+        // audio files are relative to the config file they appear in
+        let audio = stage.audio
+            .map(|audio| path.parent().unwrap_or(Path::new(".")).join(audio));
+        if let Some(audio) = audio.as_ref().filter(|audio| !audio.is_file()) {
+            return Err(format!(
+                "{}: Audio file {} does not exist",
+                path.display(), audio.display()
+            ));
+        }
+
+        if let Some(pace) = stage.pace.filter(|pace| !(*pace > 0.0 && *pace <= 120.0)) {
+            return Err(format!(
+                "{}: Pace {} is out of range, expected up to 120 cycles per minute",
+                path.display(), pace
+            ));
+        }
+
+        let color = stage.color
+            .map(|color| parse_color(&color).ok_or_else(|| format!(
+                "{}: Could not parse colour {}, expected e.g. \"#5b9bd5\" or \"blue\"",
+                path.display(), color
+            )))
+            .transpose()?;
+
+        Ok(ConfiguredStage {
+            name: stage.name,
+            period,
+            enabled: stage.enabled,
+            zone: stage.zone,
+            minute_chime: stage.minute_chime,
+            checkpoints,
+            countdown,
+            agitation,
+            after,
+            exercise: stage.exercise,
+            note: stage.note,
+            group: stage.group,
+            audio,
+            pace_cpm: stage.pace,
+            rest: stage.rest,
+            tags: stage.tags,
+            batch: stage.batch,
+            warn_s: stage.warn.map(|warn| warn.seconds(path)).transpose()?,
+            color,
+            on_start: stage.on_start,
+            on_end: stage.on_end,
+            runs_while_paused: stage.runs_while_paused,
+            count_up,
+            overtime: stage.overtime,
+            manual_advance: stage.manual_advance,
+            compensate: stage.compensate
+        })
+        .and_then(|configured| sides(configured, each_side, switch, path))
+    }).collect::<Result<Vec<_>, String>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    if let Some(scale) = config.scale {
+        if !(scale.is_finite() && scale > 0.0) {
+            return Err(format!("{}: Scale {} is not a positive number", path.display(), scale));
+        }
+        // stages counting up have no duration to scale
+        for stage in stages.iter_mut().filter(|stage| !stage.count_up) {
+            stage.period = compensation::scale(stage.period, &mut stage.checkpoints, scale);
+            if let Some(after) = &mut stage.after {
+                after.period = compensation::scale(after.period, &mut Vec::new(), scale)
+                    .min(stage.period);
+            }
+        }
+    }
+    if config.repeat == Some(0) {
+        return Err(format!("{}: Stages cannot be repeated 0 times", path.display()));
+    }
+    let names = config.stage_names.as_deref().unwrap_or(generators::DEFAULT_STAGE_NAMES);
+    let stages = generators::repeat(stages, config.repeat.unwrap_or(1), names)
+        .map_err(|message| format!("{}: {}", path.display(), message))?;
+
+    let mut cues: Vec<CueRule> = config.cues.into_iter().map(|cue| {
+        Ok(CueRule {
+            tag: cue.tag,
+            remaining: cue.remaining.into_iter()
+                .map(|remaining| remaining.countdown(path))
+                .collect::<Result<_, String>>()?,
+            message: cue.message
+        })
+    }).collect::<Result<_, String>>()?;
+    cues.extend(included_cues);
+
+    Ok(LoadedConfig {
+        stages,
+        confirm: config.confirm,
+        exercises: config.exercises.into_iter()
+            .map(Exercise::from)
+            .chain(included_exercises)
+            .collect(),
+        cues,
+        on_complete: config.on_complete,
+        options: config.options
+    })
+}
+
+fn read_config(path: &Path) -> Result<ConfigFile, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!(
+        "Could not read config file {}: {}", path.display(), e
+    ))?;
+    let is_yaml = path.extension()
+        .is_some_and(|extension| extension == "yaml" || extension == "yml");
+    if is_yaml {
+        serde_yaml::from_str(&content).map_err(|e| e.to_string())
+    } else {
+        toml::from_str(&content).map_err(|e| e.to_string())
+    }.map_err(|e| format!(
+        "Could not parse config file {}: {}", path.display(), e
+    ))
+}
+
+/// `config` with the stages of its parent preset, if it has one. What
+/// `config` sets takes the place of the parent's settings, its cues,
+/// exercises and options come after the parent's and scales multiply.
+fn inherit(mut config: ConfigFile, path: &Path, depth: usize) -> Result<ConfigFile, String> {
+    let parent = match config.parent.take() {
+        Some(parent) => parent,
+        None => return Ok(config)
+    };
+    if depth == MAX_DEPTH {
+        return Err(format!("{}: Presets derive from each other in a loop", path.display()));
+    }
+    if !config.stages.is_empty() {
+        return Err(format!(
+            "{}: Stages come from the parent preset \"{}\", there can't be any others",
+            path.display(), parent
+        ));
+    }
+    let parent_path = presets::find(&parent).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut base = inherit(read_config(&parent_path)?, &parent_path, depth + 1)?;
+    base.exercises.extend(config.exercises);
+    base.cues.extend(config.cues);
+    base.options.extend(config.options);
+    Ok(ConfigFile {
+        stages: base.stages,
+        confirm: ConfirmConfig {
+            quit: config.confirm.quit.or(base.confirm.quit),
+            skip: config.confirm.skip.or(base.confirm.skip),
+            jump: config.confirm.jump.or(base.confirm.jump)
+        },
+        exercises: base.exercises,
+        cues: base.cues,
+        repeat: config.repeat.or(base.repeat),
+        stage_names: config.stage_names.or(base.stage_names),
+        on_complete: config.on_complete.or(base.on_complete),
+        options: base.options,
+        parent: None,
+        scale: match (config.scale, base.scale) {
+            (Some(scale), Some(base_scale)) => Some(scale * base_scale),
+            (scale, base_scale) => scale.or(base_scale)
+        }
+    })
+}
+
+/// Parse a colour given as `#rrggbb` or one of a few common names.
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+    match color.to_lowercase().as_str() {
+        "red" => Some([0xe0, 0x30, 0x30]),
+        "orange" => Some([0xed, 0x7d, 0x31]),
+        "yellow" => Some([0xff, 0xe5, 0x00]),
+        "green" => Some([0x70, 0xad, 0x47]),
+        "blue" => Some([0x5b, 0x9b, 0xd5]),
+        "purple" => Some([0x9b, 0x59, 0xb6]),
+        "white" => Some([0xff, 0xff, 0xff]),
+        hex => {
+            let hex = hex.strip_prefix('#')?;
+            if hex.len() != 6 {
+                return None;
+            }
+            let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+            Some([channel(0)?, channel(2)?, channel(4)?])
+        }
+    }
+}
+
+/// Load an exercise library of the form
+///
+/// ```toml
+/// [[exercise]]
+/// name = "Burpee"
+/// instructions = "Squat, kick back, push-up, jump."
+/// diagram = '''
+///  o   o
+/// /|\\ _|_
+/// '''
+/// link = "https://example.com/burpee"
+/// ```
+///
+/// Stages refer to exercises by name with `exercise = "Burpee"`.
+pub fn load_exercises(path: &Path) -> Result<Vec<Exercise>, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!(
+        "Could not read exercise library {}: {}", path.display(), e
+    ))?;
+    let library: ExerciseLibraryFile = toml::from_str(&content).map_err(|e| format!(
+        "Could not parse exercise library {}: {}", path.display(), e
+    ))?;
+
+    Ok(library.exercises.into_iter().map(Exercise::from).collect())
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct SettingsFile {
+    warn: Option<ConfigTime>,
+    time_format: Option<TimeFormat>,
+    tenths: Option<bool>,
+    overtime: Option<bool>,
+    manual_advance: Option<bool>,
+    keep_output: Option<bool>,
+    allow_sleep: Option<bool>,
+    audio_player: Option<String>,
+    heart_rate_command: Option<String>,
+    rest_below: Option<u16>,
+    max_rest_extension: Option<ConfigTime>,
+    units: Option<Units>,
+    sound: Option<bool>,
+    alert_sound: Option<PathBuf>,
+    sensor_command: Option<String>,
+    notify: Option<bool>,
+    speak: Option<bool>,
+    notify_message: Option<String>,
+    notifiers: Option<Vec<Channel>>,
+    webhook_url: Option<String>,
+    push_url: Option<String>,
+    mqtt_broker: Option<String>,
+    mqtt_topic: Option<String>,
+    mqtt_username: Option<String>,
+    mqtt_password: Option<String>,
+    deviation_tolerance: Option<ConfigTime>,
+    pause_budget: Option<ConfigTime>,
+    on_stage_start: Option<String>,
+    on_stage_end: Option<String>,
+    enlarger_on: Option<String>,
+    enlarger_off: Option<String>,
+    on_complete: Option<Completion>,
+    adjust_step: Option<ConfigTime>,
+    idle_pause: Option<ConfigTime>,
+    input_devices: Option<Vec<PathBuf>>,
+    checkpoint_interval: Option<ConfigTime>,
+    #[serde(default)]
+    confirm: ConfirmConfig,
+    #[serde(default)]
+    control: ControlConfig,
+    #[serde(default)]
+    serve: ServeConfig,
+    #[serde(default)]
+    alerts: Routes,
+    #[serde(default)]
+    retention: RetentionConfig,
+    theme: Option<ThemeConfig>,
+    display: Option<DisplayMode>,
+    orientation: Option<Orientation>,
+    sparkline: Option<bool>,
+    accessible: Option<bool>,
+    gradient: Option<bool>,
+    gauge_title: Option<String>,
+    gauge_label: Option<GaugeLabel>,
+    upcoming_stages: Option<u32>,
+    stage_names: Option<String>,
+    /// Pairs of temperature, in `units`, and factor
+    temperature_curve: Option<Vec<(f64, f64)>>
+}
+
+/// User settings that apply to every run, as opposed to the stage lists in
+/// config files. Config files and command line options take precedence.
+#[derive(Default)]
+pub struct Settings {
+    pub warn: Option<u32>,
+    pub time_format: Option<TimeFormat>,
+    /// Whether to count down in tenths of a second
+    pub tenths: Option<bool>,
+    /// Whether stages keep counting past their end until finished
+    pub overtime: Option<bool>,
+    /// Whether the timer waits for ENTER before starting the next stage
+    pub manual_advance: Option<bool>,
+    /// Whether to print a summary of the run once the TUI is closed
+    pub keep_output: Option<bool>,
+    /// Whether the system may go to sleep while a timer runs
+    pub allow_sleep: Option<bool>,
+    /// Command used to play audio files, the file is appended as last
+    /// argument
+    pub audio_player: Option<String>,
+    /// Prints the heart rate, one reading per line, that rest stages last
+    /// until it is below `rest_below`
+    pub heart_rate_command: Option<String>,
+    pub rest_below: Option<u16>,
+    /// How long a rest may go on past its end while the heart rate is up
+    pub max_rest_extension: Option<u32>,
+    pub units: Option<Units>,
+    /// Whether to announce stage transitions and warnings
+    pub sound: Option<bool>,
+    /// Played instead of the terminal bell
+    pub alert_sound: Option<PathBuf>,
+    /// Prints the ambient conditions, read at the start of every stage
+    pub sensor_command: Option<String>,
+    /// Whether to show desktop notifications when stages end
+    pub notify: Option<bool>,
+    /// Whether to read out loud which stage ended and which one follows
+    pub speak: Option<bool>,
+    /// Template of notifications and announcements
+    pub notify_message: Option<String>,
+    /// Every channel to notify through, in place of those `sound`, `notify`
+    /// and `speak` ask for
+    pub notifiers: Option<Vec<Channel>>,
+    /// Where the webhook notifier posts to
+    pub webhook_url: Option<String>,
+    /// Where the push notifier sends to, e.g. a topic on ntfy.sh
+    pub push_url: Option<String>,
+    /// MQTT broker to publish the state of the timer to, and how
+    pub mqtt_broker: Option<String>,
+    pub mqtt_topic: Option<String>,
+    pub mqtt_username: Option<String>,
+    pub mqtt_password: Option<String>,
+    /// How far a stage may run off its planned duration before it is flagged
+    pub deviation_tolerance: Option<u32>,
+    /// How long a run may be paused in all before it is flagged
+    pub pause_budget: Option<u32>,
+    /// Shell commands run when any stage starts and ends
+    pub on_stage_start: Option<String>,
+    pub on_stage_end: Option<String>,
+    /// Shell commands switching the enlarger on and off for the exposures
+    /// of --split-grade
+    pub enlarger_on: Option<String>,
+    pub enlarger_off: Option<String>,
+    /// What happens once all stages are done, unless the config file says
+    pub on_complete: Option<Completion>,
+    /// How much + and - add to or take off the running stage
+    pub adjust_step: Option<u32>,
+    /// How long a stage waiting for the user may go without a key press
+    /// before the timer pauses
+    pub idle_pause: Option<u32>,
+    /// Event files of foot pedals and buttons that control the timer
+    pub input_devices: Option<Vec<PathBuf>>,
+    /// How often to save a running timer for --resume
+    pub checkpoint_interval: Option<u32>,
+    pub confirm: ConfirmConfig,
+    pub control: ControlConfig,
+    pub serve: ServeConfig,
+    /// Channels of warnings, stage ends and the end of the last stage
+    pub alerts: Routes,
+    pub retention: RetentionConfig,
+    pub theme: Option<Theme>,
+    pub display: Option<DisplayMode>,
+    pub orientation: Option<Orientation>,
+    /// Whether to show how long each stage was paused and ran over
+    pub sparkline: Option<bool>,
+    /// Whether to mark stages with text instead of colour and draw gauges
+    /// in ASCII
+    pub accessible: Option<bool>,
+    /// Whether the running stage goes from one colour to the next as its
+    /// time runs out
+    pub gradient: Option<bool>,
+    /// Template of the stages' gauges' titles
+    pub gauge_title: Option<String>,
+    /// What is written inside the stages' gauges
+    pub gauge_label: Option<GaugeLabel>,
+    /// How many stages after the running one get a gauge
+    pub upcoming_stages: Option<u32>,
+    /// Template of the names of stages generated on the command line, see
+    /// [`generators::DEFAULT_STAGE_NAMES`]
+    pub stage_names: Option<String>,
+    /// Factors of development times at temperatures in °C, replacing
+    /// [`compensation::STANDARD_CURVE`]
+    pub temperature_curve: Option<Vec<(f64, f64)>>,
+    /// Colours of stages with one of these tags or names that don't have
+    /// their own, keys in lowercase
+    pub stage_colors: HashMap<String, [u8; 3]>
+}
+
+const SETTINGS_TEMPLATE: &str = "\
+# Settings for staged-timer, applied to every run.
+
+# Highlight the countdown bar when this much time is left on a stage.
+# warn = \"0:30\"
+
+# Write durations as 45s, 9:30 or 1:02:00 depending on the length of the
+# stage (\"smart\") or always as hh:mm:ss (\"fixed\").
+# time_format = \"smart\"
+
+# Count down in tenths of a second, like --tenths.
+# tenths = true
+
+# Keep counting past the end of a stage until ENTER is pressed, like
+# --overtime. Stages can say otherwise with their own `overtime`.
+# overtime = true
+
+# Wait for ENTER at the end of every stage before starting the next one,
+# like --manual-advance. Stages can say otherwise with their own
+# `manual_advance`.
+# manual_advance = true
+
+# Print a summary of the run once the timer is closed, like --keep-output.
+# keep_output = true
+
+# Let the system go to sleep while a timer runs, like --allow-sleep. It is
+# kept awake otherwise.
+# allow_sleep = true
+
+# Program used to play audio files. Detected automatically if not set.
+# audio_player = \"mpv --no-video\"
+
+# Read the heart rate from a command printing one per line, e.g. from a
+# chest strap, and end rest stages once it is below rest_below. A rest still
+# short of it goes on for up to max_rest_extension past its end. Like
+# --heart-rate-command, --rest-below and --max-rest-extension.
+# heart_rate_command = \"read-chest-strap\"
+# rest_below = 110
+# max_rest_extension = \"1:00\"
+
+# Give temperatures in °C and volumes in ml (\"metric\") or in °F and fl oz
+# (\"imperial\").
+# units = \"metric\"
+
+# Ring the terminal bell when a stage ends or reaches the warning threshold,
+# like --sound. Only quick timers and alarms ring if this isn't set.
+# sound = true
+
+# Play this file instead of ringing the bell.
+# alert_sound = \"/usr/share/sounds/freedesktop/stereo/complete.oga\"
+
+# Show a desktop notification whenever a stage ends, like --notify.
+# notify = true
+
+# Read out loud which stage ended and which one follows, like --speak.
+# speak = true
+
+# What notifications and --speak say when a stage ends, like
+# --notify-message. {finished} and {next} are the names of the stages and
+# {time} is the duration of the next one.
+# notify_message = \"{finished} done. {next}: {time}.\"
+
+# Every way to be told about the timer, in place of what sound, notify and
+# speak ask for: \"bell\", \"sound\" (alert_sound, or the bell without it),
+# \"desktop\", \"speech\", \"webhook\" and \"push\". The bell and the
+# alert sound also go off at warnings and checkpoints, the others only when
+# a stage ends.
+# notifiers = [\"sound\", \"desktop\"]
+
+# Where the webhook notifier posts a JSON object for every stage that ends,
+# with curl.
+# webhook_url = \"http://localhost:8080/staged-timer\"
+
+# Where the push notifier sends notifications to, a topic of ntfy or a
+# service that works the same way, with curl.
+# push_url = \"https://ntfy.sh/my-darkroom\"
+
+# MQTT broker to publish the running stage, its time left and the state of
+# the timer to, like --mqtt and --mqtt-topic, with credentials if it needs
+# them.
+# mqtt_broker = \"homeassistant.local:1883\"
+# mqtt_topic = \"darkroom/timer\"
+# mqtt_username = \"darkroom\"
+# mqtt_password = \"secret\"
+
+# Warn about stages whose actual duration, pauses included, is off the plan
+# by more than this, like --tolerance.
+# deviation_tolerance = \"0:05\"
+
+# Warn once a run has been paused for longer than this in all and mark it in
+# the history, like --pause-budget.
+# pause_budget = \"5:00\"
+
+# Command printing the ambient conditions as lines like temperature=21.5 (in
+# °C) and humidity=48. Runs at the start of every stage of a recorded run.
+# sensor_command = \"read-darkroom-sensor\"
+
+# Shell commands run whenever a stage starts or ends, like --on-stage-start
+# and --on-stage-end.
+# on_stage_start = \"smartplug on\"
+# on_stage_end = \"smartplug off\"
+
+# Shell commands switching the enlarger on and off as the exposures of
+# --split-grade start and end, like --enlarger-on and --enlarger-off.
+# enlarger_on = \"relay on\"
+# enlarger_off = \"relay off\"
+
+# What to do once all stages are done: \"exit\", show a \"summary\" until a
+# key is pressed, go back to the list of \"presets\" or run the same stages
+# \"again\", like --on-complete.
+# on_complete = \"summary\"
+
+# How much time + and - add to or take off the running stage, like
+# --adjust-step.
+# adjust_step = \"0:30\"
+
+# Pause the timer and ring now and then when a stage waiting for ENTER goes
+# this long without a key press, like --idle-pause.
+# idle_pause = \"2:00\"
+
+# Foot pedals and buttons that control the timer, read from their event
+# files, like --input-device.
+# input_devices = [\"/dev/input/by-id/usb-PCsensor_FootSwitch-event-kbd\"]
+
+# Save the running timer this often so --resume can carry on with it after
+# a crash or a closed terminal, like --checkpoint-interval.
+# checkpoint_interval = \"1:00\"
+
+# Show the time left on the running stage in large digits (\"big\"), a gauge
+# for every stage (\"gauges\") or a line for every stage (\"compact\"), like
+# --display.
+# display = \"big\"
+
+# Put the stages' gauges side by side (\"horizontal\") instead of stacking
+# them (\"vertical\"), like --orientation.
+# orientation = \"horizontal\"
+
+# Show how long each stage so far was paused and ran over in a strip below
+# the stages, like --sparkline.
+# sparkline = true
+
+# Mark the running stage and its warnings with text instead of colour and
+# draw the gauges in ASCII only, like --accessible. Without colour, as with
+# NO_COLOR set, the marks are shown either way.
+# accessible = true
+
+# Let the running stage go from green through yellow to red as its time runs
+# out instead of switching to the warning colour, like --gradient. The
+# colours can be changed in [theme].
+# gradient = true
+
+# Title of every stage's gauge, like --gauge-title. {name} is the name of the
+# stage, {remaining}, {elapsed} and {total} are its times and {percent} is
+# how much of it is done.
+# gauge_title = \"{name} — {remaining} left of {total} ({percent}%)\"
+
+# What is written inside every stage's gauge, like --label: how much of the
+# stage is done (\"percent\"), the time left (\"remaining\"), the time so far
+# (\"elapsed\") or nothing (\"none\").
+# gauge_label = \"remaining\"
+
+# Only show gauges for the running stage and this many after it, like
+# --upcoming. The rest are summed up as \"… 12 more\".
+# upcoming_stages = 5
+
+# Names of the stages of --work, --ladder, --pomodoro, --repeat and repeated
+# groups of --intervals, like --stage-names. {phase} is what the stage is, e.g. Work
+# or Rest, {round} the round it is in and {rounds} how many there are.
+# stage_names = \"Round {round} – {phase}\"
+
+# How --temperature scales development times: pairs of a temperature, in
+# the units above, and the factor times are multiplied by at it. Defaults to
+# the usual chart for black and white developers, about 8% shorter per °C.
+# temperature_curve = [[18, 1.2], [20, 1.0], [22, 0.85], [24, 0.72]]
+
+# Ask for confirmation before quitting with ESC or skipping a stage with N.
+# Jumping to a stage by clicking it is confirmed unless jump is false.
+# [confirm]
+# quit = true
+# skip = true
+# jump = false
+
+# Who may do what over --control-port, which only listens on this machine
+# unless address says otherwise. Clients get a permission by their address:
+# \"none\", \"read\" for the status or \"control\" to change the timer as
+# well. Clients that aren't listed get others, \"read\" unless set, even on
+# this machine, since any of its users can connect. Sending auth and one of
+# the tokens gives a client the token's permission.
+# [control]
+# address = \"0.0.0.0\"
+# others = \"none\"
+# clients = { \"127.0.0.1\" = \"control\", \"192.168.1.20\" = \"control\" }
+# tokens = { \"long-random-secret\" = \"control\" }
+#
+# Over a network others can listen in on, e.g. at the gym, take connections
+# over TLS only, so tokens and commands can't be read or changed on the way.
+# cert = \"/home/me/.config/staged-timer/cert.pem\"
+# key = \"/home/me/.config/staged-timer/key.pem\"
+
+# Who may follow the timer on the --serve address, which anyone who can
+# reach it may unless token is set. Clients then send the token as
+# \"Authorization: Bearer TOKEN\", and the page at / is opened as
+# /?token=TOKEN. With a certificate and its key, it is served over HTTPS.
+# [serve]
+# token = \"long-random-secret\"
+# cert = \"/home/me/.config/staged-timer/cert.pem\"
+# key = \"/home/me/.config/staged-timer/key.pem\"
+
+# Which notifiers go off when a stage ends, when one reaches its warning
+# threshold and when the last one ends, even those notifiers doesn't list.
+# Unless set, stage ends go to all notifiers, warnings only ring the bell or
+# play the alert sound, and the end of the last stage goes wherever stage
+# ends go.
+# [alerts]
+# stage_end = [\"sound\"]
+# warning = [\"bell\", \"speech\"]
+# finished = [\"sound\", \"desktop\", \"push\"]
+
+# How much of what the timer collects is kept, all of it unless set. The
+# oldest runs past sessions are moved out of the history into a backup, as
+# is an event log given with --log once it grows past event_log_kib. Only the
+# newest backups are kept. Checkpoints of runs that weren't resumed within
+# checkpoint_days are deleted.
+# [retention]
+# sessions = 1000
+# backups = 3
+# checkpoint_days = 30
+# event_log_kib = 1024
+
+# Colours of the timer, like --theme: one of the built-in themes \"dark\",
+# \"light\" and \"high-contrast\", any of whose colours may be replaced with
+# #rrggbb or one of red, orange, yellow, green, blue, purple and white.
+# [theme]
+# name = \"dark\"
+# active = \"white\"
+# inactive = \"#2a2a2a\"
+# warning = \"yellow\"
+# background = \"#000000\"
+# The running stage's gauge blinks in its last seconds and turns critical in
+# the very last ones, 0 for never.
+# critical = \"red\"
+# blink = 10
+# critical_from = 5
+# With gradient = true, the running stage goes from the first of these
+# colours through the second to the third as its time runs out.
+# gradient = [\"green\", \"yellow\", \"red\"]
+#
+# Colours of stages by tag or name, unless a stage sets its own.
+# [theme.stages]
+# develop = \"blue\"
+# stop = \"yellow\"
+# fix = \"green\"
+";
+
+/// Directory holding the user's settings, following the XDG base directory
+/// spec where it applies.
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .map(|dir| dir.join("staged-timer"))
+}
+
+pub fn settings_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("settings.toml"))
+}
+
+fn parse_settings(content: &str, path: &Path) -> Result<Settings, String> {
+    let mut settings: SettingsFile = toml::from_str(content).map_err(|e| format!(
+        "Could not parse settings file {}: {}", path.display(), e
+    ))?;
+
+    Ok(Settings {
+        warn: settings.warn.map(|warn| warn.seconds(path)).transpose()?,
+        time_format: settings.time_format,
+        tenths: settings.tenths,
+        overtime: settings.overtime,
+        manual_advance: settings.manual_advance,
+        keep_output: settings.keep_output,
+        allow_sleep: settings.allow_sleep,
+        audio_player: settings.audio_player,
+        heart_rate_command: settings.heart_rate_command,
+        rest_below: settings.rest_below,
+        max_rest_extension: settings.max_rest_extension
+            .map(|extension| extension.seconds(path))
+            .transpose()?,
+        units: settings.units,
+        sound: settings.sound,
+        alert_sound: settings.alert_sound.map(|file| {
+            if file.is_file() {
+                Ok(file)
+            } else {
+                Err(format!(
+                    "{}: Alert sound {} does not exist", path.display(), file.display()
+                ))
+            }
+        }).transpose()?,
+        sensor_command: settings.sensor_command,
+        notify: settings.notify,
+        speak: settings.speak,
+        notify_message: settings.notify_message,
+        notifiers: settings.notifiers,
+        webhook_url: settings.webhook_url,
+        push_url: settings.push_url,
+        mqtt_broker: settings.mqtt_broker,
+        mqtt_topic: settings.mqtt_topic,
+        mqtt_username: settings.mqtt_username,
+        mqtt_password: settings.mqtt_password,
+        deviation_tolerance: settings.deviation_tolerance
+            .map(|tolerance| tolerance.seconds(path))
+            .transpose()?,
+        pause_budget: settings.pause_budget.map(|budget| budget.seconds(path)).transpose()?,
+        on_stage_start: settings.on_stage_start,
+        on_stage_end: settings.on_stage_end,
+        enlarger_on: settings.enlarger_on,
+        enlarger_off: settings.enlarger_off,
+        on_complete: match settings.on_complete {
+            Some(Completion::Preset(_)) => return Err(format!(
+                "{}: on_complete cannot run a preset after every run, use it \
+                in a config file instead",
+                path.display()
+            )),
+            on_complete => on_complete
+        },
+        adjust_step: settings.adjust_step.map(|step| step.seconds(path)).transpose()?,
+        idle_pause: settings.idle_pause.map(|window| window.seconds(path)).transpose()?,
+        input_devices: settings.input_devices,
+        checkpoint_interval: settings.checkpoint_interval
+            .map(|interval| interval.seconds(path))
+            .transpose()?,
+        confirm: settings.confirm,
+        control: settings.control,
+        serve: settings.serve,
+        alerts: settings.alerts,
+        retention: settings.retention,
+        display: settings.display,
+        orientation: settings.orientation,
+        sparkline: settings.sparkline,
+        accessible: settings.accessible,
+        gradient: settings.gradient,
+        gauge_title: settings.gauge_title,
+        gauge_label: settings.gauge_label,
+        upcoming_stages: settings.upcoming_stages,
+        stage_names: settings.stage_names,
+        temperature_curve: settings.temperature_curve
+            .map(|curve| parse_curve(curve, settings.units.unwrap_or(Units::Metric), path))
+            .transpose()?,
+        stage_colors: settings.theme.as_mut()
+            .map(|theme| std::mem::take(&mut theme.stages))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, color)| {
+                let rgb = parse_color(&color).ok_or_else(|| format!(
+                    "{}: Could not parse colour {} of stages \"{}\", expected e.g. \
+                    \"#5b9bd5\" or \"blue\"",
+                    path.display(), color, key
+                ))?;
+                Ok((key.to_lowercase(), rgb))
+            })
+            .collect::<Result<_, String>>()?,
+        theme: settings.theme.map(|theme| parse_theme(theme, path)).transpose()?
+    })
+}
+
+/// The temperature compensation curve `points` from the settings at `path`,
+/// with temperatures given in `units`.
+fn parse_curve(points: Vec<(f64, f64)>, units: Units, path: &Path) -> Result<Vec<(f64, f64)>, String> {
+    let curve: Vec<(f64, f64)> = points.into_iter()
+        .map(|(temperature, factor)| (units.to_celsius(temperature), factor))
+        .collect();
+    let ascending = curve.windows(2).all(|pair| pair[0].0 < pair[1].0);
+    let positive = curve.iter().all(|(_, factor)| *factor > 0.0 && factor.is_finite());
+    if curve.len() < 2 || !ascending || !positive {
+        return Err(format!(
+            "{}: temperature_curve needs at least two points with rising \
+            temperatures and factors above 0, e.g. [[18, 1.2], [24, 0.72]]",
+            path.display()
+        ));
+    }
+    Ok(curve)
+}
+
+/// The colours set in the `[theme]` section of the settings at `path`.
+fn parse_theme(config: ThemeConfig, path: &Path) -> Result<Theme, String> {
+    let mut theme = match config.name {
+        Some(name) => Theme::named(&name).ok_or_else(|| format!(
+            "{}: Unknown theme \"{}\", choose one of {}",
+            path.display(), name, theme::NAMES.join(", ")
+        ))?,
+        None => Theme::default()
+    };
+    let color = |color: &str| parse_color(color).ok_or_else(|| format!(
+        "{}: Could not parse theme colour {}, expected e.g. \"#5b9bd5\" or \"blue\"",
+        path.display(), color
+    ));
+    if let Some(active) = config.active {
+        theme.active = color(&active)?;
+    }
+    if let Some(inactive) = config.inactive {
+        theme.inactive = color(&inactive)?;
+    }
+    if let Some(warning) = config.warning {
+        theme.warning = color(&warning)?;
+    }
+    if let Some(background) = config.background {
+        theme.background = Some(color(&background)?);
+    }
+    if let Some(critical) = config.critical {
+        theme.critical = color(&critical)?;
+    }
+    if let Some(blink) = config.blink {
+        theme.blink_s = blink.seconds(path)?;
+    }
+    if let Some(critical_from) = config.critical_from {
+        theme.critical_s = critical_from.seconds(path)?;
+    }
+    if let Some(gradient) = config.gradient {
+        let colors = gradient.iter().map(|c| color(c)).collect::<Result<Vec<_>, _>>()?;
+        theme.gradient = colors.try_into().map_err(|_| format!(
+            "{}: The gradient needs three colours, for all, half and none of the time left",
+            path.display()
+        ))?;
+    }
+    Ok(theme)
+}
+
+/// Ask `question` on the terminal, the answer in lower case.
+pub fn prompt(question: &str) -> Result<String, String> {
+    eprint!("{} ", question);
+    io::stderr().flush().map_err(|e| e.to_string())?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).map_err(|e| e.to_string())?;
+    Ok(answer.trim().to_lowercase())
+}
+
+fn prompt_sound() -> Result<bool, String> {
+    Ok(prompt("Ring the bell when a stage ends? [Y/n]")? != "n")
+}
+
+/// The theme section for the kind of terminal the user has, if the default
+/// doesn't suit it.
+fn prompt_theme() -> Result<&'static str, String> {
+    Ok(match prompt("Is your terminal [d]ark or [l]ight? [D/l]")?.as_str() {
+        "l" => "\n[theme]\nname = \"light\"\n",
+        _ => ""
+    })
+}
+
+/// Ask a few questions on the very first run and write the initial settings
+/// file from the answers. Declining the wizard writes the commented
+/// template so the question isn't asked again.
+pub fn first_run_wizard() -> Result<(), String> {
+    let path = settings_path().ok_or("Could not find the config directory")?;
+    if path.exists() {
+        return Ok(());
+    }
+
+    let dir = path.parent().unwrap();
+    fs::create_dir_all(dir).map_err(|e| format!(
+        "Could not create config directory {}: {}", dir.display(), e
+    ))?;
+
+    eprintln!("Welcome to staged-timer! No settings were found.");
+    let settings = if prompt("Set up now? [Y/n]")? == "n" {
+        String::from(SETTINGS_TEMPLATE)
+    } else {
+        let use_case = prompt(
+            "What will you mostly time? [d]arkroom, [w]orkout or [o]ther:"
+        )?;
+        match use_case.chars().next() {
+            // chemistry keeps working while the timer is being restarted, so
+            // make accidental quits harder and warn well before the end
+            Some('d') => {
+                let units = match prompt(
+                    "Temperatures in [c]elsius or [f]ahrenheit? [C/f]"
+                )?.as_str() {
+                    "f" => "imperial",
+                    _ => "metric"
+                };
+                format!("\
+# Settings for staged-timer, applied to every run.
+
+warn = \"0:30\"
+units = \"{}\"
+sound = {}
+deviation_tolerance = \"0:05\"
+
+[confirm]
+quit = true
+{}", units, prompt_sound()?, prompt_theme()?)
+            },
+            Some('w') => format!("\
+# Settings for staged-timer, applied to every run.
+
+warn = \"0:10\"
+sound = {}
+{}", prompt_sound()?, prompt_theme()?),
+            _ => String::from(SETTINGS_TEMPLATE)
+        }
+    };
+
+    fs::write(&path, settings).map_err(|e| format!(
+        "Could not write settings file {}: {}", path.display(), e
+    ))?;
+    eprintln!(
+        "Settings were written to {}. Run `staged-timer config edit` to \
+        change them.",
+        path.display()
+    );
+    Ok(())
+}
+
+/// Load the user's settings, falling back to the defaults if there is no
+/// settings file.
+pub fn load_settings() -> Result<Settings, String> {
+    let path = match settings_path() {
+        Some(path) if path.exists() => path,
+        _ => return Ok(Settings::default())
+    };
+
+    let content = fs::read_to_string(&path).map_err(|e| format!(
+        "Could not read settings file {}: {}", path.display(), e
+    ))?;
+    parse_settings(&content, &path)
+}
+
+/// Open `path` in `$VISUAL` or `$EDITOR` and wait until it is closed.
+pub fn run_editor(path: &Path) -> Result<(), String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from(
+            if cfg!(windows) { "notepad" } else { "vi" }
+        ));
+    // allow editors with arguments such as `code --wait`
+    let mut editor_cmd = editor.split_whitespace();
+    let program = editor_cmd.next().ok_or("No editor configured")?;
+
+    let status = Command::new(program)
+        .args(editor_cmd)
+        .arg(path)
+        .status()
+        .map_err(|e| format!("Could not start editor {}: {}", editor, e))?;
+
+    if !status.success() {
+        return Err(format!("Editor {} exited with {}", editor, status));
+    }
+    Ok(())
+}
+
+/// Open the settings file in the user's editor, creating it from a template
+/// if necessary. Invalid settings are reported and can be fixed right away;
+/// if the user gives up, the previous settings are restored.
+pub fn edit_settings() -> Result<(), String> {
+    let path = settings_path().ok_or("Could not find the config directory")?;
+    let dir = path.parent().unwrap();
+    fs::create_dir_all(dir).map_err(|e| format!(
+        "Could not create config directory {}: {}", dir.display(), e
+    ))?;
+
+    let original = fs::read_to_string(&path).ok();
+    if original.is_none() {
+        fs::write(&path, SETTINGS_TEMPLATE).map_err(|e| format!(
+            "Could not write settings file {}: {}", path.display(), e
+        ))?;
+    }
+
+    loop {
+        run_editor(&path)?;
+
+        let content = fs::read_to_string(&path).map_err(|e| format!(
+            "Could not read settings file {}: {}", path.display(), e
+        ))?;
+        let error = match parse_settings(&content, &path) {
+            Ok(_) => return Ok(()),
+            Err(error) => error
+        };
+
+        eprintln!("{}", error);
+        if prompt("Edit again? [Y/n]")? == "n" {
+            let restored = match &original {
+                Some(content) => fs::write(&path, content),
+                None => fs::remove_file(&path)
+            };
+            restored.map_err(|e| format!(
+                "Could not restore settings file {}: {}", path.display(), e
+            ))?;
+            return Err(String::from("Settings were left unchanged"));
+        }
+    }
+}
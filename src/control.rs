@@ -0,0 +1,267 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, Ipv4Addr, TcpListener},
+    os::unix::{fs::PermissionsExt, net::{UnixListener, UnixStream}},
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicUsize, mpsc, Arc},
+    thread,
+    time::Duration
+};
+
+use staged_timer::parse_duration;
+
+use crate::{
+    clients::{AuthThrottle, Slot},
+    config::{Access, Permission},
+    input::{Command, Input, InputSource, StatusRequest}
+};
+#[cfg(feature = "tls")]
+use crate::tls;
+#[cfg(not(feature = "tls"))]
+use crate::integrations;
+
+/// How long a connection waits for the timer to answer `status`, which it
+/// doesn't while no run is going on.
+const STATUS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a client on the port may go without sending a line or reading
+/// an answer before it is dropped, so idle connections don't keep their
+/// threads around.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How long a line may be, newline included. Clients sending longer ones
+/// are dropped rather than have their line kept in memory.
+const MAX_LINE: usize = 1024;
+
+impl Access {
+    /// What a client connecting from `client` may do before it sends a
+    /// token: as listed, else what others may. Clients on this machine are
+    /// no exception, any user of it can connect to the port.
+    fn of(&self, client: IpAddr) -> Permission {
+        self.clients.get(&client.to_canonical()).copied().unwrap_or(self.others)
+    }
+}
+
+/// What a client asks for in one line.
+enum Request {
+    Command(Command),
+    Status,
+    Help,
+    Auth(String)
+}
+
+impl Request {
+    fn needs(&self) -> Permission {
+        match self {
+            Request::Command(_) => Permission::Control,
+            Request::Status => Permission::Read,
+            Request::Help | Request::Auth(_) => Permission::None
+        }
+    }
+}
+
+const HELP: &str = "commands: pause, resume, toggle, skip, skip-group, finish, add-time [-]TIME, \
+    status, auth TOKEN";
+
+fn parse_request(line: &str) -> Result<Request, String> {
+    let mut words = line.split_whitespace();
+    let command = match (words.next(), words.next()) {
+        (Some("pause"), None) => Command::Pause,
+        (Some("resume"), None) => Command::Resume,
+        (Some("toggle"), None) => Command::TogglePause,
+        (Some("skip"), None) => Command::Skip,
+        (Some("skip-group"), None) => Command::SkipGroup,
+        (Some("finish"), None) => Command::Finish,
+        (Some("add-time"), Some(time)) if words.next().is_none() => {
+            match time.strip_prefix('-') {
+                Some(time) => Command::Shorten(parse_duration(time)?),
+                None => Command::Extend(parse_duration(time)?)
+            }
+        },
+        (Some("status"), None) => return Ok(Request::Status),
+        (Some("help"), None) => return Ok(Request::Help),
+        (Some("auth"), Some(token)) if words.next().is_none() => {
+            return Ok(Request::Auth(token.to_string()))
+        },
+        _ => return Err(format!("unknown command \"{}\", {}", line.trim(), HELP))
+    };
+    Ok(Request::Command(command))
+}
+
+/// Answer the requests of one client connecting from `client` that starts
+/// out with `permission`, a line for each line it sends: `ok`, `error: …` or
+/// the status as JSON. Its tokens are checked when `throttle` says. A line
+/// longer than [`MAX_LINE`] ends the connection.
+fn serve(
+    stream: impl Read + Write,
+    (client, mut permission): (IpAddr, Permission),
+    access: &Access,
+    throttle: &AuthThrottle,
+    inputs: &mpsc::Sender<Input>
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    while (&mut reader).take(MAX_LINE as u64).read_line(&mut line)? > 0 {
+        if line.len() == MAX_LINE && !line.ends_with('\n') {
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            line.clear();
+            continue;
+        }
+        let answer = match parse_request(&line) {
+            Ok(request) if request.needs() > permission => format!(
+                "error: not allowed without the {} permission, send auth TOKEN for it",
+                request.needs()
+            ),
+            Ok(Request::Auth(token)) => {
+                throttle.wait(client);
+                match access.tokens.get(&token) {
+                    Some(granted) => {
+                        permission = permission.max(*granted);
+                        format!("ok, permission: {}", permission)
+                    },
+                    None => String::from("error: unknown token")
+                }
+            },
+            Ok(Request::Command(command)) => inputs.send(Input::Command(command))
+                .map(|_| String::from("ok"))
+                .unwrap_or_else(|_| String::from("error: the timer has exited")),
+            Ok(Request::Status) => {
+                let (request, status) = StatusRequest::new();
+                inputs.send(Input::Status(request)).ok()
+                    .and_then(|_| status.recv_timeout(STATUS_TIMEOUT).ok())
+                    .unwrap_or_else(|| String::from("error: no timer is running"))
+            },
+            Ok(Request::Help) => String::from(HELP),
+            Err(message) => format!("error: {}", message)
+        };
+        let stream = reader.get_mut();
+        writeln!(stream, "{}", answer)?;
+        stream.flush()?;
+        line.clear();
+    }
+    Ok(())
+}
+
+/// Takes commands for the running timer from other programs, one per line
+/// on a Unix socket or a TCP port, e.g.
+/// `echo skip | nc -U $XDG_RUNTIME_DIR/staged-timer/default.sock`.
+pub struct ControlSocket {
+    /// Takes the connections and passes their requests on, once the timer
+    /// starts taking them
+    accept: Option<Box<dyn FnOnce(mpsc::Sender<Input>) + Send>>,
+    /// Socket file to remove once the timer is done
+    path: Option<PathBuf>
+}
+
+impl ControlSocket {
+    /// Listen on a Unix socket at `path`, replacing a socket left behind by
+    /// a timer that is gone but not one that is still listening. Only the
+    /// user running the timer may connect.
+    pub fn bind(path: &Path) -> Result<ControlSocket, String> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| format!(
+                "Could not create directory {}: {}", dir.display(), e
+            ))?;
+        }
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(format!(
+                    "Another timer is listening on the control socket {}",
+                    path.display()
+                ));
+            }
+            let _ = fs::remove_file(path);
+        }
+        let listener = UnixListener::bind(path)
+            .and_then(|listener| {
+                fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+                Ok(listener)
+            })
+            .map_err(|e| format!(
+                "Could not listen on the control socket {}: {}", path.display(), e
+            ))?;
+        let access = Arc::new(Access::default());
+        let throttle = Arc::new(AuthThrottle::default());
+        let accept = move |sender: mpsc::Sender<Input>| {
+            for stream in listener.incoming().flatten() {
+                let (sender, access, throttle) =
+                    (sender.clone(), Arc::clone(&access), Arc::clone(&throttle));
+                // only the owner can connect, from this machine
+                let client = (IpAddr::V4(Ipv4Addr::LOCALHOST), Permission::Control);
+                thread::spawn(move || serve(stream, client, &access, &throttle, &sender));
+            }
+        };
+        Ok(ControlSocket { accept: Some(Box::new(accept)), path: Some(path.to_path_buf()) })
+    }
+
+    /// Listen on `port` of the address of `access`, letting clients do
+    /// what it allows them, over TLS if it has a certificate.
+    pub fn listen(port: u16, access: &Access) -> Result<ControlSocket, String> {
+        #[cfg(feature = "tls")]
+        let acceptor = access.tls.as_ref().map(tls::Acceptor::new).transpose()?.map(Arc::new);
+        #[cfg(not(feature = "tls"))]
+        if access.tls.is_some() {
+            integrations::TLS.require()?;
+        }
+        let listener = TcpListener::bind((access.address, port)).map_err(|e| format!(
+            "Could not take commands on {}:{}: {}", access.address, port, e
+        ))?;
+        let access = Arc::new(access.clone());
+        let throttle = Arc::new(AuthThrottle::default());
+        let open = Arc::new(AtomicUsize::new(0));
+        let accept = move |sender: mpsc::Sender<Input>| {
+            for stream in listener.incoming().flatten() {
+                // dropping the stream closes the connection
+                let slot = match Slot::take(&open) {
+                    Some(slot) => slot,
+                    None => continue
+                };
+                let client = match stream.peer_addr() {
+                    Ok(peer) => peer.ip().to_canonical(),
+                    Err(_) => continue
+                };
+                if stream.set_read_timeout(Some(IDLE_TIMEOUT))
+                    .and_then(|_| stream.set_write_timeout(Some(IDLE_TIMEOUT)))
+                    .is_err()
+                {
+                    continue;
+                }
+                let client = (client, access.of(client));
+                let (sender, access, throttle) =
+                    (sender.clone(), Arc::clone(&access), Arc::clone(&throttle));
+                #[cfg(feature = "tls")]
+                let acceptor = acceptor.clone();
+                // the handshake takes place on the client's thread, so a
+                // slow one doesn't hold up the others
+                thread::spawn(move || {
+                    let _slot = slot;
+                    #[cfg(feature = "tls")]
+                    if let Some(acceptor) = acceptor {
+                        return serve(acceptor.accept(stream)?, client, &access, &throttle, &sender);
+                    }
+                    serve(stream, client, &access, &throttle, &sender)
+                });
+            }
+        };
+        Ok(ControlSocket { accept: Some(Box::new(accept)), path: None })
+    }
+}
+
+impl InputSource for ControlSocket {
+    fn start(&mut self, inputs: mpsc::Sender<Input>) {
+        if let Some(accept) = self.accept.take() {
+            thread::spawn(move || accept(inputs));
+        }
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    env, fs::{self, File, OpenOptions}, io::{Read, Write}, path::{Path, PathBuf},
+    process::{Command, Stdio}, thread, time::{Duration, Instant}
+};
+#[cfg(unix)]
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{history, session::{self, SessionClaim, SessionLock}};
+
+/// Name of runs detached without a session name.
+pub const DEFAULT_NAME: &str = "default";
+
+/// How long `attach` waits for the background process to hand the timer
+/// over.
+const HANDOVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn state_path(name: &str) -> Result<PathBuf, String> {
+    Ok(session::runtime_dir()?.join(format!("{}.detached.toml", session::file_name(name))))
+}
+
+/// What the background process writes to stderr, kept next to its state.
+fn log_path(name: &str) -> Result<PathBuf, String> {
+    Ok(session::runtime_dir()?.join(format!("{}.detached.log", session::file_name(name))))
+}
+
+/// Created by `attach` to ask the background process to hand over.
+fn request_path(name: &str) -> Result<PathBuf, String> {
+    Ok(session::runtime_dir()?.join(format!("{}.attach", session::file_name(name))))
+}
+
+/// Where runs are checkpointed for `--resume`. Unlike the runtime
+/// directory, this survives a reboot.
+pub fn checkpoints_dir() -> Option<PathBuf> {
+    history::data_dir().map(|dir| dir.join("checkpoints"))
+}
+
+/// Where the run named `name` is checkpointed.
+fn checkpoint_path(name: &str) -> Result<PathBuf, String> {
+    checkpoints_dir()
+        .map(|dir| dir.join(format!("{}.toml", session::file_name(name))))
+        .ok_or_else(|| String::from("Could not find the data directory"))
+}
+
+/// Claim the lock that the background process running the timer detached
+/// as `name` holds for as long as it runs.
+pub fn claim(name: &str) -> Result<SessionClaim, String> {
+    session::claim(&format!("{}.detached", name))
+}
+
+/// Save `state` to `path`. The file is replaced in one go so it is never
+/// read half written, and only the user can read it.
+fn write<T: Serialize>(path: &Path, state: &T) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!(
+            "Could not create directory {}: {}", dir.display(), e
+        ))?;
+    }
+    let content = toml::to_string(state).map_err(|e| format!(
+        "Could not save the timer: {}", e
+    ))?;
+    let partial = path.with_extension("partial");
+    // a file left over from before may have been readable by others
+    let _ = fs::remove_file(&partial);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options.open(&partial)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .and_then(|_| fs::rename(&partial, path))
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+}
+
+/// The state saved to `path`, `None` if there is none. State saved by
+/// another user is refused, it could start any command as ours.
+fn read<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Could not read {}: {}", path.display(), e))
+    };
+    #[cfg(unix)]
+    {
+        let metadata = file.metadata()
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        // SAFETY: geteuid has no preconditions and cannot fail
+        if metadata.uid() != unsafe { libc::geteuid() } {
+            return Err(format!("{} belongs to another user, not reading it", path.display()));
+        }
+    }
+    let mut content = String::new();
+    file.read_to_string(&mut content)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    toml::from_str(&content).map(Some).map_err(|e| format!(
+        "Could not parse {}: {}", path.display(), e
+    ))
+}
+
+/// Save the `state` of the timer detached as `name`.
+pub fn save<T: Serialize>(name: &str, state: &T) -> Result<(), String> {
+    write(&state_path(name)?, state)
+}
+
+/// The saved state of the timer detached as `name`, `None` if there is
+/// none.
+pub fn load<T: DeserializeOwned>(name: &str) -> Result<Option<T>, String> {
+    read(&state_path(name)?)
+}
+
+/// Checkpoint the `state` of the run named `name`.
+pub fn save_checkpoint<T: Serialize>(name: &str, state: &T) -> Result<(), String> {
+    write(&checkpoint_path(name)?, state)
+}
+
+/// The last checkpoint of the run named `name`, `None` if there is none.
+pub fn load_checkpoint<T: DeserializeOwned>(name: &str) -> Result<Option<T>, String> {
+    read(&checkpoint_path(name)?)
+}
+
+/// Forget the checkpoint of the run named `name` once there is nothing left
+/// to resume.
+pub fn remove_checkpoint(name: &str) {
+    if let Ok(path) = checkpoint_path(name) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// What the background process of the timer detached as `name` wrote to
+/// stderr, `None` if nothing.
+pub fn read_log(name: &str) -> Option<String> {
+    fs::read_to_string(log_path(name).ok()?).ok()
+        .map(|log| log.trim_end().to_string())
+        .filter(|log| !log.is_empty())
+}
+
+/// Forget the timer detached as `name` once it has been picked up.
+pub fn remove(name: &str) {
+    for path in [state_path(name), request_path(name), log_path(name)].into_iter().flatten() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Start a background process that keeps running the timer detached as
+/// `name`, using the state saved before. Its stderr goes to a log next to
+/// the state for `attach` to show if it stops.
+pub fn spawn(name: &str) -> Result<(), String> {
+    let exe = env::current_exe().map_err(|e| format!(
+        "Could not find the staged-timer executable: {}", e
+    ))?;
+    let log_path = log_path(name)?;
+    let log = File::create(&log_path).map_err(|e| format!(
+        "Could not write {}: {}", log_path.display(), e
+    ))?;
+    let mut command = Command::new(exe);
+    command.args(["daemon", name])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(log);
+    // leave the terminal's session so closing the terminal doesn't end the
+    // timer
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // SAFETY: setsid is async-signal-safe and only affects the child
+        unsafe {
+            command.pre_exec(|| {
+                libc::setsid();
+                Ok(())
+            });
+        }
+    }
+    command.spawn().map(|_| ()).map_err(|e| format!(
+        "Could not start the background timer: {}", e
+    ))
+}
+
+/// Whether `attach` is waiting for the timer detached as `name`.
+pub fn attach_requested(name: &str) -> bool {
+    request_path(name).is_ok_and(|path| path.exists())
+}
+
+/// Take over the timer detached as `name`: ask its background process to
+/// hand over and wait for it to save the timer and exit. Returns the lock
+/// of the background process, to be held while the timer runs here.
+pub fn take_over(name: &str) -> Result<SessionLock, String> {
+    let path = request_path(name)?;
+    let started = Instant::now();
+    loop {
+        match claim(name)? {
+            SessionClaim::Claimed(lock) => {
+                let _ = fs::remove_file(&path);
+                return Ok(lock);
+            },
+            SessionClaim::Taken(_) if started.elapsed() > HANDOVER_TIMEOUT => {
+                let _ = fs::remove_file(&path);
+                return Err(format!(
+                    "The timer detached as \"{}\" did not respond", name
+                ));
+            },
+            SessionClaim::Taken(_) => {
+                if !path.exists() {
+                    fs::write(&path, "").map_err(|e| format!(
+                        "Could not write {}: {}", path.display(), e
+                    ))?;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::units::Units;
+
+/// Parse a dilution like `1+31`, one part concentrate to 31 parts water.
+pub fn parse_ratio(ratio_str: &str) -> Result<(f64, f64), String> {
+    ratio_str.split_once('+')
+        .and_then(|(a, b)| Some((a.trim().parse::<f64>().ok()?, b.trim().parse::<f64>().ok()?)))
+        .filter(|(a, b)| *a > 0.0 && *b >= 0.0)
+        .ok_or_else(|| format!(
+            "Could not parse dilution {}, expected e.g. 1+31", ratio_str
+        ))
+}
+
+/// Parse a volume like `500ml`, `0.5l` or `16oz` into millilitres. Volumes
+/// without a unit are in the default unit of `units`.
+pub fn parse_volume(volume_str: &str, units: Units) -> Result<f64, String> {
+    let lower = volume_str.trim().to_lowercase();
+    let (number, factor) = if let Some(ml) = lower.strip_suffix("ml") {
+        (ml, 1.0)
+    } else if let Some(l) = lower.strip_suffix('l') {
+        (l, 1000.0)
+    } else if let Some(oz) = lower.strip_suffix("oz") {
+        (oz.trim_end().trim_end_matches("fl"), Units::Imperial.volume_factor())
+    } else {
+        (lower.as_str(), units.volume_factor())
+    };
+
+    number.trim().parse::<f64>().ok()
+        .filter(|volume| *volume > 0.0 && volume.is_finite())
+        .map(|volume| volume * factor)
+        .ok_or_else(|| format!(
+            "Could not parse volume {}, expected e.g. 500ml, 1l or 16oz", volume_str
+        ))
+}
+
+/// Amounts of concentrate and water for `volume_ml` of working solution.
+pub fn split((concentrate, water): (f64, f64), volume_ml: f64) -> (f64, f64) {
+    let concentrate_ml = volume_ml * concentrate / (concentrate + water);
+    (concentrate_ml, volume_ml - concentrate_ml)
+}
+
+/// Temperature the water needs so that the mixed solution ends up at
+/// `target_c`, assuming concentrate and water hold about the same heat per
+/// millilitre. `None` if there is no water to temper.
+pub fn water_temperature(
+    (concentrate_ml, water_ml): (f64, f64),
+    stock_c: f64,
+    target_c: f64
+) -> Option<f64>
+{
+    if water_ml <= 0.0 {
+        return None;
+    }
+    Some((target_c * (concentrate_ml + water_ml) - stock_c * concentrate_ml) / water_ml)
+}
@@ -0,0 +1,352 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::{collections::VecDeque, thread, time::Instant};
+use std::time::Duration;
+
+use crate::{
+    events::{EventStream, TimerEvent},
+    timer::{Timer, TimerStage}
+};
+
+/// How long [`Events`] sleeps while the timer isn't counting, e.g. because
+/// it is paused.
+#[cfg(not(target_arch = "wasm32"))]
+const IDLE_WAIT: Duration = Duration::from_millis(100);
+
+/// Called with every event and the timer as it is after it.
+type Subscriber = Box<dyn FnMut(TimerEvent, &Timer) + Send>;
+
+/// Where a [`StagedTimer`] gets the time from, for platforms without
+/// [`std::time::Instant`] like the browser, or to control time in tests.
+///
+/// ```
+/// use std::{sync::{Arc, Mutex}, time::Duration};
+/// use staged_timer::{Clock, StagedTimer, TimerEvent, TimerStage};
+///
+/// struct FakeClock(Arc<Mutex<Duration>>);
+///
+/// impl Clock for FakeClock {
+///     fn now(&self) -> Duration {
+///         *self.0.lock().unwrap()
+///     }
+/// }
+///
+/// let time = Arc::new(Mutex::new(Duration::ZERO));
+/// let mut timer = StagedTimer::with_clock(vec![
+///     TimerStage::new(String::from("Developer"), Duration::from_secs(420)),
+///     TimerStage::new(String::from("Stop bath"), Duration::from_secs(60))
+/// ], FakeClock(time.clone()));
+/// *time.lock().unwrap() += Duration::from_secs(420);
+/// let events = timer.update();
+/// assert!(events.contains(&TimerEvent::StageEnd(0)));
+/// assert_eq!(timer.timer().current_index(), 1);
+/// ```
+pub trait Clock: Send {
+    /// Time since some fixed point, never going backwards.
+    fn now(&self) -> Duration;
+
+    /// How long `time` on this clock takes on the wall clock, to know how
+    /// long to sleep for.
+    fn wall_time(&self, time: Duration) -> Duration {
+        time
+    }
+}
+
+/// The monotonic clock of the system, which [`StagedTimer::new`] keeps
+/// time with.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct SystemClock(Instant);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock(Instant::now())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+/// `clock` running `speed` times as fast, to go through long timers in
+/// seconds, e.g. for a dry run.
+pub struct ScaledClock<C> {
+    clock: C,
+    speed: f64
+}
+
+impl<C: Clock> ScaledClock<C> {
+    pub fn new(clock: C, speed: f64) -> ScaledClock<C> {
+        ScaledClock { clock, speed }
+    }
+}
+
+impl<C: Clock> Clock for ScaledClock<C> {
+    fn now(&self) -> Duration {
+        self.clock.now().mul_f64(self.speed)
+    }
+
+    fn wall_time(&self, time: Duration) -> Duration {
+        self.clock.wall_time(time.div_f64(self.speed))
+    }
+}
+
+/// A [`Timer`] that keeps time by itself and tells its subscribers what
+/// happens to it, to embed the timer in other programs without keeping
+/// track of the clock and events.
+///
+/// ```
+/// use std::time::Duration;
+/// use staged_timer::{StagedTimer, TimerEvent, TimerStage};
+///
+/// let mut timer = StagedTimer::new(vec![
+///     TimerStage::new(String::from("Developer"), Duration::from_millis(200)),
+///     TimerStage::new(String::from("Stop bath"), Duration::from_millis(100))
+/// ]);
+/// timer.subscribe(|event, timer| {
+///     if let TimerEvent::StageStart(index) = event {
+///         println!("{} started", timer.stages()[index].name);
+///     }
+/// });
+/// let events: Vec<TimerEvent> = timer.events().collect();
+/// assert_eq!(events.last(), Some(&TimerEvent::Finished));
+/// ```
+pub struct StagedTimer {
+    timer: Timer,
+    stream: EventStream,
+    clock: Box<dyn Clock>,
+    last_update: Duration,
+    subscribers: Vec<Subscriber>,
+    finished: bool
+}
+
+impl StagedTimer {
+    /// A timer of `stages` that starts running right away.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new(stages: Vec<TimerStage>) -> StagedTimer {
+        StagedTimer::with_clock(stages, SystemClock::default())
+    }
+
+    /// A timer of `stages` that keeps time with `clock`, starting right
+    /// away.
+    pub fn with_clock(stages: Vec<TimerStage>, clock: impl Clock + 'static) -> StagedTimer {
+        StagedTimer {
+            timer: Timer::new(stages),
+            stream: EventStream::new(),
+            last_update: clock.now(),
+            clock: Box::new(clock),
+            subscribers: Vec::new(),
+            finished: false
+        }
+    }
+
+    /// Also tell when a stage reaches its warning threshold, `warning_s`
+    /// unless the stage has its own, see [`EventStream::with_warning`].
+    pub fn with_warning(mut self, warning_s: u32) -> StagedTimer {
+        self.stream = EventStream::with_warning(warning_s);
+        self
+    }
+
+    /// Call `callback` with every event from now on.
+    pub fn subscribe(&mut self, callback: impl FnMut(TimerEvent, &Timer) + Send + 'static) {
+        self.subscribers.push(Box::new(callback));
+    }
+
+    /// Advance the timer by the time since the last update and tell the
+    /// subscribers what happened, which is also returned. Call it as often
+    /// as the program needs to know, e.g. on every frame of a GUI.
+    pub fn update(&mut self) -> Vec<TimerEvent> {
+        let now = self.clock.now().max(self.last_update);
+        self.timer.advance(now - self.last_update);
+        self.last_update = now;
+
+        let events = self.stream.update(&self.timer);
+        for event in &events {
+            for subscriber in &mut self.subscribers {
+                subscriber(*event, &self.timer);
+            }
+        }
+        self.finished |= events.contains(&TimerEvent::Finished);
+        events
+    }
+
+    /// The events as they happen, until the timer has finished. Waits for
+    /// each of them, sleeping until the running stage reaches its next
+    /// second. Not available on wasm32, which can't wait.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn events(&mut self) -> Events<'_> {
+        Events { timer: self, pending: VecDeque::new() }
+    }
+
+    /// Run the timer to the end, telling the subscribers what happens.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn run(&mut self) {
+        self.events().for_each(drop);
+    }
+
+    pub fn timer(&self) -> &Timer {
+        &self.timer
+    }
+
+    /// The timer to control, e.g. to pause it. What that changes is told
+    /// on the next update.
+    pub fn timer_mut(&mut self) -> &mut Timer {
+        &mut self.timer
+    }
+
+    /// Whether the timer has finished and told so.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+/// Iterator over the events of a [`StagedTimer`], see
+/// [`StagedTimer::events`].
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Events<'a> {
+    timer: &'a mut StagedTimer,
+    pending: VecDeque<TimerEvent>
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Iterator for Events<'_> {
+    type Item = TimerEvent;
+
+    fn next(&mut self) -> Option<TimerEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            if self.timer.finished {
+                return None;
+            }
+            self.pending.extend(self.timer.update());
+            if self.pending.is_empty() {
+                let wait = self.timer.timer.until_next_step().map_or(IDLE_WAIT, |wait| {
+                    self.timer.clock.wall_time(wait)
+                });
+                thread::sleep(wait);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    /// A clock that only moves when the test moves it.
+    #[derive(Clone, Default)]
+    struct FakeClock(Arc<Mutex<Duration>>);
+
+    impl FakeClock {
+        fn set(&self, time: Duration) {
+            *self.0.lock().unwrap() = time;
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Duration {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn staged_timer(clock: impl Clock + 'static) -> StagedTimer {
+        StagedTimer::with_clock(vec![
+            TimerStage::new(String::from("Developer"), secs(10)),
+            TimerStage::new(String::from("Stop bath"), secs(5))
+        ], clock)
+    }
+
+    #[test]
+    fn scaled_clock_runs_faster_than_the_wall_clock() {
+        let clock = FakeClock::default();
+        let scaled = ScaledClock::new(clock.clone(), 4.0);
+        clock.set(secs(3));
+        assert_eq!(scaled.now(), secs(12));
+        assert_eq!(scaled.wall_time(secs(12)), secs(3));
+        assert_eq!(ScaledClock::new(clock, 0.5).wall_time(secs(1)), secs(2));
+    }
+
+    #[test]
+    fn timer_follows_the_clock() {
+        let clock = FakeClock::default();
+        let mut timer = staged_timer(clock.clone());
+        assert!(timer.update().contains(&TimerEvent::StageStart(0)));
+
+        clock.set(secs(4));
+        timer.update();
+        assert_eq!(timer.timer().current_stage().unwrap().elapsed, secs(4));
+
+        clock.set(secs(12));
+        let events = timer.update();
+        assert!(events.contains(&TimerEvent::StageEnd(0)), "{:?}", events);
+        assert!(events.contains(&TimerEvent::StageStart(1)), "{:?}", events);
+        assert_eq!(timer.timer().current_stage().unwrap().elapsed, secs(2));
+
+        clock.set(secs(15));
+        assert!(timer.update().contains(&TimerEvent::Finished));
+        assert!(timer.is_finished());
+    }
+
+    #[test]
+    fn scaled_clock_speeds_up_the_timer() {
+        let clock = FakeClock::default();
+        let mut timer = staged_timer(ScaledClock::new(clock.clone(), 10.0));
+        timer.update();
+        clock.set(Duration::from_millis(1500));
+        assert!(timer.update().contains(&TimerEvent::Finished));
+    }
+
+    #[test]
+    fn clock_going_backwards_is_ignored() {
+        let clock = FakeClock::default();
+        clock.set(secs(5));
+        let mut timer = staged_timer(clock.clone());
+        clock.set(secs(7));
+        timer.update();
+        clock.set(secs(6));
+        timer.update();
+        assert_eq!(timer.timer().current_stage().unwrap().elapsed, secs(2));
+        // time is counted again once the clock is past where it was
+        clock.set(secs(8));
+        timer.update();
+        assert_eq!(timer.timer().current_stage().unwrap().elapsed, secs(3));
+    }
+
+    #[test]
+    fn paused_time_is_not_counted() {
+        let clock = FakeClock::default();
+        let mut timer = staged_timer(clock.clone());
+        clock.set(secs(2));
+        timer.update();
+        timer.timer_mut().pause();
+        clock.set(secs(30));
+        timer.update();
+        timer.timer_mut().resume();
+        clock.set(secs(31));
+        timer.update();
+        assert_eq!(timer.timer().current_index(), 0);
+        assert_eq!(timer.timer().current_stage().unwrap().elapsed, secs(3));
+    }
+
+    #[test]
+    fn subscribers_hear_of_every_event() {
+        let clock = FakeClock::default();
+        let mut timer = staged_timer(clock.clone());
+        let heard = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&heard);
+        timer.subscribe(move |event, _| log.lock().unwrap().push(event));
+        clock.set(secs(20));
+        let events = timer.update();
+        assert_eq!(*heard.lock().unwrap(), events);
+    }
+}
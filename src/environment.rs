@@ -0,0 +1,153 @@
+use std::{
+    io::Read,
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant}
+};
+
+use staged_timer::Timer;
+
+use crate::history::Environment;
+
+/// How long the sensor command may take before it is killed and the stage
+/// goes without a reading.
+const SENSOR_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the sensor command is checked on while it runs.
+const SENSOR_POLL: Duration = Duration::from_millis(50);
+
+/// Read the environment from the output of a sensor command, which prints
+/// lines like `temperature=21.5` (in °C) and `humidity=48`.
+fn parse_reading(output: &str) -> Environment {
+    let mut environment = Environment::default();
+    for line in output.lines() {
+        let (key, value) = match line.split_once(['=', ':']) {
+            Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+            None => continue
+        };
+        let value = value.trim_end_matches(['%', '°', 'C']).trim().parse::<f64>().ok();
+        match key.as_str() {
+            "temperature" => environment.temperature_c = value,
+            "humidity" => environment.humidity_pct = value,
+            _ => {}
+        }
+    }
+    environment
+}
+
+/// Run the sensor command and read its output, giving up on it after
+/// [`SENSOR_TIMEOUT`].
+fn read_sensor(command: &str) -> Option<Environment> {
+    let deadline = Instant::now() + SENSOR_TIMEOUT;
+    let mut command_parts = command.split_whitespace();
+    let mut command = Command::new(command_parts.next()?);
+    command.args(command_parts)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    // a group of its own, so whatever the command starts goes down with it
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = command.spawn().ok()?;
+    // the output is read on the side, so a sensor that hangs without
+    // writing anything can still be killed
+    let mut stdout = child.stdout.take()?;
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = String::new();
+        let _ = sender.send(stdout.read_to_string(&mut output).map(|_| output));
+    });
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(SENSOR_POLL),
+            _ => {
+                #[cfg(unix)]
+                if let Ok(group) = libc::pid_t::try_from(child.id()) {
+                    // SAFETY: signals the process group led by our own child
+                    unsafe { libc::kill(-group, libc::SIGKILL); }
+                }
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    };
+    if !status.success() {
+        return None;
+    }
+    // whatever the command started may still hold on to its output
+    let output = receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .ok()?
+        .ok()?;
+    Some(parse_reading(&output)).filter(|environment| !environment.is_empty())
+}
+
+/// Reads the sensor command at the start of every stage. Readings are taken
+/// in the background so a slow sensor doesn't hold up the timer.
+pub struct Capture {
+    command: Option<String>,
+    stage: Option<usize>,
+    readings: Vec<(usize, JoinHandle<Option<Environment>>)>,
+    /// Readings that are in already, by stage
+    taken: Vec<Option<Environment>>
+}
+
+impl Capture {
+    pub fn new(command: Option<String>) -> Capture {
+        Capture { command, stage: None, readings: Vec::new(), taken: Vec::new() }
+    }
+
+    /// Take up a timer that is already running with the readings `taken`
+    /// so far. The sensor is only read for the running stage if its reading
+    /// wasn't in yet.
+    pub fn resume(&mut self, timer: &Timer, taken: Vec<Option<Environment>>) {
+        let stage = timer.current_index();
+        self.stage = taken.get(stage).copied().flatten().map(|_| stage);
+        self.taken = taken;
+    }
+
+    pub fn update(&mut self, timer: &Timer) {
+        let command = match self.command.as_ref() {
+            Some(command) if !timer.is_finished() => command,
+            _ => return
+        };
+        if self.stage != Some(timer.current_index()) {
+            self.stage = Some(timer.current_index());
+            let command = command.clone();
+            self.readings.push((
+                timer.current_index(),
+                thread::spawn(move || read_sensor(&command))
+            ));
+        }
+    }
+
+    /// Move the readings that are in to `taken`, waiting for the
+    /// outstanding ones if `wait`.
+    fn gather(&mut self, stage_count: usize, wait: bool) {
+        self.taken.resize(stage_count, None);
+        let (done, pending) = self.readings.drain(..)
+            .partition(|(_, reading)| wait || reading.is_finished());
+        self.readings = pending;
+        for (stage, reading) in done {
+            if let Some(environment) = self.taken.get_mut(stage) {
+                *environment = reading.join().ok().flatten();
+            }
+        }
+    }
+
+    /// Wait for outstanding readings and return the reading of each of the
+    /// `stage_count` stages.
+    pub fn collect(&mut self, stage_count: usize) -> Vec<Option<Environment>> {
+        self.gather(stage_count, true);
+        self.taken.clone()
+    }
+
+    /// The readings of the `stage_count` stages that are in so far, without
+    /// waiting for a slow sensor.
+    pub fn collected(&mut self, stage_count: usize) -> Vec<Option<Environment>> {
+        self.gather(stage_count, false);
+        self.taken.clone()
+    }
+}
@@ -0,0 +1,36 @@
+use std::{fmt, io};
+
+/// Why a run ended early. It is returned up to `main` instead of exiting on
+/// the spot, so the terminal, the session name and the sleep inhibitor are
+/// all released before it is printed.
+#[derive(Debug)]
+pub enum RunError {
+    /// Drawing on or reading from the terminal failed
+    Io(io::Error),
+    /// What went wrong, to be printed as is
+    Message(String),
+    /// Nothing to print, the user was told already or said no
+    Quiet
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::Io(e) => write!(f, "Could not use the terminal: {}", e),
+            RunError::Message(message) => f.write_str(message),
+            RunError::Quiet => Ok(())
+        }
+    }
+}
+
+impl From<io::Error> for RunError {
+    fn from(e: io::Error) -> RunError {
+        RunError::Io(e)
+    }
+}
+
+impl From<String> for RunError {
+    fn from(message: String) -> RunError {
+        RunError::Message(message)
+    }
+}
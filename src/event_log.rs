@@ -0,0 +1,105 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path
+};
+
+use chrono::{Local, SecondsFormat};
+use serde_json::Value;
+
+use staged_timer::{EventStream, Timer, TimerEvent};
+
+use crate::schema::{self, Adjustment, Event, LogEntry, StageRef};
+
+/// Columns of an event log in CSV, named like the fields of the JSON.
+const CSV_COLUMNS: [&str; 9] = [
+    "time", "event", "stage", "index", "count", "time_s", "adjustment", "by_s", "aborted"
+];
+
+/// Appends what happens to the timer, and when, to a file given with
+/// `--log`: CSV if its name ends in `.csv`, lines of JSON otherwise.
+pub struct EventLog {
+    file: File,
+    csv: bool,
+    stream: EventStream,
+    started: bool
+}
+
+impl EventLog {
+    /// Open `path` to append to, starting a CSV file with its header.
+    pub fn open(path: &Path) -> Result<EventLog, String> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|e| format!("Could not open event log {}: {}", path.display(), e))?;
+        let csv = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+        let empty = file.metadata().is_ok_and(|metadata| metadata.len() == 0);
+        if csv && empty {
+            writeln!(file, "{}", CSV_COLUMNS.join(","))
+                .map_err(|e| format!("Could not write event log {}: {}", path.display(), e))?;
+        }
+        Ok(EventLog { file, csv, stream: EventStream::new(), started: false })
+    }
+
+    /// Log what happened to `timer` since the last update.
+    pub fn update(&mut self, timer: &Timer) {
+        if !self.started {
+            self.started = true;
+            self.write(Event::Started { count: timer.stages().len() });
+        }
+        for event in self.stream.update(timer) {
+            if !matches!(event, TimerEvent::Tick | TimerEvent::Warning(_) | TimerEvent::Countdown(_)) {
+                self.write(Event::of(event, timer));
+            }
+        }
+    }
+
+    /// Log that the user is about to change the running stage, by `by_s`
+    /// seconds for a stage made longer or shorter.
+    pub fn adjusted(&mut self, timer: &Timer, adjustment: Adjustment, by_s: Option<u32>) {
+        if timer.current_stage().is_some() {
+            let stage = StageRef::of(timer, timer.current_index());
+            self.write(Event::Adjusted { stage, adjustment, by_s });
+        }
+    }
+
+    /// Take up a timer that is already running, without logging its start
+    /// again.
+    pub fn resume(&mut self, timer: &Timer) {
+        self.started = true;
+        self.stream.update(timer);
+    }
+
+    /// Log whether the run was quit before it was done.
+    pub fn finish(&mut self, timer: &Timer) {
+        self.update(timer);
+        if !timer.is_finished() && !timer.is_aborted() {
+            let stage = timer.current_stage().map(|_| StageRef::of(timer, timer.current_index()));
+            self.write(Event::Quit { stage });
+        }
+    }
+
+    fn write(&mut self, event: Event) {
+        let entry = LogEntry {
+            time: Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+            event
+        };
+        let line = if self.csv { csv_row(&entry) } else { schema::line(&entry) };
+        // a log that can't be written mustn't stop the timer
+        let _ = writeln!(self.file, "{}", line);
+    }
+}
+
+/// `entry` as a row of [`CSV_COLUMNS`], empty where it has no such field.
+fn csv_row(entry: &LogEntry) -> String {
+    let fields = serde_json::to_value(entry).unwrap_or_default();
+    CSV_COLUMNS.iter()
+        .map(|column| match &fields[column] {
+            Value::Null => String::new(),
+            Value::String(text) if text.contains([',', '"', '\n']) => {
+                format!("\"{}\"", text.replace('"', "\"\""))
+            },
+            Value::String(text) => text.clone(),
+            value => value.to_string()
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
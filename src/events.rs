@@ -0,0 +1,114 @@
+use crate::timer::Timer;
+
+/// Something that happened to a [`Timer`], for a frontend to show or pass
+/// on. Stages are given by their position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerEvent {
+    /// The running stage reached a whole second
+    Tick,
+    StageStart(usize),
+    /// The stage ended, whether it ran to the end or not
+    StageEnd(usize),
+    /// The running stage reached its warning threshold, see
+    /// [`EventStream::with_warning`]
+    Warning(usize),
+    /// The running stage reached one of its countdown cues, see
+    /// [`TimerStage::countdown`](crate::TimerStage::countdown)
+    Countdown(usize),
+    Paused,
+    Resumed,
+    /// All stages are done or the timer was aborted
+    Finished
+}
+
+/// Follows a timer and tells what happened to it since it was last looked
+/// at, so every frontend sees the same events whatever changed the timer.
+#[derive(Default)]
+pub struct EventStream {
+    stage: Option<usize>,
+    elapsed_s: u32,
+    remaining_s: u32,
+    paused: bool,
+    finished: bool,
+    /// Warning threshold of stages without their own
+    warning_s: u32
+}
+
+impl EventStream {
+    pub fn new() -> EventStream {
+        EventStream::default()
+    }
+
+    /// Also tell when a stage has `warning_s` left, or as much as its own
+    /// warning threshold says. Stages no longer than their threshold and
+    /// stages counting up don't warn.
+    pub fn with_warning(warning_s: u32) -> EventStream {
+        EventStream { warning_s, ..EventStream::default() }
+    }
+
+    /// The events since the last call, in the order they happened. Stages
+    /// that were passed over in between, e.g. by a long
+    /// [`Timer::advance`], start and end as well.
+    pub fn update(&mut self, timer: &Timer) -> Vec<TimerEvent> {
+        let mut events = Vec::new();
+        if self.finished {
+            return events;
+        }
+
+        let stage = timer.current_index();
+        let elapsed_s = timer.current_stage().map_or(0, |stage| stage.elapsed_s());
+        let remaining_s = timer.current_stage().map_or(0, |stage| stage.remaining_s());
+        match self.stage {
+            Some(previous) if previous == stage => {
+                if elapsed_s != self.elapsed_s {
+                    events.push(TimerEvent::Tick);
+                }
+                let threshold_s = timer.current_stage()
+                    .filter(|running| !running.count_up)
+                    .map(|running| (running.warn_s.unwrap_or(self.warning_s), running.period_s()))
+                    .filter(|(threshold_s, period_s)| *threshold_s > 0 && threshold_s < period_s)
+                    .map(|(threshold_s, _)| threshold_s);
+                if threshold_s.is_some_and(|threshold_s| {
+                    self.remaining_s > threshold_s && remaining_s <= threshold_s
+                }) {
+                    events.push(TimerEvent::Warning(stage));
+                }
+                if timer.current_stage().is_some_and(|running| {
+                    running.countdown_s()
+                        .any(|cue_s| self.remaining_s > cue_s && remaining_s <= cue_s)
+                }) {
+                    events.push(TimerEvent::Countdown(stage));
+                }
+            },
+            previous => {
+                if let Some(previous) = previous {
+                    events.push(TimerEvent::StageEnd(previous));
+                }
+                let passed = previous.map_or(0, |previous| previous + 1)..stage;
+                for index in passed.filter(|index| !timer.stages()[*index].is_marker()) {
+                    events.extend([TimerEvent::StageStart(index), TimerEvent::StageEnd(index)]);
+                }
+                if !timer.is_finished() {
+                    events.push(TimerEvent::StageStart(stage));
+                }
+            }
+        }
+        self.stage = Some(stage);
+        self.elapsed_s = elapsed_s;
+        self.remaining_s = remaining_s;
+
+        if timer.is_paused() != self.paused {
+            self.paused = timer.is_paused();
+            events.push(if self.paused { TimerEvent::Paused } else { TimerEvent::Resumed });
+        }
+
+        if timer.is_aborted() {
+            events.push(TimerEvent::StageEnd(stage));
+        }
+        if timer.is_finished() || timer.is_aborted() {
+            self.finished = true;
+            events.push(TimerEvent::Finished);
+        }
+        events
+    }
+}
@@ -0,0 +1,188 @@
+use std::{path::PathBuf, time::Duration};
+
+use serde::Serialize;
+
+use staged_timer::{CountdownCue, TimerStage};
+
+/// What `staged-timer export` prints the stages as.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Json,
+    Toml
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<ExportFormat> {
+        match name {
+            "json" => Some(ExportFormat::Json),
+            "toml" => Some(ExportFormat::Toml),
+            _ => None
+        }
+    }
+}
+
+/// The stages of a run the way a config file has them, so the TOML can be
+/// saved as a preset as it is.
+#[derive(Serialize)]
+struct ExportFile {
+    #[serde(rename = "stage")]
+    stages: Vec<ExportedStage>
+}
+
+/// A time as in a config file: whole or fractional seconds, or text such as
+/// `"open"` and `"50%"`.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ExportedTime {
+    Seconds(u64),
+    Fractional(f64),
+    Text(String)
+}
+
+impl From<Duration> for ExportedTime {
+    fn from(duration: Duration) -> ExportedTime {
+        if duration.subsec_nanos() == 0 {
+            ExportedTime::Seconds(duration.as_secs())
+        } else {
+            ExportedTime::Fractional(duration.as_secs_f64())
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExportedAgitation {
+    initial: u32,
+    every: u32,
+    #[serde(rename = "for")]
+    length: u32
+}
+
+#[derive(Serialize)]
+struct ExportedPhase {
+    name: String,
+    time: ExportedTime
+}
+
+#[derive(Serialize)]
+struct ExportedCheckpoint {
+    at: ExportedTime,
+    message: String
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+#[derive(Serialize)]
+struct ExportedStage {
+    name: String,
+    time: ExportedTime,
+    #[serde(skip_serializing_if = "is_true")]
+    enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zone: Option<u8>,
+    #[serde(skip_serializing_if = "is_false")]
+    minute_chime: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exercise: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audio: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pace: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warn: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    countdown: Vec<ExportedTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    on_start: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    on_end: Option<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    runs_while_paused: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    overtime: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    manual_advance: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    agitation: Option<ExportedAgitation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    after: Option<ExportedPhase>,
+    #[serde(rename = "checkpoint", skip_serializing_if = "Vec::is_empty")]
+    checkpoints: Vec<ExportedCheckpoint>
+}
+
+impl ExportedStage {
+    fn new(stage: &TimerStage, enabled: bool) -> ExportedStage {
+        // a config file gives the part at the end on top of the stage's time
+        let after_period = stage.after.as_ref().map_or(Duration::ZERO, |after| after.period);
+        let time = if stage.count_up {
+            ExportedTime::Text(String::from("open"))
+        } else {
+            ExportedTime::from(stage.period.saturating_sub(after_period))
+        };
+        ExportedStage {
+            name: stage.name.clone(),
+            time,
+            enabled,
+            group: stage.group.clone(),
+            zone: stage.zone,
+            minute_chime: stage.minute_chime,
+            note: stage.note.clone(),
+            exercise: stage.exercise.as_ref().map(|exercise| exercise.name.clone()),
+            audio: stage.audio.clone(),
+            pace: stage.pace_cpm,
+            warn: stage.warn_s,
+            countdown: stage.countdown.iter().map(|cue| match *cue {
+                CountdownCue::Remaining(remaining_s) => ExportedTime::Seconds(remaining_s.into()),
+                CountdownCue::Percent(percent) => ExportedTime::Text(format!("{}%", percent))
+            }).collect(),
+            color: stage.color.map(|[r, g, b]| format!("#{:02x}{:02x}{:02x}", r, g, b)),
+            on_start: stage.on_start.clone(),
+            on_end: stage.on_end.clone(),
+            runs_while_paused: stage.runs_while_paused,
+            overtime: stage.overtime,
+            manual_advance: stage.manual_advance,
+            agitation: stage.agitation.map(|agitation| ExportedAgitation {
+                initial: agitation.initial_s,
+                every: agitation.every_s,
+                length: agitation.for_s
+            }),
+            after: stage.after.as_ref().map(|after| ExportedPhase {
+                name: after.name.clone(),
+                time: ExportedTime::from(after.period)
+            }),
+            checkpoints: stage.checkpoints.iter().map(|checkpoint| ExportedCheckpoint {
+                at: match checkpoint.percent {
+                    Some(percent) => ExportedTime::Text(format!("{}%", percent)),
+                    None => ExportedTime::Seconds(checkpoint.at_s.into())
+                },
+                message: checkpoint.message.clone()
+            }).collect()
+        }
+    }
+}
+
+/// `stages`, each with whether it is enabled, in `format`.
+pub fn export<'a>(
+    stages: impl Iterator<Item = (&'a TimerStage, bool)>,
+    format: ExportFormat
+) -> Result<String, String> {
+    let file = ExportFile {
+        stages: stages.map(|(stage, enabled)| ExportedStage::new(stage, enabled)).collect()
+    };
+    match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&file).map_err(|e| e.to_string()),
+        ExportFormat::Toml => toml::to_string(&file).map_err(|e| e.to_string())
+    }
+    .map_err(|e| format!("Could not export the stages: {}", e))
+}
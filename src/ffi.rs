@@ -0,0 +1,288 @@
+//! C interface to the engine, built with the `ffi` feature. The header is
+//! `include/staged_timer.h`. Timers are created with [`st_timer_new`],
+//! advanced by the caller with [`st_timer_advance`] or [`st_timer_tick`]
+//! and freed with [`st_timer_free`]. Events are passed to the callback set
+//! with [`st_timer_set_callback`] while the timer is advanced or controlled.
+
+use std::{
+    ffi::{c_char, c_void, CStr, CString},
+    ptr,
+    time::Duration
+};
+
+use crate::{EventStream, Timer, TimerEvent, TimerStage};
+
+pub const ST_EVENT_TICK: u32 = 0;
+pub const ST_EVENT_STAGE_START: u32 = 1;
+pub const ST_EVENT_STAGE_END: u32 = 2;
+pub const ST_EVENT_WARNING: u32 = 3;
+pub const ST_EVENT_PAUSED: u32 = 4;
+pub const ST_EVENT_RESUMED: u32 = 5;
+pub const ST_EVENT_FINISHED: u32 = 6;
+pub const ST_EVENT_COUNTDOWN: u32 = 7;
+
+/// Called with the `user_data` it was set with, one of the `ST_EVENT_`
+/// codes and the stage the event is about, or the running stage for events
+/// that aren't about one.
+pub type StCallback = extern "C" fn(user_data: *mut c_void, event: u32, stage: usize);
+
+/// A timer and what C callers need of it.
+pub struct StTimer {
+    timer: Timer,
+    events: EventStream,
+    /// Stage names handed out by [`st_timer_stage_name`]
+    names: Vec<CString>,
+    callback: Option<(StCallback, *mut c_void)>
+}
+
+impl StTimer {
+    /// What happened since the callback was last told, as `ST_EVENT_` codes
+    /// and the stages they are about.
+    fn events(&mut self) -> Vec<(u32, usize)> {
+        let running = self.timer.current_index();
+        self.events.update(&self.timer).into_iter().map(|event| match event {
+            TimerEvent::Tick => (ST_EVENT_TICK, running),
+            TimerEvent::StageStart(stage) => (ST_EVENT_STAGE_START, stage),
+            TimerEvent::StageEnd(stage) => (ST_EVENT_STAGE_END, stage),
+            TimerEvent::Warning(stage) => (ST_EVENT_WARNING, stage),
+            TimerEvent::Countdown(stage) => (ST_EVENT_COUNTDOWN, stage),
+            TimerEvent::Paused => (ST_EVENT_PAUSED, running),
+            TimerEvent::Resumed => (ST_EVENT_RESUMED, running),
+            TimerEvent::Finished => (ST_EVENT_FINISHED, running)
+        }).collect()
+    }
+}
+
+/// Pass `events` to the callback of `timer`. No reference to the timer is
+/// held while the callback runs, so it can query the timer.
+///
+/// # Safety
+///
+/// `timer` must be a live timer from [`st_timer_new`].
+unsafe fn notify(timer: *mut StTimer, events: Vec<(u32, usize)>) {
+    if let Some((callback, user_data)) = (*timer).callback {
+        for (code, stage) in events {
+            callback(user_data, code, stage);
+        }
+    }
+}
+
+/// Create a timer of `count` stages named `names`, UTF-8 and NUL
+/// terminated, lasting `seconds` each. Stages within `warning_s` of their
+/// end get an `ST_EVENT_WARNING`, 0 for none. Returns NULL if a name isn't
+/// valid UTF-8 or a duration is negative or not a number.
+///
+/// # Safety
+///
+/// `names` and `seconds` must point to `count` elements each, and every
+/// name to a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn st_timer_new(
+    names: *const *const c_char,
+    seconds: *const f64,
+    count: usize,
+    warning_s: u32
+) -> *mut StTimer {
+    if count > 0 && (names.is_null() || seconds.is_null()) {
+        return ptr::null_mut();
+    }
+    let mut stages = Vec::with_capacity(count);
+    let mut c_names = Vec::with_capacity(count);
+    for i in 0..count {
+        let name = match CStr::from_ptr(*names.add(i)).to_str() {
+            Ok(name) => name,
+            Err(_) => return ptr::null_mut()
+        };
+        let period = match Duration::try_from_secs_f64(*seconds.add(i)) {
+            Ok(period) => period,
+            Err(_) => return ptr::null_mut()
+        };
+        stages.push(TimerStage::new(name.to_string(), period));
+        c_names.push(CStr::from_ptr(*names.add(i)).to_owned());
+    }
+    let mut timer = StTimer {
+        timer: Timer::new(stages),
+        events: EventStream::with_warning(warning_s),
+        names: c_names,
+        callback: None
+    };
+    // the first stage starts right away, but there is nobody to tell yet
+    timer.events.update(&timer.timer);
+    Box::into_raw(Box::new(timer))
+}
+
+/// Free a timer created with [`st_timer_new`]. Does nothing with NULL.
+///
+/// # Safety
+///
+/// `timer` must be NULL or a timer from [`st_timer_new`] that wasn't freed
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn st_timer_free(timer: *mut StTimer) {
+    if !timer.is_null() {
+        drop(Box::from_raw(timer));
+    }
+}
+
+/// Call `callback` with `user_data` for every event from now on, or stop
+/// calling back with NULL. `user_data` is passed on as it is. The callback
+/// may query the timer, but must not advance, control or free it.
+///
+/// # Safety
+///
+/// `timer` must be a live timer from [`st_timer_new`].
+#[no_mangle]
+pub unsafe extern "C" fn st_timer_set_callback(
+    timer: *mut StTimer,
+    callback: Option<StCallback>,
+    user_data: *mut c_void
+) {
+    if let Some(timer) = timer.as_mut() {
+        timer.callback = callback.map(|callback| (callback, user_data));
+    }
+}
+
+/// Advance the timer by `ms` milliseconds that have passed, calling back
+/// with what happened. Returns how many times the running stage reached a
+/// whole second or its end.
+///
+/// # Safety
+///
+/// `timer` must be a live timer from [`st_timer_new`].
+#[no_mangle]
+pub unsafe extern "C" fn st_timer_advance(timer: *mut StTimer, ms: u64) -> u32 {
+    let (steps, events) = match timer.as_mut() {
+        Some(st) => (st.timer.advance(Duration::from_millis(ms)), st.events()),
+        None => return 0
+    };
+    notify(timer, events);
+    steps
+}
+
+/// Advance the timer by one second, see [`Timer::tick`]. Returns false once
+/// it has finished.
+///
+/// # Safety
+///
+/// `timer` must be a live timer from [`st_timer_new`].
+#[no_mangle]
+pub unsafe extern "C" fn st_timer_tick(timer: *mut StTimer) -> bool {
+    let (running, events) = match timer.as_mut() {
+        Some(st) => (st.timer.tick(), st.events()),
+        None => return false
+    };
+    notify(timer, events);
+    running
+}
+
+/// Pause the timer, or resume it with `paused` false.
+///
+/// # Safety
+///
+/// `timer` must be a live timer from [`st_timer_new`].
+#[no_mangle]
+pub unsafe extern "C" fn st_timer_set_paused(timer: *mut StTimer, paused: bool) {
+    let events = match timer.as_mut() {
+        Some(st) => {
+            if paused {
+                st.timer.pause();
+            } else {
+                st.timer.resume();
+            }
+            st.events()
+        }
+        None => return
+    };
+    notify(timer, events);
+}
+
+/// Move on to the next stage without finishing the running one.
+///
+/// # Safety
+///
+/// `timer` must be a live timer from [`st_timer_new`].
+#[no_mangle]
+pub unsafe extern "C" fn st_timer_skip_stage(timer: *mut StTimer) {
+    let events = match timer.as_mut() {
+        Some(st) => {
+            st.timer.skip_stage();
+            st.events()
+        }
+        None => return
+    };
+    notify(timer, events);
+}
+
+/// Position of the running stage, or the number of stages once the timer
+/// has finished.
+///
+/// # Safety
+///
+/// `timer` must be a live timer from [`st_timer_new`].
+#[no_mangle]
+pub unsafe extern "C" fn st_timer_current_stage(timer: *const StTimer) -> usize {
+    timer.as_ref().map_or(0, |timer| timer.timer.current_index())
+}
+
+/// # Safety
+///
+/// `timer` must be a live timer from [`st_timer_new`].
+#[no_mangle]
+pub unsafe extern "C" fn st_timer_stage_count(timer: *const StTimer) -> usize {
+    timer.as_ref().map_or(0, |timer| timer.timer.stages().len())
+}
+
+/// Name of the stage at `index`, NULL if there is none. The name lives as
+/// long as the timer.
+///
+/// # Safety
+///
+/// `timer` must be a live timer from [`st_timer_new`].
+#[no_mangle]
+pub unsafe extern "C" fn st_timer_stage_name(timer: *const StTimer, index: usize) -> *const c_char {
+    timer.as_ref()
+        .and_then(|timer| timer.names.get(index))
+        .map_or(ptr::null(), |name| name.as_ptr())
+}
+
+/// Whole seconds the running stage has run for, 0 once the timer has
+/// finished.
+///
+/// # Safety
+///
+/// `timer` must be a live timer from [`st_timer_new`].
+#[no_mangle]
+pub unsafe extern "C" fn st_timer_elapsed_s(timer: *const StTimer) -> u32 {
+    timer.as_ref()
+        .and_then(|timer| timer.timer.current_stage())
+        .map_or(0, TimerStage::elapsed_s)
+}
+
+/// Seconds left of the running stage, rounded up, 0 once the timer has
+/// finished.
+///
+/// # Safety
+///
+/// `timer` must be a live timer from [`st_timer_new`].
+#[no_mangle]
+pub unsafe extern "C" fn st_timer_remaining_s(timer: *const StTimer) -> u32 {
+    timer.as_ref()
+        .and_then(|timer| timer.timer.current_stage())
+        .map_or(0, TimerStage::remaining_s)
+}
+
+/// # Safety
+///
+/// `timer` must be a live timer from [`st_timer_new`].
+#[no_mangle]
+pub unsafe extern "C" fn st_timer_is_paused(timer: *const StTimer) -> bool {
+    timer.as_ref().is_some_and(|timer| timer.timer.is_paused())
+}
+
+/// # Safety
+///
+/// `timer` must be a live timer from [`st_timer_new`].
+#[no_mangle]
+pub unsafe extern "C" fn st_timer_is_finished(timer: *const StTimer) -> bool {
+    timer.as_ref().is_none_or(|timer| timer.timer.is_finished())
+}
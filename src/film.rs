@@ -0,0 +1,157 @@
+use std::{fs, io, path::PathBuf};
+use chrono::DateTime;
+use serde::Deserialize;
+
+use staged_timer::format_seconds;
+
+use crate::{config::config_dir, history::{self, Environment, SessionRecord}, units::Units};
+
+/// A roll of film from the registry that development runs can be logged
+/// against.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Roll {
+    pub id: String,
+    /// Film stock, e.g. "HP5 Plus"
+    pub stock: String,
+    /// Exposure index the roll was shot at
+    pub ei: Option<u32>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RollRegistryFile {
+    #[serde(default, rename = "roll")]
+    rolls: Vec<Roll>
+}
+
+pub fn registry_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("rolls.toml"))
+}
+
+/// Load the roll registry of the form
+///
+/// ```toml
+/// [[roll]]
+/// id = "2024-017"
+/// stock = "HP5 Plus"
+/// ei = 800 # optional
+/// ```
+///
+/// A missing registry has no rolls.
+pub fn load_rolls() -> Result<Vec<Roll>, String> {
+    let path = match registry_path() {
+        Some(path) => path,
+        None => return Ok(Vec::new())
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!(
+            "Could not read roll registry {}: {}", path.display(), e
+        ))
+    };
+    let registry: RollRegistryFile = toml::from_str(&content).map_err(|e| format!(
+        "Could not parse roll registry {}: {}", path.display(), e
+    ))?;
+
+    Ok(registry.rolls)
+}
+
+fn describe(roll: &Roll) -> String {
+    match roll.ei {
+        Some(ei) => format!("{} @ EI {}", roll.stock, ei),
+        None => roll.stock.clone()
+    }
+}
+
+/// The runs that developed the roll `id` along with their session IDs.
+fn developments<'a>(
+    history: &'a [SessionRecord],
+    id: &'a str
+) -> impl Iterator<Item = (usize, &'a SessionRecord)>
+{
+    history.iter()
+        .enumerate()
+        .filter(move |(_, session)| session.roll.as_deref() == Some(id))
+        .map(|(index, session)| (index + 1, session))
+}
+
+fn describe_environment(environment: &Environment, units: Units) -> String {
+    let mut parts = Vec::new();
+    if let Some(temperature_c) = environment.temperature_c {
+        parts.push(units.format_temperature(temperature_c));
+    }
+    if let Some(humidity_pct) = environment.humidity_pct {
+        parts.push(format!("{:.0}% humidity", humidity_pct));
+    }
+    parts.join(", ")
+}
+
+/// Print the registered rolls, or the development log of the roll `id`.
+pub fn print_log(id: Option<&str>, units: Units) -> Result<(), String> {
+    let rolls = load_rolls()?;
+    let history = history::load()?;
+
+    let id = match id {
+        Some(id) => id,
+        None => {
+            if rolls.is_empty() {
+                println!("No rolls are registered.");
+            }
+            for roll in rolls.iter() {
+                println!(
+                    "{}: {} ({} developments)",
+                    roll.id, describe(roll), developments(&history, &roll.id).count()
+                );
+            }
+            return Ok(());
+        }
+    };
+
+    let roll = rolls.iter().find(|roll| roll.id == id)
+        .ok_or_else(|| format!("Roll \"{}\" is not registered.", id))?;
+    println!("{}: {}", roll.id, describe(roll));
+    for (session_id, session) in developments(&history, id) {
+        let started = DateTime::parse_from_rfc3339(&session.started)
+            .map(|started| started.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|_| session.started.clone());
+        let stages: Vec<String> = session.stages.iter()
+            .map(|stage| match stage.actual_s.filter(|_| stage.deviated) {
+                _ if stage.aborted => format!(
+                    "{} {} (ABORTED)",
+                    stage.name, format_seconds(stage.period_s)
+                ),
+                Some(actual_s) => format!(
+                    "{} {} (DEVIATED: took {})",
+                    stage.name, format_seconds(stage.period_s), format_seconds(actual_s)
+                ),
+                None => format!("{} {}", stage.name, format_seconds(stage.period_s))
+            })
+            .collect();
+        println!(
+            "  #{} {}{}  {}",
+            session_id,
+            started,
+            match (session.completed, session.aborted) {
+                (_, true) => " (ABORTED)",
+                (true, false) => "",
+                (false, false) => " (stopped early)"
+            },
+            stages.join(", ")
+        );
+        if let Some(environment) = session.environment.as_ref() {
+            println!("    Ambient: {}", describe_environment(environment, units));
+        }
+        for stage in session.stages.iter() {
+            if let Some(environment) = stage.environment.as_ref() {
+                println!("    {}: {}", stage.name, describe_environment(environment, units));
+            }
+        }
+        for note in session.notes.iter() {
+            println!("    {}", note.replace('\n', "\n    "));
+        }
+    }
+
+    Ok(())
+}
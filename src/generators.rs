@@ -0,0 +1,549 @@
+use std::time::Duration;
+
+use staged_timer::{parse_duration, parse_time, CountdownCue, Phase};
+
+use crate::{config::ConfiguredStage, history::SessionRecord};
+
+/// How stages that are generated or repeated are named, with `{phase}`
+/// replaced by what the stage is, e.g. "Work", and `{round}` and `{rounds}`
+/// by the round it is in and how many there are.
+pub const DEFAULT_STAGE_NAMES: &str = "{phase} {round}/{rounds}";
+
+/// The name of the `phase` of a stage in `round` of `rounds` as `template`
+/// has it, see [`DEFAULT_STAGE_NAMES`].
+fn round_name(template: &str, phase: &str, round: usize, rounds: usize) -> String {
+    // the phase last, so braces in the names of stages are left as they are
+    template.replace("{rounds}", &rounds.to_string())
+        .replace("{round}", &round.to_string())
+        .replace("{phase}", phase)
+}
+
+/// Repeated stages and interval specs make at most this many stages, so a
+/// slip in a number of rounds doesn't take all memory.
+pub const MAX_STAGES: usize = 10_000;
+
+/// Generated work stages are tagged `work` and rests `rest` so cue rules
+/// can refer to them.
+fn stage(name: String, period_s: u32, tag: &str) -> ConfiguredStage {
+    ConfiguredStage {
+        name: Some(name),
+        period: Duration::from_secs(period_s.into()),
+        tags: vec![tag.to_string()],
+        ..ConfiguredStage::default()
+    }
+}
+
+fn rest(name: String, period_s: u32) -> ConfiguredStage {
+    ConfiguredStage {
+        rest: true,
+        ..stage(name, period_s, "rest")
+    }
+}
+
+/// Generate `rounds` pairs of work and rest stages where every rest lasts
+/// `rest_part / work_part` times as long as the work before it. There is no
+/// rest after the last round. Stages are named after `names`.
+pub fn work_rest(
+    work_s: u32,
+    (work_part, rest_part): (u32, u32),
+    rounds: u32,
+    names: &str
+) -> Vec<ConfiguredStage>
+{
+    let rest_s = (u64::from(work_s) * u64::from(rest_part)
+        + u64::from(work_part) / 2)
+        / u64::from(work_part);
+    let rest_s = u32::try_from(rest_s).unwrap_or(u32::MAX);
+
+    let rounds = rounds as usize;
+    (1..=rounds).flat_map(|round| {
+        let work = stage(round_name(names, "Work", round, rounds), work_s, "work");
+        if round < rounds {
+            vec![work, rest(round_name(names, "Rest", round, rounds), rest_s)]
+        } else {
+            vec![work]
+        }
+    }).collect()
+}
+
+/// Generate an interval ladder from the given work durations, optionally
+/// mirrored back down into a pyramid (1-2-3 becomes 1-2-3-2-1), with a rest
+/// of `rest_s` between intervals. A zero rest leaves out the rest stages.
+/// Stages are named after `names`.
+pub fn ladder(steps: &[u32], pyramid: bool, rest_s: u32, names: &str) -> Vec<ConfiguredStage> {
+    let mut work: Vec<u32> = steps.to_vec();
+    if pyramid {
+        work.extend(steps.iter().rev().skip(1));
+    }
+
+    let count = work.len();
+    work.into_iter().enumerate().flat_map(|(i, work_s)| {
+        let interval = stage(round_name(names, "Interval", i + 1, count), work_s, "work");
+        if rest_s > 0 && i + 1 < count {
+            vec![interval, rest(round_name(names, "Rest", i + 1, count), rest_s)]
+        } else {
+            vec![interval]
+        }
+    }).collect()
+}
+
+/// Run `stages` `rounds` times in a row. The rounds are counted in the names
+/// of named stages as `names` has it, e.g. "Work 3/8". Fails if that makes
+/// more than [`MAX_STAGES`] stages.
+pub fn repeat(stages: Vec<ConfiguredStage>, rounds: u32, names: &str) -> Result<Vec<ConfiguredStage>, String> {
+    if rounds <= 1 {
+        return Ok(stages);
+    }
+    let count = stages.len() as u64 * u64::from(rounds);
+    if count > MAX_STAGES as u64 {
+        return Err(format!(
+            "{} rounds make {} stages, at most {} are allowed",
+            rounds, count, MAX_STAGES
+        ));
+    }
+
+    let rounds = rounds as usize;
+    Ok((1..=rounds).flat_map(|round| {
+        stages.iter().map(move |stage| ConfiguredStage {
+            name: stage.name.as_ref().map(|name| round_name(names, name, round, rounds)),
+            ..stage.clone()
+        })
+    }).collect())
+}
+
+/// The Pomodoro technique as `--pomodoro` runs it without a spec: four
+/// 25 minute focus stages with 5 minute breaks and a 15 minute break at
+/// the end.
+pub const DEFAULT_POMODORO: &str = "4x25/5+15";
+
+/// A time in a Pomodoro spec, in minutes unless it says otherwise.
+fn pomodoro_time(time: &str) -> Result<u32, String> {
+    match time.trim().parse::<u32>() {
+        Ok(minutes) => Ok(minutes.saturating_mul(60)),
+        Err(_) => parse_time(time.trim())
+    }
+}
+
+/// The stages of a Pomodoro spec like `4x25/5+15`: as many rounds of focus
+/// as before the `x`, each followed by a short break, but for the last one,
+/// which is followed by the long break after the `+`. Everything but the
+/// focus time may be left out, e.g. `50/10`. Stages are named after `names`.
+pub fn pomodoro(spec: &str, names: &str) -> Result<Vec<ConfiguredStage>, String> {
+    let error = |message: String| format!("Could not parse pomodoro spec \"{}\": {}", spec, message);
+    let (rounds, times) = match spec.split_once(['x', 'X', '×']) {
+        Some((rounds, times)) => match rounds.trim().parse::<u32>() {
+            Ok(rounds) if rounds > 0 => (rounds, times),
+            _ => return Err(error(format!("\"{}\" is not a number of rounds", rounds.trim())))
+        },
+        None => (4, spec)
+    };
+    let (times, long) = match times.split_once('+') {
+        Some((times, long)) => (times, Some(long)),
+        None => (times, None)
+    };
+    let (focus, short) = match times.split_once('/') {
+        Some((focus, short)) => (focus, Some(short)),
+        None => (times, None)
+    };
+    let focus_s = pomodoro_time(focus).map_err(error)?;
+    let short_s = short.map_or(Ok(5 * 60), pomodoro_time).map_err(error)?;
+    let long_s = long.map_or(Ok(15 * 60), pomodoro_time).map_err(error)?;
+    if focus_s == 0 {
+        return Err(error(String::from("the focus time has to be longer than 0")));
+    }
+
+    let rounds = rounds as usize;
+    Ok((1..=rounds).flat_map(|round| {
+        let focus = stage(round_name(names, "Focus", round, rounds), focus_s, "work");
+        let rest = if round < rounds {
+            stage(round_name(names, "Break", round, rounds), short_s, "rest")
+        } else {
+            stage(round_name(names, "Long break", round, rounds), long_s, "rest")
+        };
+        // a break of no time is left out
+        std::iter::once(focus).chain((rest.period > Duration::ZERO).then_some(rest))
+    }).collect())
+}
+
+/// Contrast grade of a split-grade print unless the spec says otherwise, an
+/// even split between the softest and the hardest filter.
+const DEFAULT_GRADE: f64 = 2.5;
+
+/// Countdown before the end of an exposure, so dodging and burning can be
+/// timed without looking at the timer.
+const EXPOSURE_COUNTDOWN: [u32; 3] = [3, 2, 1];
+
+/// A number of stops like `1/3` or `0.5`.
+fn stops(stops: &str) -> Result<f64, String> {
+    let number = |number: &str| number.trim().replace(',', ".").parse::<f64>().ok();
+    match stops.split_once('/') {
+        Some((numerator, denominator)) => number(numerator)
+            .zip(number(denominator).filter(|denominator| *denominator != 0.0))
+            .map(|(numerator, denominator)| numerator / denominator),
+        None => number(stops)
+    }.ok_or_else(|| format!("\"{}\" is not a number of stops, e.g. 1/3", stops.trim()))
+}
+
+/// The stages of a split-grade print from a spec like `16@3.5+1/3`: the
+/// base exposure, split between the softest (00) and the hardest (5) filter
+/// so the print comes out at the contrast grade after the `@`, from 0 to 5,
+/// and made longer or shorter by the stops after the sign. Each exposure
+/// follows a stage that holds the timer until the filter has been changed.
+/// Exposures run the commands `on` and `off` as they start and end, e.g. to
+/// switch the enlarger with a relay.
+pub fn split_grade(spec: &str, (on, off): (Option<&str>, Option<&str>)) -> Result<Vec<ConfiguredStage>, String> {
+    let error = |message: String| format!("Could not parse split-grade spec \"{}\": {}", spec, message);
+    let (exposure, adjustment) = match spec.find(['+', '-']) {
+        Some(sign) => (&spec[..sign], Some(&spec[sign..])),
+        None => (spec, None)
+    };
+    let (base, grade) = match exposure.split_once('@') {
+        Some((base, grade)) => (base, Some(grade)),
+        None => (exposure, None)
+    };
+    let base = parse_duration(base.trim()).map_err(error)?;
+    let grade = match grade {
+        Some(grade) => match grade.trim().replace(',', ".").parse::<f64>() {
+            Ok(grade) if (0.0..=5.0).contains(&grade) => grade,
+            _ => return Err(error(format!("\"{}\" is not a grade from 0 to 5", grade.trim())))
+        },
+        None => DEFAULT_GRADE
+    };
+    let adjustment = match adjustment {
+        Some(adjustment) => {
+            let magnitude = stops(&adjustment[1..]).map_err(error)?;
+            if adjustment.starts_with('-') { -magnitude } else { magnitude }
+        },
+        None => 0.0
+    };
+    if base.is_zero() {
+        return Err(error(String::from("the base exposure has to be longer than 0")));
+    }
+
+    let total_s = base.as_secs_f64() * adjustment.exp2();
+    // enlarger timers go by tenths of a second
+    let tenths = |share: f64| Duration::from_millis((total_s * share * 10.0).round() as u64 * 100);
+    let filters = [("00", tenths((5.0 - grade) / 5.0)), ("5", tenths(grade / 5.0))];
+    Ok(filters.into_iter()
+        // an exposure of no time and the change to its filter are left out
+        .filter(|(_, period)| !period.is_zero())
+        .flat_map(|(filter, period)| {
+            let hold = ConfiguredStage {
+                name: Some(format!("Grade {} filter", filter)),
+                note: Some(format!("Put in the grade {} filter, then press ENTER", filter)),
+                tags: vec![String::from("filter")],
+                count_up: true,
+                ..ConfiguredStage::default()
+            };
+            let exposure = ConfiguredStage {
+                name: Some(format!("Grade {} exposure", filter)),
+                period,
+                countdown: EXPOSURE_COUNTDOWN.iter().map(|s| CountdownCue::Remaining(*s)).collect(),
+                tags: vec![String::from("exposure")],
+                on_start: on.map(String::from),
+                on_end: off.map(String::from),
+                // the paper can't be exposed longer than planned
+                overtime: Some(false),
+                manual_advance: Some(false),
+                ..ConfiguredStage::default()
+            };
+            [hold, exposure]
+        })
+        .collect())
+}
+
+/// What went wrong in an interval spec, and the characters it is about.
+type SpecError = (String, (usize, usize));
+
+/// Recursive descent parser for the interval specs of `--intervals`, e.g.
+/// `warmup=5:00, (work=0:40, rest=0:20)x10, cooldown=3:00`, with the grammar
+///
+/// ```text
+/// sequence = item { "," item }
+/// item     = name "=" time [ "+" name "=" time ]
+///          | "(" sequence ")" [ "x" rounds ]
+/// ```
+///
+/// where whitespace may appear between any of them.
+struct IntervalParser<'a> {
+    chars: Vec<char>,
+    position: usize,
+    /// How the stages of repeated groups are named
+    names: &'a str
+}
+
+impl IntervalParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    /// An error about the character at the current position.
+    fn unexpected(&self, expected: &str) -> SpecError {
+        let message = match self.peek() {
+            Some(c) => format!("unexpected '{}', expected {}", c, expected),
+            None => format!("unexpected end, expected {}", expected)
+        };
+        (message, (self.position, self.position + 1))
+    }
+
+    fn sequence(&mut self) -> Result<Vec<ConfiguredStage>, SpecError> {
+        let mut stages = self.item()?;
+        self.skip_whitespace();
+        while self.peek() == Some(',') {
+            self.position += 1;
+            let start = self.position;
+            stages.extend(self.item()?);
+            if stages.len() > MAX_STAGES {
+                return Err((
+                    format!("this makes more than {} stages", MAX_STAGES),
+                    (start, self.position)
+                ));
+            }
+            self.skip_whitespace();
+        }
+        Ok(stages)
+    }
+
+    fn item(&mut self) -> Result<Vec<ConfiguredStage>, SpecError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('(') => self.group(),
+            Some(c) if c != ',' && c != ')' => self.stage().map(|stage| vec![stage]),
+            _ => Err(self.unexpected("a stage like work=0:40 or a group in parentheses"))
+        }
+    }
+
+    /// A sequence in parentheses, repeated as many times as follows them.
+    fn group(&mut self) -> Result<Vec<ConfiguredStage>, SpecError> {
+        let open = self.position;
+        self.position += 1;
+        let stages = self.sequence()?;
+        if self.peek() != Some(')') {
+            let (message, _) = self.unexpected("',' or ')'");
+            return Err((
+                format!("{}, the group opened here isn't closed", message),
+                (open, self.position.max(open + 1))
+            ));
+        }
+        self.position += 1;
+        self.skip_whitespace();
+        if !matches!(self.peek(), Some('x' | 'X' | '×')) {
+            return Ok(stages);
+        }
+        self.position += 1;
+        self.skip_whitespace();
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        if self.position == start {
+            return Err(self.unexpected("the number of rounds"));
+        }
+        let digits: String = self.chars[start..self.position].iter().collect();
+        let rounds = digits.parse::<u32>().map_err(|_| (
+            format!("{} rounds make more than {} stages", digits, MAX_STAGES),
+            (start, self.position)
+        ))?;
+        if rounds == 0 {
+            return Err((String::from("the number of rounds has to be at least 1"), (start, self.position)));
+        }
+        repeat(stages, rounds, self.names).map_err(|message| (message, (start, self.position)))
+    }
+
+    /// A stage as `name=time`, optionally followed by `+name=time` for a
+    /// part at its end, e.g. `hold=0:30+switch=0:05`. Stages are tagged with
+    /// their name, so cue rules for e.g. `work` apply to them.
+    fn stage(&mut self) -> Result<ConfiguredStage, SpecError> {
+        let (name, period) = self.named_time()?;
+        let after = if self.peek() == Some('+') {
+            self.position += 1;
+            self.skip_whitespace();
+            let (name, period) = self.named_time()?;
+            Some(Phase { name, period })
+        } else {
+            None
+        };
+        Ok(ConfiguredStage {
+            tags: vec![name.to_lowercase()],
+            name: Some(name),
+            period: period + after.as_ref().map_or(Duration::ZERO, |after| after.period),
+            after,
+            ..ConfiguredStage::default()
+        })
+    }
+
+    fn named_time(&mut self) -> Result<(String, Duration), SpecError> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| !matches!(c, ',' | '(' | ')' | '+')) {
+            self.position += 1;
+        }
+        let segment: String = self.chars[start..self.position].iter().collect();
+        let span = (start, self.position);
+        let (name, time) = segment.split_once('=')
+            .ok_or_else(|| (format!("expected NAME=TIME, not \"{}\"", segment.trim()), span))?;
+        if name.trim().is_empty() {
+            return Err((String::from("the stage has no name"), span));
+        }
+        let time_start = start + name.chars().count() + 1;
+        let period = parse_duration(time.trim()).map_err(|message| (message, (time_start, span.1)))?;
+        Ok((name.trim().to_string(), period))
+    }
+}
+
+/// The stages of an interval spec like
+/// `warmup=5:00, (work=0:40, rest=0:20)x10, cooldown=3:00`. Stages in a
+/// repeated group count the rounds in their names as `names` has it, e.g.
+/// "work 3/10". Errors show the spec with the part that is wrong underlined.
+pub fn intervals(spec: &str, names: &str) -> Result<Vec<ConfiguredStage>, String> {
+    let mut parser = IntervalParser { chars: spec.chars().collect(), position: 0, names };
+    parser.sequence()
+        .and_then(|stages| match parser.peek() {
+            None => Ok(stages),
+            Some(')') => Err((String::from("')' without a group to close"), (parser.position, parser.position + 1))),
+            Some(_) => Err(parser.unexpected("',' or the end"))
+        })
+        .map_err(|(message, (start, end))| {
+            // the leading whitespace of a segment isn't part of what is wrong
+            let start = start + parser.chars[start..end.min(parser.chars.len())].iter()
+                .take_while(|c| c.is_whitespace())
+                .count();
+            format!(
+                "Could not parse intervals: {}\n  {}\n  {}{}",
+                message,
+                spec,
+                " ".repeat(start),
+                "^".repeat(end.saturating_sub(start).max(1))
+            )
+        })
+}
+
+/// The stages of a recorded run, lasting as long as they really took.
+/// Stages that were aborted or not run at all keep their planned duration.
+pub fn from_session(session: &SessionRecord) -> Vec<ConfiguredStage> {
+    session.stages.iter().map(|stage| {
+        let period_s = stage.actual_s
+            .filter(|_| !stage.aborted && stage.period_s > 0)
+            .unwrap_or(stage.period_s);
+        ConfiguredStage {
+            name: Some(stage.name.clone()),
+            period: Duration::from_secs(period_s.into()),
+            ..ConfiguredStage::default()
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(spec: &str) -> Vec<String> {
+        intervals(spec, DEFAULT_STAGE_NAMES).unwrap().into_iter()
+            .map(|stage| stage.name.unwrap())
+            .collect()
+    }
+
+    /// The message of the error about `spec` and where its underline starts
+    /// and ends.
+    fn error(spec: &str) -> (String, (usize, usize)) {
+        let error = intervals(spec, DEFAULT_STAGE_NAMES).err().unwrap();
+        let underline = error.lines().nth(2).unwrap();
+        let start = underline.find('^').unwrap() - 2;
+        (error.lines().next().unwrap().to_string(), (start, underline.len() - 2))
+    }
+
+    #[test]
+    fn groups_repeat_their_stages() {
+        assert_eq!(
+            names("warmup=1:00, (work=0:40, rest=0:20)x2, cooldown=1:00"),
+            ["warmup", "work 1/2", "rest 1/2", "work 2/2", "rest 2/2", "cooldown"]
+        );
+        assert_eq!(names("(a=1) × 2"), ["a 1/2", "a 2/2"]);
+        assert_eq!(names("(a=1, b=2)"), ["a", "b"]);
+    }
+
+    #[test]
+    fn groups_nest() {
+        assert_eq!(
+            names("((a=1)x2, b=1)x2"),
+            ["a 1/2 1/2", "a 2/2 1/2", "b 1/2", "a 1/2 2/2", "a 2/2 2/2", "b 2/2"]
+        );
+    }
+
+    #[test]
+    fn part_at_the_end_adds_to_the_stage() {
+        let stages = intervals("hold=0:30+switch=0:05", DEFAULT_STAGE_NAMES).unwrap();
+        assert_eq!(stages[0].period, Duration::from_secs(35));
+        assert_eq!(stages[0].after.as_ref().unwrap().name, "switch");
+        assert_eq!(stages[0].tags, ["hold"]);
+    }
+
+    #[test]
+    fn unclosed_group_is_underlined_from_where_it_opens() {
+        let (message, span) = error("a=1, (b=1, c=1");
+        assert!(message.contains("the group opened here isn't closed"), "{}", message);
+        assert_eq!(span, (5, 14));
+        let (message, span) = error("a=1)");
+        assert!(message.contains("')' without a group to close"), "{}", message);
+        assert_eq!(span, (3, 4));
+    }
+
+    #[test]
+    fn stages_need_a_name_and_a_time() {
+        let (message, span) = error("a=1, =0:30");
+        assert!(message.contains("the stage has no name"), "{}", message);
+        assert_eq!(span, (5, 10));
+        let (message, _) = error("a=1, b");
+        assert!(message.contains("expected NAME=TIME, not \"b\""), "{}", message);
+        let (message, _) = error("a=1,, b=1");
+        assert!(message.contains("unexpected ','"), "{}", message);
+        let (message, _) = error("()x2");
+        assert!(message.contains("unexpected ')'"), "{}", message);
+    }
+
+    #[test]
+    fn bad_time_is_underlined() {
+        let (_, span) = error("a=1, b=soon");
+        assert_eq!(span, (7, 11));
+    }
+
+    #[test]
+    fn rounds_are_at_least_one() {
+        let (message, span) = error("(a=1)x0");
+        assert!(message.contains("at least 1"), "{}", message);
+        assert_eq!(span, (6, 7));
+        let (message, _) = error("(a=1)x");
+        assert!(message.contains("expected the number of rounds"), "{}", message);
+    }
+
+    #[test]
+    fn stage_count_is_capped() {
+        assert_eq!(intervals("(a=1)x10000", DEFAULT_STAGE_NAMES).unwrap().len(), MAX_STAGES);
+        let (message, span) = error("(a=1)x4294967295");
+        assert!(message.contains("4294967295 rounds make 4294967295 stages"), "{}", message);
+        assert_eq!(span, (6, 16));
+        let (message, _) = error("(a=1)x99999999999");
+        assert!(message.contains("more than 10000 stages"), "{}", message);
+        let (message, span) = error("((a=1)x100000)x100000");
+        assert!(message.contains("100000 rounds make 100000 stages"), "{}", message);
+        assert_eq!(span, (7, 13));
+        let (_, span) = error("((a=1)x1000)x1000");
+        assert_eq!(span, (13, 17));
+        let (message, span) = error("(a=1)x10000, b=1");
+        assert!(message.contains("more than 10000 stages"), "{}", message);
+        assert_eq!(span, (13, 16));
+    }
+
+    #[test]
+    fn repeat_is_capped() {
+        let stages = vec![ConfiguredStage::default(); 2];
+        assert_eq!(repeat(stages.clone(), 5000, DEFAULT_STAGE_NAMES).unwrap().len(), MAX_STAGES);
+        let error = repeat(stages, 5001, DEFAULT_STAGE_NAMES).err().unwrap();
+        assert!(error.contains("5001 rounds make 10002 stages"), "{}", error);
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    io::{BufRead, BufReader},
+    process::{Child, ChildStdout, Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant}
+};
+
+use serde::{Deserialize, Serialize};
+use staged_timer::Timer;
+
+use crate::input::{Input, InputSource};
+
+/// How long a reading counts for. A sensor that went quiet, e.g. because
+/// its strap came off, leaves the rests to run on time.
+const READING_STALE: Duration = Duration::from_secs(10);
+
+/// How much time is left of a rest when it is extended, so it is extended
+/// before it ends even though the timer only looks once a second.
+const EXTEND_BEFORE_S: u32 = 5;
+
+/// How much a rest is extended by at a time.
+const EXTEND_STEP_S: u32 = 10;
+
+/// How long the notice about ending or extending a rest is shown.
+const NOTICE_SHOWN: Duration = Duration::from_secs(5);
+
+/// Heart rates outside of this range are taken for misreadings.
+const PLAUSIBLE_BPM: std::ops::RangeInclusive<u16> = 20..=250;
+
+/// Where the heart rate comes from and how it gates the rest stages.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HeartRateOptions {
+    /// Prints a heart rate in beats per minute on every line for as long as
+    /// the run goes on
+    #[serde(skip)]
+    pub command: String,
+    /// Heart rate a rest lasts until
+    pub below_bpm: u16,
+    /// How much longer than planned a rest may go on while the heart rate is
+    /// still up
+    pub max_extension_s: u32
+}
+
+/// The heart rate in a line of output, the last number in it, so `72`,
+/// `bpm=72` and `Heart rate: 72 bpm` all read as 72.
+fn parse_bpm(line: &str) -> Option<u16> {
+    line.rsplit(|c: char| !c.is_ascii_digit())
+        .find(|part| !part.is_empty())?
+        .parse()
+        .ok()
+        .filter(|bpm| PLAUSIBLE_BPM.contains(bpm))
+}
+
+/// The heart rate command, running for as long as the run goes on. Each
+/// heart rate it prints comes in as an input.
+pub struct Monitor {
+    child: Child,
+    /// Output of the command until it is read from
+    stdout: Option<ChildStdout>
+}
+
+impl Monitor {
+    /// Start `command`, e.g. a script reading a Bluetooth chest strap.
+    pub fn start(command: &str) -> Result<Monitor, String> {
+        let mut command_parts = command.split_whitespace();
+        let program = command_parts.next()
+            .ok_or_else(|| String::from("The heart rate command is empty"))?;
+        let mut process = Command::new(program);
+        process.args(command_parts)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        // a group of its own, so whatever the command starts goes down with it
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut process, 0);
+        let mut child = process.spawn().map_err(|e| format!(
+            "Could not start the heart rate command \"{}\": {}", command, e
+        ))?;
+
+        let stdout = child.stdout.take();
+        Ok(Monitor { child, stdout })
+    }
+}
+
+impl InputSource for Monitor {
+    fn start(&mut self, inputs: mpsc::Sender<Input>) {
+        let stdout = self.stdout.take();
+        thread::spawn(move || {
+            // a command that quit has no more readings to send
+            let lines = stdout.into_iter()
+                .flat_map(|stdout| BufReader::new(stdout).lines().map_while(Result::ok));
+            for bpm in lines.filter_map(|line| parse_bpm(&line)) {
+                if inputs.send(Input::HeartRate(bpm)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Ok(group) = libc::pid_t::try_from(self.child.id()) {
+            // SAFETY: signals the process group led by our own child
+            unsafe { libc::kill(-group, libc::SIGTERM); }
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Ends rest stages once the heart rate is down below the threshold, and
+/// keeps them going past their end, up to the maximum extension, while it
+/// isn't.
+pub struct RestGate {
+    options: HeartRateOptions,
+    /// Latest heart rate and when it came in
+    reading: Option<(u16, Instant)>,
+    /// The stage extended so far, and by how much
+    extended: Option<(usize, u32)>,
+    /// Why the last rest was ended or extended, and when
+    notice: Option<(String, Instant)>
+}
+
+impl RestGate {
+    pub fn new(options: HeartRateOptions) -> RestGate {
+        RestGate { options, reading: None, extended: None, notice: None }
+    }
+
+    /// Take a heart rate that came in from the monitor.
+    pub fn reading(&mut self, bpm: u16) {
+        self.reading = Some((bpm, Instant::now()));
+    }
+
+    /// Why the last rest was ended or extended, for a few seconds after.
+    pub fn notice(&self) -> Option<&str> {
+        self.notice.as_ref()
+            .filter(|(_, at)| at.elapsed() < NOTICE_SHOWN)
+            .map(|(notice, _)| notice.as_str())
+    }
+
+    /// The heart rate, unless the last reading is too old to go by.
+    fn bpm(&self) -> Option<u16> {
+        self.reading
+            .filter(|(_, at)| at.elapsed() < READING_STALE)
+            .map(|(bpm, _)| bpm)
+    }
+
+    /// End or extend the running stage of `timer` if it is a rest, and say
+    /// why.
+    pub fn check(&mut self, timer: &mut Timer) -> Option<&str> {
+        let notice = self.gate(timer)?;
+        self.notice = Some((notice, Instant::now()));
+        self.notice()
+    }
+
+    fn gate(&mut self, timer: &mut Timer) -> Option<String> {
+        let bpm = self.bpm()?;
+        let index = timer.current_index();
+        let stage = timer.current_stage().filter(|stage| stage.rest)?;
+        if timer.is_paused() {
+            return None;
+        }
+
+        if bpm < self.options.below_bpm {
+            // the rest took as long as it did, not as long as planned
+            timer.end_stage();
+            return Some(format!("Heart rate down to {} bpm, rest is over", bpm));
+        }
+
+        let extended_s = match self.extended {
+            Some((stage, extended_s)) if stage == index => extended_s,
+            _ => 0
+        };
+        let step_s = EXTEND_STEP_S.min(self.options.max_extension_s.saturating_sub(extended_s));
+        if stage.remaining() > Duration::from_secs(EXTEND_BEFORE_S.into()) || step_s == 0 {
+            return None;
+        }
+        timer.extend_stage(Duration::from_secs(step_s.into()));
+        self.extended = Some((index, extended_s + step_s));
+        Some(format!("Heart rate still at {} bpm, resting on", bpm))
+    }
+}
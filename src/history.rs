@@ -0,0 +1,284 @@
+use std::{env, fs::{self, OpenOptions}, io::{self, Write}, path::{Path, PathBuf}};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+
+use staged_timer::format_seconds;
+
+use crate::maintenance;
+
+/// Directory for data the timer keeps between runs, following the XDG base
+/// directory spec where it applies.
+pub fn data_dir() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .map(|dir| dir.join("staged-timer"))
+}
+
+pub fn history_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("history.toml"))
+}
+
+/// Ambient conditions during a run or stage.
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+pub struct Environment {
+    pub temperature_c: Option<f64>,
+    /// Relative humidity in percent
+    pub humidity_pct: Option<f64>
+}
+
+impl Environment {
+    pub fn is_empty(&self) -> bool {
+        self.temperature_c.is_none() && self.humidity_pct.is_none()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StageRecord {
+    pub name: String,
+    pub period_s: u32,
+    /// Wall clock time the stage took, pauses included
+    pub actual_s: Option<u32>,
+    /// Whether the actual time was off the plan by more than the tolerance
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deviated: bool,
+    /// Whether the stage was given up on because something went wrong
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub aborted: bool,
+    /// Read from the sensor command when the stage started
+    pub environment: Option<Environment>
+}
+
+/// A run of the timer as kept in the history.
+#[derive(Serialize, Deserialize)]
+pub struct SessionRecord {
+    /// Local time the run started at, in RFC 3339 format
+    pub started: String,
+    /// Whether all stages ran to the end
+    pub completed: bool,
+    /// Whether the run was given up on because something went wrong, as
+    /// opposed to being quit on purpose
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub aborted: bool,
+    /// Film roll the run developed
+    pub roll: Option<String>,
+    /// Preset the stages of the run came from
+    pub preset: Option<String>,
+    /// Chemical batches the run used
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub batches: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+    /// Given on the command line
+    pub environment: Option<Environment>,
+    /// Whether the pauses of the run took longer than its pause budget
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub over_pause_budget: bool,
+    #[serde(default, rename = "stage")]
+    pub stages: Vec<StageRecord>
+}
+
+#[derive(Serialize, Deserialize)]
+struct HistoryFile {
+    /// ID of the first run in the file, 1 unless older runs were moved out
+    /// of it, see [`compact`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    first_id: Option<usize>,
+    #[serde(default, rename = "session")]
+    sessions: Vec<SessionRecord>
+}
+
+/// Add a run to the end of the history file. Each run is written as its own
+/// `[[session]]` table so the file only ever grows by appending.
+pub fn append(record: SessionRecord) -> Result<(), String> {
+    let path = history_path()
+        .ok_or("Could not find a data directory for the history")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!(
+            "Could not create data directory {}: {}", dir.display(), e
+        ))?;
+    }
+
+    let entry = toml::to_string(&HistoryFile { first_id: None, sessions: vec![record] })
+        .map_err(|e| format!("Could not write history entry: {}", e))?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", entry))
+        .map_err(|e| format!(
+            "Could not write history file {}: {}", path.display(), e
+        ))
+}
+
+/// All runs in the history, oldest first.
+pub fn load() -> Result<Vec<SessionRecord>, String> {
+    read().map(|history| history.sessions)
+}
+
+/// All runs in the history with their IDs, oldest first. Runs are numbered
+/// by their position in the history, starting at 1, which never changes as
+/// the history only grows and runs moved out of it keep their numbers.
+pub fn numbered() -> Result<Vec<(usize, SessionRecord)>, String> {
+    let history = read()?;
+    let first_id = history.first_id.unwrap_or(1);
+    Ok((first_id..).zip(history.sessions).collect())
+}
+
+fn read() -> Result<HistoryFile, String> {
+    let path = match history_path() {
+        Some(path) => path,
+        None => return Ok(HistoryFile { first_id: None, sessions: Vec::new() })
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(HistoryFile { first_id: None, sessions: Vec::new() });
+        },
+        Err(e) => return Err(format!(
+            "Could not read history file {}: {}", path.display(), e
+        ))
+    };
+    toml::from_str(&content).map_err(|e| format!(
+        "Could not parse history file {}: {}", path.display(), e
+    ))
+}
+
+/// The run with the ID `id`, see [`numbered`].
+pub fn session(id: usize) -> Result<SessionRecord, String> {
+    let sessions = numbered()?;
+    let ids = match (sessions.first(), sessions.last()) {
+        (Some((first, _)), Some((last, _))) => format!("has sessions {} to {}", first, last),
+        _ => String::from("is empty")
+    };
+    sessions.into_iter()
+        .find(|(session_id, _)| *session_id == id)
+        .map(|(_, session)| session)
+        .ok_or_else(|| format!("There is no session {} in the history, which {}", id, ids))
+}
+
+/// Keep only the newest `keep` runs in the history, moving the older ones
+/// to a new backup of it next to it, of which the newest `backups` are
+/// kept. Returns how many runs were moved.
+pub fn compact(keep: usize, backups: u32) -> Result<usize, String> {
+    let path = match history_path() {
+        Some(path) => path,
+        None => return Ok(0)
+    };
+    let history = read()?;
+    let moved = history.sessions.len().saturating_sub(keep);
+    if moved == 0 {
+        return Ok(0);
+    }
+    let first_id = history.first_id.unwrap_or(1);
+    let mut sessions = history.sessions;
+    let kept = sessions.split_off(moved);
+
+    if let Some(backup) = maintenance::make_room(&path, backups)? {
+        write(&backup, &HistoryFile { first_id: Some(first_id), sessions })?;
+    }
+    write(&path, &HistoryFile { first_id: Some(first_id + moved), sessions: kept })?;
+    Ok(moved)
+}
+
+/// Replace the history file at `path` with `history` in one go.
+fn write(path: &Path, history: &HistoryFile) -> Result<(), String> {
+    let content = toml::to_string(history)
+        .map_err(|e| format!("Could not write history file {}: {}", path.display(), e))?;
+    let partial = path.with_extension("partial");
+    fs::write(&partial, content)
+        .and_then(|_| fs::rename(&partial, path))
+        .map_err(|e| format!("Could not write history file {}: {}", path.display(), e))
+}
+
+/// Print every run in the history with its ID and how long its stages
+/// took.
+pub fn print_sessions() -> Result<(), String> {
+    let sessions = numbered()?;
+    if sessions.is_empty() {
+        println!("No sessions have been recorded yet.");
+    }
+    for (id, session) in &sessions {
+        let started = DateTime::parse_from_rfc3339(&session.started)
+            .map(|started| started.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|_| session.started.clone());
+        let roll = session.roll.as_ref()
+            .map(|roll| format!(" (roll {})", roll))
+            .unwrap_or_default();
+        let over_budget = if session.over_pause_budget { " (over the pause budget)" } else { "" };
+        let stages: Vec<String> = session.stages.iter()
+            .map(|stage| match stage.actual_s.filter(|actual_s| *actual_s != stage.period_s) {
+                Some(actual_s) => format!(
+                    "{} {} (took {})",
+                    stage.name, format_seconds(stage.period_s), format_seconds(actual_s)
+                ),
+                None => format!("{} {}", stage.name, format_seconds(stage.period_s))
+            })
+            .collect();
+        println!("#{} {}{}{}  {}", id, started, roll, over_budget, stages.join(", "));
+    }
+    Ok(())
+}
+
+fn pending_notes_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("pending_notes.toml"))
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingNotesFile {
+    #[serde(default, rename = "note")]
+    notes: Vec<String>
+}
+
+/// Keep a note for the next run that is recorded in the history.
+pub fn add_pending_note(note: &str) -> Result<(), String> {
+    let path = pending_notes_path()
+        .ok_or("Could not find a data directory for the note")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!(
+            "Could not create data directory {}: {}", dir.display(), e
+        ))?;
+    }
+
+    let mut pending = match fs::read_to_string(&path) {
+        Ok(content) => toml::from_str(&content).map_err(|e| format!(
+            "Could not parse pending notes {}: {}", path.display(), e
+        ))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => PendingNotesFile { notes: Vec::new() },
+        Err(e) => return Err(format!(
+            "Could not read pending notes {}: {}", path.display(), e
+        ))
+    };
+    pending.notes.push(note.to_string());
+
+    let content = toml::to_string(&pending)
+        .map_err(|e| format!("Could not write pending notes: {}", e))?;
+    fs::write(&path, content).map_err(|e| format!(
+        "Could not write pending notes {}: {}", path.display(), e
+    ))
+}
+
+/// Remove and return the notes kept for the next run.
+pub fn take_pending_notes() -> Result<Vec<String>, String> {
+    let path = match pending_notes_path() {
+        Some(path) => path,
+        None => return Ok(Vec::new())
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!(
+            "Could not read pending notes {}: {}", path.display(), e
+        ))
+    };
+    let pending: PendingNotesFile = toml::from_str(&content).map_err(|e| format!(
+        "Could not parse pending notes {}: {}", path.display(), e
+    ))?;
+    fs::remove_file(&path).map_err(|e| format!(
+        "Could not remove pending notes {}: {}", path.display(), e
+    ))?;
+
+    Ok(pending.notes)
+}
@@ -0,0 +1,132 @@
+use std::{
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant}
+};
+
+use serde::{Deserialize, Serialize};
+
+use staged_timer::Timer;
+
+/// Hooks are run at most this often. Stages the timer passes through
+/// faster, e.g. markers or stages skipped in quick succession, get no hooks
+/// of their own, so a hook calling a web service can't hammer it.
+const HOOK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether a hook is run for a stage that starts or one that ends.
+#[derive(Clone, Copy)]
+enum Event {
+    Start,
+    End
+}
+
+/// Runs shell commands when stages start and end. Commands given for all
+/// stages run before the stage's own.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Hooks {
+    #[serde(skip)]
+    on_start: Option<String>,
+    #[serde(skip)]
+    on_end: Option<String>,
+    /// Stage the hooks last ran for
+    #[serde(skip)]
+    stage: Option<usize>,
+    /// When the hooks last ran
+    #[serde(skip)]
+    ran: Option<Instant>,
+    /// Whether nothing is run, not even the commands of the stages
+    #[serde(skip)]
+    off: bool
+}
+
+impl Hooks {
+    pub fn new(on_start: Option<String>, on_end: Option<String>) -> Hooks {
+        Hooks { on_start, on_end, stage: None, ran: None, off: false }
+    }
+
+    /// Hooks that run nothing at all, for a dry run.
+    pub fn off() -> Hooks {
+        Hooks { off: true, ..Hooks::new(None, None) }
+    }
+
+    /// The commands run for every stage, by the setting they come from.
+    pub fn commands(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [("on_stage_start", &self.on_start), ("on_stage_end", &self.on_end)].into_iter()
+            .filter_map(|(setting, command)| Some((setting, command.as_deref()?)))
+    }
+
+    /// Run the command in the system shell with the stage exported as
+    /// `STAGED_TIMER_*` environment variables.
+    fn run(&self, timer: &Timer, index: usize, event: Event, aborted: bool) {
+        if self.off {
+            return;
+        }
+        let stage = &timer.stages()[index];
+        let (global, own) = match event {
+            Event::Start => (&self.on_start, &stage.on_start),
+            Event::End => (&self.on_end, &stage.on_end)
+        };
+        for command_line in [global, own].into_iter().flatten() {
+            let mut command = if cfg!(windows) {
+                let mut command = Command::new("cmd");
+                command.args(["/C", command_line]);
+                command
+            } else {
+                let mut command = Command::new("sh");
+                command.args(["-c", command_line]);
+                command
+            };
+            command.env("STAGED_TIMER_EVENT", match event {
+                    Event::Start => "start",
+                    Event::End => "end"
+                })
+                .env("STAGED_TIMER_STAGE", &stage.name)
+                .env("STAGED_TIMER_STAGE_INDEX", (index + 1).to_string())
+                .env("STAGED_TIMER_STAGE_COUNT", timer.stages().len().to_string())
+                .env("STAGED_TIMER_STAGE_TIME", stage.period.as_secs_f64().to_string())
+                .env("STAGED_TIMER_ABORTED", if aborted || stage.aborted { "1" } else { "0" })
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null());
+            if let Ok(mut child) = command.spawn() {
+                thread::spawn(move || child.wait());
+            }
+        }
+    }
+
+    /// Take up a timer that is already running, without running the start
+    /// hook of its running stage again.
+    pub fn resume(&mut self, timer: &Timer) {
+        self.stage = Some(timer.current_index());
+    }
+
+    /// Run the end hook of the stage that was running and the start hook of
+    /// the one that is running now, if the timer has moved on since the
+    /// hooks last ran and that was at least [`HOOK_INTERVAL`] ago.
+    pub fn update(&mut self, timer: &Timer) {
+        let stage = timer.current_index();
+        if self.stage == Some(stage) || self.ran.is_some_and(|ran| ran.elapsed() < HOOK_INTERVAL) {
+            return;
+        }
+        if let Some(previous) = self.stage.replace(stage) {
+            self.run(timer, previous, Event::End, false);
+        }
+        if !timer.is_finished() {
+            self.run(timer, stage, Event::Start, false);
+        }
+        self.ran = Some(Instant::now());
+    }
+
+    /// Run the end hook of a stage that is still running when the timer is
+    /// quit, so e.g. devices switched on at its start are switched off, or
+    /// of one that ended too soon after the last hooks to run them then.
+    pub fn finish(self, timer: &Timer) {
+        match self.stage {
+            Some(stage) if stage != timer.current_index() => {
+                self.run(timer, stage, Event::End, false);
+            },
+            Some(stage) if !timer.is_finished() => self.run(timer, stage, Event::End, true),
+            _ => {}
+        }
+    }
+}
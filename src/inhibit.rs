@@ -0,0 +1,178 @@
+pub use platform::Inhibitor;
+
+/// What the system is told the timer keeps it awake for.
+const REASON: &str = "A timer is running";
+
+/// Keep the system from going to sleep for as long as the returned
+/// inhibitor is held, through whatever the platform offers for it.
+pub fn keep_awake() -> Result<Inhibitor, String> {
+    platform::acquire(REASON)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use std::{
+        process::{Child, Command, Stdio},
+        thread,
+        time::Duration
+    };
+
+    /// How long systemd-inhibit gets to fail, e.g. without logind.
+    const STARTUP: Duration = Duration::from_millis(100);
+
+    /// A running `systemd-inhibit`, which holds the inhibitor lock for as
+    /// long as the `cat` it runs waits on its input. That input is ours, so
+    /// the lock goes away with the timer however it ends.
+    pub struct Inhibitor {
+        child: Child
+    }
+
+    pub fn acquire(why: &str) -> Result<Inhibitor, String> {
+        let mut child = Command::new("systemd-inhibit")
+            .args(["--what=idle:sleep", "--who=staged-timer", "--mode=block"])
+            .arg(format!("--why={}", why))
+            .arg("cat")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Could not start systemd-inhibit: {}", e))?;
+        thread::sleep(STARTUP);
+        match child.try_wait() {
+            Ok(None) => Ok(Inhibitor { child }),
+            Ok(Some(status)) => Err(format!("systemd-inhibit exited with {}", status)),
+            Err(e) => Err(format!("Could not start systemd-inhibit: {}", e))
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::{c_char, c_void, CString};
+
+    type CFStringRef = *const c_void;
+
+    /// `kCFStringEncodingUTF8`
+    const ENCODING_UTF8: u32 = 0x0800_0100;
+
+    /// `kIOPMAssertionLevelOn`
+    const ASSERTION_LEVEL_ON: u32 = 255;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFStringCreateWithCString(
+            allocator: *const c_void, text: *const c_char, encoding: u32
+        ) -> CFStringRef;
+        fn CFRelease(object: *const c_void);
+    }
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IOPMAssertionCreateWithName(
+            kind: CFStringRef, level: u32, name: CFStringRef, id: *mut u32
+        ) -> i32;
+        fn IOPMAssertionRelease(id: u32) -> i32;
+    }
+
+    /// A power assertion keeping the system from idle sleep, the same one
+    /// `caffeinate -i` takes.
+    pub struct Inhibitor {
+        id: u32
+    }
+
+    /// A CoreFoundation string of `text`, to be released with `CFRelease`.
+    fn cf_string(text: &str) -> Result<CFStringRef, String> {
+        let text = CString::new(text).map_err(|e| e.to_string())?;
+        // SAFETY: `text` is a valid C string for the duration of the call,
+        // the default allocator is used for a null one
+        let string = unsafe {
+            CFStringCreateWithCString(std::ptr::null(), text.as_ptr(), ENCODING_UTF8)
+        };
+        if string.is_null() {
+            return Err(String::from("Could not create a CoreFoundation string"));
+        }
+        Ok(string)
+    }
+
+    pub fn acquire(why: &str) -> Result<Inhibitor, String> {
+        let kind = cf_string("PreventUserIdleSystemSleep")?;
+        let name = cf_string(why).inspect_err(|_| {
+            // SAFETY: `kind` was created above and isn't used afterwards
+            unsafe { CFRelease(kind) };
+        })?;
+        let mut id = 0;
+        // SAFETY: both strings are valid until they are released below and
+        // `id` outlives the call
+        let result = unsafe {
+            let result = IOPMAssertionCreateWithName(kind, ASSERTION_LEVEL_ON, name, &mut id);
+            CFRelease(kind);
+            CFRelease(name);
+            result
+        };
+        if result != 0 {
+            return Err(format!("Could not create a power assertion, IOKit error {:#x}", result));
+        }
+        Ok(Inhibitor { id })
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            // SAFETY: `id` is an assertion created in `acquire` that hasn't
+            // been released yet
+            unsafe { IOPMAssertionRelease(self.id) };
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::marker::PhantomData;
+
+    /// `ES_CONTINUOUS`, the state lasts until it is changed again.
+    const ES_CONTINUOUS: u32 = 0x8000_0000;
+
+    /// `ES_SYSTEM_REQUIRED`
+    const ES_SYSTEM_REQUIRED: u32 = 0x0000_0001;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetThreadExecutionState(flags: u32) -> u32;
+    }
+
+    /// The execution state of the thread that acquired it, which is why it
+    /// can't be sent to another thread to be dropped there.
+    pub struct Inhibitor {
+        _thread: PhantomData<*const ()>
+    }
+
+    pub fn acquire(_why: &str) -> Result<Inhibitor, String> {
+        // SAFETY: this only sets the execution state of the calling thread
+        if unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) } == 0 {
+            return Err(String::from("Could not set the thread's execution state"));
+        }
+        Ok(Inhibitor { _thread: PhantomData })
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            // SAFETY: see `acquire`
+            unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    pub struct Inhibitor;
+
+    pub fn acquire(_why: &str) -> Result<Inhibitor, String> {
+        Err(String::from("Keeping the system awake isn't supported on this platform"))
+    }
+}
@@ -0,0 +1,332 @@
+use std::{collections::VecDeque, io, path::PathBuf, sync::mpsc, thread, time::Duration};
+#[cfg(unix)]
+use std::{
+    fs::File,
+    io::Read,
+    os::fd::FromRawFd,
+    sync::{atomic::{AtomicI32, Ordering}, Once}
+};
+#[cfg(target_os = "linux")]
+use std::path::Path;
+
+use crossterm::event::{self, Event};
+
+use staged_timer::Timer;
+
+use crate::schema;
+
+/// What the user asks of the running timer, whichever way they ask.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Command {
+    TogglePause,
+    // only sent over the control socket for now
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Pause,
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Resume,
+    /// Move on to the next stage
+    Skip,
+    /// Move on past the rest of the running stage's group
+    SkipGroup,
+    /// Finish a stage counting up, in overtime or waiting, like ENTER
+    Finish,
+    /// Add to the running stage
+    Extend(Duration),
+    /// Take off the running stage
+    Shorten(Duration)
+}
+
+/// One thing that came in from any of the sources.
+pub enum Input {
+    /// A key, click or resize in the terminal the timer is drawn on, handled
+    /// by what is shown
+    Terminal(Event),
+    /// A command from outside the terminal, carried out right away
+    Command(Command),
+    /// Somebody wants to know how the timer is doing
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Status(StatusRequest),
+    /// A heart rate in beats per minute from the heart rate command
+    HeartRate(u16)
+}
+
+/// A request for the status of the timer, answered with a line of JSON.
+pub struct StatusRequest {
+    reply: mpsc::Sender<String>
+}
+
+impl StatusRequest {
+    /// A request along with where its answer arrives.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    pub fn new() -> (StatusRequest, mpsc::Receiver<String>) {
+        let (reply, answer) = mpsc::channel();
+        (StatusRequest { reply }, answer)
+    }
+
+    pub fn answer(self, timer: &Timer) {
+        // the asker may have given up waiting
+        let _ = self.reply.send(schema::line(&schema::Event::status(timer)));
+    }
+}
+
+/// Somewhere inputs come from. Each source waits for its inputs on a thread
+/// of its own and sends them on as they come in, so the run wakes up for
+/// them right away and sleeps while there are none.
+pub trait InputSource {
+    /// Send the inputs of the source to `inputs` from now on.
+    fn start(&mut self, inputs: mpsc::Sender<Input>);
+}
+
+/// Keys, clicks and resizes in the terminal.
+pub struct Terminal;
+
+impl InputSource for Terminal {
+    fn start(&mut self, inputs: mpsc::Sender<Input>) {
+        thread::spawn(move || {
+            // a terminal that can't be read from any more has no keys to send
+            while let Ok(event) = event::read() {
+                if inputs.send(Input::Terminal(event)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// Lines on stdin, each finishing the running stage, for runs without a
+/// terminal to press ENTER in.
+pub struct Lines;
+
+impl InputSource for Lines {
+    fn start(&mut self, inputs: mpsc::Sender<Input>) {
+        thread::spawn(move || {
+            for _ in io::stdin().lines() {
+                if inputs.send(Input::Command(Command::Finish)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// Write end of the pipe the signal handler passes signals on through.
+#[cfg(unix)]
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+#[cfg(unix)]
+static HANDLERS: Once = Once::new();
+
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    let fd = SIGNAL_PIPE.load(Ordering::SeqCst);
+    // signals are numbered well below 256
+    let byte = signal as u8;
+    // SAFETY: write is async-signal-safe and only reads the byte
+    unsafe {
+        libc::write(fd, (&byte as *const u8).cast(), 1);
+    }
+}
+
+/// `SIGUSR1` pauses or resumes the timer and `SIGUSR2` skips to the next
+/// stage, e.g. `pkill -USR1 staged-timer`.
+#[cfg(unix)]
+pub struct Signals;
+
+#[cfg(unix)]
+impl InputSource for Signals {
+    fn start(&mut self, inputs: mpsc::Sender<Input>) {
+        let mut fds = [0; 2];
+        // SAFETY: pipe only writes the two file descriptors to `fds`
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return;
+        }
+        SIGNAL_PIPE.store(fds[1], Ordering::SeqCst);
+        HANDLERS.call_once(|| {
+            for signal in [libc::SIGUSR1, libc::SIGUSR2] {
+                // SAFETY: the handler only writes to the pipe
+                unsafe {
+                    let handler: extern "C" fn(libc::c_int) = on_signal;
+                    libc::signal(signal, handler as libc::sighandler_t);
+                }
+            }
+        });
+        // SAFETY: the read end of the pipe belongs to nothing else
+        let mut pipe = unsafe { File::from_raw_fd(fds[0]) };
+        thread::spawn(move || {
+            let mut signal = [0];
+            while pipe.read_exact(&mut signal).is_ok() {
+                let command = if libc::c_int::from(signal[0]) == libc::SIGUSR1 {
+                    Command::TogglePause
+                } else {
+                    Command::Skip
+                };
+                if inputs.send(Input::Command(command)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// Event types and key codes of the Linux input subsystem.
+#[cfg(target_os = "linux")]
+mod codes {
+    pub const EV_KEY: u16 = 0x01;
+    pub const KEY_ENTER: u16 = 28;
+    pub const KEY_N: u16 = 49;
+    pub const KEY_SPACE: u16 = 57;
+    pub const KEY_KPMINUS: u16 = 74;
+    pub const KEY_KPPLUS: u16 = 78;
+    pub const KEY_KPENTER: u16 = 96;
+    pub const KEY_VOLUMEDOWN: u16 = 114;
+    pub const KEY_VOLUMEUP: u16 = 115;
+    pub const KEY_NEXTSONG: u16 = 163;
+    pub const KEY_PLAYPAUSE: u16 = 164;
+    pub const BTN_0: u16 = 0x100;
+    pub const BTN_1: u16 = 0x101;
+    pub const BTN_2: u16 = 0x102;
+    pub const BTN_LEFT: u16 = 0x110;
+    pub const BTN_RIGHT: u16 = 0x111;
+    pub const BTN_MIDDLE: u16 = 0x112;
+}
+
+#[cfg(target_os = "linux")]
+use codes::*;
+
+/// The command a key or button of a device stands for, by its Linux key
+/// code. Foot pedals and GPIO buttons usually send one of these.
+#[cfg(target_os = "linux")]
+fn device_command(code: u16, step: Duration) -> Option<Command> {
+    match code {
+        KEY_SPACE | KEY_PLAYPAUSE | BTN_0 | BTN_LEFT => Some(Command::TogglePause),
+        KEY_ENTER | KEY_KPENTER | BTN_1 | BTN_MIDDLE => Some(Command::Finish),
+        KEY_N | KEY_NEXTSONG | BTN_2 | BTN_RIGHT => Some(Command::Skip),
+        KEY_KPPLUS | KEY_VOLUMEUP => Some(Command::Extend(step)),
+        KEY_KPMINUS | KEY_VOLUMEDOWN => Some(Command::Shorten(step)),
+        _ => None
+    }
+}
+
+/// An input device like a foot pedal or a button, read directly from its
+/// event file under `/dev/input` so it works whichever window has focus.
+#[cfg(target_os = "linux")]
+pub struct Device {
+    /// The event file until it is read from
+    file: Option<File>,
+    step: Duration
+}
+
+#[cfg(target_os = "linux")]
+impl Device {
+    /// Open the device at `path` to read its key presses, with + and -
+    /// adding or taking off `step`.
+    pub fn open(path: &Path, step: Duration) -> Result<Device, String> {
+        let file = File::open(path)
+            .map_err(|e| format!("Could not open input device {}: {}", path.display(), e))?;
+        Ok(Device { file: Some(file), step })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl InputSource for Device {
+    fn start(&mut self, inputs: mpsc::Sender<Input>) {
+        let step = self.step;
+        // the device is read on one thread only
+        let mut file = match self.file.take() {
+            Some(file) => file,
+            None => return
+        };
+        thread::spawn(move || {
+            // struct input_event: a timeval followed by type, code and value
+            let mut event = [0; std::mem::size_of::<libc::timeval>() + 8];
+            let at = event.len() - 8;
+            while file.read_exact(&mut event).is_ok() {
+                let kind = u16::from_ne_bytes([event[at], event[at + 1]]);
+                let code = u16::from_ne_bytes([event[at + 2], event[at + 3]]);
+                let value = i32::from_ne_bytes([event[at + 4], event[at + 5], event[at + 6], event[at + 7]]);
+                // presses only, neither releases nor repeats
+                if kind != EV_KEY || value != 1 {
+                    continue;
+                }
+                // a device that was unplugged just stops sending
+                let sent = device_command(code, step).map(|command| inputs.send(Input::Command(command)));
+                if let Some(Err(_)) = sent {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// Every source of input of a run, merged into one stream.
+pub struct Inputs {
+    sender: mpsc::Sender<Input>,
+    inputs: mpsc::Receiver<Input>,
+    /// Inputs that came in while waiting for the terminal, in order
+    held: VecDeque<Input>,
+    /// Kept until the run is over, e.g. to remove their sockets then
+    sources: Vec<Box<dyn InputSource>>,
+    reads_lines: bool
+}
+
+impl Default for Inputs {
+    fn default() -> Inputs {
+        let (sender, inputs) = mpsc::channel();
+        Inputs { sender, inputs, held: VecDeque::new(), sources: Vec::new(), reads_lines: false }
+    }
+}
+
+impl Inputs {
+    /// Signals and the input devices at `devices`. Signals are only taken
+    /// on Unix and input devices can only be read on Linux.
+    pub fn open(devices: &[PathBuf], step: Duration) -> Result<Inputs, String> {
+        #[cfg_attr(not(unix), allow(unused_mut))]
+        let mut inputs = Inputs::default();
+        #[cfg(unix)]
+        inputs.add(Signals);
+        #[cfg(target_os = "linux")]
+        for path in devices {
+            inputs.add(Device::open(path, step)?);
+        }
+        #[cfg(not(target_os = "linux"))]
+        if let Some(path) = devices.first() {
+            let _ = step;
+            return Err(format!(
+                "Input devices like {} can only be read on Linux", path.display()
+            ));
+        }
+        Ok(inputs)
+    }
+
+    pub fn add(&mut self, mut source: impl InputSource + 'static) {
+        source.start(self.sender.clone());
+        self.sources.push(Box::new(source));
+    }
+
+    /// Finish stages with lines on stdin from now on.
+    pub fn read_lines(&mut self) {
+        if !self.reads_lines {
+            self.reads_lines = true;
+            self.add(Lines);
+        }
+    }
+
+    /// The next input from any source, waiting for up to `timeout`.
+    pub fn next(&mut self, timeout: Duration) -> Option<Input> {
+        self.held.pop_front().or_else(|| self.inputs.recv_timeout(timeout).ok())
+    }
+
+    /// Wait for the next key, click or resize in the terminal, for screens
+    /// other than the timer. Inputs from other sources wait until the timer
+    /// runs.
+    pub fn terminal_event(&mut self) -> Event {
+        loop {
+            match self.inputs.recv() {
+                Ok(Input::Terminal(event)) => return event,
+                Ok(input) => self.held.push_back(input),
+                // the inputs keep a sender of their own, so the channel
+                // stays open
+                Err(_) => {}
+            }
+        }
+    }
+}
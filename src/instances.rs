@@ -0,0 +1,149 @@
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    process,
+    time::Duration
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::session;
+
+/// How long `status`, `pause` and `unpause` wait for a timer to answer.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where running timers register themselves.
+fn dir() -> Result<PathBuf, String> {
+    Ok(session::runtime_dir()?.join("instances"))
+}
+
+/// A running timer as it registered itself.
+#[derive(Serialize, Deserialize)]
+pub struct Instance {
+    pub pid: u32,
+    /// Session name of its run, if it was given one
+    pub name: Option<String>,
+    /// Control socket it takes commands on
+    pub socket: PathBuf
+}
+
+impl Instance {
+    /// The timer as the user knows it, by name if it has one.
+    fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("\"{}\" (PID {})", name, self.pid),
+            None => format!("PID {}", self.pid)
+        }
+    }
+}
+
+/// Where this process takes commands unless `--control-socket` says
+/// otherwise.
+pub fn socket_path() -> Result<PathBuf, String> {
+    Ok(dir()?.join(format!("{}.sock", process::id())))
+}
+
+/// Keeps this process registered for as long as it runs its timer.
+pub struct Registration {
+    path: PathBuf
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Register this process, running the session `name` and taking commands
+/// on `socket`.
+pub fn register(name: Option<&str>, socket: &Path) -> Result<Registration, String> {
+    let dir = dir()?;
+    fs::create_dir_all(&dir).map_err(|e| format!(
+        "Could not create directory {}: {}", dir.display(), e
+    ))?;
+    let instance = Instance {
+        pid: process::id(),
+        name: name.map(String::from),
+        socket: socket.to_path_buf()
+    };
+    let path = dir.join(format!("{}.toml", instance.pid));
+    let content = toml::to_string(&instance).map_err(|e| format!(
+        "Could not register the timer: {}", e
+    ))?;
+    fs::write(&path, content).map_err(|e| format!(
+        "Could not write {}: {}", path.display(), e
+    ))?;
+    Ok(Registration { path })
+}
+
+/// The registered timers that are still running, by PID. Those that went
+/// away without saying so are forgotten.
+pub fn list() -> Result<Vec<Instance>, String> {
+    let dir = dir()?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Could not read {}: {}", dir.display(), e))
+    };
+    let mut instances = Vec::new();
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.extension().is_none_or(|extension| extension != "toml") {
+            continue;
+        }
+        let instance = fs::read_to_string(&path).ok()
+            .and_then(|content| toml::from_str::<Instance>(&content).ok());
+        match instance {
+            Some(instance) if UnixStream::connect(&instance.socket).is_ok() => instances.push(instance),
+            // a timer that crashed leaves its registration behind, but
+            // nobody listens on its socket any more
+            _ => {
+                let _ = fs::remove_file(&path);
+                let _ = fs::remove_file(path.with_extension("sock"));
+            }
+        }
+    }
+    instances.sort_by_key(|instance| instance.pid);
+    Ok(instances)
+}
+
+/// The running timer `target` names by session name or PID, or the only
+/// one running if there is no `target`.
+pub fn find(target: Option<&str>) -> Result<Instance, String> {
+    let mut instances = list()?;
+    if instances.is_empty() {
+        return Err(String::from("No timer is running"));
+    }
+    let target = match target {
+        Some(target) => target,
+        None if instances.len() == 1 => return Ok(instances.remove(0)),
+        None => {
+            let labels: Vec<String> = instances.iter().map(Instance::label).collect();
+            return Err(format!(
+                "Several timers are running: {}. Pick one with --instance NAME or PID",
+                labels.join(", ")
+            ));
+        }
+    };
+    let pid = target.parse::<u32>().ok();
+    instances.into_iter()
+        .find(|instance| instance.name.as_deref() == Some(target) || Some(instance.pid) == pid)
+        .ok_or_else(|| format!("No timer named \"{}\" or with that PID is running", target))
+}
+
+/// Send `request`, one of the commands of the control socket, to
+/// `instance` and return its answer.
+pub fn send(instance: &Instance, request: &str) -> Result<String, String> {
+    let failed = |e: std::io::Error| format!("Could not reach the timer {}: {}", instance.label(), e);
+    let mut stream = UnixStream::connect(&instance.socket).map_err(failed)?;
+    stream.set_read_timeout(Some(ANSWER_TIMEOUT)).map_err(failed)?;
+    writeln!(stream, "{}", request).map_err(failed)?;
+    let mut answer = String::new();
+    BufReader::new(stream).read_line(&mut answer).map_err(failed)?;
+    let answer = answer.trim_end().to_string();
+    match answer.strip_prefix("error: ") {
+        Some(message) => Err(format!("The timer {} answered: {}", instance.label(), message)),
+        None => Ok(answer)
+    }
+}
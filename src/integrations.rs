@@ -0,0 +1,84 @@
+use std::sync::OnceLock;
+
+/// An optional integration with the world outside the terminal. Each is a
+/// cargo feature, all of them enabled by default, so builds can leave out
+/// what they don't need, e.g. `cargo build --no-default-features --features
+/// audio`.
+pub struct Integration {
+    /// Name of the cargo feature
+    pub name: &'static str,
+    /// What the integration does, to complete "Cannot …"
+    pub purpose: &'static str,
+    /// Whether this build includes it
+    pub enabled: bool
+}
+
+pub const AUDIO: Integration = Integration {
+    name: "audio",
+    purpose: "play audio files",
+    enabled: cfg!(feature = "audio")
+};
+
+pub const HTTP: Integration = Integration {
+    name: "http",
+    purpose: "serve over HTTP",
+    enabled: cfg!(feature = "http")
+};
+
+pub const MQTT: Integration = Integration {
+    name: "mqtt",
+    purpose: "publish to MQTT",
+    enabled: cfg!(feature = "mqtt")
+};
+
+pub const TLS: Integration = Integration {
+    name: "tls",
+    purpose: "serve over TLS",
+    enabled: cfg!(feature = "tls")
+};
+
+pub const TTS: Integration = Integration {
+    name: "tts",
+    purpose: "read announcements out loud",
+    enabled: cfg!(feature = "tts")
+};
+
+/// Every integration there is.
+pub const ALL: &[Integration] = &[AUDIO, HTTP, MQTT, TLS, TTS];
+
+impl Integration {
+    /// An error saying how to get the integration unless this build includes
+    /// it.
+    pub fn require(&self) -> Result<(), String> {
+        if self.enabled {
+            return Ok(());
+        }
+        Err(self.missing())
+    }
+
+    /// Why the integration can't be used in this build and how to get it.
+    pub fn missing(&self) -> String {
+        format!(
+            "Cannot {}, this build of staged-timer doesn't include it. Build \
+            it with `--features {}` to do so.",
+            self.purpose, self.name
+        )
+    }
+}
+
+/// The version of the program with the integrations this build includes,
+/// for `--version`.
+pub fn long_version() -> &'static str {
+    static LONG_VERSION: OnceLock<String> = OnceLock::new();
+    LONG_VERSION.get_or_init(|| {
+        let enabled: Vec<&str> = ALL.iter()
+            .filter(|integration| integration.enabled)
+            .map(|integration| integration.name)
+            .collect();
+        format!(
+            "{}\nIntegrations: {}",
+            env!("CARGO_PKG_VERSION"),
+            if enabled.is_empty() { String::from("none") } else { enabled.join(", ") }
+        )
+    })
+}
@@ -0,0 +1,1151 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime}
+};
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, BorderType, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    Terminal
+};
+use crossterm::event::{
+    Event as InputEvent, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind
+};
+
+use staged_timer::{parse_duration, Timer, TimerStage};
+
+use crate::{
+    after_reached, agitation_reached, apply_command, blinks, checkpoint_reached, create_timer_list,
+    draw_timer, heart_rate, input, minute_elapsed, observe, overtime_reached, paint_background,
+    presets, schema, section_totals, session_totals, split_bottom, stage_summary, warning_reached,
+    ConfirmAction, Controls, DisplayOptions, PresetLoader, Round, SessionReport, StageAreas,
+    StageSpec, StageWatchers, ZONE_COLORS
+};
+use crate::config::Completion;
+use crate::render::Renderer;
+use crate::theme::{PaletteBackend, Theme};
+
+/// Let the user switch stages on and off before the timer starts.
+///
+/// Returns `false` if the user chose to quit instead of starting the timer.
+pub fn select_stages<B: Backend>(
+    terminal: &mut Terminal<B>,
+    inputs: &mut input::Inputs,
+    stages: &mut [StageSpec],
+    display: &DisplayOptions
+) -> Result<bool, io::Error>
+{
+    let mut list_state = ListState::default();
+    list_state.select(Some(0));
+
+    loop {
+        terminal.draw(|f| {
+            paint_background(f, &display.theme);
+            let items: Vec<ListItem> = stages.iter().map(|stage| {
+                ListItem::new(format!(
+                    "[{}] {} ({})",
+                    if stage.enabled { "x" } else { " " },
+                    stage.stage.name,
+                    display.time_format.format(stage.stage.period_s(), stage.stage.period_s())
+                ))
+                .style(Style::default().fg(
+                    if stage.enabled { display.theme.active() } else { display.theme.inactive() }
+                ))
+            }).collect();
+
+            let list = List::new(items)
+            .block(
+                Block::default()
+                .title("Select stages: SPACE toggles, ENTER starts, ESC quits")
+                .borders(Borders::NONE)
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+            .highlight_symbol("> ");
+
+            let area = Layout::default()
+            .margin(1)
+            .constraints([Constraint::Min(0)])
+            .split(f.size())[0];
+            f.render_stateful_widget(list, area, &mut list_state);
+        })?;
+
+        let selected = list_state.selected().unwrap_or(0);
+        if let InputEvent::Key(KeyEvent{modifiers, code}) = inputs.terminal_event() {
+            match code {
+                KeyCode::Esc => return Ok(false),
+                KeyCode::Char('c') if modifiers == KeyModifiers::CONTROL => {
+                    return Ok(false)
+                },
+                KeyCode::Up => list_state.select(Some(selected.saturating_sub(1))),
+                KeyCode::Down => list_state.select(
+                    Some((selected + 1).min(stages.len() - 1))
+                ),
+                KeyCode::Char(' ') => {
+                    stages[selected].enabled = !stages[selected].enabled
+                },
+                KeyCode::Enter if stages.iter().any(|stage| stage.enabled) => {
+                    return Ok(true)
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+/// What the text typed in the stage editor is for.
+enum InputField {
+    /// Name of a stage to add, whose time is typed next
+    NewName,
+    /// Time of a stage to add with this name
+    NewTime(String),
+    /// New name of the selected stage
+    Name,
+    /// New time of the selected stage
+    Time
+}
+
+/// A stage name or time being typed in the stage editor.
+struct StageInput {
+    field: InputField,
+    text: String,
+    /// Why the text was not taken
+    error: Option<String>
+}
+
+impl StageInput {
+    fn new(field: InputField, text: String) -> StageInput {
+        StageInput { field, text, error: None }
+    }
+
+    fn prompt(&self) -> String {
+        let field = match self.field {
+            InputField::NewName | InputField::Name => "Name",
+            InputField::NewTime(_) | InputField::Time => "Time"
+        };
+        match &self.error {
+            Some(error) => format!("{}: {}▏ ({})", field, self.text, error),
+            None => format!("{}: {}▏", field, self.text)
+        }
+    }
+}
+
+/// Let the user add, rename, reorder, re-time and switch stages on or off
+/// before the timer starts. Stages added start out as `new_stage`.
+///
+/// Returns `false` if the user chose to quit instead of starting the timer.
+pub fn edit_stages<B: Backend>(
+    terminal: &mut Terminal<B>,
+    inputs: &mut input::Inputs,
+    stages: &mut Vec<StageSpec>,
+    display: &DisplayOptions,
+    new_stage: &TimerStage
+) -> Result<bool, io::Error>
+{
+    let mut list_state = ListState::default();
+    list_state.select(Some(0));
+    let mut input: Option<StageInput> = None;
+
+    loop {
+        terminal.draw(|f| {
+            paint_background(f, &display.theme);
+            let items: Vec<ListItem> = stages.iter().map(|stage| {
+                ListItem::new(format!(
+                    "[{}] {} ({})",
+                    if stage.enabled { "x" } else { " " },
+                    stage.stage.name,
+                    display.time_format.format(stage.stage.period_s(), stage.stage.period_s())
+                ))
+                .style(Style::default().fg(
+                    if stage.enabled { display.theme.active() } else { display.theme.inactive() }
+                ))
+            }).collect();
+
+            let list = List::new(items)
+            .block(
+                Block::default()
+                .title("Edit stages: ENTER starts, ESC quits")
+                .borders(Borders::NONE)
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+            .highlight_symbol("> ");
+
+            let area = Layout::default()
+            .margin(1)
+            .constraints([Constraint::Min(0)])
+            .split(f.size())[0];
+            let (list_area, help_area) = split_bottom(area, 2);
+            f.render_stateful_widget(list, list_area, &mut list_state);
+            let help = match &input {
+                Some(input) => input.prompt(),
+                None if stages.is_empty() => String::from("A: add a stage"),
+                None => String::from(
+                    "A: add, R: rename, T: time, X: remove, SHIFT+↑↓: move, SPACE: on/off"
+                )
+            };
+            f.render_widget(
+                Paragraph::new(help).style(Style::default().fg(display.theme.muted())),
+                Rect { y: help_area.y + 1, height: 1, ..help_area }
+            );
+        })?;
+
+        let selected = list_state.selected().unwrap_or(0).min(stages.len().saturating_sub(1));
+        let (modifiers, code) = match inputs.terminal_event() {
+            InputEvent::Key(KeyEvent{modifiers, code}) => (modifiers, code),
+            _ => continue
+        };
+        if code == KeyCode::Char('c') && modifiers == KeyModifiers::CONTROL {
+            return Ok(false);
+        }
+
+        // TYPE the name or time of a stage, ENTER takes it and ESC cancels
+        if let Some(typing) = input.as_mut() {
+            match code {
+                KeyCode::Esc => input = None,
+                KeyCode::Backspace => {
+                    typing.text.pop();
+                    typing.error = None;
+                },
+                KeyCode::Char(c) => {
+                    typing.text.push(c);
+                    typing.error = None;
+                },
+                KeyCode::Enter if matches!(typing.field, InputField::NewName | InputField::Name) => {
+                    let name = typing.text.trim().to_string();
+                    if name.is_empty() {
+                        typing.error = Some(String::from("a stage needs a name"));
+                    } else if let InputField::NewName = typing.field {
+                        *typing = StageInput::new(InputField::NewTime(name), String::new());
+                    } else {
+                        stages[selected].stage.name = name;
+                        input = None;
+                    }
+                },
+                KeyCode::Enter => match parse_duration(typing.text.trim()) {
+                    Err(message) => typing.error = Some(message),
+                    Ok(period) => {
+                        if let InputField::NewTime(name) = &typing.field {
+                            let at = if stages.is_empty() { 0 } else { selected + 1 };
+                            stages.insert(at, StageSpec {
+                                stage: TimerStage { name: name.clone(), period, ..new_stage.clone() },
+                                enabled: true
+                            });
+                            list_state.select(Some(at));
+                        } else {
+                            let stage = &mut stages[selected].stage;
+                            stage.period = period;
+                            stage.count_up = false;
+                        }
+                        input = None;
+                    }
+                },
+                _ => {}
+            }
+            continue;
+        }
+
+        match code {
+            KeyCode::Esc => return Ok(false),
+            // MOVE the selected stage with SHIFT and the arrow keys
+            KeyCode::Up if modifiers.contains(KeyModifiers::SHIFT) && selected > 0 => {
+                stages.swap(selected, selected - 1);
+                list_state.select(Some(selected - 1));
+            },
+            KeyCode::Down if modifiers.contains(KeyModifiers::SHIFT)
+                && selected + 1 < stages.len() =>
+            {
+                stages.swap(selected, selected + 1);
+                list_state.select(Some(selected + 1));
+            },
+            KeyCode::Up => list_state.select(Some(selected.saturating_sub(1))),
+            KeyCode::Down => list_state.select(
+                Some((selected + 1).min(stages.len().saturating_sub(1)))
+            ),
+            KeyCode::Char('a') => {
+                input = Some(StageInput::new(InputField::NewName, String::new()))
+            },
+            KeyCode::Char('r') if !stages.is_empty() => {
+                let name = stages[selected].stage.name.clone();
+                input = Some(StageInput::new(InputField::Name, name));
+            },
+            KeyCode::Char('t') if !stages.is_empty() => {
+                let period_s = stages[selected].stage.period_s();
+                let time = display.time_format.format(period_s, period_s);
+                input = Some(StageInput::new(InputField::Time, time));
+            },
+            KeyCode::Char('x') | KeyCode::Delete if !stages.is_empty() => {
+                stages.remove(selected);
+                list_state.select(Some(selected.min(stages.len().saturating_sub(1))));
+            },
+            KeyCode::Char(' ') if !stages.is_empty() => {
+                stages[selected].enabled = !stages[selected].enabled
+            },
+            KeyCode::Enter if stages.iter().any(|stage| stage.enabled) => {
+                return Ok(true)
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Show how the run went until a key is pressed.
+pub fn show_summary<B: Backend>(
+    terminal: &mut Terminal<B>,
+    inputs: &mut input::Inputs,
+    timer: &Timer,
+    report: &SessionReport,
+    display: &DisplayOptions
+) -> Result<(), io::Error>
+{
+    loop {
+        terminal.draw(|f| {
+            paint_background(f, &display.theme);
+            let mut items: Vec<ListItem> = timer.stages().iter()
+                .zip(&report.actual_s)
+                .filter(|(stage, _)| !stage.is_marker())
+                .map(|(stage, actual_s)| {
+                    ListItem::new(stage_summary(stage, *actual_s, display))
+                    .style(Style::default().fg(
+                        if stage.aborted { ZONE_COLORS[4] } else { display.theme.active() }
+                    ))
+                })
+                .collect();
+            // an empty string has no lines at all
+            items.push(ListItem::new(Spans::default()));
+            items.extend(section_totals(timer, report, display).into_iter().map(ListItem::new));
+            items.push(ListItem::new(session_totals(timer, report, display)));
+            if !report.warnings.is_empty() {
+                items.push(ListItem::new(Spans::default()));
+            }
+            items.extend(report.warnings.iter().map(|warning| {
+                ListItem::new(format!("Warning: {}", warning))
+                .style(Style::default().fg(display.theme.warning()))
+            }));
+
+            let list = List::new(items)
+            .block(
+                Block::default()
+                .title("All stages are done, press any key to exit")
+                .borders(Borders::NONE)
+            );
+
+            let area = Layout::default()
+            .margin(1)
+            .constraints([Constraint::Min(0)])
+            .split(f.size())[0];
+            f.render_widget(list, area);
+        })?;
+
+        if let InputEvent::Key(_) = inputs.terminal_event() {
+            return Ok(());
+        }
+    }
+}
+
+/// Let the user pick the preset to run next, with the `error` that kept the
+/// last pick from running.
+///
+/// Returns `None` if the user chose to quit instead.
+pub fn select_preset<B: Backend>(
+    terminal: &mut Terminal<B>,
+    inputs: &mut input::Inputs,
+    error: Option<&str>,
+    theme: &Theme
+) -> Result<Option<String>, io::Error>
+{
+    let (names, list_error) = match presets::list() {
+        Ok(presets) => (presets.into_iter().map(|(name, _)| name).collect(), None),
+        Err(message) => (Vec::new(), Some(message))
+    };
+    let error = error.or(list_error.as_deref());
+    let mut list_state = ListState::default();
+    list_state.select(Some(0));
+
+    loop {
+        terminal.draw(|f| {
+            paint_background(f, theme);
+            let items: Vec<ListItem> = if names.is_empty() {
+                vec![ListItem::new("No presets have been saved yet.")]
+            } else {
+                names.iter().map(|name| ListItem::new(name.as_str())).collect()
+            };
+
+            let list = List::new(items)
+            .block(
+                Block::default()
+                .title("Run a preset next: ENTER starts, ESC quits")
+                .borders(Borders::NONE)
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+            .highlight_symbol("> ");
+
+            let sections = Layout::default()
+            .margin(1)
+            .constraints([Constraint::Min(0), Constraint::Length(2)])
+            .split(f.size());
+            f.render_stateful_widget(list, sections[0], &mut list_state);
+            if let Some(error) = error {
+                f.render_widget(
+                    Paragraph::new(error)
+                    .style(Style::default().fg(theme.warning()))
+                    .wrap(Wrap { trim: true }),
+                    sections[1]
+                );
+            }
+        })?;
+
+        let selected = list_state.selected().unwrap_or(0);
+        if let InputEvent::Key(KeyEvent{modifiers, code}) = inputs.terminal_event() {
+            match code {
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Char('c') if modifiers == KeyModifiers::CONTROL => {
+                    return Ok(None)
+                },
+                KeyCode::Up => list_state.select(Some(selected.saturating_sub(1))),
+                KeyCode::Down => list_state.select(
+                    Some((selected + 1).min(names.len().saturating_sub(1)))
+                ),
+                KeyCode::Enter if !names.is_empty() => {
+                    return Ok(Some(names[selected].clone()))
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+/// How far the user scrolled the stage list away from the running stage.
+#[derive(Clone, Copy, Default)]
+pub struct Scroll {
+    /// The running stage when the list was scrolled
+    stage: usize,
+    /// Stages scrolled down, negative for up
+    offset: isize
+}
+
+impl Scroll {
+    /// How far the list is scrolled, back to the running stage once the
+    /// timer moved on to another one.
+    pub fn offset(self, timer: &Timer) -> isize {
+        if timer.current_index() == self.stage { self.offset } else { 0 }
+    }
+
+    /// Scrolled `stages` further down, or up if negative.
+    fn by(self, timer: &Timer, stages: isize) -> Scroll {
+        let count = isize::try_from(timer.stages().len()).unwrap_or(isize::MAX);
+        Scroll {
+            stage: timer.current_index(),
+            offset: (self.offset(timer) + stages).clamp(-count, count)
+        }
+    }
+}
+
+/// The timers of a run, a single one unless several run side by side, each
+/// with watchers of its own and a label for the column it is drawn in.
+pub struct Timers {
+    pub timers: Vec<Timer>,
+    pub watchers: Vec<StageWatchers>,
+    pub labels: Vec<String>,
+    /// The round of `--loop` the first timer is in
+    pub round: Option<Round>,
+    /// Reloads the stages of the first timer from its config file
+    pub reload: Option<StageReload>
+}
+
+/// The terminal the TUI draws on, in the colours of the palette and mirrored
+/// for `attach --observe`.
+type TuiTerminal = Terminal<PaletteBackend<observe::Mirror<CrosstermBackend<Box<dyn Write>>>>>;
+
+/// The TUI as a [`Renderer`], along with what it shows besides the timer.
+/// Timers run side by side are drawn in columns of their own, titled by
+/// their `labels`.
+struct Screen<'a> {
+    terminal: &'a mut TuiTerminal,
+    display: &'a DisplayOptions,
+    labels: &'a [String],
+    /// The round of `--loop` the first timer is in
+    round: Option<Round>,
+    /// Timer the keys go to
+    focus: usize,
+    /// Whether each timer has been started, those after the first one wait
+    /// for SPACE
+    started: Vec<bool>,
+    /// Scroll of the timer in focus
+    scroll: Scroll,
+    /// Action waiting for the user to confirm it
+    pending_confirmation: Option<ConfirmAction>,
+    /// Shown until the first key press
+    notice: Option<String>,
+    /// Why the heart rate ended or extended the last rest, for a few seconds
+    rest_notice: Option<String>,
+    /// Where each timer and its stages were drawn last
+    areas: Vec<(Rect, StageAreas)>
+}
+
+impl Screen<'_> {
+    fn draw(&mut self, timers: &[Timer]) -> io::Result<()> {
+        let (display, labels, focus, scroll, round) = (self.display, self.labels, self.focus, self.scroll, self.round);
+        let started = &self.started;
+        let prompt = self.pending_confirmation.map(ConfirmAction::prompt)
+            .or(self.notice.as_deref())
+            .or(self.rest_notice.as_deref());
+        let mut areas = Vec::new();
+        self.terminal.draw(|f| {
+            paint_background(f, &display.theme);
+            if let [timer] = timers {
+                areas.push((f.size(), draw_timer(f, f.size(), timer, round, display, scroll, prompt)));
+                return;
+            }
+            let size = f.size();
+            let count = u16::try_from(timers.len()).unwrap_or(u16::MAX);
+            for (index, timer) in timers.iter().enumerate() {
+                // the last column takes what is left over of the width
+                let x = size.x + size.width / count * index as u16;
+                let width = if index + 1 == timers.len() { size.right() - x } else { size.width / count };
+                let column = Rect { x, width, ..size };
+                let focused = index == focus;
+                let block = Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(if focused { BorderType::Thick } else { BorderType::Plain })
+                    .border_style(Style::default().fg(if focused {
+                        display.theme.active()
+                    } else {
+                        display.theme.muted()
+                    }))
+                    .title(Span::styled(
+                        format!(" {} ", labels.get(index).map_or("", String::as_str)),
+                        Style::default().add_modifier(Modifier::BOLD)
+                    ));
+                let inner = block.inner(column);
+                f.render_widget(block, column);
+                let prompt = match (started[index], focused) {
+                    (false, true) => Some("Press SPACE to start"),
+                    (false, false) => Some("Not started yet"),
+                    (true, true) => prompt,
+                    (true, false) => None
+                };
+                let scroll = if focused { scroll } else { Scroll::default() };
+                let round = round.filter(|_| index == 0);
+                areas.push((column, draw_timer(f, inner, timer, round, display, scroll, prompt)));
+            }
+        })?;
+        self.areas = areas;
+        Ok(())
+    }
+
+    /// The timer drawn at `column` and `row`, if any.
+    fn timer_at(&self, column: u16, row: u16) -> Option<usize> {
+        self.areas.iter().position(|(area, _)| {
+            (area.left()..area.right()).contains(&column)
+                && (area.top()..area.bottom()).contains(&row)
+        })
+    }
+}
+
+impl Renderer for Screen<'_> {
+    fn render(&mut self, timer: &Timer) -> io::Result<()> {
+        self.draw(std::slice::from_ref(timer))
+    }
+}
+
+/// Pauses a timer whose stage waits for the user to press ENTER once no key
+/// has been pressed for a while, and rings every so often after that until
+/// one is, so a forgotten stage doesn't hold up the plan unnoticed.
+struct IdleWatch {
+    window: Duration,
+    /// Last key press, or when the stage started waiting if later
+    active: Instant,
+    /// Whether the running stage waits for the user
+    waiting: bool,
+    /// When the alarm last rang, since the timer was paused for being idle
+    alarmed: Option<Instant>
+}
+
+impl IdleWatch {
+    fn new(window: Duration) -> IdleWatch {
+        IdleWatch { window, active: Instant::now(), waiting: false, alarmed: None }
+    }
+
+    /// Someone pressed a key, so the timer is no longer left alone.
+    fn input(&mut self) {
+        self.active = Instant::now();
+        self.alarmed = None;
+    }
+
+    /// Pause `timer` once it has waited for the user for longer than the
+    /// window. Returns whether to ring the alarm.
+    fn check(&mut self, timer: &mut Timer) -> bool {
+        let waiting = timer.is_waiting()
+            || timer.current_stage().is_some_and(TimerStage::awaits_finish);
+        // a pause of the user's own isn't forgotten
+        let paused_by_user = timer.is_paused() && self.alarmed.is_none();
+        if !waiting || paused_by_user {
+            self.waiting = false;
+            self.alarmed = None;
+            return false;
+        }
+        if !self.waiting {
+            self.waiting = true;
+            self.active = Instant::now();
+        }
+        let last = self.alarmed.unwrap_or(self.active);
+        if last.elapsed() < self.window {
+            return false;
+        }
+        timer.pause();
+        self.alarmed = Some(Instant::now());
+        true
+    }
+}
+
+/// How often the config file of a run is checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reloads the stages of a run from the config file they came from once it
+/// changes, so edits apply to the stages that haven't started yet.
+pub struct StageReload {
+    path: PathBuf,
+    presets: PresetLoader,
+    /// When the file was changed last, as of the last check
+    modified: Option<SystemTime>,
+    checked: Instant,
+    /// The stages as they were last reloaded, for runs that start over
+    pub planned: Option<Vec<TimerStage>>
+}
+
+impl StageReload {
+    pub fn new(path: PathBuf, presets: PresetLoader) -> StageReload {
+        let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+        StageReload { path, presets, modified, checked: Instant::now(), planned: None }
+    }
+
+    /// Give `timer` the stages after the running one from the file if it
+    /// changed since the last check. Returns the notice to show about it.
+    fn check(&mut self, timer: &mut Timer) -> Option<String> {
+        if self.checked.elapsed() < RELOAD_INTERVAL || timer.is_finished() {
+            return None;
+        }
+        self.checked = Instant::now();
+        let modified = fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok();
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        match self.presets.load_path(&self.path, Completion::Exit) {
+            Ok((stages, _)) => {
+                let planned = create_timer_list(stages);
+                if let Err(message) = timer.replace_upcoming(planned.clone()) {
+                    return Some(format!("Did not reload {}: {}", name, message));
+                }
+                self.planned = Some(planned);
+                Some(format!("Reloaded the stages from {}", name))
+            },
+            // the first line says which file and where it went wrong
+            Err(message) => message.lines().next().map(String::from)
+        }
+    }
+}
+
+/// How often the TUI draws the timers while they are animated.
+const FRAME_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long finished timers stay on screen before the TUI exits.
+const FINISHED_LINGER: Duration = Duration::from_secs(1);
+
+/// Longest the TUI sleeps without an input, to look after idle timers, held
+/// back notices and changes to the config file.
+const IDLE_WAKE: Duration = Duration::from_secs(1);
+
+/// Whether `timer` shows more than its seconds: a pacing guide, flashing
+/// agitation, blinking final seconds or tenths counting down.
+fn is_animated(timer: &Timer, display: &DisplayOptions) -> bool {
+    timer.is_counting() && (
+        timer.stages().iter().any(|stage| display.shows_tenths(stage.period))
+        || timer.current_stage().is_some_and(|stage| {
+            stage.pace_cpm.is_some() || stage.is_agitating() || blinks(stage, &display.theme)
+        })
+    )
+}
+
+/// How long the TUI can sleep before the timers that were `started` have
+/// something new to show: the next frame of an animation, or the next second
+/// of a running stage or a pause.
+fn until_next_frame(timers: &[Timer], started: &[bool], display: &DisplayOptions) -> Duration {
+    let started = || timers.iter().zip(started).filter(|(_, started)| **started).map(|(timer, _)| timer);
+    if started().any(|timer| is_animated(timer, display)) {
+        return FRAME_INTERVAL;
+    }
+    started()
+        .filter_map(|timer| timer.until_next_step().or_else(|| {
+            let paused_for = timer.paused_for()?;
+            Some(Duration::from_secs(1) - Duration::from_nanos(paused_for.subsec_nanos().into()))
+        }))
+        .fold(IDLE_WAKE, Duration::min)
+}
+
+/// Why the TUI stopped running the timer.
+pub enum TuiExit {
+    /// All stages are done or the user quit
+    Stopped,
+    /// The user asked to carry on in the background
+    Detached
+}
+
+/// Run the timers in the TUI until all stages of all of them are done or the
+/// user quits. Keys and commands go to the timer in focus, which TAB and
+/// clicks move on. `notice` is shown until the first key press.
+pub fn run_tui(
+    terminal: &mut TuiTerminal,
+    timers: &mut Timers,
+    display: &DisplayOptions,
+    controls: &Controls,
+    inputs: &mut input::Inputs,
+    notice: Option<&str>
+) -> Result<TuiExit, io::Error>
+{
+    let Timers { timers, watchers, labels, round, reload } = timers;
+    // the heart rate gates the rests of the first timer
+    let mut rest_gate = controls.heart_rate.clone().map(heart_rate::RestGate::new);
+    let idle_notice = controls.idle_pause.map(|window| {
+        let window_s = u32::try_from(window.as_secs()).unwrap_or(u32::MAX);
+        format!("Paused after {} without a key press", display.time_format.format(window_s, window_s))
+    });
+    let mut idle_watches: Vec<Option<IdleWatch>> = timers.iter()
+        .map(|_| controls.idle_pause.map(IdleWatch::new))
+        .collect();
+    let mut screen = Screen {
+        terminal,
+        display,
+        labels,
+        round: *round,
+        focus: 0,
+        // the first timer starts right away
+        started: (0..timers.len()).map(|index| index == 0).collect(),
+        scroll: Scroll::default(),
+        pending_confirmation: None,
+        notice: notice.map(String::from),
+        rest_notice: None,
+        areas: Vec::new()
+    };
+    // advance by the time measured on the monotonic clock rather than by
+    // counting ticks, so neither pauses nor a slow loop make the timer drift.
+    // The first stage starts with the clock, so the time its hooks and
+    // sounds take comes out of it rather than before it.
+    let clock = display.run_clock();
+    let mut last_advance = clock.now();
+    screen.draw(timers)?;
+    watchers[0].update(&timers[0]);
+
+    let mut shown_pause_s: Vec<Option<u64>> = vec![None; timers.len()];
+    let mut keep_running = true;
+    let mut finished_at: Option<Instant> = None;
+    while keep_running {
+        // sleep until an input comes in or the timers have something new to
+        // show
+        let linger = finished_at.map_or(IDLE_WAKE, |at| FINISHED_LINGER.saturating_sub(at.elapsed()));
+        // hooks, sounds and drawing since the timers were last advanced took
+        // from the wait already
+        let until_next_frame = until_next_frame(timers, &screen.started, display)
+            .saturating_sub(clock.now() - last_advance);
+        let until_next_frame = clock.wall_time(until_next_frame);
+        let input = inputs.next(until_next_frame.min(linger));
+        let frame_started = Instant::now();
+        let now = clock.now();
+        let mut ticked = false;
+        for ((timer, watchers), _) in timers.iter_mut().zip(watchers.iter_mut())
+            .zip(&screen.started)
+            .filter(|(_, started)| **started)
+        {
+            let stage_before = timer.current_index();
+            let stepped = timer.advance(now - last_advance) > 0;
+            if !timer.follow_deadline(SystemTime::now()) && !stepped {
+                continue;
+            }
+            ticked = true;
+            watchers.update(timer);
+            // stages that end are announced by the watchers already
+            if timer.current_index() != stage_before {
+                continue;
+            }
+            if warning_reached(timer, display.warning_threshold) {
+                watchers.warn(timer);
+            } else if minute_elapsed(timer)
+                || checkpoint_reached(timer)
+                || agitation_reached(timer)
+                || after_reached(timer)
+                || overtime_reached(timer)
+                || timer.is_waiting()
+            {
+                watchers.alert();
+            }
+        }
+        last_advance = now;
+        if let Some(gate) = rest_gate.as_mut().filter(|_| ticked) {
+            if gate.check(&mut timers[0]).is_some() {
+                watchers[0].update(&timers[0]);
+            }
+            screen.rest_notice = gate.notice().map(String::from);
+        }
+        for ((timer, watchers), _) in timers.iter().zip(watchers.iter_mut())
+            .zip(&screen.started)
+            .filter(|(_, started)| **started)
+        {
+            watchers.dispatch_held(timer);
+        }
+        for (((timer, watchers), idle), _) in timers.iter_mut().zip(watchers.iter_mut())
+            .zip(&mut idle_watches)
+            .zip(&screen.started)
+            .filter(|(_, started)| **started)
+        {
+            if idle.as_mut().is_some_and(|idle| idle.check(timer)) {
+                watchers.alert();
+                screen.notice = idle_notice.clone();
+            }
+        }
+        if let Some(reload) = reload.as_mut() {
+            if let Some(notice) = reload.check(&mut timers[0]) {
+                // a run saved for `--resume` starts over with the new stages
+                if let (Some(checkpoint), Some(planned)) = (&mut watchers[0].checkpoint, &reload.planned) {
+                    checkpoint.planned = planned.clone();
+                }
+                watchers[0].update(&timers[0]);
+                screen.notice = Some(notice);
+                screen.draw(timers)?;
+            }
+        }
+        // a frame is advancing the timers and drawing them, whatever else
+        // happens on a tick takes its own time
+        let advance_took = frame_started.elapsed();
+        if ticked {
+            let drawing = Instant::now();
+            keep_running = screen.draw(timers).is_ok() && keep_running;
+            watchers[0].frames.record(advance_took + drawing.elapsed());
+        }
+
+        // leave the finished timers on screen for a moment before exiting
+        if timers.iter().all(Timer::is_finished)
+            && finished_at.get_or_insert(frame_started).elapsed() >= FINISHED_LINGER
+        {
+            keep_running = false;
+        }
+
+        // animate between ticks
+        let smooth = timers.iter().zip(&screen.started)
+            .any(|(timer, started)| *started && is_animated(timer, display));
+        // and count up how long the timers have been paused
+        let pause_s: Vec<Option<u64>> = timers.iter()
+            .map(|timer| timer.paused_for().map(|paused_for| paused_for.as_secs()))
+            .collect();
+        let pause_changed = pause_s != shown_pause_s;
+        shown_pause_s = pause_s;
+        if !ticked && (smooth || pause_changed) {
+            let drawing = Instant::now();
+            screen.draw(timers)?;
+            watchers[0].frames.record(advance_took + drawing.elapsed());
+        }
+
+        let count = timers.len();
+        let focus = screen.focus;
+        let idle = !screen.started[focus];
+        let (timer, watchers) = (&mut timers[focus], &mut watchers[focus]);
+        if let Some(input::Input::Command(_) | input::Input::Terminal(InputEvent::Key(_))) = input {
+            idle_watches.iter_mut().flatten().for_each(IdleWatch::input);
+        }
+        match input {
+            // a timer that hasn't started yet starts with a command to go on
+            Some(input::Input::Command(input::Command::TogglePause | input::Command::Resume))
+                if idle =>
+            {
+                screen.started[focus] = true;
+                watchers.update(timer);
+                screen.draw(timers)?;
+            },
+            // commands from outside the terminal are carried out right away
+            Some(input::Input::Command(command)) if !idle && apply_command(timer, watchers, command) => {
+                screen.draw(timers)?;
+            },
+            Some(input::Input::Status(request)) => request.answer(timer),
+            Some(input::Input::Terminal(event)) => {
+                if let InputEvent::Key(_)
+                    | InputEvent::Mouse(MouseEvent{kind: MouseEventKind::Down(_), ..}) = event
+                {
+                    screen.notice = None;
+                }
+                match event {
+                    // EXIT immediately with CTRL+C
+                    InputEvent::Key(KeyEvent{
+                        modifiers: KeyModifiers::CONTROL,
+                        code: KeyCode::Char('c')
+                    }) => break,
+
+                    // CONFIRM a pending action with Y, any other key cancels it
+                    InputEvent::Key(KeyEvent{code, ..})
+                    if screen.pending_confirmation.is_some() => {
+                        let action = screen.pending_confirmation.take();
+                        match (action, code) {
+                            (Some(ConfirmAction::Quit), KeyCode::Char('y')) => break,
+                            (Some(ConfirmAction::Abort), KeyCode::Char('y')) => {
+                                watchers.adjusted(timer, schema::Adjustment::Aborted, None);
+                                timer.abort_stage();
+                                watchers.update(timer);
+                            },
+                            (Some(ConfirmAction::Abort), KeyCode::Char('x')) => {
+                                watchers.adjusted(timer, schema::Adjustment::Aborted, None);
+                                timer.abort();
+                                if timers.iter().all(Timer::is_finished) {
+                                    break;
+                                }
+                            },
+                            (Some(ConfirmAction::Skip), KeyCode::Char('y')) => {
+                                apply_command(timer, watchers, input::Command::Skip);
+                            },
+                            (Some(ConfirmAction::SkipGroup), KeyCode::Char('y')) => {
+                                apply_command(timer, watchers, input::Command::SkipGroup);
+                            },
+                            (Some(ConfirmAction::Jump(stage)), KeyCode::Char('y')) => {
+                                watchers.adjusted(timer, schema::Adjustment::Jumped, None);
+                                let going_back = stage < timer.current_index();
+                                timer.jump_to(stage);
+                                watchers.update(timer);
+                                // moving on is announced by the watchers already
+                                if going_back {
+                                    watchers.alert();
+                                }
+                            },
+                            _ => {}
+                        }
+                        screen.draw(timers)?;
+                    },
+
+                    // EXIT with ESC
+                    InputEvent::Key(KeyEvent{
+                        code: KeyCode::Esc,
+                        ..
+                    }) => {
+                        if !controls.confirm.quit {
+                            break;
+                        }
+                        screen.pending_confirmation = Some(ConfirmAction::Quit);
+                        screen.draw(timers)?;
+                    },
+
+                    // FOCUS the next timer with TAB, or the previous one with
+                    // SHIFT+TAB
+                    InputEvent::Key(KeyEvent{
+                        code: code @ (KeyCode::Tab | KeyCode::BackTab),
+                        ..
+                    }) if count > 1 => {
+                        screen.focus = if code == KeyCode::Tab {
+                            (focus + 1) % count
+                        } else {
+                            (focus + count - 1) % count
+                        };
+                        screen.scroll = Scroll::default();
+                        screen.draw(timers)?;
+                    },
+
+                    // START a timer waiting for it with SPACE, keys for the
+                    // running stage have nothing to act on until then
+                    InputEvent::Key(KeyEvent{code, ..}) if idle => {
+                        if code == KeyCode::Char(' ') {
+                            screen.started[focus] = true;
+                            watchers.update(timer);
+                        }
+                        screen.draw(timers)?;
+                    },
+
+                    // ABORT the running stage with A, after confirming it and
+                    // whether to go on
+                    InputEvent::Key(KeyEvent{
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('a')
+                    }) if !timer.is_finished() => {
+                        screen.pending_confirmation = Some(ConfirmAction::Abort);
+                        screen.draw(timers)?;
+                    },
+
+                    // SKIP to the next stage with N
+                    InputEvent::Key(KeyEvent{
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('n')
+                    }) if !timer.is_finished() => {
+                        if controls.confirm.skip {
+                            screen.pending_confirmation = Some(ConfirmAction::Skip);
+                        } else {
+                            apply_command(timer, watchers, input::Command::Skip);
+                        }
+                        screen.draw(timers)?;
+                    },
+
+                    // SKIP the rest of the running stage's section with
+                    // SHIFT+N
+                    InputEvent::Key(KeyEvent{
+                        code: KeyCode::Char('N'),
+                        ..
+                    }) if !timer.is_finished() => {
+                        if controls.confirm.skip {
+                            screen.pending_confirmation = Some(ConfirmAction::SkipGroup);
+                        } else {
+                            apply_command(timer, watchers, input::Command::SkipGroup);
+                        }
+                        screen.draw(timers)?;
+                    },
+
+                    // RESTART the running stage with R, or go back to the
+                    // PREVIOUS one with P
+                    InputEvent::Key(KeyEvent{
+                        modifiers: KeyModifiers::NONE,
+                        code: code @ (KeyCode::Char('r') | KeyCode::Char('p'))
+                    }) if !timer.is_finished() => {
+                        if code == KeyCode::Char('p') {
+                            watchers.adjusted(timer, schema::Adjustment::Previous, None);
+                            timer.previous_stage();
+                        } else {
+                            watchers.adjusted(timer, schema::Adjustment::Restarted, None);
+                            timer.restart_stage();
+                        }
+                        watchers.update(timer);
+                        watchers.restart_stage(timer);
+                        screen.draw(timers)?;
+                    },
+
+                    // EXTEND the running stage with + or SHORTEN it with -
+                    InputEvent::Key(KeyEvent{
+                        code: code @ (KeyCode::Char('+') | KeyCode::Char('-')),
+                        ..
+                    }) if !timer.is_finished() => {
+                        apply_command(timer, watchers, if code == KeyCode::Char('+') {
+                            input::Command::Extend(controls.adjust_step)
+                        } else {
+                            input::Command::Shorten(controls.adjust_step)
+                        });
+                        screen.draw(timers)?;
+                    },
+
+                    // FINISH a stage counting up, in overtime or waiting with ENTER
+                    InputEvent::Key(KeyEvent{
+                        code: KeyCode::Enter,
+                        ..
+                    }) if timer.is_waiting()
+                        || timer.current_stage().is_some_and(TimerStage::awaits_finish) =>
+                    {
+                        apply_command(timer, watchers, input::Command::Finish);
+                        screen.draw(timers)?;
+                    },
+
+                    // DETACH with D to carry on in the background
+                    InputEvent::Key(KeyEvent{
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('d')
+                    }) if !timer.is_finished() => {
+                        if display.dry_run.is_some() {
+                            screen.notice = Some(String::from("Dry runs can't be detached"));
+                        } else if count == 1 {
+                            return Ok(TuiExit::Detached);
+                        } else {
+                            screen.notice = Some(String::from("Timers side by side can't be detached"));
+                        }
+                        screen.draw(timers)?;
+                    },
+
+                    // PAUSE timer with SPACE BAR
+                    InputEvent::Key(KeyEvent{
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char(' ')
+                    }) => {
+                        apply_command(timer, watchers, input::Command::TogglePause);
+                        screen.draw(timers)?;
+                    },
+
+                    // SCROLL through the stages with the arrow keys
+                    InputEvent::Key(KeyEvent{
+                        code: code @ (KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right),
+                        ..
+                    }) => {
+                        let stages = if matches!(code, KeyCode::Up | KeyCode::Left) { -1 } else { 1 };
+                        screen.scroll = screen.scroll.by(timer, stages);
+                        screen.draw(timers)?;
+                    },
+
+                    // FOCUS another timer by clicking it, PAUSE by clicking
+                    // the running stage, JUMP to another stage by clicking it
+                    InputEvent::Mouse(MouseEvent{
+                        kind: MouseEventKind::Down(MouseButton::Left),
+                        column,
+                        row,
+                        ..
+                    }) => {
+                        // a click cancels a pending action like any other key
+                        let cancelled = screen.pending_confirmation.take().is_some();
+                        let clicked_timer = screen.timer_at(column, row);
+                        if clicked_timer.is_some_and(|clicked| clicked != focus) {
+                            screen.focus = clicked_timer.unwrap_or(focus);
+                            screen.scroll = Scroll::default();
+                            screen.draw(timers)?;
+                            continue;
+                        }
+                        // a timer waiting to start starts with a click on it
+                        if idle {
+                            if !cancelled && clicked_timer.is_some() {
+                                screen.started[focus] = true;
+                                watchers.update(timer);
+                            }
+                            screen.draw(timers)?;
+                            continue;
+                        }
+                        let clicked = screen.areas.get(focus)
+                            .and_then(|(_, stages)| stages.stage_at(column, row))
+                            .filter(|_| !cancelled && !timer.is_finished());
+                        match clicked {
+                            Some(stage) if stage == timer.current_index() => {
+                                timer.toggle_pause();
+                                watchers.update(timer);
+                            },
+                            Some(stage) if timer.stages()[stage].is_marker() => {},
+                            Some(stage) if controls.confirm.jump => {
+                                screen.pending_confirmation = Some(ConfirmAction::Jump(stage));
+                            },
+                            Some(stage) => {
+                                watchers.adjusted(timer, schema::Adjustment::Jumped, None);
+                                let going_back = stage < timer.current_index();
+                                timer.jump_to(stage);
+                                watchers.update(timer);
+                                // moving on is announced by the watchers already
+                                if going_back {
+                                    watchers.alert();
+                                }
+                            },
+                            None => {}
+                        }
+                        screen.draw(timers)?;
+                    },
+
+                    // SCROLL through the stages with the mouse wheel
+                    InputEvent::Mouse(MouseEvent{
+                        kind: kind @ (MouseEventKind::ScrollUp | MouseEventKind::ScrollDown),
+                        ..
+                    }) => {
+                        let stages = if kind == MouseEventKind::ScrollUp { -1 } else { 1 };
+                        screen.scroll = screen.scroll.by(timer, stages);
+                        screen.draw(timers)?;
+                    },
+
+                    InputEvent::Resize(_, _) => screen.draw(timers)?,
+                    _ => {}
+                }
+            },
+            Some(input::Input::HeartRate(bpm)) => {
+                if let Some(gate) = rest_gate.as_mut() {
+                    gate.reading(bpm);
+                }
+            },
+            Some(input::Input::Command(_)) | None => {}
+        }
+    }
+
+    Ok(TuiExit::Stopped)
+}
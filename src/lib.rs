@@ -0,0 +1,48 @@
+//! Timers made of several stages that run one after the other, e.g. the
+//! steps of developing a film or the intervals of a workout.
+//!
+//! ```
+//! use std::time::{Duration, Instant};
+//! use staged_timer::{Timer, TimerStage};
+//!
+//! let mut timer = Timer::new(vec![
+//!     TimerStage::new(String::from("Developer"), Duration::from_secs(2)),
+//!     TimerStage::new(String::from("Stop bath"), Duration::from_millis(500))
+//! ]);
+//! let mut last = Instant::now();
+//! while !timer.is_finished() {
+//!     std::thread::sleep(Duration::from_millis(100));
+//!     let now = Instant::now();
+//!     timer.advance(now - last);
+//!     last = now;
+//! }
+//! ```
+//!
+//! Frontends can follow a timer through an [`EventStream`] rather than
+//! comparing its state themselves. To embed the timer without keeping time
+//! at all, [`StagedTimer`] advances it with the clock and calls back with
+//! every event. Built for wasm32, it keeps time with a [`Clock`] it is
+//! given, and the `wasm` feature adds bindings for JavaScript. A
+//! [`ScaledClock`] runs a timer faster than the wall clock.
+//!
+//! The `staged-timer` binary is a terminal frontend for this library.
+
+pub mod compensation;
+mod engine;
+mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod time;
+mod timer;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use engine::{Events, SystemClock};
+pub use engine::{Clock, ScaledClock, StagedTimer};
+pub use events::{EventStream, TimerEvent};
+pub use time::{
+    format_seconds, parse_duration, parse_duration_with, parse_time, parse_time_with,
+    DecimalSeparator, TimeFormat
+};
+pub use timer::{Agitation, Checkpoint, CountdownCue, Exercise, Phase, Timer, TimerStage};
@@ -1,345 +1,3724 @@
-use std::{io, thread, sync::mpsc::channel, time::Duration};
+use std::{
+    fmt::Write as _,
+    io,
+    io::{IsTerminal, Write},
+    process::ExitCode,
+    thread,
+    time::{Duration, Instant, SystemTime}
+};
 use tui::{
     backend::Backend,
     backend::CrosstermBackend,
-    widgets::{Block, Gauge, Borders},
-    layout::{Layout, Constraint, Direction},
+    buffer::Buffer,
+    widgets::{
+        Widget, Block, BorderType, Clear, Gauge, LineGauge, Borders, Paragraph, Sparkline, Wrap
+    },
+    layout::{Alignment, Margin, Rect},
     style::{Style, Color, Modifier},
+    text::{Span, Spans},
+    symbols,
+    Frame,
     Terminal
 };
-use crossterm::{
-    event::{
-        read as read_event,
-        poll as poll_event,
-        Event as InputEvent,
-        KeyEvent,
-        KeyModifiers,
-        KeyCode,
-        DisableMouseCapture,
-        EnableMouseCapture
-    },
-    execute,
-    terminal::{
-        disable_raw_mode,
-        enable_raw_mode,
-        EnterAlternateScreen,
-        LeaveAlternateScreen
-    },
+
+use std::{collections::HashMap, fs, ops::Range, path::{Path, PathBuf}};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use staged_timer::{
+    Checkpoint, Clock, CountdownCue, EventStream, Exercise, ScaledClock, SystemClock, TimeFormat,
+    Timer, TimerEvent, TimerStage
 };
 
-extern crate clap;
-use clap::{Arg, App, builder::TypedValueParser};
+mod args;
+mod audio;
+mod backup;
+mod calibration;
+mod chemicals;
+mod cli;
+// limits of the control port, which only Unix has, and of --serve
+#[cfg(any(unix, feature = "http"))]
+mod clients;
+mod config;
+#[cfg(unix)]
+mod control;
+mod detach;
+mod dilution;
+mod environment;
+mod error;
+mod event_log;
+mod export;
+mod film;
+mod generators;
+mod heart_rate;
+mod history;
+mod hooks;
+mod inhibit;
+mod input;
+#[cfg(unix)]
+mod instances;
+mod integrations;
+mod interactive;
+mod maintenance;
+mod mqtt;
+mod notify;
+mod observe;
+mod overlay;
+mod preflight;
+mod presets;
+mod reciprocity;
+mod render;
+mod schema;
+mod session;
+mod terminal_guard;
+mod theme;
+// used by the control port, which only Unix has, and by --serve
+#[cfg(all(feature = "tls", any(unix, feature = "http")))]
+mod tls;
+mod units;
 
-const GREY:Color = Color::Rgb(42, 42, 42);
-const MUSTARD_YELLOW:Color = Color::Rgb(0xff, 0xe5, 0);
+use config::{Completion, DisplayMode, GaugeLabel, Orientation};
+use error::RunError;
+use render::Renderer;
+use theme::{Palette, PaletteBackend, Theme};
 
-struct TimerStage {
-    name: String,
-    period_s: u32,
-    elapsed_s: u32
+/// How long the message of a checkpoint stays on screen after it was reached.
+const CHECKPOINT_DISPLAY_S:u32 = 10;
+
+/// Badge colours for training intensity zones 1 to 5.
+const ZONE_COLORS:[Color; 5] = [
+    Color::Rgb(0x5b, 0x9b, 0xd5),
+    Color::Rgb(0x70, 0xad, 0x47),
+    Color::Rgb(0xff, 0xe5, 0),
+    Color::Rgb(0xed, 0x7d, 0x31),
+    Color::Rgb(0xe0, 0x30, 0x30)
+];
+
+/// Colour of a stage that has run past its end.
+const OVERTIME_COLOR: Color = Color::Rgb(0xe0, 0x30, 0x30);
+
+struct StageSpec {
+    stage: TimerStage,
+    enabled: bool
 }
 
-struct Timer {
-    stages: Vec<TimerStage>,
-    current_timer: usize,
-    paused: bool
+/// What `validate` and `export` do with the stages of a run instead of
+/// running it.
+#[derive(Clone, Copy)]
+enum StagesOnly {
+    /// Say that they are fine, errors are reported on the way
+    Validate,
+    /// Print them in the format of `--format`
+    Export
+}
+
+/// Which round of `--loop` a run is in.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct Round {
+    /// Counting from 1
+    number: u32,
+    /// How many rounds there are, `None` to go on until the timer is quit
+    of: Option<u32>
 }
 
-#[derive(Clone)]
-struct TimeValueParser {}
+impl Round {
+    /// The round after this one, if there is another.
+    fn next(self) -> Option<Round> {
+        let number = self.number.saturating_add(1);
+        self.of.is_none_or(|of| number <= of).then_some(Round { number, ..self })
+    }
 
-impl TypedValueParser for TimeValueParser {
-    type Value = u32;
+    /// How many rounds come after this one, `None` if they don't end.
+    fn remaining(self) -> Option<u32> {
+        self.of.map(|of| of.saturating_sub(self.number))
+    }
 
-    fn parse_ref(
-        &self,
-        _: &clap::Command,
-        _: Option<&clap::Arg>,
-        value: &std::ffi::OsStr,
-    ) -> Result<Self::Value, clap::Error>
-    {
-        let segments: Vec<&str>;
-        if let Some(time_str) = value.to_str() {
-            segments = time_str.split(":").collect();
+    fn label(self) -> String {
+        match self.of {
+            Some(of) => format!("Round {}/{}", self.number, of),
+            None => format!("Round {}", self.number)
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct ConfirmPolicy {
+    quit: bool,
+    skip: bool,
+    jump: bool
+}
+
+/// How the running timer responds to keys and other input.
+#[derive(Clone, Serialize, Deserialize)]
+struct Controls {
+    confirm: ConfirmPolicy,
+    /// How much + and - add to or take off the running stage
+    adjust_step: Duration,
+    /// Event files of foot pedals and buttons that control the timer
+    input_devices: Vec<PathBuf>,
+    /// Unix socket and TCP port other programs control the timer through
+    control_socket: Option<PathBuf>,
+    control_port: Option<u16>,
+    /// Who may do what over the port
+    control_access: config::Access,
+    /// How long a stage waiting for the user may go without a key press
+    /// before the timer pauses
+    #[serde(default)]
+    idle_pause: Option<Duration>,
+    /// Where the heart rate comes from that rest stages last until
+    #[serde(default)]
+    heart_rate: Option<heart_rate::HeartRateOptions>
+}
+
+/// Runtime actions that may have to be confirmed before they are carried
+/// out.
+#[derive(Clone, Copy)]
+enum ConfirmAction {
+    Quit,
+    /// Give up on the running stage, and possibly the whole session
+    Abort,
+    /// Move on to the next stage before the running one is done
+    Skip,
+    /// Move on past the rest of the running stage's section
+    SkipGroup,
+    /// Make the stage at this position the running one
+    Jump(usize)
+}
+
+impl ConfirmAction {
+    fn prompt(self) -> &'static str {
+        match self {
+            ConfirmAction::Quit => "Quit? Press y to confirm, any other key to cancel",
+            ConfirmAction::Abort => "Abort stage? y: next stage, x: abort session, \
+                other keys: cancel",
+            ConfirmAction::Skip => "Skip stage? Press y to confirm, any other key to cancel",
+            ConfirmAction::SkipGroup => "Skip the rest of this section? Press y to confirm, \
+                any other key to cancel",
+            ConfirmAction::Jump(_) => "Jump to the clicked stage? Press y to confirm, \
+                any other key to cancel"
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct DisplayOptions {
+    warning_threshold: u32,
+    time_format: TimeFormat,
+    /// Whether to count down in tenths of a second
+    tenths: bool,
+    /// Whether to print a summary of the run once the TUI is closed
+    keep_output: bool,
+    /// Whether to print events as JSON lines instead of showing the timer
+    headless: bool,
+    /// Whether to print lines for a status bar instead of showing the timer
+    #[serde(default)]
+    statusbar: bool,
+    theme: Theme,
+    mode: DisplayMode,
+    orientation: Orientation,
+    /// Template of the gauges' titles, see [`DEFAULT_GAUGE_TITLE`]
+    gauge_title: String,
+    /// What is written inside the gauges
+    #[serde(default)]
+    gauge_label: GaugeLabel,
+    /// How many stages after the running one get a gauge, all if not set
+    upcoming: Option<u32>,
+    /// How long the run may be paused in all before it is warned about
+    #[serde(default)]
+    pause_budget_s: Option<u32>,
+    /// Whether a strip below the stages shows how long each of them was
+    /// paused and ran over
+    #[serde(default)]
+    sparkline: bool,
+    /// Colours the terminal is drawn in
+    #[serde(default)]
+    palette: Palette,
+    /// Whether gauges are drawn in ASCII only
+    #[serde(default)]
+    accessible: bool,
+    /// Whether the running stage's gauge goes through the theme's gradient
+    /// as its time runs out
+    #[serde(default)]
+    gradient: bool,
+    /// How many times faster than real time the stages go, for a dry run
+    #[serde(default)]
+    dry_run: Option<f64>
+}
+
+impl DisplayOptions {
+    /// The clock the timer keeps time with, sped up for a dry run.
+    fn run_clock(&self) -> Box<dyn Clock> {
+        match self.dry_run {
+            Some(speed) => Box::new(ScaledClock::new(SystemClock::default(), speed)),
+            None => Box::new(SystemClock::default())
+        }
+    }
+
+    /// Whether times of a stage lasting `period` are shown in tenths.
+    fn shows_tenths(&self, period: Duration) -> bool {
+        self.tenths || period.subsec_nanos() != 0
+    }
+
+    /// The stages of `timer` that get a gauge where there is room for
+    /// `room` of them, and how many of the stages after them are left out.
+    /// Where not all of them fit the running stage is kept in the middle,
+    /// give or take `scroll` stages.
+    fn visible_stages(&self, timer: &Timer, room: usize, scroll: isize) -> (Range<usize>, usize) {
+        let count = timer.stages().len();
+        let mut shown = self.upcoming
+            .map(|upcoming| usize::try_from(upcoming).unwrap_or(usize::MAX).saturating_add(1))
+            .unwrap_or(count)
+            .min(count);
+        if shown < count || count > room {
+            // the stages left out take up a line of their own
+            shown = shown.min(room.saturating_sub(1)).max(1);
+        }
+        // only a limited number of upcoming stages starts at the running one
+        let centered = match self.upcoming {
+            Some(_) => timer.current_index(),
+            None => timer.current_index().saturating_sub((shown - 1) / 2)
+        };
+        // the last stages stay on screen once the timer has finished
+        let first = centered.saturating_add_signed(scroll).min(count.saturating_sub(shown));
+        let end = first.saturating_add(shown).min(count);
+        (first..end, count - end)
+    }
+
+    /// How much longer than the pause budget `timer` has been paused, if it
+    /// has.
+    fn over_pause_budget(&self, timer: &Timer) -> Option<Duration> {
+        let budget = Duration::from_secs(self.pause_budget_s?.into());
+        Some(timer.paused_total().saturating_sub(budget)).filter(|over| !over.is_zero())
+    }
+
+    /// The smallest area the chosen layout is drawn in as it is, if it
+    /// needs more than the compact view, which fits anywhere.
+    fn min_size(&self) -> Option<(u16, u16)> {
+        match (self.mode, self.orientation) {
+            (DisplayMode::Compact, _) => None,
+            (DisplayMode::Big, _) => Some((MIN_BIG_WIDTH, MIN_BIG_HEIGHT)),
+            (DisplayMode::Gauges, Orientation::Horizontal) => {
+                Some((MIN_FULL_WIDTH, MIN_COLUMNS_HEIGHT))
+            },
+            (DisplayMode::Gauges, Orientation::Vertical) => Some((MIN_FULL_WIDTH, MIN_FULL_HEIGHT))
+        }
+    }
+
+    /// What the clock of `stage` shows, see [`TimerStage::clock`]. Stages
+    /// counting up show whole seconds passed, stages in overtime the time
+    /// over with a minus.
+    fn clock(&self, stage: &TimerStage) -> String {
+        if stage.count_up {
+            let elapsed = Duration::from_secs(stage.elapsed.as_secs());
+            self.format(elapsed, stage.period.max(elapsed))
+        } else if stage.is_overtime() && !stage.clock().is_zero() {
+            format!("-{}", self.format(stage.clock(), stage.period))
         } else {
-            return Err(clap::Error::raw(
-                clap::ErrorKind::InvalidUtf8,
-                "Could not convert input string to unicode"
-            ));
+            self.format(stage.clock(), stage.period)
         }
+    }
 
-        let mut sec = 0;
-        let mut factor = 1;
-        for segm in segments.iter().rev() {
-            // TODO: handle persing error more robustly
-            if let Ok(parsed) = segm.parse::<u32>() {
-                sec += parsed * factor;
-                factor *= 60;
-            } else {
-                return Err(clap::Error::raw(
-                    clap::ErrorKind::InvalidValue,
-                    format!("Could not parse time string {}", segments.join(":"))
-                ));
+    /// Format a time of a stage lasting `period`. Stages that aren't a whole
+    /// number of seconds long are shown with tenths of a second either way.
+    fn format(&self, duration: Duration, period: Duration) -> String {
+        let magnitude_s = u32::try_from(period.as_secs()).unwrap_or(u32::MAX);
+        if self.shows_tenths(period) {
+            self.time_format.format_tenths(duration, magnitude_s)
+        } else {
+            // round up so the countdown shows zero only at the end
+            let seconds = duration.as_secs() + u64::from(duration.subsec_nanos() > 0);
+            self.time_format.format(
+                u32::try_from(seconds).unwrap_or(u32::MAX),
+                magnitude_s
+            )
+        }
+    }
+}
+
+/// Whether anything is played or rung at all.
+#[derive(Clone, Serialize, Deserialize)]
+struct SoundOptions {
+    enabled: bool
+}
+
+struct RunOptions {
+    stages: Vec<StageSpec>,
+    controls: Controls,
+    display: DisplayOptions,
+    sound: SoundOptions,
+    select_stages: bool,
+    /// Whether to edit the stages before starting
+    edit_stages: bool,
+    wait: bool,
+    /// Whether the system may go to sleep while the timer runs
+    allow_sleep: bool,
+    session_name: Option<String>,
+    audio_player: Option<audio::Player>,
+    /// The channels stage transitions and alerts are sent through
+    notify: notify::NotifyOptions,
+    /// Runs shell commands when stages start and end
+    hooks: hooks::Hooks,
+    /// Shows the running stage to streaming software
+    overlay: Option<overlay::Overlay>,
+    mqtt: Option<mqtt::Mqtt>,
+    log: SessionLog,
+    /// What happens once all stages are done
+    completion: Completion,
+    /// The round of `--loop` to start with, if the stages run in a loop
+    round: Option<Round>,
+    /// Presets run side by side with the stages, by name
+    splits: Vec<(String, Vec<StageSpec>)>,
+    presets: PresetLoader,
+    /// Config file all stages came from, reloaded when it changes
+    watched_config: Option<PathBuf>,
+    /// How often to save the run for `--resume` while it runs
+    checkpoint_interval: Option<Duration>,
+    /// A detached run taken up again with `attach` or `--resume`
+    resumed: Option<ResumedRun>
+}
+
+/// Loads presets that are run after the first stages, either following
+/// them or picked from the list of presets.
+#[derive(Clone, Serialize, Deserialize)]
+struct PresetLoader {
+    /// Exercise libraries given on the command line
+    exercises: HashMap<String, Exercise>,
+    /// Whether the warning threshold given on the command line applies to
+    /// every stage
+    override_warn: bool,
+    /// What happens after presets that don't say
+    completion: Completion,
+    /// Colours of the theme for stages by tag or name
+    stage_colors: HashMap<String, [u8; 3]>,
+    /// Factor of `--temperature` the stages are scaled by
+    compensation: Option<f64>,
+    /// Whether stages that don't say go into overtime
+    overtime: bool,
+    /// Whether the timer waits after stages that don't say
+    manual_advance: bool,
+    /// Countdown cues of stages without their own
+    countdown: Vec<CountdownCue>
+}
+
+impl PresetLoader {
+    /// The stages of the preset `name` and what happens after them, which is
+    /// `after` unless the preset says.
+    fn load(&self, name: &str, after: Completion) -> Result<(Vec<StageSpec>, Completion), String> {
+        let (stages, completion) = self.load_path(&presets::find(name)?, after)?;
+        if !stages.iter().any(|stage| stage.enabled) {
+            return Err(format!("Preset \"{}\" has no enabled stages", name));
+        }
+        Ok((stages, completion))
+    }
+
+    /// The stages of the config file at `path` and what happens after them,
+    /// which is `after` unless the file says.
+    fn load_path(&self, path: &Path, after: Completion) -> Result<(Vec<StageSpec>, Completion), String> {
+        let mut loaded = config::load(path)?;
+        if let Some(factor) = self.compensation {
+            config::compensate(&mut loaded.stages, factor);
+        }
+        config::default_countdown(&mut loaded.stages, &self.countdown);
+        let mut exercises = self.exercises.clone();
+        exercises.extend(loaded.exercises.into_iter().map(|e| (e.name.clone(), e)));
+        let stages = stage_specs(
+            loaded.stages,
+            &loaded.cues,
+            &exercises,
+            &self.stage_colors,
+            self.override_warn,
+            self.overtime,
+            self.manual_advance
+        )?;
+        Ok((stages, loaded.on_complete.unwrap_or(after)))
+    }
+}
+
+/// What to record about a run in the history.
+#[derive(Default, Clone, Serialize, Deserialize)]
+struct SessionLog {
+    /// Film roll to log the run against
+    roll: Option<String>,
+    /// Preset all stages of the run came from, runs of a preset are
+    /// recorded for `calibrate`
+    preset: Option<String>,
+    /// Chemical batches the run uses
+    batches: Vec<String>,
+    notes: Vec<String>,
+    /// Ambient conditions given on the command line
+    environment: history::Environment,
+    /// Command that reads the ambient conditions at the start of each stage
+    sensor_command: Option<String>,
+    /// How far the actual duration of a stage may be off the plan before it
+    /// is flagged
+    tolerance_s: Option<u32>,
+    /// File to append every event of the run to, with when it happened
+    event_log: Option<PathBuf>,
+    /// File to write how the run went to once it is over
+    summary_json: Option<PathBuf>
+}
+
+/// Overall session progress as a single bar, split into segments whose
+/// widths are proportional to the stage durations, with a playhead marking
+/// the current position.
+struct Timeline<'a> {
+    stages: &'a [TimerStage],
+    theme: &'a Theme,
+    /// Whether to draw it in ASCII only
+    ascii: bool
+}
+
+impl Widget for Timeline<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.height < 2 || area.width == 0 {
+            return;
+        }
+
+        let total_ms: u128 = self.stages.iter().map(|t| t.period.as_millis()).sum();
+        let elapsed_ms: u128 = self.stages.iter().map(|t| t.elapsed.as_millis()).sum();
+        if total_ms == 0 {
+            return;
+        }
+
+        let width = u128::from(area.width);
+        let column_of = |ms: u128| (ms * width / total_ms).min(width - 1) as u16;
+
+        let playhead = column_of(elapsed_ms);
+        buf.get_mut(area.x + playhead, area.y)
+            .set_symbol(if self.ascii { "v" } else { "▼" })
+            .set_fg(self.theme.active());
+
+        // colour every column by the stage it belongs to, in the stage's own
+        // colour or else alternating shades so neighbouring stages can be
+        // told apart
+        let muted = self.theme.muted();
+        let mut stage_start_ms = 0;
+        for (i, stage) in self.stages.iter().enumerate() {
+            let stage_end_ms = stage_start_ms + stage.period.as_millis();
+            let (from, to) = (column_of(stage_start_ms), column_of(stage_end_ms));
+            for x in from..=to {
+                let done = x < playhead;
+                let color = match (stage.color, done, i % 2 == 0) {
+                    (Some(color), true, _) => theme::rgb(color),
+                    (Some(color), false, _) => self.theme.dimmed(color),
+                    (None, true, true) => self.theme.active(),
+                    (None, true, false) => muted,
+                    (None, false, true) => self.theme.inactive(),
+                    (None, false, false) => self.theme.faint()
+                };
+                let symbol = match (self.ascii, done) {
+                    (true, true) => "=",
+                    (true, false) => "-",
+                    (false, _) => "▀"
+                };
+                buf.get_mut(area.x + x, area.y + 1)
+                    .set_symbol(symbol)
+                    .set_fg(color);
             }
+            stage_start_ms = stage_end_ms;
         }
-    
-        Ok(sec)
     }
 }
 
-fn format_seconds(seconds: u32) -> String {
-    let hrs = seconds / (60 * 60);
-    let min = (seconds % (60 * 60)) / 60;
-    let sec = seconds % 60;
-    format!("{:#02}:{:#02}:{:#02}", hrs, min, sec)
+/// Plays the audio file of each stage when it starts, pauses it along with
+/// the timer and stops it when the stage is over.
+struct StageAudio {
+    player: Option<audio::Player>,
+    stage: Option<usize>,
+    playback: Option<audio::Playback>,
+    paused: bool
 }
 
-fn update_state(timer: &mut Timer) -> bool {
-    let Timer{
-        stages,
-        current_timer,
-        paused
-    } = timer;
+impl StageAudio {
+    fn new(player: Option<audio::Player>) -> StageAudio {
+        StageAudio { player, stage: None, playback: None, paused: false }
+    }
 
-    if *current_timer >= stages.len() {
-        return false;
+    /// Play the running stage's audio from the start on the next update.
+    fn restart(&mut self) {
+        self.stage = None;
+    }
+
+    fn update(&mut self, timer: &Timer) {
+        if self.stage != Some(timer.current_index()) {
+            self.stage = Some(timer.current_index());
+            self.playback = None;
+            self.paused = false;
+
+            let file = timer.current_stage()
+                .and_then(|stage| stage.audio.as_ref());
+            if let (Some(player), Some(file)) = (&self.player, file) {
+                self.playback = player.play(file).ok();
+            }
+        }
+
+        if let Some(playback) = self.playback.as_ref() {
+            if timer.is_paused() != self.paused {
+                if timer.is_paused() { playback.pause() } else { playback.resume() }
+                self.paused = timer.is_paused();
+            }
+        }
+    }
+}
+
+/// Whether the running stage has just reached its warning threshold.
+fn warning_reached(timer: &Timer, warning_threshold: u32) -> bool {
+    timer.current_stage().is_some_and(|stage| {
+        let threshold = stage.warn_s.unwrap_or(warning_threshold);
+        timer.is_counting()
+        && threshold > 0
+        && Duration::from_secs(threshold.into()) < stage.period
+        && stage.remaining_s() == threshold
+    })
+}
+
+/// Whether the running stage has just completed a full minute and asked to
+/// be told about it.
+fn minute_elapsed(timer: &Timer) -> bool {
+    timer.current_stage().is_some_and(|stage| {
+        stage.minute_chime
+        && timer.is_counting()
+        && stage.elapsed_s() > 0
+        && stage.elapsed_s() % 60 == 0
+    })
+}
+
+/// Whether the running stage has just reached one of its checkpoints.
+fn checkpoint_reached(timer: &Timer) -> bool {
+    timer.current_stage().is_some_and(|stage| {
+        timer.is_counting()
+        && stage.checkpoints.iter().any(|c| c.position_s(stage.period) == stage.elapsed_s())
+    })
+}
+
+/// Whether the running stage has just reached its part at the end.
+fn after_reached(timer: &Timer) -> bool {
+    timer.current_stage().is_some_and(|stage| {
+        timer.is_counting()
+        && stage.after_starts().is_some_and(|starts| starts.as_secs() == stage.elapsed.as_secs())
+    })
+}
+
+/// Whether the running stage has just run out and gone into overtime.
+fn overtime_reached(timer: &Timer) -> bool {
+    timer.current_stage().is_some_and(|stage| {
+        timer.is_counting() && stage.is_overtime() && stage.elapsed_s() == stage.period_s()
+    })
+}
+
+/// Whether the running stage has just reached an agitation after the
+/// initial one, which starts with the stage.
+fn agitation_reached(timer: &Timer) -> bool {
+    timer.current_stage().is_some_and(|stage| {
+        timer.is_counting()
+        && stage.elapsed_s() > 0
+        && stage.is_agitating()
+        && !stage.agitation.is_some_and(|agitation| agitation.is_due(stage.elapsed_s() - 1))
+    })
+}
+
+/// Draw a thin vertical marker on a gauge at each of the given offsets into
+/// the stage. Marks the stage has already passed are drawn dimmed so the
+/// upcoming ones stand out.
+fn render_gauge_marks<B: Backend>(
+    f: &mut Frame<B>,
+    gauge_area: Rect,
+    stage: &TimerStage,
+    marks_s: impl Iterator<Item = u32>,
+    display: &DisplayOptions
+) {
+    let theme = &display.theme;
+    let period_ms = stage.period.as_millis();
+    if gauge_area.width == 0 || gauge_area.height == 0 || period_ms == 0 {
+        return;
     }
 
-    if *paused {
-        return true;
+    for mark_s in marks_s {
+        let offset = u128::from(mark_s) * 1000 * u128::from(gauge_area.width)
+            / period_ms;
+        let offset = u16::try_from(offset).unwrap_or(u16::MAX)
+            .min(gauge_area.width - 1);
+        let marker = Paragraph::new(
+            vec![Spans::from(if display.accessible { "|" } else { "│" }); usize::from(gauge_area.height)]
+        )
+        .style(if mark_s <= stage.elapsed_s() {
+            Style::default().fg(theme.muted())
+        } else {
+            Style::default().fg(theme.warning()).add_modifier(Modifier::BOLD)
+        });
+        f.render_widget(
+            marker,
+            Rect { x: gauge_area.x + offset, width: 1, ..gauge_area }
+        );
     }
+}
+
+fn in_warning_period(stage: &TimerStage, warning_threshold: u32) -> bool {
+    let warning_threshold = stage.warn_s.unwrap_or(warning_threshold);
+    !stage.count_up
+    && warning_threshold > 0
+    && stage.remaining_s() <= warning_threshold
+}
+
+/// Whether `stage` is in its last `seconds`, unless it isn't longer than
+/// that.
+fn in_final_seconds(stage: &TimerStage, seconds: u32) -> bool {
+    !stage.count_up
+    && seconds > 0
+    && seconds < stage.period_s()
+    && stage.remaining_s() <= seconds
+}
 
-    let t = &mut stages[*current_timer];
-    t.elapsed_s += 1;
+/// Whether the gauge of the running `stage` blinks, see [`Theme::blink_s`].
+fn blinks(stage: &TimerStage, theme: &Theme) -> bool {
+    !stage.is_overtime() && in_final_seconds(stage, theme.blink_s)
+}
+
+/// Terminals smaller than this get the compact view.
+const MIN_FULL_WIDTH:u16 = 24;
+const MIN_FULL_HEIGHT:u16 = 8;
+
+/// Room for the smallest block digits of a time with hours, with the name
+/// of the stage above them and the strip of stages below.
+const MIN_BIG_WIDTH: u16 = 25;
+const MIN_BIG_HEIGHT: u16 = 16;
+
+/// Room for the stages as columns rather than rows.
+const MIN_COLUMNS_HEIGHT: u16 = 10;
 
-    if t.period_s - t.elapsed_s == 0 {
-        *current_timer += 1;
+/// Terminals lower than this leave out the progress summary.
+const MIN_SUMMARY_HEIGHT: u16 = 12;
+
+/// Room for the round and stage of a `--loop` in block digits above the
+/// gauges.
+const MIN_BIG_COUNTER_HEIGHT: u16 = 24;
+
+/// Time spent and left over all stages and which stage is next, to know
+/// when to get it ready.
+fn progress_summary(timer: &Timer, display: &DisplayOptions) -> String {
+    let stages = timer.stages();
+    // stages counting up last as long as they have run so far
+    let total: Duration = stages.iter()
+        .map(|stage| stage.period.max(Duration::from_secs(stage.elapsed.as_secs())))
+        .sum();
+    let elapsed: Duration = stages.iter().map(|stage| stage.elapsed).sum();
+    let remaining: Duration = stages.iter()
+        .skip(timer.current_index())
+        .map(TimerStage::remaining)
+        .sum();
+    let percent = if total.is_zero() {
+        100.0
+    } else {
+        elapsed.as_secs_f64() / total.as_secs_f64() * 100.0
+    };
+    let mut summary = format!(
+        "{} elapsed · {} left · {:.0}%",
+        display.format(elapsed, total),
+        display.format(remaining, total),
+        percent
+    );
+    // markers between stages don't take any time
+    let next = stages.iter()
+        .skip(timer.current_index() + 1)
+        .find(|stage| !stage.is_marker());
+    match (timer.current_stage(), next) {
+        (Some(_), Some(next)) if timer.is_waiting() => {
+            let _ = write!(summary, " · Next: {} once ENTER is pressed", next.name);
+        },
+        (Some(current), Some(next)) if current.awaits_finish() => {
+            let _ = write!(summary, " · Next: {} when this one is done", next.name);
+        },
+        (Some(current), Some(next)) => {
+            let _ = write!(
+                summary,
+                " · Next: {} in {}",
+                next.name,
+                display.format(current.remaining(), current.period)
+            );
+        },
+        (Some(_), None) => summary.push_str(" · Last stage"),
+        (None, _) => {}
     }
+    summary
+}
 
-    true
+/// Name of `stage`, followed by that of its part at the end while it is in
+/// it, e.g. "Hold · Switch sides".
+fn running_name(stage: &TimerStage) -> String {
+    match stage.current_after() {
+        Some(after) => format!("{} · {}", stage.name, after.name),
+        None => stage.name.clone()
+    }
 }
 
-fn update_display<B: Backend>(
-    terminal: &mut Terminal<B>,
+/// Minimal view for tiny terminals, e.g. a small floating window: just the
+/// name of the running stage and its countdown. Where the chosen layout
+/// needs a terminal of at least `needed`, how to get it is said below them.
+fn render_compact<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
     timer: &Timer,
-    warning_threshold: u32
-) -> Result<(), io::Error>
-{
-    terminal.draw(|f| {
-        let Timer{
-            stages,
-            current_timer,
-            paused
-        } = timer;
-
-        let num_chunks: u16 = (stages.len() + (100 % stages.len())).try_into().unwrap();
-        let chunk_height: u16 = 100 / num_chunks;
-        let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .margin(1)
-        .constraints(
-            (0..num_chunks)
-                .map(|_| Constraint::Percentage(chunk_height))
-                .collect::<Vec<Constraint>>()
-        )
-        .split(f.size());
+    display: &DisplayOptions,
+    needed: Option<(u16, u16)>
+) {
+    // the timer comes first, the message only gets the rows it leaves
+    let area = match needed {
+        Some((width, height)) if area.height >= 3 => {
+            let message = format!(
+                "Enlarge the terminal to at least {}×{} or use --display compact",
+                width, height
+            );
+            let mut lines = word_wrap(&message, area.width);
+            if lines.len() > usize::from(area.height - 2) {
+                lines = vec![format!("{}×{} needed", width, height)];
+            }
+            let rows = u16::try_from(lines.len()).unwrap_or(u16::MAX);
+            let (timer_area, message_area) = split_bottom(area, rows);
+            f.render_widget(
+                Paragraph::new(lines.into_iter().map(Spans::from).collect::<Vec<_>>())
+                .style(Style::default().fg(display.theme.muted()))
+                .alignment(Alignment::Center),
+                message_area
+            );
+            timer_area
+        },
+        _ => area
+    };
 
-        for (i, timer) in stages.iter().enumerate() {
-            // let style = if i == *current_timer { BOLD_GREEN } else { DIM };
-            let timer_completion = 1f64
-                - (timer.period_s - timer.elapsed_s) as f64
-                / timer.period_s as f64;
-
-            let progr_bar = Gauge::default()
-            .block(
-                Block::default()
-                .title(if *paused {
-                    format!("{}: Paused", timer.name.to_string())
+    let (name, countdown, style) = match timer.current_stage() {
+        Some(stage) => (
+            running_name(stage),
+            if timer.is_paused() && stage.runs_while_paused {
+                format!("{} (paused, still running)", display.clock(stage))
+            } else if timer.is_paused() {
+                String::from("Paused")
+            } else if stage.is_agitating() {
+                format!("{} AGITATE", display.clock(stage))
+            } else {
+                display.clock(stage)
+            },
+            Style::default().add_modifier(Modifier::BOLD).fg(
+                if stage.is_overtime() {
+                    OVERTIME_COLOR
+                } else if in_warning_period(stage, display.warning_threshold)
+                    || stage.is_agitating()
+                {
+                    display.theme.warning()
                 } else {
-                    format!(
-                        "{}: {} / {}",
-                        timer.name.to_string(),
-                        format_seconds(timer.period_s - timer.elapsed_s),
-                        format_seconds(timer.period_s)
-                    )
-                })
-                .borders(Borders::NONE)
-            )
-            .gauge_style(
-                Style::default()
-                .fg(
-                    if i == *current_timer {
-                        if warning_threshold > 0 
-                        && timer.period_s - timer.elapsed_s <= warning_threshold {
-                            MUSTARD_YELLOW
-                        } else {
-                            Color::White
-                        }
-                    } else {
-                        GREY
-                    }
-                )
-                // .bg(Color::Black)
-                .add_modifier(Modifier::BOLD)
+                    display.theme.active()
+                }
             )
-            .ratio(timer_completion)
-            .label("");
-            f.render_widget(progr_bar, chunks[i]);
-        }
-    })?;
+        ),
+        None => (String::new(), String::from("Done"), Style::default())
+    };
 
-    Ok(())
+    let lines = if area.height >= 2 {
+        vec![
+            Spans::from(Span::raw(name)),
+            Spans::from(Span::styled(countdown, style))
+        ]
+    } else {
+        vec![Spans::from(vec![
+            Span::raw(format!("{} ", name)),
+            Span::styled(countdown, style)
+        ])]
+    };
+
+    // centre vertically, Paragraph only takes care of the horizontal part
+    let top = area.height.saturating_sub(lines.len() as u16) / 2;
+    f.render_widget(
+        Paragraph::new(lines).alignment(Alignment::Center),
+        Rect { y: area.y + top, height: area.height - top, ..area }
+    );
 }
 
-fn parse_cl_args() -> (Vec<(String, u32)>, u32) {
-    let arg_match = App::new("Staged Timer")
-        .version("0.1.0")
-        .author("Jan Hettenkofer")
-        .about(
-            "Configurable multi-stage timer for film development or workouts"
-        )
-        .arg(Arg::with_name("name")
-            .help("Name of the timer stage.")
-            .long("name")
-            .short('n')
-            .value_name("TIMER_NAME")
-            .takes_value(true)
-            .action(clap::ArgAction::Append)
-            .required(true)
-        )
-        .arg(Arg::with_name("time")
-            .help(
-                "Duration of the timer stage. Specify in seconds or \
-                [[hrs:]min:]sec, e.g. 1:32:14.")
-            .long("time")
-            .short('t')
-            .value_name("TIME")
-            .takes_value(true)
-            .value_parser(TimeValueParser{})
-            .action(clap::ArgAction::Append)
-            .required(true)
-        )
-        .arg(Arg::with_name("warn")
-            .help("Highlight the countdown bar when <REMAINING_TIME> is left \
-            on the timer")
-            .long("warn")
-            .short('w')
-            .value_name("REMAINING_TIME")
-            .takes_value(true)
-            .value_parser(TimeValueParser{})
-            .default_value("0")
-        )
-        .get_matches();
+fn exercise_panel<'a>(exercise: &'a Exercise, theme: &Theme) -> Paragraph<'a> {
+    let mut lines: Vec<Spans> = exercise.instructions.lines()
+        .map(Spans::from)
+        .collect();
+    if let Some(diagram) = &exercise.diagram {
+        lines.push(Spans::from(""));
+        lines.extend(diagram.lines().map(Spans::from));
+    }
+    if let Some(link) = &exercise.link {
+        lines.push(Spans::from(""));
+        lines.push(Spans::from(Span::styled(
+            link.as_str(),
+            Style::default().add_modifier(Modifier::UNDERLINED)
+        )));
+    }
 
-    let input_names = arg_match.get_many::<String>("name").unwrap();
-    let input_times = arg_match.get_many::<u32>("time").unwrap();
-    let input_warn = arg_match.get_one::<u32>("warn").unwrap();
+    Paragraph::new(lines)
+    .block(
+        Block::default()
+        .title(Span::styled(
+            exercise.name.as_str(),
+            Style::default().add_modifier(Modifier::BOLD)
+        ))
+        .borders(Borders::LEFT)
+        .border_style(Style::default().fg(theme.inactive()))
+    )
+    .wrap(Wrap { trim: false })
+}
 
-    if input_times.len() != input_names.len() {
-        println!(
-            "Cannot match timer stage names with their durations. \
-            {} names and {} durations were provided.",
-            input_names.len(), input_times.len()
-        );
-        std::process::exit(1);
+/// The note of the running stage, set apart from the gauges by `borders`.
+fn note_panel<'a>(note: &'a str, borders: Borders, theme: &Theme) -> Paragraph<'a> {
+    Paragraph::new(note.lines().map(Spans::from).collect::<Vec<Spans>>())
+    .block(
+        Block::default()
+        .title(Span::styled("Note", Style::default().add_modifier(Modifier::BOLD)))
+        .borders(borders)
+        .border_style(Style::default().fg(theme.inactive()))
+    )
+    .wrap(Wrap { trim: false })
+}
+
+/// `text` broken between words into lines of at most `width` columns.
+/// Words longer than that get a line of their own.
+fn word_wrap(text: &str, width: u16) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= usize::from(width) => {
+                line.push(' ');
+                line.push_str(word);
+            },
+            _ => lines.push(word.to_string())
+        }
     }
+    lines
+}
 
-    (input_names.into_iter().cloned().zip(input_times.into_iter().cloned()).collect(), *input_warn)
+/// Rows `text` takes up when wrapped to `width` columns.
+fn wrapped_height(text: &str, width: u16) -> u16 {
+    let width = usize::from(width.max(1));
+    let rows: usize = text.lines()
+        .map(|line| line.chars().count().max(1).div_ceil(width))
+        .sum();
+    u16::try_from(rows).unwrap_or(u16::MAX)
 }
 
-fn create_timer_list(names_and_times: &[(String, u32)]) -> Vec<TimerStage> {
-    names_and_times.iter().map(
-        |(name, time)| {
-            TimerStage {name: name.to_string(), period_s: *time, elapsed_s: 0}
+/// Pacing guide that grows over the first half of each cycle and shrinks
+/// over the second, e.g. to breathe along with.
+struct Pacer {
+    /// Position within the current cycle from 0 to 1
+    phase: f64
+}
+
+impl Widget for Pacer {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.height < 2 || area.width < 3 {
+            return;
         }
-    ).collect()
+
+        let inhale = self.phase < 0.5;
+        let size = (1.0 - (self.phase * std::f64::consts::TAU).cos()) / 2.0;
+        let width = 1 + ((f64::from(area.width) - 1.0) * size).round() as u16;
+
+        let label = if inhale { "Breathe in" } else { "Breathe out" };
+        Paragraph::new(label)
+        .alignment(Alignment::Center)
+        .render(Rect { height: 1, ..area }, buf);
+
+        let bar = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + 1,
+            width,
+            height: area.height - 1
+        };
+        buf.set_style(bar, Style::default().bg(
+            if inhale { Color::Rgb(0x5b, 0x9b, 0xd5) } else { Color::Rgb(0x70, 0xad, 0x47) }
+        ));
+    }
 }
 
-fn main() -> Result<(), io::Error> {
-    // == Data setup ===========================================================
-    let (names_and_times, warn) = parse_cl_args();
+/// Pixels of the digits and separators of a time or count, five rows high.
+fn glyph(c: char) -> Option<[&'static str; 5]> {
+    Some(match c {
+        '0' => ["███", "█ █", "█ █", "█ █", "███"],
+        '1' => [" █ ", "██ ", " █ ", " █ ", "███"],
+        '2' => ["███", "  █", "███", "█  ", "███"],
+        '3' => ["███", "  █", "███", "  █", "███"],
+        '4' => ["█ █", "█ █", "███", "  █", "  █"],
+        '5' => ["███", "█  ", "███", "  █", "███"],
+        '6' => ["███", "█  ", "███", "█ █", "███"],
+        '7' => ["███", "  █", "  █", "  █", "  █"],
+        '8' => ["███", "█ █", "███", "█ █", "███"],
+        '9' => ["███", "█ █", "███", "  █", "███"],
+        ':' => [" ", "█", " ", "█", " "],
+        '.' => [" ", " ", " ", " ", "█"],
+        '/' => ["  █", "  █", " █ ", "█  ", "█  "],
+        ' ' => [" ", " ", " ", " ", " "],
+        _ => return None
+    })
+}
 
-    let mut timer = Timer {
-        current_timer: 0,
-        stages: create_timer_list(&names_and_times),
-        paused: false
+/// A time or count in block digits as large as fit, to be read from across
+/// the room. Falls back to plain text where even the smallest digits don't
+/// fit.
+struct BigText<'a> {
+    text: &'a str,
+    style: Style
+}
+
+impl Widget for BigText<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // the unit is left out, the gauges below give it
+        let glyphs: Vec<[&str; 5]> = self.text.trim_end_matches('s')
+            .chars()
+            .filter_map(glyph)
+            .collect();
+        let pixels_wide = glyphs.iter()
+            .map(|glyph| glyph[0].chars().count() as u16 + 1)
+            .sum::<u16>()
+            .saturating_sub(1);
+        // pixels are twice as wide as high so they look square
+        let scale = (1..=area.height / 5).rev()
+            .map(|scale| (2 * scale, scale))
+            .chain([(1, 1)])
+            .find(|(x_scale, y_scale)| {
+                pixels_wide * x_scale <= area.width && 5 * y_scale <= area.height
+            });
+        let (x_scale, y_scale) = match scale {
+            Some(scale) if !glyphs.is_empty() => scale,
+            _ => {
+                Paragraph::new(self.text)
+                .style(self.style)
+                .alignment(Alignment::Center)
+                .render(area, buf);
+                return;
+            }
+        };
+
+        let left = area.x + (area.width - pixels_wide * x_scale) / 2;
+        let top = area.y + (area.height - 5 * y_scale) / 2;
+        let mut column = 0;
+        for glyph in glyphs {
+            for (row, line) in glyph.iter().enumerate() {
+                for (x, pixel) in line.chars().enumerate() {
+                    if pixel == ' ' {
+                        continue;
+                    }
+                    let x = left + (column + x as u16) * x_scale;
+                    let y = top + row as u16 * y_scale;
+                    for cell_y in y..y + y_scale {
+                        for cell_x in x..x + x_scale {
+                            buf.get_mut(cell_x, cell_y).set_symbol("█").set_style(self.style);
+                        }
+                    }
+                }
+            }
+            column += glyph[0].chars().count() as u16 + 1;
+        }
+    }
+}
+
+/// The name and time left of the running stage, the time in block digits.
+fn render_big_time<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    timer: &Timer,
+    display: &DisplayOptions
+) {
+    let stage = match timer.current_stage() {
+        Some(stage) => stage,
+        None => return
     };
+    let stopped = timer.is_paused() && !stage.runs_while_paused;
+    let color = if stopped { display.theme.muted() } else { gauge_color(stage, display) };
 
-    // == TUI setup ============================================================
+    let title = if stopped { format!("{} (paused)", stage.name) } else { running_name(stage) };
+    f.render_widget(
+        Paragraph::new(title)
+        .style(Style::default().add_modifier(Modifier::BOLD))
+        .alignment(Alignment::Center),
+        Rect { height: 1.min(area.height), ..area }
+    );
+    f.render_widget(
+        BigText {
+            text: &display.clock(stage),
+            style: Style::default().fg(color)
+        },
+        Rect { y: area.y + 1, height: area.height.saturating_sub(1), ..area }
+    );
+}
 
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+/// Split `area` into its top `height` rows and the rest. What is drawn on
+/// every frame is laid out by hand, as a [`tui::layout::Layout`] allocates
+/// on every split and keeps every split it has made for good.
+fn split_top(area: Rect, height: u16) -> (Rect, Rect) {
+    let height = height.min(area.height);
+    (
+        Rect { height, ..area },
+        Rect { y: area.y + height, height: area.height - height, ..area }
+    )
+}
 
-    // == Main loop ============================================================
+/// Split `area` into the rest and its bottom `height` rows.
+fn split_bottom(area: Rect, height: u16) -> (Rect, Rect) {
+    split_top(area, area.height.saturating_sub(height))
+}
 
-    update_display(&mut terminal, &timer, warn)?;
+/// Fill the screen with the theme's background, with text in its active
+/// colour unless drawn otherwise.
+fn paint_background<B: Backend>(f: &mut Frame<B>, theme: &Theme) {
+    let mut style = Style::default().fg(theme.active());
+    if let Some(background) = theme.background {
+        style = style.bg(theme::rgb(background));
+    }
+    f.render_widget(Block::default().style(style), f.size());
+}
 
-    let (tick_tx, tick_rx) = channel();
+/// Where each stage shown was drawn, to find the stage under the mouse.
+#[derive(Default)]
+struct StageAreas(Vec<(usize, Rect)>);
 
-    thread::spawn(move || {
-        loop {
-            thread::sleep(Duration::from_secs(1));
-            tick_tx.send("tick").unwrap();
+impl StageAreas {
+    /// Position of the stage drawn at `column` and `row`, if any.
+    fn stage_at(&self, column: u16, row: u16) -> Option<usize> {
+        self.0.iter()
+            .find(|(_, area)| {
+                (area.left()..area.right()).contains(&column)
+                    && (area.top()..area.bottom()).contains(&row)
+            })
+            .map(|(stage, _)| *stage)
+    }
+}
+
+/// Draw `timer` in `area`, in `round` of `--loop` if it runs in one, with
+/// `prompt` above its stages in place of what the timer would prompt for.
+fn draw_timer<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    timer: &Timer,
+    round: Option<Round>,
+    display: &DisplayOptions,
+    scroll: interactive::Scroll,
+    prompt: Option<&str>
+) -> StageAreas
+{
+    // recovers by itself once the terminal is resized
+    let needed = display.min_size()
+        .filter(|(width, height)| area.width < *width || area.height < *height);
+    if needed.is_some() || area.width < MIN_FULL_WIDTH || area.height < MIN_FULL_HEIGHT {
+        render_compact(f, area, timer, display, needed);
+        return StageAreas::default();
+    }
+
+    let stages = timer.stages();
+    let current_timer = timer.current_index();
+    let paused = timer.is_paused();
+
+    // the summary above the timeline only where it doesn't crowd out
+    // the gauges
+    let footer_height = if area.height >= MIN_SUMMARY_HEIGHT { 3 } else { 2 };
+    // the round and stage of a loop in block digits between the counter and
+    // the prompt, where there is room for them
+    let big_counter = round.filter(|_| area.height >= MIN_BIG_COUNTER_HEIGHT).map(|round| {
+        let stage = (current_timer + 1).min(stages.len());
+        match round.of {
+            Some(of) => format!("{}/{} {}/{}", round.number, of, stage, stages.len()),
+            None => format!("{} {}/{}", round.number, stage, stages.len())
         }
     });
+    let header_height = if big_counter.is_some() { 8 } else { 2 };
+    let (header_area, rest) = split_top(
+        area.inner(&Margin { vertical: 1, horizontal: 1 }),
+        header_height
+    );
+    let (main_area, footer_area) = split_bottom(rest, footer_height);
+    let sections = [header_area, main_area, footer_area];
 
-    let mut keep_running = true;
-    while keep_running {
-        thread::sleep(Duration::from_millis(50));
-
-        let _ = tick_rx.try_recv().map(|_| {
-            keep_running = update_state(&mut timer);
-            keep_running = match update_display(
-                &mut terminal,
-                &timer,
-                warn
-            ) {
-                Ok(_) => keep_running,
-                Err(_) => false
-            };
-        });
+    let timeline = Rect {
+        y: sections[2].bottom().saturating_sub(2),
+        height: sections[2].height.min(2),
+        ..sections[2]
+    };
+    f.render_widget(Timeline { stages, theme: &display.theme, ascii: display.accessible }, timeline);
+    if footer_height == 3 {
+        f.render_widget(
+            Paragraph::new(progress_summary(timer, display))
+            .style(Style::default().fg(display.theme.muted())),
+            Rect { height: 1, ..sections[2] }
+        );
+    }
 
-        if poll_event(Duration::from_millis(50))? {
-            let event = read_event()?;
-            match event {
-                // EXIT with CTRL+C or ESC
-                InputEvent::Key(KeyEvent{
-                    modifiers,
-                    code
-                }) if code == KeyCode::Esc || (
-                    code == KeyCode::Char('c')
-                    && modifiers == KeyModifiers::CONTROL
-                ) => break,
-
-                // PAUSE timer with SPACE BAR
-                InputEvent::Key(KeyEvent{
-                    modifiers: KeyModifiers::NONE,
-                    code: KeyCode::Char(' ')
-                }) => {
-                    timer.paused = !timer.paused;
-                    update_display(
-                        &mut terminal,
-                        &timer,
-                        warn
-                    )?;
+    // stage counter, kept separate from the gauges so it stays readable
+    // no matter how many stages there are, with the time left overall
+    let total: Duration = stages.iter().map(|stage| stage.period).sum();
+    let remaining: Duration = stages.iter()
+        .skip(current_timer)
+        .map(TimerStage::remaining)
+        .sum();
+    let mut counter = format!("Stage {}/{}", (current_timer + 1).min(stages.len()), stages.len());
+    if let Some(group) = stages.get(current_timer).and_then(|stage| stage.group.as_ref()) {
+        counter = format!("{} · {}", group, counter);
+    }
+    if let Some(round) = round {
+        counter = format!("{} · {}", round.label(), counter);
+    }
+    if let Some(speed) = display.dry_run {
+        counter = format!("Dry run ×{} · {}", speed, counter);
+    }
+    // the rounds still to come count towards the time left, which there is
+    // no telling of when they go on forever
+    if let Some(rounds_after) = round.map_or(Some(0), Round::remaining) {
+        let left = remaining + total * rounds_after;
+        let _ = write!(counter, " · {} left", display.format(left, total * (rounds_after + 1)));
+    }
+    let counter = Spans::from(Span::styled(counter, Style::default().add_modifier(Modifier::BOLD)));
+    // show the latest checkpoint of the running stage for a little while
+    // unless something needs the user's attention more urgently
+    let checkpoint_message = stages.get(current_timer).and_then(|stage| {
+        stage.checkpoints.iter()
+            .map(|c| (c.position_s(stage.period), c))
+            .filter(|(at_s, _)| *at_s <= stage.elapsed_s())
+            .filter(|(at_s, _)| stage.elapsed_s() - at_s < CHECKPOINT_DISPLAY_S)
+            .max_by_key(|(at_s, _)| *at_s)
+            .map(|(_, c)| c)
+            .map(|c| c.message.as_str())
+    });
+    // pausing doesn't stop a stage that runs while paused, which must
+    // not go unnoticed
+    let still_running = stages.get(current_timer)
+        .filter(|stage| paused && stage.runs_while_paused)
+        .map(|_| "Paused, but this stage keeps running!");
+    let count_up = stages.get(current_timer)
+        .filter(|stage| stage.is_open())
+        .map(|_| "Press ENTER when this stage is done");
+    let overtime = stages.get(current_timer)
+        .filter(|stage| stage.is_overtime())
+        .map(|_| "Time's up! Press ENTER to move on");
+    let waiting = timer.is_waiting().then_some("Done. Press ENTER to start the next stage");
+    let agitate = stages.get(current_timer)
+        .filter(|stage| timer.is_counting() && stage.is_agitating())
+        .map(|_| "AGITATE");
+    let after = stages.get(current_timer)
+        .and_then(TimerStage::current_after)
+        .map(|after| after.name.as_str());
+    let over_budget = display.over_pause_budget(timer);
+    let over_budget_message = over_budget.map(|over| pause_budget_warning(over, display));
+    let urgent = prompt.or(still_running).or(agitate).or(overtime).or(waiting).or(checkpoint_message);
+    // once over the pause budget, that stays in view unless something more
+    // urgent comes up
+    let bold = agitate.is_some() || (urgent.is_none() && over_budget.is_some());
+    let prompt = Spans::from(Span::styled(
+        urgent.or(over_budget_message.as_deref()).or(after).or(count_up).unwrap_or(""),
+        Style::default().fg(display.theme.warning()).add_modifier(if bold {
+            Modifier::BOLD
+        } else {
+            Modifier::empty()
+        })
+    ));
+    match big_counter {
+        Some(big_counter) => {
+            let (counter_area, rest) = split_top(sections[0], 1);
+            let (digits_area, prompt_area) = split_bottom(rest, 1);
+            f.render_widget(Paragraph::new(counter).alignment(Alignment::Center), counter_area);
+            f.render_widget(
+                BigText {
+                    text: &big_counter,
+                    style: Style::default().fg(display.theme.active())
                 },
-                _ => {}
-            }
+                Rect { height: digits_area.height.saturating_sub(1), ..digits_area }
+            );
+            f.render_widget(Paragraph::new(prompt).alignment(Alignment::Center), prompt_area);
+        },
+        None => f.render_widget(
+            Paragraph::new(vec![counter, prompt]).alignment(Alignment::Center),
+            sections[0]
+        )
+    }
+
+    // give the exercise of the running stage, or else its note, a panel
+    // beside the gauges if there is enough room for it
+    let exercise = stages.get(current_timer).and_then(|t| t.exercise.as_ref());
+    let note = stages.get(current_timer).and_then(|t| t.note.as_deref());
+    let beside = sections[1].width >= 60;
+    let side_panel = match (exercise, note) {
+        (Some(exercise), _) => Some(exercise_panel(exercise, &display.theme)),
+        (None, Some(note)) => Some(note_panel(note, Borders::LEFT, &display.theme)),
+        (None, None) => None
+    };
+    let gauge_section = match side_panel {
+        Some(panel) if beside => {
+            let width = sections[1].width * 3 / 5;
+            let gauges = Rect { width, ..sections[1] };
+            let area = Rect {
+                x: sections[1].x + width,
+                width: sections[1].width - width,
+                ..sections[1]
+            };
+            f.render_widget(panel, area);
+            gauges
+        },
+        _ => sections[1]
+    };
+
+    // a note that isn't beside the gauges goes below them
+    let gauge_section = match note {
+        Some(note) if !(beside && exercise.is_none()) && gauge_section.height >= 8 => {
+            let height = (wrapped_height(note, gauge_section.width) + 1)
+                .min(gauge_section.height / 3);
+            let (gauges, panel) = split_bottom(gauge_section, height);
+            f.render_widget(note_panel(note, Borders::TOP, &display.theme), panel);
+            gauges
+        },
+        _ => gauge_section
+    };
+
+    // the pacing guide of the running stage goes below its gauges
+    let pace_cpm = stages.get(current_timer).and_then(|t| t.pace_cpm);
+    let gauge_section = match pace_cpm {
+        Some(pace_cpm) if timer.is_counting() && gauge_section.height >= 8 => {
+            let (gauges, pacer) = split_bottom(gauge_section, 4);
+            let stage = &stages[current_timer];
+            let elapsed_s = stage.elapsed.as_secs_f64();
+            f.render_widget(
+                Pacer { phase: (elapsed_s * pace_cpm / 60.0).fract() },
+                Rect { y: pacer.y + 1, height: 3, ..pacer }
+            );
+            gauges
+        },
+        _ => gauge_section
+    };
+
+    // how long the stages so far were paused and ran over goes below them
+    let gauge_section = if display.sparkline && gauge_section.height >= 8 {
+        let (gauges, strip) = split_bottom(gauge_section, 2);
+        render_sparklines(f, strip, timer, display);
+        gauges
+    } else {
+        gauge_section
+    };
+
+    let gauge_section = match display.mode {
+        DisplayMode::Big => {
+            let (visible, hidden) = display.visible_stages(timer, usize::MAX, 0);
+            let lines = 2 * visible.len() + usize::from(hidden > 0);
+            let strip = u16::try_from(lines).unwrap_or(u16::MAX)
+                .min(gauge_section.height / 3);
+            let (time_area, strip_area) = split_bottom(gauge_section, strip);
+            render_big_time(f, time_area, timer, display);
+            strip_area
+        },
+        DisplayMode::Gauges | DisplayMode::Compact => gauge_section
+    };
+    let areas = render_gauges(f, gauge_section, timer, display, scroll.offset(timer));
+
+    if let Some(paused_for) = timer.paused_for().filter(|_| !timer.is_counting()) {
+        render_pause_banner(f, sections[1], paused_for, over_budget.is_some(), display);
+    }
+    areas
+}
+
+/// A banner across the middle of `area` saying how long the timer has been
+/// paused, so a paused timer isn't mistaken for a running one. It turns
+/// critical once the pauses of the run are `over_budget`.
+fn render_pause_banner<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    paused_for: Duration,
+    over_budget: bool,
+    display: &DisplayOptions
+) {
+    let seconds = paused_for.as_secs();
+    let mut text = format!("PAUSED ⏸ ({:02}:{:02})", seconds / 60, seconds % 60);
+    let color = if over_budget {
+        text.push_str(" · OVER BUDGET");
+        display.theme.critical()
+    } else {
+        display.theme.warning()
+    };
+    let width = (text.chars().count() as u16 + 4).min(area.width);
+    let height = 3.min(area.height);
+    let banner = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height
+    };
+    f.render_widget(Clear, banner);
+    f.render_widget(
+        Paragraph::new(text)
+        .style(Style::default().fg(color).add_modifier(Modifier::BOLD))
+        .alignment(Alignment::Center)
+        .block(
+            Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Thick)
+            .border_style(Style::default().fg(color))
+        ),
+        banner
+    );
+}
+
+/// Title of a stage's gauge. `{name}` is replaced with the name of the
+/// stage, `{remaining}`, `{elapsed}` and `{total}` with its times and
+/// `{percent}` with how much of it is done.
+const DEFAULT_GAUGE_TITLE: &str = "{name}: {remaining} / {total}";
+
+/// Narrowest a stage's column gets side by side, gap included.
+const MIN_COLUMN_WIDTH: u16 = 6;
+
+/// Bars of sparklines in ASCII, for `--accessible`.
+const ASCII_BARS: symbols::bar::Set = symbols::bar::Set {
+    full: "#",
+    seven_eighths: "#",
+    three_quarters: "=",
+    five_eighths: "=",
+    half: "-",
+    three_eighths: "-",
+    one_quarter: ".",
+    one_eighth: ".",
+    empty: " "
+};
+
+/// Width of the names in front of the sparklines.
+const SPARKLINE_LABEL_WIDTH: u16 = 8;
+
+/// A sparkline of how long the timer was paused during each stage so far and
+/// one of how long each ran over, the most recent stages that fit in `area`
+/// and scaled alike so the two can be compared.
+fn render_sparklines<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    timer: &Timer,
+    display: &DisplayOptions
+) {
+    let label_width = SPARKLINE_LABEL_WIDTH.min(area.width);
+    let stages: Vec<&TimerStage> = timer.stages().iter()
+        .take(timer.current_index() + 1)
+        .filter(|stage| !stage.is_marker())
+        .collect();
+    let recent = &stages[stages.len().saturating_sub(usize::from(area.width - label_width))..];
+    let paused: Vec<u64> = recent.iter().map(|stage| stage.paused.as_secs()).collect();
+    let over: Vec<u64> = recent.iter().map(|stage| stage.overtime_by().as_secs()).collect();
+    let max = paused.iter().chain(&over).copied().max().unwrap_or_default().max(1);
+
+    let lines = [
+        ("Paused", &paused, display.theme.warning()),
+        ("Over", &over, display.theme.critical())
+    ];
+    for (y, (label, data, color)) in (area.y..area.bottom()).zip(lines) {
+        let line = Rect { y, height: 1, ..area };
+        f.render_widget(
+            Paragraph::new(label).style(Style::default().fg(display.theme.muted())),
+            Rect { width: label_width, ..line }
+        );
+        f.render_widget(
+            Sparkline::default()
+            .data(data)
+            .max(max)
+            .bar_set(if display.accessible { ASCII_BARS } else { symbols::bar::NINE_LEVELS })
+            .style(Style::default().fg(color)),
+            Rect { x: line.x + label_width, width: line.width - label_width, ..line }
+        );
+    }
+}
+
+/// The stages of `timer` in `area`, drawn in the style the display options
+/// ask for where it fits and as gauge rows otherwise.
+fn render_gauges<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    timer: &Timer,
+    display: &DisplayOptions,
+    scroll: isize
+) -> StageAreas {
+    match display.orientation {
+        Orientation::Horizontal if BarColumns.fits(area) => {
+            BarColumns.render(f, area, timer, display, scroll)
+        },
+        Orientation::Horizontal | Orientation::Vertical => {
+            GaugeRows.render(f, area, timer, display, scroll)
         }
     }
+}
+
+/// A way of drawing the stages of a timer.
+trait StageStyle {
+    /// Whether there is room to draw the stages this way in `area`.
+    fn fits(&self, area: Rect) -> bool;
+
+    /// Draw the stages of `timer` that fit in `area`, `scroll` stages away
+    /// from the running one, returning where each of them went.
+    fn render<B: Backend>(
+        &self,
+        f: &mut Frame<B>,
+        area: Rect,
+        timer: &Timer,
+        display: &DisplayOptions,
+        scroll: isize
+    ) -> StageAreas;
+}
+
+/// A gauge for every stage that fits, with its name and time left as the
+/// title. Where two lines per stage don't fit, or in the compact display,
+/// each stage gets a single line; stages that don't fit at all are summed
+/// up in one line.
+struct GaugeRows;
+
+impl StageStyle for GaugeRows {
+    fn fits(&self, _area: Rect) -> bool {
+        true
+    }
 
-    // == Restore terminal state ===============================================
+    fn render<B: Backend>(
+        &self,
+        f: &mut Frame<B>,
+        area: Rect,
+        timer: &Timer,
+        display: &DisplayOptions,
+        scroll: isize
+    ) -> StageAreas {
+        let (visible, hidden) = display.visible_stages(timer, usize::MAX, scroll);
+        let lines = 2 * visible.len() + usize::from(hidden > 0);
+        let compact = display.mode == DisplayMode::Compact || lines > usize::from(area.height);
+        let (visible, hidden) = if compact {
+            display.visible_stages(timer, usize::from(area.height), scroll)
+        } else {
+            (visible, hidden)
+        };
+        let current_timer = timer.current_index();
+        let paused = timer.is_paused();
+        // everything but a stage that keeps running is dimmed while paused
+        let dimmed = paused && !timer.is_counting();
+        let first = visible.start;
+        let mut headers = section_starts(timer.stages(), &visible);
+        let stages = &timer.stages()[visible];
+
+        // split evenly, the line summing up the rest goes at the bottom and
+        // the headers of sections above their first stage, where they fit
+        let more = u16::from(hidden > 0);
+        let lines = stages.len() * if compact { 1 } else { 2 } + usize::from(more) + headers.len();
+        if lines > usize::from(area.height) {
+            headers.clear();
+        }
+        let header_lines = u16::try_from(headers.len()).unwrap_or(u16::MAX);
+        let stage_height = if compact {
+            1
+        } else {
+            (area.height - more - header_lines) / u16::try_from(stages.len()).unwrap_or(u16::MAX).max(1)
+        };
+        let mut y = area.y;
+        let mut rows = Vec::with_capacity(stages.len());
+        for (i, stage) in stages.iter().enumerate() {
+            if headers.contains(&(first + i)) {
+                f.render_widget(
+                    section_header(stage.group.as_deref().unwrap_or_default(), display),
+                    Rect { y, height: 1, ..area }
+                );
+                y += 1;
+            }
+            rows.push(Rect { y, height: stage_height, ..area });
+            y += stage_height;
+        }
+        if hidden > 0 {
+            f.render_widget(
+                Paragraph::new(format!("… {} more", hidden))
+                .style(Style::default().fg(display.theme.muted())),
+                Rect { y, height: 1, ..area }
+            );
+        }
 
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+        for (i, timer) in stages.iter().enumerate() {
+            let row = rows[i];
+            let i = first + i;
+            let running = i == current_timer;
+            let stopped = paused && !(running && timer.runs_while_paused);
+
+            let mut title: Vec<Span> = zone_badge(timer).into_iter().collect();
+            title.extend(stage_cue(timer, running, display));
+            title.push(Span::styled(
+                gauge_title(timer, stopped, display),
+                name_style(timer, dimmed)
+            ));
+            let color = if running && !dimmed {
+                gauge_color(timer, display)
+            } else if running || compact {
+                display.theme.muted()
+            } else {
+                display.theme.inactive()
+            };
+
+            if compact && display.accessible {
+                let title = Spans::from(title);
+                let label_width = u16::try_from(title.width() + 1).unwrap_or(u16::MAX).min(row.width);
+                f.render_widget(Paragraph::new(title), row);
+                f.render_widget(
+                    AsciiGauge { ratio: timer.completion(), style: Style::default().fg(color) },
+                    Rect { x: row.x + label_width, width: row.width - label_width, ..row }
+                );
+                continue;
+            } else if compact {
+                let line = LineGauge::default()
+                .gauge_style(
+                    Style::default()
+                    .fg(color)
+                    .bg(display.theme.inactive())
+                )
+                .line_set(symbols::line::THICK)
+                .ratio(timer.completion())
+                .label(Spans::from(title));
+                f.render_widget(line, row);
+                continue;
+            }
+
+            let block = Block::default()
+            .title(Spans::from(title))
+            .borders(Borders::NONE);
+            let gauge_area = block.inner(row);
+
+            let gauge_style = Style::default()
+            .fg(color)
+            .add_modifier(Modifier::BOLD);
+            if display.accessible {
+                f.render_widget(block, row);
+                f.render_widget(
+                    AsciiGauge { ratio: timer.completion(), style: gauge_style },
+                    gauge_area
+                );
+            } else {
+                let progr_bar = Gauge::default()
+                .block(block)
+                .gauge_style(gauge_style)
+                .ratio(timer.completion())
+                .label("");
+                f.render_widget(progr_bar, row);
+            }
+            let agitations = timer.agitation.iter()
+                .flat_map(|agitation| agitation.starts(timer.period_s()));
+            // where the part at the end starts splits the gauge
+            let after = timer.after_starts().map(|starts| starts.as_secs() as u32);
+            render_gauge_marks(
+                f,
+                gauge_area,
+                timer,
+                timer.checkpoints.iter().map(|c| c.position_s(timer.period)).chain(agitations).chain(after),
+                display
+            );
+            if let Some(label) = gauge_label(timer, display) {
+                f.render_widget(
+                    GaugeText {
+                        text: &label,
+                        ratio: timer.completion(),
+                        bar: color,
+                        // the bars of other stages are too faint to read text in
+                        color: if running && !dimmed { color } else { display.theme.muted() }
+                    },
+                    gauge_area
+                );
+            }
+        }
+        StageAreas(rows.into_iter().enumerate().map(|(i, row)| (first + i, row)).collect())
+    }
+}
+
+/// The stages side by side, each a column with its name and time left above
+/// a bar filling up from the bottom, for many short stages.
+struct BarColumns;
+
+impl StageStyle for BarColumns {
+    fn fits(&self, area: Rect) -> bool {
+        area.width >= MIN_COLUMN_WIDTH && area.height >= 4
+    }
+
+    fn render<B: Backend>(
+        &self,
+        f: &mut Frame<B>,
+        area: Rect,
+        timer: &Timer,
+        display: &DisplayOptions,
+        scroll: isize
+    ) -> StageAreas {
+        let (visible, hidden) = display.visible_stages(
+            timer,
+            usize::from(area.width / MIN_COLUMN_WIDTH),
+            scroll
+        );
+        let current_timer = timer.current_index();
+        let paused = timer.is_paused();
+        let dimmed = paused && !timer.is_counting();
+        let first = visible.start;
+        let headers = section_starts(timer.stages(), &visible);
+        let stages = &timer.stages()[visible];
+
+        // the headers of sections go in a line above their columns
+        let (header_area, area) = if headers.is_empty() || area.height < 5 {
+            (None, area)
+        } else {
+            let (header_area, area) = split_top(area, 1);
+            (Some(header_area), area)
+        };
+
+        let slots = u16::try_from(stages.len()).unwrap_or(u16::MAX) + u16::from(hidden > 0);
+        let width = area.width / slots.max(1);
+        let columns: Vec<Rect> = (0..slots)
+            // one column of space between stages
+            .map(|i| Rect { x: area.x + i * width, width: width - 1, ..area })
+            .collect();
+        if hidden > 0 {
+            f.render_widget(
+                Paragraph::new(vec![Spans::from(format!("… {}", hidden)), Spans::from("more")])
+                .style(Style::default().fg(display.theme.muted())),
+                columns[stages.len()]
+            );
+        }
+
+        if let Some(header_area) = header_area {
+            for start in headers.iter().map(|start| start - first) {
+                let group = stages[start].group.as_deref();
+                let end = stages[start..].iter()
+                    .position(|stage| stage.group.as_deref() != group)
+                    .map_or(stages.len(), |after| start + after);
+                let (left, right) = (columns[start], columns[end - 1]);
+                f.render_widget(
+                    section_header(group.unwrap_or_default(), display),
+                    Rect { x: left.x, width: right.right() - left.x, ..header_area }
+                );
+            }
+        }
+
+        for (i, timer) in stages.iter().enumerate() {
+            let column = columns[i];
+            let i = first + i;
+            let running = i == current_timer;
+            let stopped = paused && !(running && timer.runs_while_paused);
+
+            let mut name: Vec<Span> = zone_badge(timer).into_iter().collect();
+            name.extend(stage_cue(timer, running, display));
+            name.push(Span::styled(timer.name.clone(), name_style(timer, dimmed)));
+            let mut status = stage_status(timer, stopped, display).unwrap_or_default();
+            // narrow columns only have room for the time left
+            if status.chars().count() > usize::from(column.width) && !timer.aborted && !stopped {
+                status = display.clock(timer);
+            }
+            f.render_widget(
+                Paragraph::new(vec![Spans::from(name), Spans::from(status)]),
+                Rect { height: 2, ..column }
+            );
+            f.render_widget(
+                ColumnGauge {
+                    ratio: timer.completion(),
+                    ascii: display.accessible,
+                    style: Style::default().fg(if running && !dimmed {
+                        gauge_color(timer, display)
+                    } else if running {
+                        display.theme.muted()
+                    } else {
+                        display.theme.inactive()
+                    })
+                },
+                Rect { y: column.y + 2, height: column.height - 2, ..column }
+            );
+        }
+        StageAreas(
+            columns.into_iter()
+                .take(stages.len())
+                .enumerate()
+                .map(|(i, column)| (first + i, column))
+                .collect()
+        )
+    }
+}
+
+/// Positions of the stages in `visible` that a section header goes above:
+/// those in another group than the stage before them, and the first one
+/// shown if it is in a group at all.
+fn section_starts(stages: &[TimerStage], visible: &Range<usize>) -> Vec<usize> {
+    visible.clone()
+        .filter(|&i| stages[i].group.is_some())
+        .filter(|&i| i == visible.start || stages[i - 1].group != stages[i].group)
+        .collect()
+}
+
+/// The header of the section named `group` above its stages.
+fn section_header<'a>(group: &'a str, display: &DisplayOptions) -> Paragraph<'a> {
+    Paragraph::new(Span::styled(
+        group,
+        Style::default().fg(display.theme.muted()).add_modifier(Modifier::BOLD | Modifier::UNDERLINED)
+    ))
+}
+
+/// What is written inside the gauge of `stage`, see [`GaugeLabel`].
+fn gauge_label(stage: &TimerStage, display: &DisplayOptions) -> Option<String> {
+    if stage.is_marker() {
+        return None;
+    }
+    match display.gauge_label {
+        GaugeLabel::Percent => Some(format!("{:.0}%", stage.completion() * 100.0)),
+        GaugeLabel::Remaining => Some(display.clock(stage)),
+        GaugeLabel::Elapsed => Some(display.format(stage.elapsed, stage.period.max(stage.elapsed))),
+        GaugeLabel::None => None
+    }
+}
+
+/// Text in the middle of a gauge filled `ratio` of the way with `bar`,
+/// drawn in `color` where the gauge is empty and in a colour that stands out
+/// from the bar where it is filled.
+struct GaugeText<'a> {
+    text: &'a str,
+    ratio: f64,
+    bar: Color,
+    color: Color
+}
+
+impl Widget for GaugeText<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.height == 0 {
+            return;
+        }
+        let width = u16::try_from(self.text.chars().count()).unwrap_or(u16::MAX).min(area.width);
+        let left = area.left() + (area.width - width) / 2;
+        let y = area.top() + area.height / 2;
+        // where the gauge's bar ends, rounded as the gauge does
+        let filled = area.left() + (f64::from(area.width) * self.ratio.clamp(0.0, 1.0)).round() as u16;
+        for (x, c) in (left..left + width).zip(self.text.chars()) {
+            let style = if x < filled {
+                Style::default().fg(theme::text_on(self.bar)).bg(self.bar)
+            } else {
+                Style::default().fg(self.color)
+            };
+            buf.get_mut(x, y).set_char(c).set_style(style.add_modifier(Modifier::BOLD));
+        }
+    }
+}
+
+/// A bar filling up from the bottom, in eighths of a line or in whole
+/// lines of `#` in ASCII.
+struct ColumnGauge {
+    ratio: f64,
+    ascii: bool,
+    style: Style
+}
+
+impl Widget for ColumnGauge {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        const PARTS: [&str; 8] = [" ", "▁", "▂", "▃", "▄", "▅", "▆", "▇"];
+        let eighths = (self.ratio.clamp(0.0, 1.0) * f64::from(area.height) * 8.0).round() as u16;
+        for line in 0..eighths.div_ceil(8) {
+            let filled = (eighths - 8 * line).min(8);
+            let symbol = match (self.ascii, filled) {
+                (true, 4..) => "#",
+                (true, _) => " ",
+                (false, 8) => "█",
+                (false, _) => PARTS[usize::from(filled)]
+            };
+            for x in area.left()..area.right() {
+                buf.get_mut(x, area.bottom() - 1 - line).set_symbol(symbol).set_style(self.style);
+            }
+        }
+    }
+}
+
+/// A gauge filled with `#` from the left, the rest of it `-`, for
+/// `--accessible`.
+struct AsciiGauge {
+    ratio: f64,
+    style: Style
+}
+
+impl Widget for AsciiGauge {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let filled = area.left() + (f64::from(area.width) * self.ratio.clamp(0.0, 1.0)).round() as u16;
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                let symbol = if x < filled { "#" } else { "-" };
+                buf.get_mut(x, y).set_symbol(symbol).set_style(self.style);
+            }
+        }
+    }
+}
+
+/// Text standing in for the colour of the gauge of `stage` if it is
+/// `running` and there are no colours to tell it apart by, see
+/// [`gauge_color`].
+fn stage_cue(stage: &TimerStage, running: bool, display: &DisplayOptions) -> Option<Span<'static>> {
+    if !running || display.palette != Palette::None {
+        return None;
+    }
+    let cue = if stage.is_overtime() {
+        "! OVERTIME ! "
+    } else if in_final_seconds(stage, display.theme.critical_s)
+        || in_warning_period(stage, display.warning_threshold)
+    {
+        "! WARNING ! "
+    } else if stage.is_agitating() {
+        "! AGITATE ! "
+    } else {
+        ">> ACTIVE << "
+    };
+    Some(Span::styled(cue, Style::default().add_modifier(Modifier::BOLD)))
+}
+
+/// Badge of the heart rate zone of `stage`, if it has one.
+fn zone_badge(stage: &TimerStage) -> Option<Span<'static>> {
+    stage.zone.map(|zone| Span::styled(
+        format!(" Z{} ", zone),
+        Style::default()
+        .fg(Color::Black)
+        .bg(ZONE_COLORS[usize::from(zone) - 1])
+        .add_modifier(Modifier::BOLD)
+    ))
+}
+
+/// Stages with a colour of their own can be told apart by name. Names are
+/// `dimmed` while the timer is paused.
+fn name_style(stage: &TimerStage, dimmed: bool) -> Style {
+    let style = match stage.color {
+        Some(color) => Style::default().fg(theme::rgb(color)),
+        None => Style::default()
+    };
+    if dimmed { style.add_modifier(Modifier::DIM) } else { style }
+}
+
+/// Time left of `stage` out of its duration, or why it isn't counting
+/// down. `None` for stages without a duration.
+fn stage_status(stage: &TimerStage, stopped: bool, display: &DisplayOptions) -> Option<String> {
+    if stage.is_marker() {
+        None
+    } else if stage.aborted {
+        Some(String::from("Aborted"))
+    } else if stopped {
+        Some(String::from("Paused"))
+    } else if stage.is_open() {
+        Some(format!("{} ↑", display.clock(stage)))
+    } else {
+        Some(format!(
+            "{} / {}",
+            display.clock(stage),
+            display.format(stage.period, stage.period)
+        ))
+    }
+}
+
+/// Title of the gauge of `stage` as set up in `display.gauge_title`, unless
+/// it isn't counting down.
+fn gauge_title(stage: &TimerStage, stopped: bool, display: &DisplayOptions) -> String {
+    if stage.is_marker() {
+        stage.name.clone()
+    } else if stage.aborted {
+        format!("{}: Aborted", stage.name)
+    } else if stopped {
+        format!("{}: Paused", stage.name)
+    } else if stage.is_open() {
+        format!("{}: {} ↑", stage.name, display.clock(stage))
+    } else {
+        // in a single pass, as the titles are redrawn on every frame
+        let mut title = String::with_capacity(display.gauge_title.len() + stage.name.len());
+        let mut rest = display.gauge_title.as_str();
+        while let Some((before, after)) = rest.split_once('{') {
+            title.push_str(before);
+            let (key, after_key) = after.split_once('}').unwrap_or(("", after));
+            rest = after_key;
+            match key {
+                "name" => title.push_str(&stage.name),
+                "remaining" => title.push_str(&display.clock(stage)),
+                "elapsed" => title.push_str(&display.format(stage.elapsed, stage.period)),
+                "total" => title.push_str(&display.format(stage.period, stage.period)),
+                "percent" => {
+                    let _ = write!(title, "{:.0}", stage.completion() * 100.0);
+                },
+                // anything else is left as it is
+                _ => {
+                    title.push('{');
+                    rest = after;
+                }
+            }
+        }
+        title.push_str(rest);
+        title
+    }
+}
+
+/// Colour of the gauge of the running stage, flashing while it is to be
+/// agitated and red once it is in overtime. In its last seconds it blinks
+/// and turns critical, as set up in the theme. With `--gradient`, stages
+/// with an end go through the theme's gradient instead of turning to the
+/// warning and critical colours.
+fn gauge_color(stage: &TimerStage, display: &DisplayOptions) -> Color {
+    let flash = stage.is_agitating() && stage.elapsed.subsec_millis() < 500;
+    let theme = &display.theme;
+    if stage.is_overtime() {
+        OVERTIME_COLOR
+    } else if blinks(stage, theme) && stage.elapsed.subsec_millis() >= 500 {
+        theme.muted()
+    } else if display.gradient && !flash && !stage.count_up && !stage.period.is_zero() {
+        theme.gradient(1.0 - stage.completion(), display.palette)
+    } else if in_final_seconds(stage, theme.critical_s) {
+        theme.critical()
+    } else if flash || in_warning_period(stage, display.warning_threshold) {
+        display.theme.warning()
+    } else if let Some(color) = stage.color {
+        theme::rgb(color)
+    } else {
+        display.theme.active()
+    }
+}
+
+/// A line on how a stage went that `actual_s` were spent on.
+fn stage_summary(stage: &TimerStage, actual_s: Option<u32>, display: &DisplayOptions) -> String {
+    let planned = if stage.is_open() {
+        String::from("open")
+    } else {
+        display.format(stage.period, stage.period)
+    };
+    let mut line = format!("{} ({})", stage.name, planned);
+    if stage.aborted {
+        line.push_str(": aborted");
+    } else if let Some(actual_s) = actual_s {
+        line.push_str(&format!(": took {}", display.time_format.format(actual_s, actual_s)));
+    } else {
+        line.push_str(": not run");
+    }
+    line
+}
+
+/// What to tell about pauses that took `over` longer than the pause budget.
+fn pause_budget_warning(over: Duration, display: &DisplayOptions) -> String {
+    let budget_s = display.pause_budget_s.unwrap_or_default();
+    let over_s = u32::try_from(over.as_secs()).unwrap_or(u32::MAX);
+    let magnitude_s = budget_s.saturating_add(over_s);
+    format!(
+        "Paused {} longer than the pause budget of {}",
+        display.time_format.format(over_s, magnitude_s),
+        display.time_format.format(budget_s, magnitude_s)
+    )
+}
+
+/// A line on the run as a whole: how long it took against the plan, pauses
+/// and overtime included, and how often it was paused.
+fn session_totals(timer: &Timer, report: &SessionReport, display: &DisplayOptions) -> String {
+    let planned_s: u32 = timer.stages().iter().map(TimerStage::period_s).sum();
+    let magnitude_s = planned_s.max(report.took_s);
+    let format = |seconds| display.time_format.format(seconds, magnitude_s);
+    let paused = match report.pauses {
+        0 => String::from("not paused"),
+        1 => format!("paused once for {}", format(report.paused_s)),
+        pauses => format!("paused {} times for {}", pauses, format(report.paused_s))
+    };
+    format!("Took {} of {} planned, {}", format(report.took_s), format(planned_s), paused)
+}
+
+/// A line on each section of the run: how long its stages took against
+/// their plan. Stages outside of groups have none.
+fn section_totals(timer: &Timer, report: &SessionReport, display: &DisplayOptions) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut index = 0;
+    while index < timer.stages().len() {
+        let section = timer.group_range(index);
+        if let Some(group) = &timer.stages()[index].group {
+            let planned_s: u32 = timer.stages()[section.clone()].iter().map(TimerStage::period_s).sum();
+            let took_s: Option<u32> = report.actual_s.get(section.clone()).unwrap_or_default()
+                .iter()
+                .flatten()
+                .copied()
+                .reduce(|sum, actual_s| sum + actual_s);
+            let magnitude_s = planned_s.max(took_s.unwrap_or_default());
+            let format = |seconds| display.time_format.format(seconds, magnitude_s);
+            lines.push(match took_s {
+                Some(took_s) => format!("{}: took {} of {} planned", group, format(took_s), format(planned_s)),
+                None => format!("{} ({}): not run", group, format(planned_s))
+            });
+        }
+        index = section.end;
+    }
+    lines
+}
+
+/// How the run went as plain text, for the scrollback once the TUI is
+/// closed.
+fn plain_summary(timer: &Timer, report: &SessionReport, display: &DisplayOptions) -> String {
+    let outcome = if timer.is_finished() {
+        String::from("All stages are done")
+    } else if timer.is_aborted() {
+        String::from("The session was aborted")
+    } else {
+        format!(
+            "Stopped during {}",
+            timer.current_stage().map(|stage| stage.name.as_str()).unwrap_or_default()
+        )
+    };
+    let stages = timer.stages().iter()
+        .zip(&report.actual_s)
+        .enumerate()
+        .filter(|(_, (stage, _))| !stage.is_marker())
+        .map(|(i, (stage, actual_s))| {
+            if i == timer.current_index() && !timer.is_aborted() && stage.is_open() {
+                format!("  {} (open): stopped after {}", stage.name, display.clock(stage))
+            } else if i == timer.current_index() && !timer.is_aborted() && stage.is_overtime() {
+                format!(
+                    "  {} ({}): stopped {} over",
+                    stage.name,
+                    display.format(stage.period, stage.period),
+                    display.format(stage.clock(), stage.period)
+                )
+            } else if i == timer.current_index() && !timer.is_aborted() {
+                format!(
+                    "  {} ({}): stopped with {} left",
+                    stage.name,
+                    display.format(stage.period, stage.period),
+                    display.format(stage.remaining(), stage.period)
+                )
+            } else {
+                format!("  {}", stage_summary(stage, *actual_s, display))
+            }
+        });
+    std::iter::once(outcome)
+        .chain(stages)
+        .chain(section_totals(timer, report, display))
+        .chain([session_totals(timer, report, display)])
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Turn configured stages into timer stages with the checkpoints of `cues`
+/// and the exercises they refer to. Stages without a colour of their own get
+/// the first of `stage_colors` matching one of their tags or their name.
+/// With `override_warn` the stages' own warning thresholds are dropped.
+/// Stages that don't say whether they go into overtime or wait at their end
+/// do so if `overtime` or `manual_advance`.
+fn stage_specs(
+    mut stages: Vec<config::ConfiguredStage>,
+    cues: &[config::CueRule],
+    exercises: &HashMap<String, Exercise>,
+    stage_colors: &HashMap<String, [u8; 3]>,
+    override_warn: bool,
+    overtime: bool,
+    manual_advance: bool
+) -> Result<Vec<StageSpec>, String>
+{
+    if let Some(unknown) = stages.iter()
+        .filter_map(|stage| stage.exercise.as_ref())
+        .find(|name| !exercises.contains_key(*name))
+    {
+        return Err(format!(
+            "Exercise \"{}\" was not found in any exercise library.", unknown
+        ));
+    }
+
+    for stage in stages.iter_mut() {
+        let cued: Vec<Checkpoint> = cues.iter()
+            .flat_map(|cue| cue.checkpoints(stage))
+            .collect();
+        stage.checkpoints.extend(cued);
+        if stage.color.is_none() {
+            stage.color = stage.tags.iter()
+                .chain(stage.name.as_ref())
+                .find_map(|key| stage_colors.get(&key.to_lowercase()).copied());
+        }
+    }
+
+    Ok(stages.into_iter()
+        .enumerate()
+        .map(|(i, stage)| StageSpec {
+            stage: TimerStage {
+                name: stage.name.unwrap_or_else(|| format!("Stage {}", i + 1)),
+                period: stage.period,
+                elapsed: Duration::ZERO,
+                zone: stage.zone,
+                minute_chime: stage.minute_chime,
+                checkpoints: stage.checkpoints,
+                countdown: stage.countdown,
+                agitation: stage.agitation,
+                after: stage.after,
+                exercise: stage.exercise.map(|name| exercises[&name].clone()),
+                note: stage.note,
+                group: stage.group,
+                audio: stage.audio,
+                pace_cpm: stage.pace_cpm,
+                rest: stage.rest,
+                warn_s: stage.warn_s.filter(|_| !override_warn),
+                color: stage.color,
+                on_start: stage.on_start,
+                on_end: stage.on_end,
+                runs_while_paused: stage.runs_while_paused,
+                count_up: stage.count_up,
+                overtime: stage.overtime.unwrap_or(overtime),
+                manual_advance: stage.manual_advance.unwrap_or(manual_advance),
+                ends_at: None,
+                paused: Duration::ZERO,
+                aborted: false
+            },
+            enabled: stage.enabled
+        })
+        .collect())
+}
+
+fn create_timer_list(stages: Vec<StageSpec>) -> Vec<TimerStage> {
+    stages.into_iter()
+        .filter(|stage| stage.enabled)
+        .map(|stage| stage.stage)
+        .collect()
+}
+
+/// Carry out `command` from a key or any other input, without asking for
+/// confirmation. Returns whether it changed the timer.
+fn apply_command(timer: &mut Timer, watchers: &mut StageWatchers, command: input::Command) -> bool {
+    use input::Command;
+    if timer.is_finished() {
+        return false;
+    }
+    match command {
+        Command::TogglePause => timer.toggle_pause(),
+        Command::Pause if !timer.is_paused() => timer.pause(),
+        Command::Resume if timer.is_paused() => timer.resume(),
+        Command::Skip => {
+            watchers.adjusted(timer, schema::Adjustment::Skipped, None);
+            timer.skip_stage();
+        },
+        Command::SkipGroup => {
+            watchers.adjusted(timer, schema::Adjustment::Skipped, None);
+            timer.skip_group();
+        },
+        Command::Finish if timer.is_waiting()
+            || timer.current_stage().is_some_and(TimerStage::awaits_finish) =>
+        {
+            watchers.adjusted(timer, schema::Adjustment::Finished, None);
+            timer.finish_stage();
+        },
+        Command::Extend(step) => {
+            let step_s = u32::try_from(step.as_secs()).ok();
+            watchers.adjusted(timer, schema::Adjustment::Extended, step_s);
+            timer.extend_stage(step);
+        },
+        Command::Shorten(step) => {
+            let step_s = u32::try_from(step.as_secs()).ok();
+            watchers.adjusted(timer, schema::Adjustment::Shortened, step_s);
+            timer.shorten_stage(step);
+        },
+        Command::Pause | Command::Resume | Command::Finish => return false
+    }
+    watchers.update(timer);
+    true
+}
+
+/// A line whenever a stage starts or waits to be finished, for runs without
+/// a terminal to draw on.
+struct PlainText<'a, W: Write> {
+    out: W,
+    display: &'a DisplayOptions,
+    events: EventStream,
+    waiting: bool,
+    over_budget: bool
+}
+
+impl<'a, W: Write> PlainText<'a, W> {
+    fn new(out: W, display: &'a DisplayOptions) -> PlainText<'a, W> {
+        PlainText { out, display, events: EventStream::new(), waiting: false, over_budget: false }
+    }
+}
+
+impl<W: Write> Renderer for PlainText<'_, W> {
+    fn render(&mut self, timer: &Timer) -> io::Result<()> {
+        for event in self.events.update(timer) {
+            match event {
+                TimerEvent::StageStart(index) => {
+                    let stage = &timer.stages()[index];
+                    if stage.is_open() {
+                        writeln!(self.out, "{}: open, press ENTER when done", stage.name)?;
+                    } else {
+                        writeln!(
+                            self.out,
+                            "{}: {}",
+                            stage.name,
+                            self.display.format(stage.period, stage.period)
+                        )?;
+                    }
+                    if let Some(note) = &stage.note {
+                        writeln!(self.out, "  {}", note)?;
+                    }
+                },
+                TimerEvent::Countdown(index) => {
+                    let stage = &timer.stages()[index];
+                    writeln!(self.out, "  {} left", self.display.format(stage.remaining(), stage.period))?;
+                },
+                TimerEvent::Tick if after_reached(timer) => {
+                    if let Some(stage) = timer.current_stage() {
+                        let after = stage.after.as_ref().map(|after| after.name.as_str());
+                        writeln!(
+                            self.out,
+                            "  {}: {}",
+                            after.unwrap_or_default(),
+                            self.display.format(stage.remaining(), stage.period)
+                        )?;
+                    }
+                },
+                TimerEvent::Tick if overtime_reached(timer) => {
+                    let name = timer.current_stage().map(|stage| stage.name.as_str());
+                    writeln!(self.out, "{}: time's up, press ENTER to move on", name.unwrap_or_default())?;
+                },
+                TimerEvent::Finished => writeln!(self.out, "Done")?,
+                _ => {}
+            }
+        }
+        let waiting = timer.is_waiting();
+        if waiting && !self.waiting {
+            let name = timer.current_stage().map(|stage| stage.name.as_str());
+            writeln!(self.out, "{}: done, press ENTER to start the next stage", name.unwrap_or_default())?;
+        }
+        self.waiting = waiting;
+        let over_budget = self.display.over_pause_budget(timer).is_some();
+        if over_budget && !self.over_budget {
+            let budget_s = self.display.pause_budget_s.unwrap_or_default();
+            writeln!(
+                self.out,
+                "Over the pause budget of {}",
+                self.display.time_format.format(budget_s, budget_s)
+            )?;
+        }
+        self.over_budget = over_budget;
+        Ok(())
+    }
+
+    fn notice(&mut self, notice: &str) -> io::Result<()> {
+        writeln!(self.out, "{}", notice)
+    }
+}
+
+/// How a run without the TUI is shown on `out`: every event as JSON when
+/// headless, lines for a status bar with `--statusbar` and plain text
+/// otherwise.
+fn plain_frontend<'a>(display: &'a DisplayOptions, out: &'a mut impl Write) -> Box<dyn Renderer + 'a> {
+    if display.headless {
+        Box::new(render::Headless::new(out, display.warning_threshold))
+    } else if display.statusbar {
+        Box::new(render::Statusbar::new(out, display.time_format))
+    } else {
+        Box::new(PlainText::new(out, display))
+    }
+}
+
+/// Run the timer without a TUI, showing it with `frontend`. Used when there
+/// is no terminal to draw on at all, when running silently with `--wait`,
+/// with `--headless` and with `--statusbar`.
+fn run_plain(
+    timer: &mut Timer,
+    watchers: &mut StageWatchers,
+    (inputs, mut rest_gate): (&mut input::Inputs, Option<&mut heart_rate::RestGate>),
+    frontend: &mut dyn Renderer,
+    clock: &dyn Clock
+) -> Result<(), io::Error>
+{
+    let mut last_advance = clock.now();
+    // the first stage starts with the clock, so the time its hooks and
+    // sounds take comes out of it rather than before it
+    watchers.update(timer);
+    // without keys to press, stages counting up, in overtime or waiting are
+    // finished by a line on stdin
+    if timer.stages().iter().any(|stage| stage.count_up || stage.overtime || stage.manual_advance) {
+        inputs.read_lines();
+    }
+
+    loop {
+        if let Some(slack) = watchers.slack.latest.take() {
+            frontend.stage_slack(slack);
+        }
+        frontend.render(timer)?;
+        if timer.is_finished() {
+            return Ok(());
+        }
+
+        let awaiting = timer.is_waiting()
+            || timer.current_stage().is_some_and(TimerStage::awaits_finish);
+        // wake up for each input, or when the running stage completes its
+        // next second. Hooks and sounds since the timer was last advanced
+        // took from the wait already.
+        let next_tick = last_advance
+            + timer.until_next_step().unwrap_or(Duration::from_secs(1));
+        loop {
+            let left = next_tick.saturating_sub(clock.now());
+            let input = if left.is_zero() { None } else { inputs.next(clock.wall_time(left)) };
+            // whatever the input does takes effect from now on
+            let now = clock.now();
+            timer.advance(now - last_advance);
+            timer.follow_deadline(SystemTime::now());
+            last_advance = now;
+            match input {
+                // lines entered before the stage waited for one don't count
+                Some(input::Input::Command(input::Command::Finish)) if !awaiting => {},
+                Some(input::Input::Command(command)) => {
+                    apply_command(timer, watchers, command);
+                    break;
+                },
+                Some(input::Input::Status(request)) => request.answer(timer),
+                Some(input::Input::HeartRate(bpm)) => {
+                    if let Some(gate) = rest_gate.as_mut() {
+                        gate.reading(bpm);
+                    }
+                },
+                Some(input::Input::Terminal(_)) => {},
+                None => break
+            }
+        }
+        if let Some(notice) = rest_gate.as_mut().and_then(|gate| gate.check(timer)) {
+            frontend.notice(notice)?;
+        }
+        watchers.update(timer);
+    }
+}
+
+/// Measures how long each stage really took on the wall clock. Pauses count
+/// since chemistry keeps working while the timer is paused.
+struct StageDurations {
+    stage: Option<usize>,
+    started: Instant,
+    actual_s: Vec<Option<u32>>,
+    /// How often the timer was paused
+    pauses: u32,
+    /// Whether the timer was paused when last looked at
+    paused: bool,
+    /// When the last stage was done
+    finished: Option<DateTime<Local>>,
+    /// How many times faster than real time the timer runs
+    speed: f64
+}
+
+impl StageDurations {
+    fn new() -> StageDurations {
+        StageDurations {
+            stage: None,
+            started: Instant::now(),
+            actual_s: Vec::new(),
+            pauses: 0,
+            paused: false,
+            finished: None,
+            speed: 1.0
+        }
+    }
+
+    /// Note what `timer` is doing now. Its running stage started at
+    /// `stage_started` on the wall clock.
+    fn update(&mut self, timer: &Timer, stage_started: Instant) {
+        if timer.is_paused() && !self.paused {
+            self.pauses += 1;
+        }
+        self.paused = timer.is_paused();
+        if timer.is_finished() && self.finished.is_none() {
+            self.finished = Some(Local::now());
+        }
+        self.actual_s.resize(timer.stages().len(), None);
+        let stage = timer.current_index();
+        if self.stage == Some(stage) {
+            return;
+        }
+        if let Some(previous) = self.stage.replace(stage) {
+            let took = stage_started.saturating_duration_since(self.started);
+            let actual_s = (took.as_secs_f64() * self.speed).round() as u32;
+            if let Some(slot) = self.actual_s.get_mut(previous) {
+                *slot = Some(actual_s);
+            }
+        }
+        self.started = stage_started;
+    }
+
+    /// How long `time` on the timer's clock took on the wall clock.
+    fn wall_time(&self, time: Duration) -> Duration {
+        time.div_f64(self.speed)
+    }
+}
+
+/// How far behind the timer the watchers are when a stage starts: the time
+/// from the stage starting on the timer's clock to its hooks, sounds and
+/// notifications being done. The timer carries on from when the stage
+/// started, so the slack doesn't add up over a run.
+#[derive(Default)]
+struct TransitionSlack {
+    stage: Option<usize>,
+    /// Slack of the latest stage, until it is taken
+    latest: Option<Duration>
+}
+
+impl TransitionSlack {
+    /// Measure the slack if the running stage of `timer`, which started at
+    /// `stage_started`, is new.
+    fn update(&mut self, timer: &Timer, stage_started: Instant) {
+        let stage = timer.current_index();
+        if self.stage.replace(stage) != Some(stage) && !timer.is_finished() {
+            self.latest = Some(stage_started.elapsed());
+        }
+    }
+}
+
+/// How long a frame of the TUI, from advancing the timer to drawing it, may
+/// take. Animations are drawn every [`interactive::FRAME_INTERVAL`], so
+/// slower frames make them lag.
+const FRAME_BUDGET: Duration = Duration::from_millis(50);
+
+/// Keeps track of frames of the TUI that took longer than [`FRAME_BUDGET`].
+#[derive(Default)]
+struct FrameTimes {
+    over_budget: u32,
+    slowest: Duration
+}
+
+impl FrameTimes {
+    /// Note a frame that took `took`.
+    fn record(&mut self, took: Duration) {
+        if took > FRAME_BUDGET {
+            self.over_budget += 1;
+        }
+        self.slowest = self.slowest.max(took);
+    }
+
+    /// A warning about frames over the budget, if there were any.
+    fn warning(&self, stages: usize) -> Option<String> {
+        (self.over_budget > 0).then(|| format!(
+            "{} frame(s) took longer than {}ms to draw, up to {}ms, with {} stages",
+            self.over_budget,
+            FRAME_BUDGET.as_millis(),
+            self.slowest.as_millis(),
+            stages
+        ))
+    }
+}
+
+/// Everything that follows the timer from stage to stage on the side.
+struct StageWatchers {
+    capture: environment::Capture,
+    notifiers: notify::Notifiers,
+    hooks: hooks::Hooks,
+    overlay: Option<overlay::Overlay>,
+    mqtt: Option<mqtt::Mqtt>,
+    durations: StageDurations,
+    time_format: TimeFormat,
+    /// Saves the run for `--resume`
+    checkpoint: Option<RunCheckpoint>,
+    frames: FrameTimes,
+    slack: TransitionSlack,
+    event_log: Option<event_log::EventLog>,
+    /// Plays the audio file of the running stage in the TUI
+    stage_audio: Option<StageAudio>
+}
+
+impl StageWatchers {
+    fn update(&mut self, timer: &Timer) {
+        // the running stage started before the timer was advanced into it,
+        // by as much as it has run since
+        let now = Instant::now();
+        let stage_elapsed = timer.current_stage().map_or(Duration::ZERO, |stage| stage.elapsed);
+        let stage_started = now.checked_sub(self.durations.wall_time(stage_elapsed)).unwrap_or(now);
+        if let Some(stage_audio) = self.stage_audio.as_mut() {
+            stage_audio.update(timer);
+        }
+        self.capture.update(timer);
+        self.notifiers.update(timer, self.time_format);
+        self.hooks.update(timer);
+        // neither an overlay nor a broker that can't be reached may stop the
+        // timer
+        if let Some(overlay) = self.overlay.as_mut() {
+            let _ = overlay.render(timer);
+        }
+        if let Some(mqtt) = self.mqtt.as_mut() {
+            let _ = mqtt.render(timer);
+        }
+        // hooks and sounds of a new stage take time, which its duration
+        // mustn't lose
+        self.durations.update(timer, stage_started);
+        if let Some(event_log) = self.event_log.as_mut() {
+            event_log.update(timer);
+        }
+        if let Some(mut checkpoint) = self.checkpoint.take() {
+            if checkpoint.is_due() {
+                checkpoint.save(timer, self);
+            }
+            self.checkpoint = Some(checkpoint);
+        }
+        self.slack.update(timer, stage_started);
+    }
+
+    /// Tell the user that the running stage reached a point of interest.
+    fn alert(&mut self) {
+        self.notifiers.alert();
+    }
+
+    /// Tell the user that the running stage reached its warning threshold.
+    fn warn(&mut self, timer: &Timer) {
+        self.notifiers.warn(timer);
+    }
+
+    /// Send notices and run hooks that were held back for coming too soon
+    /// after the last ones, once their time has come. The timer isn't
+    /// updated while it is paused, so this is done on every frame.
+    fn dispatch_held(&mut self, timer: &Timer) {
+        self.notifiers.dispatch_held();
+        self.hooks.update(timer);
+    }
+
+    /// Measure the running stage from now on as it was started over, and
+    /// play its audio from the start.
+    fn restart_stage(&mut self, timer: &Timer) {
+        self.durations.started = Instant::now();
+        if let Some(stage_audio) = self.stage_audio.as_mut() {
+            stage_audio.restart();
+            stage_audio.update(timer);
+        }
+    }
+
+    /// Log that the user is about to change the running stage.
+    fn adjusted(&mut self, timer: &Timer, adjustment: schema::Adjustment, by_s: Option<u32>) {
+        if let Some(event_log) = self.event_log.as_mut() {
+            event_log.adjusted(timer, adjustment, by_s);
+        }
+    }
+
+    /// The sensor readings and actual duration of each stage. A run that
+    /// was quit before it was done is checkpointed to be resumed later,
+    /// unless it was aborted.
+    fn finish(mut self, timer: &Timer) -> (Vec<Option<history::Environment>>, Vec<Option<u32>>) {
+        if let Some(mut checkpoint) = self.checkpoint.take() {
+            if timer.is_finished() || timer.is_aborted() {
+                detach::remove_checkpoint(&checkpoint.name);
+            } else {
+                checkpoint.save(timer, &mut self);
+            }
+        }
+        self.hooks.finish(timer);
+        if let Some(event_log) = self.event_log.as_mut() {
+            event_log.finish(timer);
+        }
+        self.durations.actual_s.resize(timer.stages().len(), None);
+        (self.capture.collect(timer.stages().len()), self.durations.actual_s)
+    }
+
+    /// Take up a run detached elsewhere, which recorded `stages` so far and
+    /// has been in its running stage for `stage_elapsed`.
+    fn resume(&mut self, timer: &Timer, stages: Vec<DetachedStage>, stage_elapsed: Duration) {
+        let (actual_s, readings) = stages.into_iter()
+            .map(|stage| (stage.actual_s, stage.environment))
+            .unzip();
+        self.capture.resume(timer, readings);
+        self.notifiers.resume(timer);
+        self.hooks.resume(timer);
+        if let Some(event_log) = self.event_log.as_mut() {
+            event_log.resume(timer);
+        }
+        self.slack.stage = Some(timer.current_index());
+        let now = Instant::now();
+        self.durations = StageDurations {
+            stage: Some(timer.current_index()),
+            started: now.checked_sub(stage_elapsed).unwrap_or(now),
+            actual_s,
+            ..StageDurations::new()
+        };
+    }
+
+    /// What has been recorded of each stage so far and how long the running
+    /// stage has taken, for whoever carries on with the run. Unlike
+    /// `finish`, the running stage is left to end there.
+    fn detach(&mut self, timer: &Timer) -> (Vec<DetachedStage>, Duration) {
+        let readings = self.capture.collected(timer.stages().len());
+        self.durations.actual_s.resize(timer.stages().len(), None);
+        let stages = readings.into_iter()
+            .zip(self.durations.actual_s.iter().copied())
+            .map(|(environment, actual_s)| DetachedStage { actual_s, environment })
+            .collect();
+        (stages, self.durations.started.elapsed())
+    }
+}
+
+/// Messages about the stages whose actual duration differs from the plan by
+/// more than `tolerance_s`, by stage. Aborted stages are left out, they are
+/// reported as aborted instead.
+fn deviations(
+    timer: &Timer,
+    actual_s: &[Option<u32>],
+    tolerance_s: u32,
+    time_format: TimeFormat
+) -> Vec<(usize, String)>
+{
+    timer.stages().iter()
+        .zip(actual_s)
+        .enumerate()
+        .filter(|(_, (stage, _))| !stage.aborted)
+        .filter_map(|(i, (stage, actual_s))| {
+            let actual_s = (*actual_s)?;
+            let period_s = stage.period_s();
+            let deviation_s = actual_s.abs_diff(period_s);
+            (deviation_s > tolerance_s).then(|| (i, format!(
+                "{} took {} instead of {} ({}{})",
+                stage.name,
+                time_format.format(actual_s, period_s.max(actual_s)),
+                time_format.format(period_s, period_s.max(actual_s)),
+                if actual_s > period_s { "+" } else { "-" },
+                time_format.format(deviation_s, deviation_s)
+            )))
+        })
+        .collect()
+}
+
+/// The run as a development of a roll using chemical batches, along with
+/// notes, the ambient conditions, the sensor `readings` and `actual_s`
+/// durations of each stage, stages that deviated from the plan and whether
+/// it was paused for longer than its budget.
+fn session_record(
+    log: &SessionLog,
+    started: DateTime<Local>,
+    timer: &Timer,
+    (readings, actual_s): (Vec<Option<history::Environment>>, Vec<Option<u32>>),
+    deviated: &[usize],
+    over_pause_budget: bool
+) -> history::SessionRecord {
+    let mut notes = history::take_pending_notes().unwrap_or_else(|message| {
+        eprintln!("{}", message);
+        Vec::new()
+    });
+    notes.extend(log.notes.iter().cloned());
+    history::SessionRecord {
+        started: started.to_rfc3339(),
+        completed: timer.is_finished(),
+        aborted: timer.is_aborted(),
+        roll: log.roll.clone(),
+        preset: log.preset.clone(),
+        batches: log.batches.clone(),
+        notes,
+        environment: Some(log.environment).filter(|environment| !environment.is_empty()),
+        over_pause_budget,
+        stages: timer.stages().iter()
+            .zip(readings.into_iter().zip(actual_s))
+            .enumerate()
+            .map(|(i, (stage, (environment, actual_s)))| history::StageRecord {
+                name: stage.name.clone(),
+                period_s: stage.period_s(),
+                actual_s,
+                deviated: deviated.contains(&i),
+                aborted: stage.aborted,
+                environment
+            })
+            .collect()
+    }
+}
+
+/// Record the run in the history. Runs without a roll, batches, notes or
+/// ambient conditions are not recorded unless they ran a preset.
+fn log_session(record: history::SessionRecord) {
+    if record.roll.is_none()
+        && record.preset.is_none()
+        && record.batches.is_empty()
+        && record.notes.is_empty()
+        && record.environment.is_none()
+        && record.stages.iter().all(|stage| stage.environment.is_none())
+    {
+        return;
+    }
+    if let Err(message) = history::append(record) {
+        eprintln!("{}", message);
+    }
+}
+
+/// What there is to say about a run once it is over.
+struct SessionReport {
+    /// How long each stage really took
+    actual_s: Vec<Option<u32>>,
+    /// How long the whole run took, pauses included
+    took_s: u32,
+    /// How often the timer was paused and for how long in all
+    pauses: u32,
+    paused_s: u32,
+    /// Stages that were aborted or deviated from the plan
+    warnings: Vec<String>,
+    /// The run as it is written out as JSON
+    session: schema::Session
+}
+
+/// Find stages that were aborted or deviated from the plan, record the run
+/// in the history and write its summary where it was asked for.
+fn finish_session(
+    log: &SessionLog,
+    started: DateTime<Local>,
+    timer: &Timer,
+    mut watchers: StageWatchers,
+    display: &DisplayOptions
+) -> SessionReport {
+    let time_format = display.time_format;
+    // the last stage end may still be held back
+    watchers.notifiers.flush();
+    let slow_frames = watchers.frames.warning(timer.stages().len());
+    let failed_notifiers = watchers.notifiers.failures().to_vec();
+    let pauses = watchers.durations.pauses;
+    let over_budget = display.over_pause_budget(timer);
+    // the timer lingers on its last stage for a moment
+    let ended = watchers.durations.finished.unwrap_or_else(Local::now);
+    let speed = watchers.durations.speed;
+    let (readings, actual_s) = watchers.finish(timer);
+    let mut warnings: Vec<String> = timer.stages().iter()
+        .filter(|stage| stage.aborted)
+        .map(|stage| format!("{} was aborted", stage.name))
+        .chain(slow_frames)
+        .chain(failed_notifiers)
+        .collect();
+    let deviations = log.tolerance_s
+        .map(|tolerance_s| deviations(timer, &actual_s, tolerance_s, time_format))
+        .unwrap_or_default();
+    let deviated: Vec<usize> = deviations.iter().map(|(i, _)| *i).collect();
+    warnings.extend(deviations.into_iter().map(|(_, message)| message));
+    warnings.extend(over_budget.map(|over| pause_budget_warning(over, display)));
+    let record = session_record(
+        log, started, timer, (readings, actual_s.clone()), &deviated, over_budget.is_some()
+    );
+    let session = schema::Session::of(&record, None);
+    // a dry run only shows how the stages go
+    if display.dry_run.is_none() {
+        log_session(record);
+    }
+    let took = (ended - started).to_std().unwrap_or_default().mul_f64(speed);
+    let took_s = u32::try_from(took.as_secs()).unwrap_or_default();
+    let paused_s = timer.paused_total().as_secs_f64().round() as u32;
+    let mut report = SessionReport { actual_s, took_s, pauses, paused_s, warnings, session };
+    if let Some(path) = &log.summary_json {
+        if let Err(message) = write_summary(path, timer, &report) {
+            report.warnings.push(message);
+        }
+    }
+    report
+}
+
+/// Write `report` on the run of `timer` to `path` as JSON.
+fn write_summary(path: &Path, timer: &Timer, report: &SessionReport) -> Result<(), String> {
+    let summary = schema::Summary {
+        time_s: timer.stages().iter().map(TimerStage::period_s).sum(),
+        actual_s: report.took_s,
+        pauses: report.pauses,
+        paused_s: report.paused_s,
+        session: &report.session
+    };
+    fs::write(path, schema::line(&summary) + "\n").map_err(|e| format!(
+        "Could not write the summary to {}: {}", path.display(), e
+    ))
+}
+
+/// Run the stages without a TUI, starting with `round` if they run in a
+/// loop, followed by any presets they are to be followed by. A `resumed`
+/// run is carried on with first. Summaries and the list of presets need a
+/// terminal and are left out.
+fn run_unattended(
+    (mut stages, mut completion, mut round, mut resumed):
+        (Vec<StageSpec>, Completion, Option<Round>, Option<DetachedTimer>),
+    display: &DisplayOptions,
+    log: &SessionLog,
+    presets: &PresetLoader,
+    (new_watchers, new_checkpoint): (
+        impl Fn() -> StageWatchers,
+        impl Fn(&SessionLog, DateTime<Local>, (&Completion, &[TimerStage], Option<Round>)) -> Option<RunCheckpoint>
+    ),
+    (inputs, mut rest_gate): (&mut input::Inputs, Option<heart_rate::RestGate>),
+    out: &mut impl Write
+) -> Result<(), RunError>
+{
+    let mut log = log.clone();
+    let mut planned = create_timer_list(stages);
+    loop {
+        let (mut timer, mut watchers, started) = match resumed.take() {
+            Some(resumed) => {
+                planned = resumed.planned.clone();
+                resumed.resume(new_watchers())
+            },
+            None => (Timer::new(planned.clone()), new_watchers(), Local::now())
+        };
+        watchers.checkpoint = new_checkpoint(&log, started, (&completion, &planned, round));
+        if let Some(round) = round.filter(|_| !display.headless && !display.statusbar) {
+            writeln!(out, "{}", round.label())?;
+        }
+        run_plain(
+            &mut timer,
+            &mut watchers,
+            (inputs, rest_gate.as_mut()),
+            plain_frontend(display, out).as_mut(),
+            display.run_clock().as_ref()
+        )?;
+        let report = finish_session(&log, started, &timer, watchers, display);
+        for warning in report.warnings {
+            eprintln!("Warning: {}", warning);
+        }
+        if display.headless {
+            writeln!(out, "{}", schema::line(&schema::Event::Session { session: report.session }))?;
+        }
+
+        round = round.and_then(Round::next).filter(|_| timer.is_finished());
+        let name = match completion {
+            // the stages start over until the last round is done
+            _ if round.is_some() => continue,
+            // until the timer is quit
+            Completion::Again if timer.is_finished() => continue,
+            Completion::Preset(name) => name,
+            _ => return Ok(())
+        };
+        (stages, completion) = presets.load(&name, presets.completion.clone())?;
+        planned = create_timer_list(stages);
+        log.preset = Some(name);
+    }
+}
+
+/// What a detached run has recorded of a stage so far.
+#[derive(Clone, Serialize, Deserialize)]
+struct DetachedStage {
+    actual_s: Option<u32>,
+    environment: Option<history::Environment>
+}
+
+/// A running timer saved to be carried on with by another process.
+#[derive(Clone, Serialize, Deserialize)]
+struct DetachedTimer {
+    /// Local time the run started at, in RFC 3339 format
+    started: String,
+    /// When the timer was saved, to catch up on the time since
+    saved_at: SystemTime,
+    /// Wall clock time the running stage had taken when it was saved
+    stage_elapsed: Duration,
+    /// What happens once all stages are done
+    completion: Completion,
+    /// The stages as they were before the run started, to run them again
+    #[serde(default)]
+    planned: Vec<TimerStage>,
+    /// The round of `--loop` the run is in
+    #[serde(default)]
+    round: Option<Round>,
+    stages: Vec<DetachedStage>,
+    timer: Timer
+}
+
+impl DetachedTimer {
+    fn new(
+        timer: &Timer,
+        started: DateTime<Local>,
+        (completion, planned, round): (Completion, &[TimerStage], Option<Round>),
+        watchers: &mut StageWatchers
+    ) -> DetachedTimer {
+        let (stages, stage_elapsed) = watchers.detach(timer);
+        DetachedTimer {
+            started: started.to_rfc3339(),
+            saved_at: SystemTime::now(),
+            stage_elapsed,
+            completion,
+            planned: planned.to_vec(),
+            round,
+            stages,
+            timer: timer.clone()
+        }
+    }
+
+    /// The timer caught up with the time since it was saved, `watchers`
+    /// following it from where it was and when the run started.
+    fn resume(self, mut watchers: StageWatchers) -> (Timer, StageWatchers, DateTime<Local>) {
+        let gap = SystemTime::now().duration_since(self.saved_at).unwrap_or_default();
+        let mut timer = self.timer;
+        watchers.resume(&timer, self.stages, self.stage_elapsed + gap);
+        timer.advance(gap);
+        watchers.update(&timer);
+        let started = DateTime::parse_from_rfc3339(&self.started)
+            .map(|started| started.with_timezone(&Local))
+            .unwrap_or_else(|_| Local::now());
+        (timer, watchers, started)
+    }
+}
+
+/// A run detached from the terminal, saved for the background process that
+/// carries on with it and for `attach` to pick up. Secrets like tokens,
+/// passwords and commands are left out of it, see
+/// [`DetachedRun::restore_secrets`].
+#[derive(Clone, Serialize, Deserialize)]
+struct DetachedRun {
+    session_name: Option<String>,
+    controls: Controls,
+    display: DisplayOptions,
+    sound: SoundOptions,
+    /// How to announce stage transitions
+    notify: notify::NotifyOptions,
+    hooks: hooks::Hooks,
+    overlay: Option<overlay::OverlayOptions>,
+    #[serde(default)]
+    mqtt: Option<mqtt::MqttOptions>,
+    log: SessionLog,
+    presets: PresetLoader,
+    checkpoint_interval: Option<Duration>,
+    #[serde(default)]
+    allow_sleep: bool,
+    /// Summaries of the runs that are over, printed on `attach`
+    summaries: Vec<String>,
+    warnings: Vec<String>,
+    /// The run in progress, `None` once all runs are over
+    running: Option<DetachedTimer>
+}
+
+impl DetachedRun {
+    /// Put back what was left out of the saved run as the settings have it
+    /// now: the tokens of the control port and of `--serve`, the MQTT
+    /// password and the hook and heart rate commands. Whatever of these was
+    /// only given on the command line is gone, a heart rate command with it
+    /// leaves the rests to run on time.
+    fn restore_secrets(&mut self, settings: &config::Settings) {
+        self.controls.control_access.tokens = settings.control.tokens.clone();
+        self.hooks = hooks::Hooks::new(settings.on_stage_start.clone(), settings.on_stage_end.clone());
+        if let Some(overlay) = self.overlay.as_mut() {
+            overlay.serve_token = settings.serve.token.clone();
+        }
+        if let Some(mqtt) = self.mqtt.as_mut() {
+            mqtt.password = settings.mqtt_password.clone();
+        }
+        self.controls.heart_rate = self.controls.heart_rate.take().and_then(|heart_rate| {
+            Some(heart_rate::HeartRateOptions {
+                command: settings.heart_rate_command.clone()?,
+                ..heart_rate
+            })
+        });
+    }
+}
+
+/// Saves the run now and then, and when the timer is quit before it is
+/// done, so `--resume` can carry on with it after a crash or a closed
+/// terminal.
+struct RunCheckpoint {
+    /// Session name of the run
+    name: String,
+    /// How often to save while the timer runs, only when it is quit if
+    /// `None`
+    interval: Option<Duration>,
+    /// Everything about the run but the running timer
+    run: DetachedRun,
+    started: DateTime<Local>,
+    completion: Completion,
+    planned: Vec<TimerStage>,
+    round: Option<Round>,
+    saved: Instant
+}
+
+impl RunCheckpoint {
+    /// Save `run` as `name`, with the timer of the run that started at
+    /// `started`, when it is quit and every checkpoint interval of `run`.
+    fn new(
+        name: &str,
+        run: DetachedRun,
+        started: DateTime<Local>,
+        (completion, planned, round): (&Completion, &[TimerStage], Option<Round>)
+    ) -> RunCheckpoint {
+        RunCheckpoint {
+            name: name.to_string(),
+            interval: run.checkpoint_interval,
+            run,
+            started,
+            completion: completion.clone(),
+            planned: planned.to_vec(),
+            round,
+            saved: Instant::now()
+        }
+    }
+
+    fn is_due(&self) -> bool {
+        self.interval.is_some_and(|interval| self.saved.elapsed() >= interval)
+    }
+
+    fn save(&mut self, timer: &Timer, watchers: &mut StageWatchers) {
+        self.run.running = Some(
+            DetachedTimer::new(
+                timer, self.started, (self.completion.clone(), &self.planned, self.round), watchers
+            )
+        );
+        // a checkpoint that can't be written mustn't stop the timer
+        let _ = detach::save_checkpoint(&self.name, &self.run);
+        self.run.running = None;
+        self.saved = Instant::now();
+    }
+}
+
+/// A detached run taken up again in the terminal.
+struct ResumedRun {
+    timer: DetachedTimer,
+    summaries: Vec<String>,
+    warnings: Vec<String>
+}
+
+/// The command that attaches to the run detached as `name`.
+fn attach_command(name: &str) -> String {
+    if name == detach::DEFAULT_NAME {
+        String::from("staged-timer attach")
+    } else {
+        format!("staged-timer attach {}", name)
+    }
+}
+
+/// Hand `run` over to a background process that carries on with it as
+/// `name`.
+fn detach_run(name: &str, run: &DetachedRun) -> Result<(), String> {
+    let taken = matches!(detach::claim(name)?, session::SessionClaim::Taken(_))
+        || detach::load::<DetachedRun>(name)?.is_some();
+    if taken {
+        return Err(format!(
+            "A timer is already detached as \"{}\", run `{}` first",
+            name, attach_command(name)
+        ));
+    }
+    detach::save(name, run)?;
+    detach::spawn(name).inspect_err(|_| detach::remove(name))
+}
+
+/// Carry on with the run detached as `name` in the background until it is
+/// over or `attach` takes it over.
+fn run_daemon(name: &str) -> Result<(), String> {
+    let _lock = match detach::claim(name)? {
+        session::SessionClaim::Claimed(lock) => lock,
+        session::SessionClaim::Taken(_) => return Err(format!(
+            "A timer is already detached as \"{}\"", name
+        ))
+    };
+    let mut run: DetachedRun = detach::load(name)?.ok_or_else(|| format!(
+        "No timer is detached as \"{}\"", name
+    ))?;
+    run.restore_secrets(&config::load_settings()?);
+    let detached = run.running.take().ok_or_else(|| format!(
+        "The timer detached as \"{}\" is done already", name
+    ))?;
+    // give the terminal that detached the run a moment to let go of the
+    // session name
+    let _session_lock = run.session_name.as_deref().and_then(|session_name| {
+        (0..40).find_map(|_| match session::claim(session_name) {
+            Ok(session::SessionClaim::Claimed(lock)) => Some(lock),
+            _ => {
+                thread::sleep(Duration::from_millis(50));
+                None
+            }
+        })
+    });
+
+    // the terminal may still be serving the overlay for a moment as well
+    let overlay = run.overlay.as_ref().and_then(|options| {
+        (0..40).find_map(|_| overlay::Overlay::start(options, run.display.time_format).ok().or_else(|| {
+            thread::sleep(Duration::from_millis(50));
+            None
+        }))
+    });
+    let mqtt = run.mqtt.as_ref().and_then(|options| {
+        mqtt::Mqtt::connect(options).inspect_err(|message| eprintln!("Warning: {}", message)).ok()
+    });
+    let new_watchers = || StageWatchers {
+        capture: environment::Capture::new(run.log.sensor_command.clone()),
+        notifiers: notify::Notifiers::find(&run.notify).0,
+        hooks: run.hooks.clone(),
+        overlay: overlay.clone(),
+        mqtt: mqtt.clone(),
+        durations: StageDurations::new(),
+        time_format: run.display.time_format,
+        checkpoint: None,
+        frames: FrameTimes::default(),
+        slack: TransitionSlack::default(),
+        event_log: run.log.event_log.as_deref()
+            .and_then(|path| event_log::EventLog::open(path).ok()),
+        stage_audio: None
+    };
+
+    // there is no one to warn in the background, so the timer runs on
+    // without whichever of these doesn't start
+    let mut inputs = input::Inputs::default();
+    // for `staged-timer status`, `pause` and `unpause`
+    #[cfg(unix)]
+    let _registration = instances::socket_path().ok().and_then(|path| {
+        inputs.add(control::ControlSocket::bind(&path).ok()?);
+        instances::register(run.session_name.as_deref(), &path).ok()
+    });
+    let mut rest_gate = run.controls.heart_rate.clone().and_then(|options| {
+        inputs.add(heart_rate::Monitor::start(&options.command).ok()?);
+        Some(heart_rate::RestGate::new(options))
+    });
+
+    let _awake = (!run.allow_sleep).then(inhibit::keep_awake).and_then(Result::ok);
+
+    let mut completion = detached.completion.clone();
+    let mut planned = detached.planned.clone();
+    let mut round = detached.round;
+    let (mut timer, mut watchers, mut started) = detached.resume(new_watchers());
+    // saved for `--resume` in case the background process goes away
+    watchers.checkpoint = Some(RunCheckpoint::new(name, run.clone(), started, (&completion, &planned, round)));
+    loop {
+        let mut last_advance = Instant::now();
+        // the first stage starts with the clock, so the time its hooks and
+        // sounds take comes out of it rather than before it
+        watchers.update(&timer);
+        while !timer.is_finished() {
+            if detach::attach_requested(name) {
+                run.running = Some(
+                    DetachedTimer::new(&timer, started, (completion, &planned, round), &mut watchers)
+                );
+                return detach::save(name, &run);
+            }
+            let input = inputs.next(timer.until_next_step()
+                .unwrap_or(Duration::from_secs(1))
+                .min(Duration::from_millis(100)));
+            let now = Instant::now();
+            timer.advance(now - last_advance);
+            timer.follow_deadline(SystemTime::now());
+            last_advance = now;
+            match input {
+                Some(input::Input::Command(command)) => {
+                    apply_command(&mut timer, &mut watchers, command);
+                },
+                Some(input::Input::Status(request)) => request.answer(&timer),
+                Some(input::Input::HeartRate(bpm)) => {
+                    if let Some(gate) = rest_gate.as_mut() {
+                        gate.reading(bpm);
+                    }
+                },
+                _ => {}
+            }
+            if let Some(gate) = rest_gate.as_mut() {
+                gate.check(&mut timer);
+            }
+            watchers.update(&timer);
+        }
+
+        let report = finish_session(&run.log, started, &timer, watchers, &run.display);
+        round = round.and_then(Round::next);
+        let next_run = match &completion {
+            // the stages start over until the last round is done
+            _ if round.is_some() => Some((planned.clone(), completion.clone())),
+            Completion::Preset(preset) => {
+                match run.presets.load(preset, run.presets.completion.clone()) {
+                    Ok((stages, completion)) => {
+                        run.log.preset = Some(preset.clone());
+                        Some((create_timer_list(stages), completion))
+                    },
+                    Err(message) => {
+                        run.warnings.push(message);
+                        None
+                    }
+                }
+            },
+            Completion::Again => Some((planned.clone(), Completion::Again)),
+            _ => None
+        };
+        // the last summary is shown on attach either way
+        if run.display.keep_output || next_run.is_none() {
+            run.summaries.push(plain_summary(&timer, &report, &run.display));
+        }
+        run.warnings.extend(report.warnings);
+
+        (planned, completion) = match next_run {
+            Some(next_run) => next_run,
+            None => break
+        };
+        timer = Timer::new(planned.clone());
+        watchers = new_watchers();
+        started = Local::now();
+        watchers.checkpoint = Some(RunCheckpoint::new(name, run.clone(), started, (&completion, &planned, round)));
+    }
+    detach::save(name, &run)
+}
+
+/// Take over the run detached as `name` to carry on with it here. If it is
+/// over already, print its summary instead and return `None`.
+fn attach(name: &str, settings: &config::Settings) -> Result<Option<RunOptions>, String> {
+    let running = !matches!(detach::claim(name)?, session::SessionClaim::Claimed(_));
+    if running && !io::stdout().is_terminal() && !io::stderr().is_terminal() {
+        return Err(String::from("attach needs a terminal to show the timer on"));
+    }
+    let _lock = detach::take_over(name)?;
+    // what the background process wrote before it went away, as it ended
+    // or as it crashed
+    let log = (!running).then(|| detach::read_log(name)).flatten();
+    let mut run: DetachedRun = match detach::load(name)? {
+        Some(run) => run,
+        None => return Err(match log {
+            Some(log) => format!(
+                "No timer is detached as \"{}\", its background process wrote:\n{}", name, log
+            ),
+            None => format!("No timer is detached as \"{}\"", name)
+        })
+    };
+    detach::remove(name);
+    run.restore_secrets(settings);
+
+    let timer = match run.running.take() {
+        Some(timer) => timer,
+        None => {
+            println!("{}", run.summaries.join("\n\n"));
+            if let Some(log) = log {
+                eprintln!("{}", log);
+            }
+            for warning in run.warnings {
+                eprintln!("Warning: {}", warning);
+            }
+            return Ok(None);
+        }
+    };
+    // the timer is taken up from where it was last saved, the summary at
+    // the end says why
+    if !running {
+        run.warnings.push(match log {
+            Some(log) => format!(
+                "The background timer stopped before the run was done, it wrote:\n{}", log
+            ),
+            None => String::from("The background timer stopped before the run was done")
+        });
+    }
+    Ok(Some(resumed_options(run, timer, settings)))
+}
+
+/// Carry on with the run named `name` from its last checkpoint.
+fn resume(name: &str, settings: &config::Settings) -> Result<RunOptions, String> {
+    let mut run: DetachedRun = detach::load_checkpoint(name)?
+        .filter(|run: &DetachedRun| run.running.is_some())
+        .ok_or_else(|| match name {
+            detach::DEFAULT_NAME => String::from("There is no run to resume"),
+            name => format!("There is no run named \"{}\" to resume", name)
+        })?;
+    run.restore_secrets(settings);
+    let mut timer = run.running.take().unwrap();
+    // pick up where the run stopped rather than catching up on the time since
+    timer.saved_at = SystemTime::now();
+    Ok(resumed_options(run, timer, settings))
+}
+
+/// Options to carry on with `run` in the terminal, starting from `timer`.
+fn resumed_options(
+    run: DetachedRun,
+    timer: DetachedTimer,
+    settings: &config::Settings
+) -> RunOptions {
+    let audio_player = audio::Player::find(settings.audio_player.as_deref())
+        .filter(|_| run.sound.enabled);
+    let overlay = run.overlay.as_ref().and_then(|options| {
+        overlay::Overlay::start(options, run.display.time_format)
+            .inspect_err(|message| eprintln!("Warning: {}", message))
+            .ok()
+    });
+    let mqtt = run.mqtt.as_ref().and_then(|options| {
+        mqtt::Mqtt::connect(options).inspect_err(|message| eprintln!("Warning: {}", message)).ok()
+    });
+    RunOptions {
+        stages: Vec::new(),
+        controls: run.controls,
+        display: run.display,
+        sound: run.sound,
+        select_stages: false,
+        edit_stages: false,
+        wait: false,
+        allow_sleep: run.allow_sleep,
+        session_name: run.session_name,
+        audio_player,
+        notify: run.notify,
+        hooks: run.hooks,
+        overlay,
+        mqtt,
+        log: run.log,
+        completion: timer.completion.clone(),
+        round: timer.round,
+        splits: Vec::new(),
+        presets: run.presets,
+        watched_config: None,
+        checkpoint_interval: run.checkpoint_interval,
+        resumed: Some(ResumedRun { timer, summaries: run.summaries, warnings: run.warnings })
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            // everything `run` held is released by now, the terminal included
+            let message = error.to_string();
+            if !message.is_empty() {
+                eprintln!("{}", message);
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Everything `main` does, ending early with an error rather than exiting
+/// so whatever it holds is released first.
+fn run() -> Result<(), RunError> {
+    // == Data setup ===========================================================
+    let RunOptions{
+        mut stages,
+        controls,
+        display,
+        sound,
+        select_stages: show_stage_selection,
+        edit_stages: show_stage_editor,
+        wait,
+        allow_sleep,
+        session_name,
+        audio_player,
+        notify: notify_options,
+        hooks,
+        overlay,
+        mqtt,
+        log,
+        completion,
+        round,
+        mut splits,
+        presets,
+        watched_config,
+        checkpoint_interval,
+        resumed
+    } = match args::parse_cl_args()? {
+        Some(options) => options,
+        // a command other than a run was done
+        None => return Ok(())
+    };
+
+    // held until the end of main, releasing the session name on exit
+    let _session_lock = match session_name.as_deref().map(session::claim) {
+        None => None,
+        Some(Ok(session::SessionClaim::Claimed(lock))) => Some(lock),
+        Some(Ok(session::SessionClaim::Taken(pid))) => {
+            let owner = pid.map(|pid| format!(" (PID {})", pid)).unwrap_or_default();
+            eprint!(
+                "A session named \"{}\" is already running{}. ",
+                session_name.as_deref().unwrap_or_default(), owner
+            );
+            if wait || !io::stdin().is_terminal() {
+                eprintln!();
+                return Err(RunError::Quiet);
+            }
+            eprint!("Start another one anyway? [y/N] ");
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer)?;
+            if !answer.trim().eq_ignore_ascii_case("y") {
+                return Err(RunError::Quiet);
+            }
+            None
+        },
+        Some(Err(message)) => return Err(message.into())
+    };
+
+    let batch_warnings = chemicals::check(&log.batches)?;
+    for warning in batch_warnings.iter() {
+        eprintln!("Warning: {}", warning);
+    }
+    if !batch_warnings.is_empty() && !wait && resumed.is_none() && io::stdin().is_terminal() {
+        eprint!("Start anyway? [y/N] ");
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            return Err(RunError::Quiet);
+        }
+    }
+
+    let new_watchers = || StageWatchers {
+        capture: environment::Capture::new(log.sensor_command.clone()),
+        notifiers: notify::Notifiers::find(&notify_options).0,
+        hooks: hooks.clone(),
+        overlay: overlay.clone(),
+        mqtt: mqtt.clone(),
+        durations: StageDurations {
+            speed: display.dry_run.unwrap_or(1.0),
+            ..StageDurations::new()
+        },
+        time_format: display.time_format,
+        checkpoint: None,
+        frames: FrameTimes::default(),
+        slack: TransitionSlack::default(),
+        event_log: log.event_log.as_deref()
+            .and_then(|path| event_log::EventLog::open(path).ok()),
+        stage_audio: None
+    };
+
+    #[cfg_attr(not(unix), allow(clippy::bind_instead_of_map))]
+    let mut inputs = input::Inputs::open(&controls.input_devices, controls.adjust_step)
+        .and_then(|inputs| {
+            // other systems turn both down before the run
+            #[cfg(unix)]
+            let mut inputs = inputs;
+            #[cfg(unix)]
+            if let Some(path) = &controls.control_socket {
+                inputs.add(control::ControlSocket::bind(path)?);
+            }
+            #[cfg(unix)]
+            if let Some(port) = controls.control_port {
+                inputs.add(control::ControlSocket::listen(port, &controls.control_access)?);
+            }
+            Ok(inputs)
+        })?;
+    // registered for `staged-timer status`, `pause` and `unpause`, on a
+    // socket of its own unless --control-socket gave one
+    #[cfg(unix)]
+    let _registration = match &controls.control_socket {
+        Some(path) => Ok(path.clone()),
+        None => instances::socket_path().and_then(|path| {
+            control::ControlSocket::bind(&path).map(|socket| {
+                inputs.add(socket);
+                path
+            })
+        })
+    }.and_then(|path| instances::register(session_name.as_deref(), &path)).inspect_err(|message| {
+        eprintln!("Warning: `staged-timer status` won't find this timer. {}", message);
+    }).ok();
+    if let Some(heart_rate) = &controls.heart_rate {
+        inputs.add(heart_rate::Monitor::start(&heart_rate.command)?);
+    }
+
+    // held until the end of main, the timer can't tick while asleep
+    let _awake = (!allow_sleep).then(inhibit::keep_awake).and_then(|awake| {
+        awake.inspect_err(|message| {
+            eprintln!("Warning: The system may go to sleep while the timer runs. {}", message);
+        }).ok()
+    });
+
+    let detach_name = session_name.as_deref().unwrap_or(detach::DEFAULT_NAME);
+    // everything about a run but its timer, to carry on with it later
+    let saved_run = |log: &SessionLog, summaries: &[String], warnings: &[String]| DetachedRun {
+        session_name: session_name.clone(),
+        controls: controls.clone(),
+        display: display.clone(),
+        sound: sound.clone(),
+        notify: notify_options.clone(),
+        hooks: hooks.clone(),
+        overlay: overlay.as_ref().map(|overlay| overlay.options().clone()),
+        mqtt: mqtt.as_ref().map(|mqtt| mqtt.options().clone()),
+        log: log.clone(),
+        presets: presets.clone(),
+        checkpoint_interval,
+        allow_sleep,
+        summaries: summaries.to_vec(),
+        warnings: warnings.to_vec(),
+        running: None
+    };
+    // unattended runs are saved for `--resume` as well, dry runs aside
+    let new_checkpoint = |
+        log: &SessionLog,
+        started: DateTime<Local>,
+        plan: (&Completion, &[TimerStage], Option<Round>)
+    | {
+        display.dry_run.is_none()
+            .then(|| RunCheckpoint::new(detach_name, saved_run(log, &[], &[]), started, plan))
+    };
+
+    if wait {
+        let mut out: Box<dyn Write> = if display.headless || display.statusbar {
+            Box::new(io::stdout())
+        } else {
+            Box::new(io::sink())
+        };
+        return run_unattended(
+            (stages, completion, round, resumed.map(|resumed| resumed.timer)),
+            &display,
+            &log,
+            &presets,
+            (new_watchers, new_checkpoint),
+            (&mut inputs, controls.heart_rate.clone().map(heart_rate::RestGate::new)),
+            &mut out
+        );
+    }
+
+    // == TUI setup ============================================================
+
+    // keep escape codes out of redirected output by drawing on stderr, and
+    // don't draw at all if neither goes to a terminal
+    let (mut out, screen): (Box<dyn Write>, _) = if io::stdout().is_terminal() {
+        (Box::new(io::stdout()), terminal_guard::Screen::Stdout)
+    } else if io::stderr().is_terminal() {
+        (Box::new(io::stderr()), terminal_guard::Screen::Stderr)
+    } else if show_stage_editor {
+        return Err(RunError::Message(String::from("--edit needs a terminal to draw on.")));
+    } else if !splits.is_empty() {
+        return Err(RunError::Message(String::from("--split needs a terminal to draw on.")));
+    } else {
+        return run_unattended(
+            (stages, completion, round, resumed.map(|resumed| resumed.timer)),
+            &display,
+            &log,
+            &presets,
+            (new_watchers, new_checkpoint),
+            (&mut inputs, controls.heart_rate.clone().map(heart_rate::RestGate::new)),
+            &mut io::stdout()
+        );
+    };
+
+    // restores the terminal however the program ends
+    let guard = terminal_guard::TerminalGuard::enter(&mut out, screen)?;
+    // shown in other terminals with `attach --observe`
+    let backend = PaletteBackend::new(observe::Mirror::new(
+        CrosstermBackend::new(out),
+        session_name.as_deref().unwrap_or(observe::DEFAULT_NAME)
+    ), display.palette);
+    let mut terminal = Terminal::new(backend)?;
+    inputs.add(input::Terminal);
+
+    let start = if show_stage_editor {
+        let new_stage = TimerStage {
+            overtime: presets.overtime,
+            manual_advance: presets.manual_advance,
+            ..TimerStage::new(String::new(), Duration::ZERO)
+        };
+        interactive::edit_stages(&mut terminal, &mut inputs, &mut stages, &display, &new_stage)?
+    } else {
+        !show_stage_selection || interactive::select_stages(&mut terminal, &mut inputs, &mut stages, &display)?
+    };
+
+    // == Main loop ============================================================
+
+    // the bell rings in the terminal the timer is drawn on
+    let bell_out = || screen.writer();
+    let (mut resumed, mut summaries, mut warnings) = match resumed {
+        Some(ResumedRun { timer, summaries, warnings }) => (Some(timer), summaries, warnings),
+        None => (None, Vec::new(), Vec::new())
+    };
+    let mut detached = false;
+    // presets that run next are recorded as such
+    let mut run_log = log.clone();
+    let mut next_run = start.then(|| (create_timer_list(stages), completion));
+    let mut round = round;
+    let mut reload = watched_config.map(|path| interactive::StageReload::new(path, presets.clone()));
+    while let Some((mut planned, completion)) = next_run.take() {
+        let (timer, mut watchers, started) = match resumed.take() {
+            Some(resumed) => {
+                planned = resumed.planned.clone();
+                resumed.resume(new_watchers())
+            },
+            None => (Timer::new(planned.clone()), new_watchers(), Local::now())
+        };
+        watchers.notifiers.attach_terminal(bell_out, audio_player.as_ref());
+        watchers.stage_audio = Some(StageAudio::new(audio_player.clone()));
+        let mut timers = interactive::Timers {
+            timers: vec![timer],
+            watchers: Vec::new(),
+            labels: vec![run_log.preset.clone().unwrap_or_else(|| String::from("Timer 1"))],
+            round,
+            reload: reload.take()
+        };
+        // only the first run has timers beside it, which are neither saved
+        // for `--resume` nor shown by the overlay and MQTT, as they would
+        // take turns at it. Dry runs aren't saved either.
+        if splits.is_empty() && display.dry_run.is_none() {
+            watchers.checkpoint = Some(RunCheckpoint::new(
+                detach_name,
+                saved_run(&run_log, &summaries, &warnings),
+                started,
+                (&completion, &planned, round)
+            ));
+        }
+        timers.watchers.push(watchers);
+        for (name, stages) in splits.drain(..) {
+            let mut watchers = StageWatchers { overlay: None, mqtt: None, ..new_watchers() };
+            watchers.notifiers.attach_terminal(bell_out, audio_player.as_ref());
+            watchers.stage_audio = Some(StageAudio::new(audio_player.clone()));
+            timers.timers.push(Timer::new(create_timer_list(stages)));
+            timers.watchers.push(watchers);
+            timers.labels.push(name);
+        }
+        let mut notice = None;
+        while let interactive::TuiExit::Detached = interactive::run_tui(
+            &mut terminal,
+            &mut timers,
+            &display,
+            &controls,
+            &mut inputs,
+            notice.as_deref()
+        )? {
+            if let Some(reloaded) = timers.reload.as_ref().and_then(|reload| reload.planned.clone()) {
+                planned = reloaded;
+            }
+            let running = DetachedTimer::new(
+                &timers.timers[0],
+                started,
+                (completion.clone(), &planned, round),
+                &mut timers.watchers[0]
+            );
+            let run = DetachedRun {
+                running: Some(running),
+                ..saved_run(&run_log, &summaries, &warnings)
+            };
+            match detach_run(detach_name, &run) {
+                Ok(()) => {
+                    // the background process keeps a state of its own
+                    detach::remove_checkpoint(detach_name);
+                    detached = true;
+                    break;
+                },
+                Err(message) => notice = Some(message)
+            }
+        }
+        // the background process takes it from here
+        if detached {
+            break;
+        }
+        // runs that start over do so with the stages as they were reloaded
+        reload = timers.reload.take();
+        if let Some(reloaded) = reload.as_ref().and_then(|reload| reload.planned.clone()) {
+            planned = reloaded;
+        }
+        let mut reports = Vec::new();
+        for (index, (timer, watchers)) in timers.timers.iter().zip(timers.watchers).enumerate() {
+            // timers beside the first one are runs of their presets
+            let log = match index {
+                0 => run_log.clone(),
+                _ => SessionLog {
+                    preset: Some(timers.labels[index].clone())
+                        .filter(|_| presets.compensation.is_none()),
+                    summary_json: None,
+                    ..run_log.clone()
+                }
+            };
+            let report = finish_session(&log, started, timer, watchers, &display);
+            if display.keep_output {
+                summaries.push(plain_summary(timer, &report, &display));
+            }
+            reports.push(report);
+        }
+        let timer = &timers.timers[0];
+        let report = reports.remove(0);
+        warnings.extend(reports.into_iter().flat_map(|report| report.warnings));
+
+        // quitting or aborting the session skips whatever would come next
+        if timer.is_finished() {
+            round = round.and_then(Round::next);
+            next_run = match completion {
+                // the stages start over until the last round is done
+                _ if round.is_some() => Some((planned, completion)),
+                Completion::Exit => None,
+                Completion::Summary => {
+                    interactive::show_summary(&mut terminal, &mut inputs, timer, &report, &display)?;
+                    None
+                },
+                Completion::Presets => {
+                    reload = None;
+                    let mut error = None;
+                    loop {
+                        match interactive::select_preset(&mut terminal, &mut inputs, error.as_deref(), &display.theme)? {
+                            None => break None,
+                            Some(name) => match presets.load(&name, Completion::Presets) {
+                                Ok((stages, completion)) => {
+                                    run_log.preset = Some(name);
+                                    break Some((create_timer_list(stages), completion))
+                                },
+                                Err(message) => error = Some(message)
+                            }
+                        }
+                    }
+                },
+                Completion::Preset(name) => {
+                    reload = None;
+                    match presets.load(&name, presets.completion.clone()) {
+                        Ok((stages, completion)) => {
+                            run_log.preset = Some(name);
+                            Some((create_timer_list(stages), completion))
+                        },
+                        Err(message) => {
+                            warnings.push(message);
+                            None
+                        }
+                    }
+                },
+                Completion::Again => Some((planned, Completion::Again))
+            };
+        }
+        warnings.extend(report.warnings);
+    }
+
+    // == Restore terminal state ===============================================
+
+    drop(terminal);
+    drop(guard);
+
+    if detached {
+        println!("Detached, run `{}` to come back.", attach_command(detach_name));
+        return Ok(());
+    }
+    if !summaries.is_empty() {
+        println!("{}", summaries.join("\n\n"));
+    }
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
 
     Ok(())
 
@@ -0,0 +1,102 @@
+use std::{fs, io, path::{Path, PathBuf}, time::Duration};
+
+use crate::{config::RetentionConfig, detach, history};
+
+/// Backups kept of the history and of event logs unless the settings say
+/// otherwise.
+const DEFAULT_BACKUPS: u32 = 3;
+
+/// Keep what the timer collects within `retention`: move the oldest runs
+/// out of the history, delete stale checkpoints and move the event log at
+/// `event_log` to a backup once it has grown too large. Returns what went
+/// wrong, which isn't reason enough not to run the timer.
+pub fn tidy(retention: &RetentionConfig, event_log: Option<&Path>) -> Vec<String> {
+    let backups = retention.backups.unwrap_or(DEFAULT_BACKUPS);
+    let mut problems = Vec::new();
+    if let Some(keep) = retention.sessions {
+        problems.extend(history::compact(keep, backups).err());
+    }
+    if let Some(days) = retention.checkpoint_days {
+        problems.extend(prune_checkpoints(Duration::from_secs(u64::from(days) * 24 * 60 * 60)).err());
+    }
+    if let (Some(path), Some(kib)) = (event_log, retention.event_log_kib) {
+        let large = fs::metadata(path).is_ok_and(|metadata| metadata.len() > kib * 1024);
+        if large {
+            problems.extend(rotate(path, backups).err());
+        }
+    }
+    problems
+}
+
+/// The `n`th backup of `path`, with `.n` added to its name.
+fn backup_path(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Make room for a new backup of `path` by moving its backups up by one,
+/// deleting the oldest so no more than `backups` are left. Returns where
+/// the new backup goes, `None` if none are kept.
+pub fn make_room(path: &Path, backups: u32) -> Result<Option<PathBuf>, String> {
+    if backups == 0 {
+        return Ok(None);
+    }
+    remove(&backup_path(path, backups))?;
+    for n in (1..backups).rev() {
+        let from = backup_path(path, n);
+        if from.exists() {
+            let to = backup_path(path, n + 1);
+            fs::rename(&from, &to).map_err(|e| format!(
+                "Could not move {} to {}: {}", from.display(), to.display(), e
+            ))?;
+        }
+    }
+    Ok(Some(backup_path(path, 1)))
+}
+
+/// Move `path` to a new backup of it, of which the newest `backups` are
+/// kept, or delete it if none are.
+fn rotate(path: &Path, backups: u32) -> Result<(), String> {
+    match make_room(path, backups)? {
+        Some(backup) => fs::rename(path, &backup).map_err(|e| format!(
+            "Could not move {} to {}: {}", path.display(), backup.display(), e
+        )),
+        None => remove(path)
+    }
+}
+
+/// Delete `path` if it exists.
+fn remove(path: &Path) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(format!(
+            "Could not delete {}: {}", path.display(), e
+        )),
+        _ => Ok(())
+    }
+}
+
+/// Delete the checkpoints that weren't saved for longer than `max_age`,
+/// those of runs that were never resumed.
+fn prune_checkpoints(max_age: Duration) -> Result<(), String> {
+    let dir = match detach::checkpoints_dir() {
+        Some(dir) => dir,
+        None => return Ok(())
+    };
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("Could not read {}: {}", dir.display(), e))
+    };
+    for entry in entries.flatten() {
+        let stale = entry.metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > max_age);
+        if stale && entry.path().extension().is_some_and(|extension| extension == "toml") {
+            remove(&entry.path())?;
+        }
+    }
+    Ok(())
+}
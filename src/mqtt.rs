@@ -0,0 +1,313 @@
+use std::io;
+#[cfg(feature = "mqtt")]
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{mpsc, Arc},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant}
+};
+
+use serde::{Deserialize, Serialize};
+
+use staged_timer::Timer;
+#[cfg(feature = "mqtt")]
+use staged_timer::{EventStream, TimerEvent};
+
+use crate::render::Renderer;
+#[cfg(feature = "mqtt")]
+use crate::{render, schema};
+#[cfg(not(feature = "mqtt"))]
+use crate::integrations;
+
+/// Topics are published under this unless told otherwise.
+pub const DEFAULT_TOPIC: &str = "staged-timer";
+
+/// Port of MQTT brokers without TLS.
+pub const DEFAULT_PORT: u16 = 1883;
+
+/// How long the broker waits for a sign of life before it drops the
+/// connection. A ping is sent after half of it without anything to publish.
+#[cfg(feature = "mqtt")]
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+
+/// How long to wait for a broker that can't be reached or doesn't take what
+/// is sent.
+#[cfg(feature = "mqtt")]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long to wait before connecting again after the broker couldn't be
+/// reached.
+#[cfg(feature = "mqtt")]
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Which MQTT broker to publish the state of the timer to.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MqttOptions {
+    /// `HOST` or `HOST:PORT` of the broker
+    pub broker: String,
+    /// Prefix of every topic published to, see [`Mqtt`]
+    pub topic: String,
+    pub username: Option<String>,
+    #[serde(skip)]
+    pub password: Option<String>
+}
+
+/// A message for the broker.
+#[cfg(feature = "mqtt")]
+struct Message {
+    topic: String,
+    payload: String,
+    /// Whether the broker keeps it for clients that subscribe later
+    retain: bool
+}
+
+/// MQTT strings and binary data: their length followed by the bytes.
+#[cfg(feature = "mqtt")]
+fn put_string(packet: &mut Vec<u8>, string: &str) {
+    packet.extend((string.len() as u16).to_be_bytes());
+    packet.extend(string.as_bytes());
+}
+
+/// A packet of type `header` with `body`, its length encoded in seven bits
+/// per byte.
+#[cfg(feature = "mqtt")]
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut length = body.len();
+    loop {
+        let byte = (length % 128) as u8;
+        length /= 128;
+        packet.push(if length > 0 { byte | 0x80 } else { byte });
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend(body);
+    packet
+}
+
+#[cfg(feature = "mqtt")]
+fn publish_packet(message: &Message) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_string(&mut body, &message.topic);
+    body.extend(message.payload.as_bytes());
+    // QoS 0, delivered at most once
+    packet(0x30 | u8::from(message.retain), &body)
+}
+
+/// Connect to the broker of `options` as MQTT 3.1.1. Should the timer go away
+/// without saying goodbye, the broker publishes `offline` as its state.
+#[cfg(feature = "mqtt")]
+fn connect(options: &MqttOptions) -> Result<TcpStream, String> {
+    let failed = |e: &dyn std::fmt::Display| format!(
+        "Could not connect to the MQTT broker {}: {}", options.broker, e
+    );
+    let address = if options.broker.contains(':') {
+        options.broker.clone()
+    } else {
+        format!("{}:{}", options.broker, DEFAULT_PORT)
+    };
+    let address = address.to_socket_addrs().map_err(|e| failed(&e))?
+        .next()
+        .ok_or_else(|| failed(&"no such host"))?;
+    let mut stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+        .map_err(|e| failed(&e))?;
+
+    let mut body = Vec::new();
+    put_string(&mut body, "MQTT");
+    // protocol level 4 is MQTT 3.1.1
+    body.push(4);
+    // a clean session with a retained will, and credentials if there are any
+    let mut flags = 0x02 | 0x04 | 0x20;
+    if options.username.is_some() {
+        flags |= 0x80;
+    }
+    if options.password.is_some() {
+        flags |= 0x40;
+    }
+    body.push(flags);
+    body.extend((KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+    put_string(&mut body, &format!("staged-timer-{}", std::process::id()));
+    put_string(&mut body, &format!("{}/state", options.topic));
+    put_string(&mut body, "offline");
+    for credential in [&options.username, &options.password].into_iter().flatten() {
+        put_string(&mut body, credential);
+    }
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT)).map_err(|e| failed(&e))?;
+    stream.set_write_timeout(Some(CONNECT_TIMEOUT)).map_err(|e| failed(&e))?;
+    stream.write_all(&packet(0x10, &body)).map_err(|e| failed(&e))?;
+
+    let mut connack = [0; 4];
+    stream.read_exact(&mut connack).map_err(|e| failed(&e))?;
+    match connack {
+        [0x20, 2, _, 0] => Ok(stream),
+        [0x20, 2, _, 4 | 5] => Err(failed(&"the broker did not accept the username or password")),
+        [0x20, 2, _, code] => Err(failed(&format!("the broker refused with code {}", code))),
+        _ => Err(failed(&"the broker did not answer in MQTT"))
+    }
+}
+
+/// Hand `messages` to the broker of `options` over `stream` until the
+/// timer is gone, reconnecting whenever the connection is lost.
+#[cfg(feature = "mqtt")]
+fn publish(options: MqttOptions, stream: TcpStream, messages: mpsc::Receiver<Message>) {
+    let mut stream = Some(stream);
+    let mut lost = Instant::now();
+    loop {
+        let sent = match messages.recv_timeout(KEEP_ALIVE / 2) {
+            Ok(message) => {
+                // messages while the broker can't be reached are lost, the
+                // retained ones are brought up to date by the next ones
+                if stream.is_none() && lost.elapsed() >= RECONNECT_INTERVAL {
+                    stream = connect(&options).ok();
+                    lost = Instant::now();
+                }
+                stream.as_mut().map(|stream| stream.write_all(&publish_packet(&message)))
+            },
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                stream.as_mut().map(|stream| stream.write_all(&packet(0xc0, &[])))
+            },
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                if let Some(mut stream) = stream {
+                    let _ = stream.write_all(&packet(0xe0, &[]));
+                }
+                return;
+            }
+        };
+        if let Some(Err(_)) = sent {
+            stream = None;
+            lost = Instant::now();
+        }
+    }
+}
+
+/// The connection to the broker, shared by every [`Mqtt`] of the program.
+#[cfg(feature = "mqtt")]
+struct Connection {
+    messages: Option<mpsc::Sender<Message>>,
+    publisher: Option<JoinHandle<()>>
+}
+
+#[cfg(feature = "mqtt")]
+impl Drop for Connection {
+    /// Let the publisher hand over what is left and say goodbye to the
+    /// broker, so the end of the last run isn't lost as the program exits.
+    fn drop(&mut self) {
+        self.messages.take();
+        if let Some(publisher) = self.publisher.take() {
+            let _ = publisher.join();
+        }
+    }
+}
+
+/// Publishes the state of the timer to an MQTT broker, for lighting and home
+/// automation to follow. Under the topic of its options:
+///
+/// - `stage`: name of the running stage, empty once the timer is done
+/// - `remaining`: seconds left of the running stage
+/// - `state`: running, paused, waiting, overtime or finished, and offline
+///   when the timer went away
+/// - `event`: the events of `--headless` as JSON, without ticks
+///
+/// All but `event` are retained, so that clients subscribing later get the
+/// current state right away.
+#[cfg(feature = "mqtt")]
+pub struct Mqtt {
+    options: MqttOptions,
+    connection: Arc<Connection>,
+    events: EventStream,
+    /// What was published of `stage`, `remaining` and `state` last
+    published: [Option<String>; 3]
+}
+
+#[cfg(feature = "mqtt")]
+impl Mqtt {
+    /// Connect to the broker of `options`. Fails if it can't be reached.
+    pub fn connect(options: &MqttOptions) -> Result<Mqtt, String> {
+        let stream = connect(options)?;
+        let (messages, receiver) = mpsc::channel();
+        let publisher = options.clone();
+        let publisher = thread::spawn(move || publish(publisher, stream, receiver));
+        Ok(Mqtt {
+            options: options.clone(),
+            connection: Arc::new(Connection {
+                messages: Some(messages),
+                publisher: Some(publisher)
+            }),
+            events: EventStream::new(),
+            published: Default::default()
+        })
+    }
+
+    pub fn options(&self) -> &MqttOptions {
+        &self.options
+    }
+
+    fn send(&self, topic: &str, payload: String, retain: bool) -> io::Result<()> {
+        let message = Message { topic: format!("{}/{}", self.options.topic, topic), payload, retain };
+        self.connection.messages.as_ref()
+            .and_then(|messages| messages.send(message).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "MQTT publisher stopped"))
+    }
+}
+
+#[cfg(feature = "mqtt")]
+impl Clone for Mqtt {
+    /// Another publisher over the same connection that publishes everything
+    /// afresh, for the next run.
+    fn clone(&self) -> Mqtt {
+        Mqtt {
+            options: self.options.clone(),
+            connection: Arc::clone(&self.connection),
+            events: EventStream::new(),
+            published: Default::default()
+        }
+    }
+}
+
+#[cfg(feature = "mqtt")]
+impl Renderer for Mqtt {
+    fn render(&mut self, timer: &Timer) -> io::Result<()> {
+        for event in self.events.update(timer) {
+            if event != TimerEvent::Tick {
+                self.send("event", schema::line(&schema::Event::of(event, timer)), false)?;
+            }
+        }
+        let state = [
+            timer.current_stage().map(|stage| stage.name.clone()).unwrap_or_default(),
+            timer.current_stage().map_or(0, |stage| stage.remaining_s()).to_string(),
+            String::from(render::state_class(timer))
+        ];
+        for (index, (topic, value)) in ["stage", "remaining", "state"].into_iter().zip(state).enumerate() {
+            if self.published[index].as_ref() != Some(&value) {
+                self.send(topic, value.clone(), true)?;
+                self.published[index] = Some(value);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Stands in for the publisher in builds without MQTT, which can't connect.
+#[cfg(not(feature = "mqtt"))]
+#[derive(Clone)]
+pub enum Mqtt {}
+
+#[cfg(not(feature = "mqtt"))]
+impl Mqtt {
+    pub fn connect(_options: &MqttOptions) -> Result<Mqtt, String> {
+        Err(integrations::MQTT.missing())
+    }
+
+    pub fn options(&self) -> &MqttOptions {
+        match *self {}
+    }
+}
+
+#[cfg(not(feature = "mqtt"))]
+impl Renderer for Mqtt {
+    fn render(&mut self, _timer: &Timer) -> io::Result<()> {
+        match *self {}
+    }
+}
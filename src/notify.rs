@@ -0,0 +1,648 @@
+use std::{
+    env,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant}
+};
+
+use serde::{Deserialize, Serialize};
+
+use staged_timer::{EventStream, TimeFormat, Timer, TimerEvent};
+
+use crate::audio;
+#[cfg(not(feature = "tts"))]
+use crate::integrations;
+
+/// Announcement made when a stage ends and another one follows.
+/// `{finished}` and `{next}` are replaced with the names of the stages and
+/// `{time}` with the duration of the next one.
+pub const DEFAULT_MESSAGE: &str = "{finished} done. {next}: {time}.";
+
+/// Notices closer together than this are coalesced, so stages skipped in
+/// quick succession or checkpoints piling up don't flood webhooks, push
+/// services and the user.
+const NOTICE_INTERVAL: Duration = Duration::from_secs(1);
+
+fn in_path(program: &str) -> bool {
+    env::var_os("PATH").is_some_and(|paths| {
+        env::split_paths(&paths).any(|dir| {
+            dir.join(program).is_file()
+            || dir.join(format!("{}.exe", program)).is_file()
+        })
+    })
+}
+
+/// Run `script` with Windows PowerShell. Text goes in through the
+/// environment variables `vars`, which the script reads as `$env:NAME`, so
+/// it is never parsed as PowerShell.
+fn powershell(script: &str, vars: &[(&str, &str)]) -> Command {
+    let mut command = Command::new("powershell");
+    command.args(["-NoProfile", "-NonInteractive", "-Command", script]);
+    command.envs(vars.iter().copied());
+    command
+}
+
+/// Start `command` without holding up the timer while it runs, but right
+/// away so it is done even if the timer exits next.
+fn spawn(mut command: Command) -> Result<(), String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Could not start {}: {}", program, e))?;
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// A way of telling the user about the timer, listed in `notifiers` in the
+/// settings.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    /// The terminal bell
+    Bell,
+    /// The alert sound, or the bell if there is none
+    Sound,
+    /// Desktop notifications
+    Desktop,
+    /// Reading announcements out loud
+    Speech,
+    /// A JSON POST to `webhook_url`
+    Webhook,
+    /// A push notification through the ntfy style service at `push_url`
+    Push
+}
+
+impl Channel {
+    /// Whether the channel needs the terminal the timer is drawn on.
+    fn is_terminal(self) -> bool {
+        matches!(self, Channel::Bell | Channel::Sound)
+    }
+}
+
+/// How the user is told about the timer.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NotifyOptions {
+    /// Every channel to notify through, in order
+    pub channels: Vec<Channel>,
+    /// Template of the announcement, see [`DEFAULT_MESSAGE`]
+    pub message: String,
+    /// Played by [`Channel::Sound`]
+    pub alert_file: Option<PathBuf>,
+    pub webhook_url: Option<String>,
+    pub push_url: Option<String>,
+    /// Channels of the notices that don't go to all of them
+    #[serde(default)]
+    pub routes: Routes
+}
+
+/// Which channels warnings, stage ends and the end of the last stage go to,
+/// as the `[alerts]` section of the settings. Channels listed here are used
+/// even if `notifiers` doesn't list them.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Routes {
+    /// Stages that end with another one following, the notifiers if unset
+    pub stage_end: Option<Vec<Channel>>,
+    /// Stages reaching their warning threshold, the bell or alert sound of
+    /// the notifiers if unset
+    pub warning: Option<Vec<Channel>>,
+    /// The last stage ending, like other stages if unset
+    pub finished: Option<Vec<Channel>>
+}
+
+impl NotifyOptions {
+    /// Every channel used for any notice, in order.
+    pub fn all_channels(&self) -> Vec<Channel> {
+        let routes = &self.routes;
+        let mut channels: Vec<Channel> = Vec::new();
+        let routed = [&routes.stage_end, &routes.warning, &routes.finished];
+        for &channel in self.channels.iter().chain(routed.into_iter().flatten().flatten()) {
+            if !channels.contains(&channel) {
+                channels.push(channel);
+            }
+        }
+        channels
+    }
+
+    /// Whether `notice` goes to `channel`.
+    fn routes_to(&self, notice: &Notice, channel: Channel) -> bool {
+        let listed = |route: &Option<Vec<Channel>>| route.as_ref().map(|route| route.contains(&channel));
+        let default = self.channels.contains(&channel);
+        match notice {
+            Notice::Warning(_) => listed(&self.routes.warning).unwrap_or(default && channel.is_terminal()),
+            Notice::StageEnd(announcement) if announcement.next.is_none() => listed(&self.routes.finished)
+                .or_else(|| listed(&self.routes.stage_end))
+                .unwrap_or(default),
+            Notice::StageEnd(_) => listed(&self.routes.stage_end).unwrap_or(default),
+            Notice::Alert | Notice::Countdown(_) | Notice::Start(_) => default
+        }
+    }
+}
+
+/// What a stage that just ended is announced with.
+#[derive(Clone)]
+pub struct Announcement {
+    /// Name of the stage that ended
+    pub finished: String,
+    /// Name of the stage that follows, `None` once all stages are done
+    pub next: Option<String>,
+    /// Title of notifications, e.g. "Developer finished"
+    pub summary: String,
+    /// The announcement with the time of the next stage as it is shown
+    pub message: String,
+    /// The announcement with the time in words, to be read out loud
+    #[cfg_attr(not(feature = "tts"), allow(dead_code))]
+    pub spoken: String
+}
+
+/// Something the user is told about.
+pub enum Notice<'a> {
+    /// The running stage reached a checkpoint or some other point of
+    /// interest
+    Alert,
+    /// The running stage reached its warning threshold, with the time left
+    /// in words, e.g. "Developer: 30 seconds left"
+    Warning(&'a str),
+    /// The running stage reached one of its countdown cues, with the time
+    /// left in words to be read out loud, e.g. "Developer: 2 minutes left"
+    #[cfg_attr(not(feature = "tts"), allow(dead_code))]
+    Countdown(&'a str),
+    /// The first stage started, with its name and time in words to be read
+    /// out loud, e.g. "Plank: 30 seconds". Only speech says anything.
+    #[cfg_attr(not(feature = "tts"), allow(dead_code))]
+    Start(&'a str),
+    /// A stage ended and the timer moved on
+    StageEnd(&'a Announcement)
+}
+
+/// A channel notifications are sent through. Notifying must not hold up
+/// the timer: whatever takes a while is started and left to finish on its
+/// own, and only failing to start it is an error.
+pub trait Notifier: Send {
+    fn notify(&mut self, notice: &Notice) -> Result<(), String>;
+}
+
+/// Rings the bell of the terminal the timer is drawn on.
+pub struct Bell {
+    out: Box<dyn Write + Send>
+}
+
+impl Bell {
+    pub fn new(out: Box<dyn Write + Send>) -> Bell {
+        Bell { out }
+    }
+}
+
+impl Notifier for Bell {
+    fn notify(&mut self, notice: &Notice) -> Result<(), String> {
+        if let Notice::Start(_) = notice {
+            return Ok(());
+        }
+        write!(self.out, "\x07")
+            .and_then(|_| self.out.flush())
+            .map_err(|e| format!("Could not ring the bell: {}", e))
+    }
+}
+
+/// Plays the alert sound, or rings the bell if it can't.
+pub struct Sound {
+    player: Option<audio::Player>,
+    file: Option<PathBuf>,
+    playback: Option<audio::Playback>,
+    bell: Bell
+}
+
+impl Notifier for Sound {
+    fn notify(&mut self, notice: &Notice) -> Result<(), String> {
+        if let Notice::Start(_) = notice {
+            return Ok(());
+        }
+        if let (Some(player), Some(file)) = (&self.player, &self.file) {
+            if let Ok(playback) = player.play(file) {
+                // replacing an alert that is still playing stops it
+                self.playback = Some(playback);
+                return Ok(());
+            }
+        }
+        self.bell.notify(notice)
+    }
+}
+
+/// How to show a desktop notification on this system.
+#[derive(Clone, Copy)]
+enum Backend {
+    /// `notify-send` from libnotify on Linux and BSD
+    NotifySend,
+    /// AppleScript's `display notification` on macOS
+    AppleScript,
+    /// A toast of the Windows notification center, through PowerShell
+    Toast
+}
+
+/// Shows a desktop notification when a stage ends.
+pub struct Desktop {
+    backend: Backend
+}
+
+impl Desktop {
+    /// `None` if this system has no way of showing notifications.
+    pub fn find() -> Option<Desktop> {
+        let backend = if cfg!(target_os = "macos") && in_path("osascript") {
+            Backend::AppleScript
+        } else if cfg!(windows) && in_path("powershell") {
+            Backend::Toast
+        } else if in_path("notify-send") {
+            Backend::NotifySend
+        } else {
+            return None;
+        };
+        Some(Desktop { backend })
+    }
+}
+
+impl Notifier for Desktop {
+    fn notify(&mut self, notice: &Notice) -> Result<(), String> {
+        let (summary, body) = match notice {
+            Notice::StageEnd(announcement) => (announcement.summary.as_str(), announcement.message.as_str()),
+            Notice::Warning(spoken) => ("Warning", *spoken),
+            Notice::Alert | Notice::Countdown(_) | Notice::Start(_) => return Ok(())
+        };
+        let command = match self.backend {
+            Backend::NotifySend => {
+                let mut command = Command::new("notify-send");
+                command.args(["--app-name", "staged-timer", summary, body]);
+                command
+            },
+            Backend::AppleScript => {
+                let mut command = Command::new("osascript");
+                command.args([
+                    "-e",
+                    &format!("display notification {:?} with title {:?}", body, summary)
+                ]);
+                command
+            },
+            Backend::Toast => powershell(
+                "$manager = [Windows.UI.Notifications.ToastNotificationManager, \
+                Windows.UI.Notifications, ContentType = WindowsRuntime]; \
+                $toast = $manager::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02); \
+                $text = $toast.GetElementsByTagName('text'); \
+                $text.Item(0).InnerText = $env:STAGED_TIMER_SUMMARY; \
+                $text.Item(1).InnerText = $env:STAGED_TIMER_BODY; \
+                $manager::CreateToastNotifier('staged-timer').Show([Windows.UI.Notifications.ToastNotification]::new($toast))",
+                &[("STAGED_TIMER_SUMMARY", summary), ("STAGED_TIMER_BODY", body)]
+            )
+        };
+        spawn(command)
+    }
+}
+
+/// Reads out loud which stage ended and which one follows.
+#[cfg(feature = "tts")]
+pub struct Speech {
+    /// Command that reads text out loud, the speech synthesizer of Windows
+    /// if `None`
+    voice: Option<&'static str>
+}
+
+#[cfg(feature = "tts")]
+impl Speech {
+    /// `None` if this system can't read text out loud.
+    pub fn find() -> Option<Speech> {
+        if cfg!(windows) && in_path("powershell") {
+            return Some(Speech { voice: None });
+        }
+        ["say", "spd-say", "espeak-ng", "espeak"].into_iter()
+            .find(|program| in_path(program))
+            .map(|voice| Speech { voice: Some(voice) })
+    }
+
+    fn say(&self, text: &str) -> Result<(), String> {
+        let command = match self.voice {
+            Some(voice) => {
+                let mut command = Command::new(voice);
+                command.arg(text);
+                command
+            },
+            None => powershell(
+                "Add-Type -AssemblyName System.Speech; \
+                (New-Object System.Speech.Synthesis.SpeechSynthesizer).Speak($env:STAGED_TIMER_TEXT)",
+                &[("STAGED_TIMER_TEXT", text)]
+            )
+        };
+        spawn(command)
+    }
+}
+
+#[cfg(feature = "tts")]
+impl Notifier for Speech {
+    fn notify(&mut self, notice: &Notice) -> Result<(), String> {
+        match notice {
+            Notice::StageEnd(announcement) => self.say(&announcement.spoken),
+            Notice::Countdown(spoken) | Notice::Start(spoken) | Notice::Warning(spoken) => self.say(spoken),
+            Notice::Alert => Ok(())
+        }
+    }
+}
+
+/// Posts every stage that ends to a URL as JSON with `curl`, e.g.
+/// `{"event": "stage_end", "finished": "Developer", "next": "Stop", "message": "…"}`,
+/// and warnings routed to it as `{"event": "warning", "message": "…"}`.
+pub struct Webhook {
+    url: String
+}
+
+impl Notifier for Webhook {
+    fn notify(&mut self, notice: &Notice) -> Result<(), String> {
+        let body = match notice {
+            Notice::StageEnd(announcement) => serde_json::json!({
+                "event": "stage_end",
+                "finished": announcement.finished,
+                "next": announcement.next,
+                "message": announcement.message
+            }),
+            Notice::Warning(spoken) => serde_json::json!({
+                "event": "warning",
+                "message": spoken
+            }),
+            Notice::Alert | Notice::Countdown(_) | Notice::Start(_) => return Ok(())
+        };
+        let mut command = Command::new("curl");
+        command.args(["--silent", "--max-time", "10", "--header", "Content-Type: application/json"])
+            .args(["--data-raw", &body.to_string(), &self.url]);
+        spawn(command)
+    }
+}
+
+/// Sends a push notification for every stage that ends, and warnings routed
+/// to it, through a service
+/// like ntfy, which takes the message as body of a POST and its title in a
+/// `Title` header.
+pub struct Push {
+    url: String
+}
+
+impl Notifier for Push {
+    fn notify(&mut self, notice: &Notice) -> Result<(), String> {
+        let (title, message) = match notice {
+            Notice::StageEnd(announcement) => (announcement.summary.as_str(), announcement.message.as_str()),
+            Notice::Warning(spoken) => ("Warning", *spoken),
+            Notice::Alert | Notice::Countdown(_) | Notice::Start(_) => return Ok(())
+        };
+        // a line break in a stage name would end the header and start another
+        let title: String = title.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+        let mut command = Command::new("curl");
+        command.args(["--silent", "--max-time", "10"])
+            .args(["--header", &format!("Title: {}", title)])
+            .args(["--data-raw", message, &self.url]);
+        spawn(command)
+    }
+}
+
+/// `seconds` in words, e.g. "1 minute 30 seconds", so speech doesn't read a
+/// time like "5:00" as a time of day.
+fn spoken(seconds: u32) -> String {
+    let parts = [
+        (seconds / 3600, "hour"),
+        (seconds / 60 % 60, "minute"),
+        (seconds % 60, "second")
+    ];
+    let words: Vec<String> = parts.iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, unit)| format!("{} {}{}", count, unit, if *count == 1 { "" } else { "s" }))
+        .collect();
+    if words.is_empty() { String::from("0 seconds") } else { words.join(" ") }
+}
+
+/// The channels the user asked for, stacked: each notice goes to every
+/// one of them its [`Routes`] allow, at most one per [`NOTICE_INTERVAL`].
+/// A channel that fails
+/// is dropped and reported at the end of the run.
+pub struct Notifiers {
+    options: NotifyOptions,
+    channels: Vec<(Channel, Box<dyn Notifier>)>,
+    stage: Option<usize>,
+    failures: Vec<String>,
+    /// When the last notice was passed on
+    sent: Option<Instant>,
+    /// Stage end waiting for the interval to pass
+    held: Option<Announcement>,
+    /// Tells when the running stage reaches a countdown cue
+    events: EventStream
+}
+
+impl Notifiers {
+    /// The channels of `options` that work without a terminal, along with
+    /// warnings about those that can't be used here.
+    pub fn find(options: &NotifyOptions) -> (Notifiers, Vec<String>) {
+        let mut warnings = Vec::new();
+        let mut channels: Vec<(Channel, Box<dyn Notifier>)> = Vec::new();
+        let curl = in_path("curl");
+        for channel in options.all_channels().into_iter().filter(|channel| !channel.is_terminal()) {
+            let notifier: Result<Box<dyn Notifier>, String> = match channel {
+                Channel::Bell | Channel::Sound => continue,
+                Channel::Desktop => Desktop::find()
+                    .map(|desktop| Box::new(desktop) as Box<dyn Notifier>)
+                    .ok_or_else(|| String::from(
+                        "desktop notifications need notify-send (or osascript on \
+                        macOS), no notifications will be shown."
+                    )),
+                #[cfg(feature = "tts")]
+                Channel::Speech => Speech::find()
+                    .map(|speech| Box::new(speech) as Box<dyn Notifier>)
+                    .ok_or_else(|| String::from(
+                        "reading announcements out loud needs say, spd-say, \
+                        espeak-ng or espeak, nothing will be read out."
+                    )),
+                #[cfg(not(feature = "tts"))]
+                Channel::Speech => Err(integrations::TTS.missing()),
+                Channel::Webhook => match (&options.webhook_url, curl) {
+                    (None, _) => Err(String::from(
+                        "the webhook notifier needs webhook_url in the settings, \
+                        nothing will be posted."
+                    )),
+                    (Some(_), false) => Err(String::from(
+                        "the webhook notifier needs curl, nothing will be posted."
+                    )),
+                    (Some(url), true) => Ok(Box::new(Webhook { url: url.clone() }))
+                },
+                Channel::Push => match (&options.push_url, curl) {
+                    (None, _) => Err(String::from(
+                        "the push notifier needs push_url in the settings, no \
+                        push notifications will be sent."
+                    )),
+                    (Some(_), false) => Err(String::from(
+                        "the push notifier needs curl, no push notifications \
+                        will be sent."
+                    )),
+                    (Some(url), true) => Ok(Box::new(Push { url: url.clone() }))
+                }
+            };
+            match notifier {
+                Ok(notifier) => channels.push((channel, notifier)),
+                Err(message) => warnings.push(message)
+            }
+        }
+        let notifiers = Notifiers {
+            options: options.clone(),
+            channels,
+            stage: None,
+            failures: Vec::new(),
+            sent: None,
+            held: None,
+            events: EventStream::new()
+        };
+        (notifiers, warnings)
+    }
+
+    /// Add the channels that need a terminal, ringing its bell through
+    /// `out` and playing the alert sound with `player`.
+    pub fn attach_terminal(
+        &mut self,
+        out: impl Fn() -> Box<dyn Write + Send>,
+        player: Option<&audio::Player>
+    ) {
+        for channel in self.options.all_channels().into_iter().filter(|channel| channel.is_terminal()) {
+            let notifier: Box<dyn Notifier> = match channel {
+                Channel::Sound => Box::new(Sound {
+                    player: player.cloned(),
+                    file: self.options.alert_file.clone(),
+                    playback: None,
+                    bell: Bell::new(out())
+                }),
+                _ => Box::new(Bell::new(out()))
+            };
+            self.channels.push((channel, notifier));
+        }
+    }
+
+    /// Pass `notice` to every channel, unless the last notice was passed
+    /// on less than [`NOTICE_INTERVAL`] ago. An alert then goes with that
+    /// one, and a stage end waits for the interval to pass, taking the place
+    /// of one waiting already.
+    pub fn notify(&mut self, notice: &Notice) {
+        if self.sent.is_some_and(|sent| sent.elapsed() < NOTICE_INTERVAL) {
+            if let Notice::StageEnd(announcement) = notice {
+                self.held = Some((*announcement).clone());
+            }
+            return;
+        }
+        self.send(notice);
+    }
+
+    /// Pass on a stage end that was held back once the interval has passed.
+    pub fn dispatch_held(&mut self) {
+        if self.sent.is_none_or(|sent| sent.elapsed() >= NOTICE_INTERVAL) {
+            self.flush();
+        }
+    }
+
+    /// Pass on a stage end that was held back right away, as the run is
+    /// over.
+    pub fn flush(&mut self) {
+        if let Some(announcement) = self.held.take() {
+            self.send(&Notice::StageEnd(&announcement));
+        }
+    }
+
+    fn send(&mut self, notice: &Notice) {
+        self.sent = Some(Instant::now());
+        let (options, failures) = (&self.options, &mut self.failures);
+        self.channels.retain_mut(|(channel, notifier)| {
+            !options.routes_to(notice, *channel) || notifier.notify(notice)
+                .inspect_err(|message| failures.push(message.clone()))
+                .is_ok()
+        });
+    }
+
+    /// Tell the user that the running stage reached a point of interest.
+    pub fn alert(&mut self) {
+        self.notify(&Notice::Alert);
+    }
+
+    /// Tell the user that the running stage of `timer` reached its warning
+    /// threshold.
+    pub fn warn(&mut self, timer: &Timer) {
+        if let Some(stage) = timer.current_stage() {
+            let spoken = format!("{}: {} left", stage.name, spoken(stage.remaining_s()));
+            self.notify(&Notice::Warning(&spoken));
+        }
+    }
+
+    /// Take up a timer that is already running.
+    pub fn resume(&mut self, timer: &Timer) {
+        self.stage = Some(timer.current_index());
+    }
+
+    /// Announce the stage that just ended, if the timer has moved on since
+    /// the last call, and the time left when the running stage reaches one
+    /// of its countdown cues. The first call names the stage the timer
+    /// starts with. Going back to an earlier stage isn't announced.
+    pub fn update(&mut self, timer: &Timer, time_format: TimeFormat) {
+        self.dispatch_held();
+        for event in self.events.update(timer) {
+            if let TimerEvent::Countdown(index) = event {
+                let stage = &timer.stages()[index];
+                let spoken = format!("{}: {} left", stage.name, spoken(stage.remaining_s()));
+                self.notify(&Notice::Countdown(&spoken));
+            }
+        }
+        let stage = timer.current_index();
+        let previous = match self.stage.replace(stage) {
+            Some(previous) if previous < stage => previous,
+            Some(_) => return,
+            None => {
+                if let Some(first) = timer.current_stage() {
+                    let spoken = if first.is_open() {
+                        first.name.clone()
+                    } else {
+                        format!("{}: {}", first.name, spoken(first.period_s()))
+                    };
+                    self.notify(&Notice::Start(&spoken));
+                }
+                return;
+            }
+        };
+
+        let finished = timer.stages()[previous].name.clone();
+        let summary = format!("{} finished", finished);
+        let announcement = match timer.current_stage() {
+            Some(next) => {
+                let message = |time: &str| self.options.message
+                    .replace("{finished}", &finished)
+                    .replace("{next}", &next.name)
+                    .replace("{time}", time);
+                // stages counting up take as long as they take
+                let (time, spoken_time) = if next.is_open() {
+                    (String::from("open"), String::from("open"))
+                } else {
+                    (time_format.format(next.period_s(), next.period_s()), spoken(next.period_s()))
+                };
+                Announcement {
+                    message: message(&time),
+                    spoken: message(&spoken_time),
+                    next: Some(next.name.clone()),
+                    finished,
+                    summary
+                }
+            },
+            None => Announcement {
+                message: String::from("All stages are done."),
+                spoken: format!("{} done. All stages are done.", finished),
+                next: None,
+                finished,
+                summary
+            }
+        };
+        self.notify(&Notice::StageEnd(&announcement));
+    }
+
+    /// Why channels were dropped during the run.
+    pub fn failures(&self) -> &[String] {
+        &self.failures
+    }
+}
@@ -0,0 +1,363 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime}
+};
+use tui::{
+    backend::Backend,
+    buffer::{Buffer, Cell},
+    layout::Rect,
+    style::{Color, Modifier}
+};
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{poll as poll_event, read as read_event, Event, KeyCode, KeyEvent, KeyModifiers},
+    execute,
+    queue,
+    style::{self, Attribute, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor},
+    terminal::{
+        disable_raw_mode,
+        enable_raw_mode,
+        Clear,
+        ClearType,
+        DisableLineWrap,
+        EnableLineWrap,
+        EnterAlternateScreen,
+        LeaveAlternateScreen
+    }
+};
+
+use crate::session;
+
+/// How often a running timer saves its screen for observers even if
+/// nothing on it changed, so they can tell it is still there.
+const HEARTBEAT: Duration = Duration::from_secs(1);
+
+/// How long an observer waits for the screen of the timer before deciding
+/// that it is gone.
+const GONE_AFTER: Duration = Duration::from_secs(3);
+
+/// Session name of timers started without --session-name.
+pub const DEFAULT_NAME: &str = "default";
+
+/// The files the timer running a session and its observers share.
+struct Shared {
+    /// The screen of the timer as it last saved it for observers
+    screen: PathBuf,
+    /// Created by observers to ask the timer for its screen
+    request: PathBuf
+}
+
+impl Shared {
+    /// The files of the session `name`.
+    fn of(name: &str) -> Result<Shared, String> {
+        let dir = session::runtime_dir()?;
+        let name = session::file_name(name);
+        Ok(Shared {
+            screen: dir.join(format!("{}.screen", name)),
+            request: dir.join(format!("{}.observe", name))
+        })
+    }
+}
+
+/// A backend that keeps a copy of everything drawn through it and saves it
+/// for `attach --observe` whenever somebody is watching.
+pub struct Mirror<B: Backend> {
+    backend: B,
+    /// `None` if there is no runtime directory to share the screen in
+    shared: Option<Shared>,
+    screen: Buffer,
+    changed: bool,
+    last_saved: Option<Instant>
+}
+
+impl<B: Backend> Mirror<B> {
+    /// Copy what is drawn through `backend` for observers of the session
+    /// `name`.
+    pub fn new(backend: B, name: &str) -> Mirror<B> {
+        let area = backend.size().unwrap_or_default();
+        Mirror {
+            backend,
+            shared: Shared::of(name).ok(),
+            screen: Buffer::empty(area),
+            changed: true,
+            last_saved: None
+        }
+    }
+
+    /// Save the screen if an observer asked for it and it changed or hasn't
+    /// been saved for a while.
+    fn save(&mut self) -> io::Result<()> {
+        let shared = match &self.shared {
+            Some(shared) if shared.request.exists() => shared,
+            _ => {
+                self.last_saved = None;
+                return Ok(());
+            }
+        };
+        let due = self.last_saved.is_none_or(|at| at.elapsed() >= HEARTBEAT);
+        if !self.changed && !due {
+            return Ok(());
+        }
+
+        let partial = shared.screen.with_extension("screen.part");
+        fs::write(&partial, ansi_lines(&self.screen)?)?;
+        fs::rename(&partial, &shared.screen)?;
+        self.changed = false;
+        self.last_saved = Some(Instant::now());
+        Ok(())
+    }
+}
+
+impl<B: Backend> Drop for Mirror<B> {
+    fn drop(&mut self) {
+        // observers take a missing screen to mean the timer is done
+        if let Some(shared) = self.shared.as_ref().filter(|_| self.last_saved.is_some()) {
+            let _ = fs::remove_file(&shared.screen);
+        }
+    }
+}
+
+impl<B: Backend> Backend for Mirror<B> {
+    fn draw<'a, I>(&mut self, content: I) -> Result<(), io::Error>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>
+    {
+        let area = self.backend.size()?;
+        if area != self.screen.area {
+            self.screen.resize(area);
+        }
+        let content: Vec<(u16, u16, &Cell)> = content.collect();
+        for (x, y, cell) in &content {
+            if *x < area.width && *y < area.height {
+                *self.screen.get_mut(*x, *y) = (*cell).clone();
+                self.changed = true;
+            }
+        }
+        self.backend.draw(content.into_iter())
+    }
+
+    fn hide_cursor(&mut self) -> Result<(), io::Error> {
+        self.backend.hide_cursor()
+    }
+
+    fn show_cursor(&mut self) -> Result<(), io::Error> {
+        self.backend.show_cursor()
+    }
+
+    fn get_cursor(&mut self) -> Result<(u16, u16), io::Error> {
+        self.backend.get_cursor()
+    }
+
+    fn set_cursor(&mut self, x: u16, y: u16) -> Result<(), io::Error> {
+        self.backend.set_cursor(x, y)
+    }
+
+    fn clear(&mut self) -> Result<(), io::Error> {
+        self.screen.reset();
+        self.changed = true;
+        self.backend.clear()
+    }
+
+    fn size(&self) -> Result<Rect, io::Error> {
+        self.backend.size()
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        self.backend.flush()?;
+        // watching must never get in the way of the timer itself
+        let _ = self.save();
+        Ok(())
+    }
+}
+
+// escape codes written straight to the terminal, like leaving the
+// alternate screen, bypass the copy
+impl<B: Backend + Write> Write for Mirror<B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.backend.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Write::flush(&mut self.backend)
+    }
+}
+
+/// `screen` as one line of text per row, its colours and modifiers written
+/// as escape codes.
+fn ansi_lines(screen: &Buffer) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    for y in 0..screen.area.height {
+        let mut style = None;
+        for x in 0..screen.area.width {
+            let cell = screen.get(x, y);
+            if style != Some(cell.style()) {
+                queue!(out, SetAttribute(Attribute::Reset))?;
+                queue!(out, SetForegroundColor(color(cell.fg)), SetBackgroundColor(color(cell.bg)))?;
+                for (modifier, attribute) in ATTRIBUTES {
+                    if cell.modifier.contains(modifier) {
+                        queue!(out, SetAttribute(attribute))?;
+                    }
+                }
+                style = Some(cell.style());
+            }
+            out.extend_from_slice(cell.symbol.as_bytes());
+        }
+        queue!(out, SetAttribute(Attribute::Reset), ResetColor)?;
+        out.push(b'\n');
+    }
+    Ok(out)
+}
+
+/// `color` as an escape code colour, like the crossterm backend of tui
+/// writes it.
+fn color(color: Color) -> style::Color {
+    match color {
+        Color::Reset => style::Color::Reset,
+        Color::Black => style::Color::Black,
+        Color::Red => style::Color::DarkRed,
+        Color::Green => style::Color::DarkGreen,
+        Color::Yellow => style::Color::DarkYellow,
+        Color::Blue => style::Color::DarkBlue,
+        Color::Magenta => style::Color::DarkMagenta,
+        Color::Cyan => style::Color::DarkCyan,
+        Color::Gray => style::Color::Grey,
+        Color::DarkGray => style::Color::DarkGrey,
+        Color::LightRed => style::Color::Red,
+        Color::LightGreen => style::Color::Green,
+        Color::LightBlue => style::Color::Blue,
+        Color::LightYellow => style::Color::Yellow,
+        Color::LightMagenta => style::Color::Magenta,
+        Color::LightCyan => style::Color::Cyan,
+        Color::White => style::Color::White,
+        Color::Indexed(i) => style::Color::AnsiValue(i),
+        Color::Rgb(r, g, b) => style::Color::Rgb { r, g, b }
+    }
+}
+
+/// Text modifiers and the escape codes that show them.
+const ATTRIBUTES: [(Modifier, Attribute); 8] = [
+    (Modifier::BOLD, Attribute::Bold),
+    (Modifier::DIM, Attribute::Dim),
+    (Modifier::ITALIC, Attribute::Italic),
+    (Modifier::UNDERLINED, Attribute::Underlined),
+    (Modifier::SLOW_BLINK, Attribute::SlowBlink),
+    (Modifier::RAPID_BLINK, Attribute::RapidBlink),
+    (Modifier::REVERSED, Attribute::Reverse),
+    (Modifier::CROSSED_OUT, Attribute::CrossedOut)
+];
+
+/// How watching the running timer ended.
+pub enum Observed {
+    /// ESC, Q or CTRL+C
+    Stopped,
+    /// The timer went away, finished or quit
+    Gone
+}
+
+/// Show the screen of the timer running the session `name` in another
+/// terminal until it is gone or the observer presses ESC, Q or CTRL+C.
+/// Every other key is ignored, so watching can never pause or stop the
+/// timer.
+pub fn observe(name: &str) -> Result<Observed, String> {
+    let dir = session::runtime_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| format!(
+        "Could not create runtime directory {}: {}", dir.display(), e
+    ))?;
+    let shared = Shared::of(name)?;
+    request_screen(&shared)?;
+    if !wait_for_screen(&shared) {
+        let _ = fs::remove_file(&shared.request);
+        return Err(format!("No timer is running the session \"{}\"", name));
+    }
+
+    let mut stdout = io::stdout();
+    enable_raw_mode().map_err(|e| e.to_string())?;
+    execute!(stdout, EnterAlternateScreen, DisableLineWrap, Hide).map_err(|e| e.to_string())?;
+    let observed = follow(&shared, &mut stdout);
+    let _ = execute!(stdout, Show, EnableLineWrap, LeaveAlternateScreen);
+    let _ = disable_raw_mode();
+
+    // other observers ask again if they are still watching
+    let _ = fs::remove_file(&shared.request);
+    observed
+}
+
+/// Ask the running timer for its screen. Every observer asks again now and
+/// then, in case another one that stopped took the request with it.
+fn request_screen(shared: &Shared) -> Result<(), String> {
+    let path = &shared.request;
+    if path.exists() {
+        return Ok(());
+    }
+    fs::write(path, "").map_err(|e| format!(
+        "Could not write {}: {}", path.display(), e
+    ))
+}
+
+/// Whether the running timer saved its screen in time, after it was asked
+/// for it.
+fn wait_for_screen(shared: &Shared) -> bool {
+    let asked = Instant::now();
+    while asked.elapsed() < GONE_AFTER {
+        if saved_within(shared, GONE_AFTER) {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    false
+}
+
+/// Whether the screen of the running timer was saved no longer than `age`
+/// ago.
+fn saved_within(shared: &Shared, age: Duration) -> bool {
+    fs::metadata(&shared.screen)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|since| since < age)
+}
+
+/// Draw the screens the running timer saves until it is gone or the
+/// observer stops.
+fn follow(shared: &Shared, out: &mut impl Write) -> Result<Observed, String> {
+    let mut shown: Option<Vec<u8>> = None;
+    loop {
+        if !saved_within(shared, GONE_AFTER) {
+            return Ok(Observed::Gone);
+        }
+        request_screen(shared)?;
+
+        let screen = fs::read(&shared.screen).ok();
+        if screen.is_some() && screen != shown {
+            draw(out, screen.as_deref().unwrap_or_default()).map_err(|e| e.to_string())?;
+            shown = screen;
+        }
+
+        // nothing else is carried out, the timer is not ours to control
+        if poll_event(Duration::from_millis(100)).map_err(|e| e.to_string())? {
+            if let Event::Key(KeyEvent{
+                code: KeyCode::Esc | KeyCode::Char('q'),
+                ..
+            } | KeyEvent{
+                modifiers: KeyModifiers::CONTROL,
+                code: KeyCode::Char('c')
+            }) = read_event().map_err(|e| e.to_string())? {
+                return Ok(Observed::Stopped);
+            }
+        }
+    }
+}
+
+/// Draw a saved `screen` from the top left corner, cut off where the
+/// observer's terminal ends.
+fn draw(out: &mut impl Write, screen: &[u8]) -> io::Result<()> {
+    let (_, height) = crossterm::terminal::size()?;
+    queue!(out, Clear(ClearType::All))?;
+    for (y, line) in screen.split(|&byte| byte == b'\n').take(height.into()).enumerate() {
+        queue!(out, MoveTo(0, y as u16))?;
+        out.write_all(line)?;
+    }
+    out.flush()
+}
@@ -0,0 +1,363 @@
+use std::{
+    fs,
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex}
+};
+#[cfg(feature = "http")]
+use std::{
+    io::{Read, Write},
+    net::{IpAddr, TcpListener, TcpStream},
+    sync::atomic::AtomicUsize,
+    thread,
+    time::{Duration, Instant}
+};
+
+use serde::{Deserialize, Serialize};
+
+use staged_timer::{TimeFormat, Timer};
+
+use crate::{config::TlsOptions, render::Renderer, schema};
+#[cfg(feature = "http")]
+use crate::clients::{AuthThrottle, Slot};
+#[cfg(not(all(feature = "http", feature = "tls")))]
+use crate::integrations;
+#[cfg(all(feature = "http", feature = "tls"))]
+use crate::tls;
+
+/// Page served by `--serve`, which polls `/status.json` every second.
+#[cfg(feature = "http")]
+const STATUS_PAGE: &str = include_str!("status_page.html");
+
+/// How long a client gets to send its request and read the answer.
+#[cfg(feature = "http")]
+const CLIENT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long the head of a request may be. Longer ones are turned away.
+#[cfg(feature = "http")]
+const MAX_REQUEST: usize = 8 * 1024;
+
+/// Text of the overlay. `{stage}` is replaced with the name of the running
+/// stage, `{remaining}` with its time left, `{index}` and `{count}` with its
+/// position and `{next}` with the name of the stage after it.
+pub const DEFAULT_FORMAT: &str = "{stage} {remaining}";
+
+/// Where the overlay text goes, e.g. for a text source in streaming
+/// software.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OverlayOptions {
+    /// File that always holds the current text
+    pub file: Option<PathBuf>,
+    /// Port to serve the current text on over HTTP
+    pub port: Option<u16>,
+    /// Template of the text, see [`DEFAULT_FORMAT`]
+    pub format: String,
+    /// Address to serve the status of the timer on, as JSON at
+    /// `/status.json` and as a page at `/`
+    #[serde(default)]
+    pub serve: Option<SocketAddr>,
+    /// Token clients of `serve` have to send to get the status
+    #[serde(skip)]
+    pub serve_token: Option<String>,
+    /// Certificate and key to serve the status over HTTPS with
+    #[serde(default)]
+    pub serve_tls: Option<TlsOptions>
+}
+
+/// Keeps a line on the running stage up to date in a file and on a local
+/// HTTP endpoint, and the status of the timer on the `--serve` address.
+#[derive(Clone)]
+pub struct Overlay {
+    options: OverlayOptions,
+    /// Served over HTTP, shared with the server thread
+    text: Arc<Mutex<String>>,
+    written: Option<String>,
+    /// Served as `/status.json`, shared with the server thread
+    status: Arc<Mutex<String>>,
+    time_format: TimeFormat
+}
+
+impl Overlay {
+    /// Start serving the overlay as set up in `options`, with times in
+    /// `time_format`. Fails if the file can't be written, the port is in
+    /// use or the build can't serve HTTP.
+    pub fn start(options: &OverlayOptions, time_format: TimeFormat) -> Result<Overlay, String> {
+        let overlay = Overlay {
+            options: options.clone(),
+            text: Arc::new(Mutex::new(String::new())),
+            written: None,
+            status: Arc::new(Mutex::new(String::from("{}"))),
+            time_format
+        };
+        if let Some(file) = &options.file {
+            write(file, "").map_err(|e| format!(
+                "Could not write overlay file {}: {}", file.display(), e
+            ))?;
+        }
+        #[cfg(feature = "http")]
+        overlay.listen()?;
+        #[cfg(not(feature = "http"))]
+        if options.port.is_some() || options.serve.is_some() {
+            return Err(integrations::HTTP.missing());
+        }
+        Ok(overlay)
+    }
+
+    /// Serve the text on the port of the options and the status on their
+    /// `serve` address, where they have them.
+    #[cfg(feature = "http")]
+    fn listen(&self) -> Result<(), String> {
+        if let Some(port) = self.options.port {
+            let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| format!(
+                "Could not serve the overlay on port {}: {}", port, e
+            ))?;
+            let text = Arc::clone(&self.text);
+            let open = Arc::new(AtomicUsize::new(0));
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    // dropping the stream closes the connection
+                    let slot = match Slot::take(&open) {
+                        Some(slot) => slot,
+                        None => continue
+                    };
+                    if set_timeouts(&stream).is_err() {
+                        continue;
+                    }
+                    let text = Arc::clone(&text);
+                    thread::spawn(move || {
+                        let _slot = slot;
+                        let text = text.lock().map(|text| text.clone()).unwrap_or_default();
+                        respond(stream, &text)
+                    });
+                }
+            });
+        }
+        if let Some(address) = self.options.serve {
+            #[cfg(feature = "tls")]
+            let acceptor = self.options.serve_tls.as_ref()
+                .map(tls::Acceptor::new)
+                .transpose()?
+                .map(Arc::new);
+            #[cfg(not(feature = "tls"))]
+            if self.options.serve_tls.is_some() {
+                integrations::TLS.require()?;
+            }
+            let listener = TcpListener::bind(address).map_err(|e| format!(
+                "Could not serve the status on {}: {}", address, e
+            ))?;
+            let (status, token) = (Arc::clone(&self.status), self.options.serve_token.clone());
+            let token: Option<Arc<str>> = token.map(Arc::from);
+            let throttle = Arc::new(AuthThrottle::default());
+            let open = Arc::new(AtomicUsize::new(0));
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let slot = match Slot::take(&open) {
+                        Some(slot) => slot,
+                        None => continue
+                    };
+                    let client = match stream.peer_addr() {
+                        Ok(peer) => peer.ip().to_canonical(),
+                        Err(_) => continue
+                    };
+                    if set_timeouts(&stream).is_err() {
+                        continue;
+                    }
+                    let (status, token, throttle) =
+                        (Arc::clone(&status), token.clone(), Arc::clone(&throttle));
+                    #[cfg(feature = "tls")]
+                    let acceptor = acceptor.clone();
+                    // the handshake and the wait for a token take place on
+                    // the client's thread, so a slow client doesn't hold up
+                    // the others
+                    thread::spawn(move || {
+                        let _slot = slot;
+                        let token = token.as_deref().map(|token| (token, client, &*throttle));
+                        #[cfg(feature = "tls")]
+                        if let Some(acceptor) = acceptor {
+                            return serve(acceptor.accept(stream)?, &status, token);
+                        }
+                        serve(stream, &status, token)
+                    });
+                }
+            });
+        }
+        Ok(())
+    }
+
+    pub fn options(&self) -> &OverlayOptions {
+        &self.options
+    }
+}
+
+impl Renderer for Overlay {
+    /// Bring the text up to date with `timer`, writing the file only when
+    /// it changed.
+    fn render(&mut self, timer: &Timer) -> io::Result<()> {
+        if self.options.serve.is_some() {
+            if let Ok(mut status) = self.status.lock() {
+                *status = schema::line(&schema::Event::status(timer));
+            }
+        }
+        let text = text(&self.options.format, timer, self.time_format);
+        if self.written.as_ref() == Some(&text) {
+            return Ok(());
+        }
+        if let Some(file) = &self.options.file {
+            // a file that can't be written mustn't stop the timer
+            let _ = write(file, &text);
+        }
+        if let Ok(mut served) = self.text.lock() {
+            served.clone_from(&text);
+        }
+        self.written = Some(text);
+        Ok(())
+    }
+}
+
+/// `format` filled in with the running stage of `timer`, see
+/// [`DEFAULT_FORMAT`].
+pub fn text(format: &str, timer: &Timer, time_format: TimeFormat) -> String {
+    let stage = match timer.current_stage() {
+        Some(stage) => stage,
+        None => return String::from("Done")
+    };
+    let remaining = if timer.is_paused() && !stage.runs_while_paused {
+        String::from("Paused")
+    } else {
+        let clock_s = if stage.count_up { stage.elapsed_s() } else { stage.remaining_s() };
+        if stage.is_overtime() && stage.elapsed_s() > stage.period_s() {
+            let over_s = stage.elapsed_s() - stage.period_s();
+            format!("-{}", time_format.format(over_s, stage.period_s().max(over_s)))
+        } else {
+            time_format.format(clock_s, stage.period_s().max(clock_s))
+        }
+    };
+    let index = timer.current_index();
+    let next = timer.stages().get(index + 1).map(|next| next.name.as_str());
+    format
+        .replace("{stage}", &stage.name)
+        .replace("{remaining}", &remaining)
+        .replace("{index}", &(index + 1).to_string())
+        .replace("{count}", &timer.stages().len().to_string())
+        .replace("{next}", next.unwrap_or_default())
+}
+
+/// Replace `file` in one go so it is never read half written.
+fn write(file: &Path, text: &str) -> io::Result<()> {
+    let partial = file.with_extension("partial");
+    fs::write(&partial, text)?;
+    fs::rename(&partial, file)
+}
+
+/// Give the client on `stream` [`CLIENT_TIMEOUT`] for each read and write.
+#[cfg(feature = "http")]
+fn set_timeouts(stream: &TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))
+}
+
+/// Answer any request with `text`.
+#[cfg(feature = "http")]
+fn respond(mut stream: TcpStream, text: &str) -> io::Result<()> {
+    // the request itself doesn't matter, but has to be read before the
+    // connection is closed
+    read_request(&mut stream)?;
+    reply(stream, "text/plain", text)
+}
+
+/// Answer a request for `/status.json` with `status`, and any other with
+/// the page showing it. Where there is a token, the request has to come
+/// with it, and a client that sent a wrong one waits for the throttle.
+#[cfg(feature = "http")]
+fn serve(
+    mut stream: impl Read + Write,
+    status: &Mutex<String>,
+    token: Option<(&str, IpAddr, &AuthThrottle)>
+) -> io::Result<()> {
+    let request = read_request(&mut stream)?;
+    let target = request.split_whitespace().nth(1).unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if let Some((token, client, throttle)) = token {
+        throttle.retry(client);
+        if !authorized(&request, query, token) {
+            throttle.fail(client);
+            return refuse(stream);
+        }
+    }
+    if path == "/status.json" {
+        let status = status.lock().map(|status| status.clone()).unwrap_or_default();
+        reply(stream, "application/json", &status)
+    } else {
+        reply(stream, "text/html", STATUS_PAGE)
+    }
+}
+
+/// Whether `request`, whose target has `query`, carries `token`, as
+/// `Authorization: Bearer TOKEN` or, for the page opened in a browser, as
+/// `?token=TOKEN`.
+#[cfg(feature = "http")]
+fn authorized(request: &str, query: &str, token: &str) -> bool {
+    let bearer = request.lines()
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("authorization"))
+        .any(|(_, value)| value.trim().strip_prefix("Bearer ") == Some(token));
+    bearer || query.split('&').any(|pair| pair.strip_prefix("token=") == Some(token))
+}
+
+/// The head of the request on `stream`, its request line and headers up to
+/// the blank line after them. Fails for a head longer than [`MAX_REQUEST`]
+/// or one that takes longer than [`CLIENT_TIMEOUT`] to come in.
+#[cfg(feature = "http")]
+fn read_request(stream: &mut impl Read) -> io::Result<String> {
+    let started = Instant::now();
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    let head = loop {
+        match request.windows(4).position(|end| end == b"\r\n\r\n") {
+            Some(end) => break end,
+            None if request.len() > MAX_REQUEST => break request.len(),
+            None if started.elapsed() > CLIENT_TIMEOUT => return Err(io::ErrorKind::TimedOut.into()),
+            None => {}
+        }
+        match stream.read(&mut buffer)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            read => request.extend_from_slice(&buffer[..read])
+        }
+    };
+    if head > MAX_REQUEST {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request too long"));
+    }
+    Ok(String::from_utf8_lossy(&request[..head]).into_owned())
+}
+
+/// Turn the request on `stream` away for not having the token.
+#[cfg(feature = "http")]
+fn refuse(mut stream: impl Write) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 401 Unauthorized\r\n\
+        WWW-Authenticate: Bearer\r\n\
+        Content-Length: 0\r\n\
+        Connection: close\r\n\r\n"
+    )?;
+    stream.flush()
+}
+
+/// Send `body` as a `content_type` response and close the connection.
+#[cfg(feature = "http")]
+fn reply(mut stream: impl Write, content_type: &str, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\n\
+        Content-Type: {}; charset=utf-8\r\n\
+        Content-Length: {}\r\n\
+        Cache-Control: no-store\r\n\
+        Access-Control-Allow-Origin: *\r\n\
+        Connection: close\r\n\r\n{}",
+        content_type, body.len(), body
+    )?;
+    stream.flush()
+}
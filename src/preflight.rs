@@ -0,0 +1,155 @@
+use std::{
+    env,
+    fs::File,
+    io::{self, Read, Write},
+    net::ToSocketAddrs,
+    path::Path
+};
+
+/// One line of the checklist `--check-io` prints: what was checked and
+/// what is wrong with it, if anything.
+pub struct Check {
+    pub what: String,
+    pub problem: Option<String>
+}
+
+impl Check {
+    fn new(what: String, result: Result<(), String>) -> Check {
+        Check { what, problem: result.err() }
+    }
+
+    /// A check that failed before it could be made, e.g. a notifier whose
+    /// program is missing.
+    pub fn failed(what: &str, problem: String) -> Check {
+        Check { what: what.to_string(), problem: Some(problem) }
+    }
+}
+
+/// Magic numbers at the start of the audio files players commonly decode,
+/// with the position they are at.
+const AUDIO_SIGNATURES: &[(usize, &[u8])] = &[
+    (8, b"WAVE"),
+    (0, b"OggS"),
+    (0, b"fLaC"),
+    (0, b"ID3"),
+    (8, b"AIFF"),
+    (8, b"AIFC"),
+    (4, b"ftyp")
+];
+
+/// Whether `path` is an audio file the player can be expected to play: it
+/// can be read and starts like a WAV, Ogg, FLAC, MP3, AIFF or MP4 file.
+pub fn sound_file(what: &str, path: &Path) -> Check {
+    let result = File::open(path)
+        .and_then(|file| {
+            let mut start = Vec::new();
+            file.take(12).read_to_end(&mut start)?;
+            Ok(start)
+        })
+        .map_err(|e| format!("can't be read: {}", e))
+        .and_then(|start| {
+            // MPEG audio without a tag starts right with a frame header
+            let mpeg = start.len() >= 2 && start[0] == 0xff && start[1] & 0xe0 == 0xe0;
+            let known = AUDIO_SIGNATURES.iter().any(|(at, signature)| {
+                start.get(*at..at + signature.len()) == Some(*signature)
+            });
+            if mpeg || known {
+                Ok(())
+            } else {
+                Err(String::from("is not a WAV, Ogg, FLAC, MP3, AIFF or MP4 audio file"))
+            }
+        });
+    Check::new(format!("{} {}", what, path.display()), result)
+}
+
+/// Whether the host of `url`, e.g. `https://ntfy.sh/darkroom`, resolves.
+pub fn url_host(what: &str, url: &str) -> Check {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("https", url));
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let default_port = if scheme.eq_ignore_ascii_case("http") { 80 } else { 443 };
+    let address = if authority.ends_with(']') || !authority.contains(':') {
+        format!("{}:{}", authority, default_port)
+    } else {
+        authority.to_string()
+    };
+    Check::new(format!("{} {}", what, url), resolves(&address))
+}
+
+/// Whether the host of `address`, `HOST` or `HOST:PORT`, resolves.
+pub fn host(what: &str, address: &str, default_port: u16) -> Check {
+    let with_port = if address.contains(':') {
+        address.to_string()
+    } else {
+        format!("{}:{}", address, default_port)
+    };
+    Check::new(format!("{} {}", what, address), resolves(&with_port))
+}
+
+fn resolves(address: &str) -> Result<(), String> {
+    match address.to_socket_addrs().map(|mut addresses| addresses.next()) {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(String::from("the host has no address")),
+        Err(e) => Err(format!("the host doesn't resolve: {}", e))
+    }
+}
+
+/// Whether the input device at `path` can be opened for reading.
+pub fn device(path: &Path) -> Check {
+    let result = File::open(path).map(|_| ()).map_err(|e| format!("can't be opened: {}", e));
+    Check::new(format!("Input device {}", path.display()), result)
+}
+
+/// Whether the program `command` starts with, past any variables it
+/// assigns, is an executable file or can be found in `PATH`.
+pub fn command(what: &str, command: &str) -> Check {
+    let program = command.split_whitespace()
+        .find(|word| !word.contains('='))
+        .unwrap_or_default();
+    let result = if program.is_empty() {
+        Err(String::from("is empty"))
+    } else if program.contains('/') {
+        executable(Path::new(program))
+            .then_some(())
+            .ok_or_else(|| format!("{} is not an executable file", program))
+    } else {
+        env::var_os("PATH")
+            .is_some_and(|paths| env::split_paths(&paths).any(|dir| executable(&dir.join(program))))
+            .then_some(())
+            .ok_or_else(|| format!("{} is not found in PATH", program))
+    };
+    Check::new(format!("{} `{}`", what, command), result)
+}
+
+#[cfg(unix)]
+fn executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn executable(path: &Path) -> bool {
+    path.is_file() || path.with_extension("exe").is_file()
+}
+
+/// Print the checklist, `ok` or `FAIL` and the problem for every check, and
+/// tell whether all of them passed.
+pub fn report(checks: &[Check], out: &mut impl Write) -> io::Result<bool> {
+    if checks.is_empty() {
+        writeln!(out, "Nothing to check: no sounds, hooks, notifiers or devices are set up.")?;
+        return Ok(true);
+    }
+    for check in checks {
+        match &check.problem {
+            None => writeln!(out, "  ok    {}", check.what)?,
+            Some(problem) => writeln!(out, "  FAIL  {}: {}", check.what, problem)?
+        }
+    }
+    let failed = checks.iter().filter(|check| check.problem.is_some()).count();
+    if failed == 0 {
+        writeln!(out, "All {} checks passed.", checks.len())?;
+    } else {
+        writeln!(out, "{} of {} checks failed.", failed, checks.len())?;
+    }
+    Ok(failed == 0)
+}
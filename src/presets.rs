@@ -0,0 +1,458 @@
+use std::{fs, io, path::{Path, PathBuf}, time::Duration};
+
+use serde::Deserialize;
+
+use staged_timer::TimeFormat;
+
+use crate::config::{self, config_dir, LoadedConfig};
+
+/// Config file extensions a preset may have.
+const EXTENSIONS: [&str; 3] = ["toml", "yaml", "yml"];
+
+pub fn presets_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("presets"))
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(format!(
+            "\"{}\" is not a valid preset name, use e.g. \"c41\"", name
+        ));
+    }
+    Ok(())
+}
+
+/// The config file of the preset `name`.
+pub fn find(name: &str) -> Result<PathBuf, String> {
+    check_name(name)?;
+    let dir = presets_dir().ok_or("Could not find the config directory")?;
+    EXTENSIONS.iter()
+        .map(|extension| dir.join(format!("{}.{}", name, extension)))
+        .find(|path| path.is_file())
+        .ok_or_else(|| format!(
+            "There is no preset named \"{}\", see `staged-timer presets list`",
+            name
+        ))
+}
+
+/// Names and files of all presets, sorted by name.
+pub fn list() -> Result<Vec<(String, PathBuf)>, String> {
+    let dir = match presets_dir() {
+        Some(dir) => dir,
+        None => return Ok(Vec::new())
+    };
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!(
+            "Could not read presets directory {}: {}", dir.display(), e
+        ))
+    };
+
+    let mut presets: Vec<(String, PathBuf)> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| EXTENSIONS.contains(&extension)))
+        .filter_map(|path| Some((path.file_stem()?.to_str()?.to_string(), path)))
+        // presets being edited are copied to hidden files
+        .filter(|(name, _)| !name.starts_with('.'))
+        .collect();
+    presets.sort();
+    Ok(presets)
+}
+
+/// Write a duration so that it reads well and parses back to the same
+/// duration.
+fn config_time(period: Duration) -> String {
+    if period.as_secs() < 60 {
+        return period.as_secs_f64().to_string();
+    }
+    let seconds = u32::try_from(period.as_secs()).unwrap_or(u32::MAX);
+    let fraction = match period.subsec_millis() {
+        0 => String::new(),
+        millis => format!(".{:03}", millis).trim_end_matches('0').to_string()
+    };
+    format!("\"{}{}\"", TimeFormat::Smart.format(seconds, seconds), fraction)
+}
+
+/// Save stages given as names and durations as the preset `name`.
+pub fn save_stages(
+    name: &str,
+    stages: &[(Option<String>, Duration)],
+    overwrite: bool
+) -> Result<PathBuf, String> {
+    let content = stages.iter().map(|(stage_name, period)| {
+        let name_line = stage_name.as_ref()
+            .map(|stage_name| format!("name = {}\n", toml::Value::from(stage_name.as_str())))
+            .unwrap_or_default();
+        format!("[[stage]]\n{}time = {}\n", name_line, config_time(*period))
+    }).collect::<Vec<String>>().join("\n");
+
+    write(name, "toml", &content, overwrite)
+}
+
+/// Delete the preset `name`, returning the file it was in.
+pub fn remove(name: &str) -> Result<PathBuf, String> {
+    let path = find(name)?;
+    fs::remove_file(&path).map_err(|e| format!(
+        "Could not remove preset {}: {}", path.display(), e
+    ))?;
+    Ok(path)
+}
+
+/// Where the audio files of the stages are written in a config file.
+#[derive(Deserialize)]
+struct AudioSpans<T> {
+    #[serde(default = "Vec::new", rename = "stage")]
+    stages: Vec<StageAudioSpan<T>>
+}
+
+#[derive(Deserialize)]
+struct StageAudioSpan<T> {
+    audio: Option<T>
+}
+
+/// `audio`, relative to the config file at `source`, as an absolute path.
+fn absolute_audio(source: &Path, audio: &str) -> Result<String, String> {
+    let path = source.parent().unwrap_or(Path::new(".")).join(audio);
+    let absolute = fs::canonicalize(&path).map_err(|e| format!(
+        "Could not find audio file {}: {}", path.display(), e
+    ))?;
+    absolute.into_os_string().into_string().map_err(|path| format!(
+        "The path of audio file {} is not valid UTF-8", path.to_string_lossy()
+    ))
+}
+
+/// Save a copy of the config file at `source` as the preset `name`. Audio
+/// files are relative to the config file they appear in, so those of a
+/// TOML file are written as absolute paths to keep working from the
+/// presets directory. YAML files with relative ones are not saved.
+pub fn save_config(name: &str, source: &Path, overwrite: bool) -> Result<PathBuf, String> {
+    // only save presets that will load later
+    config::load(source)?;
+    let mut content = fs::read_to_string(source).map_err(|e| format!(
+        "Could not read config file {}: {}", source.display(), e
+    ))?;
+    let extension = source.extension()
+        .and_then(|extension| extension.to_str())
+        .filter(|extension| EXTENSIONS.contains(extension))
+        .unwrap_or("toml");
+
+    let parse_error = |e: String| format!(
+        "Could not parse config file {}: {}", source.display(), e
+    );
+    if extension == "toml" {
+        let spans: AudioSpans<toml::Spanned<String>> = toml::from_str(&content)
+            .map_err(|e| parse_error(e.to_string()))?;
+        // from the end so the spans of earlier stages stay where they are
+        for audio in spans.stages.iter().rev().filter_map(|stage| stage.audio.as_ref()) {
+            if Path::new(audio.get_ref()).is_relative() {
+                let absolute = absolute_audio(source, audio.get_ref())?;
+                content.replace_range(audio.span(), &toml::Value::from(absolute).to_string());
+            }
+        }
+    } else {
+        let spans: AudioSpans<String> = serde_yaml::from_str(&content)
+            .map_err(|e| parse_error(e.to_string()))?;
+        if let Some(audio) = spans.stages.iter()
+            .filter_map(|stage| stage.audio.as_ref())
+            .find(|audio| Path::new(audio).is_relative())
+        {
+            return Err(format!(
+                "The audio file \"{}\" in {} is relative to it and won't be found \
+                from the presets directory, give it as an absolute path: {}",
+                audio, source.display(), absolute_audio(source, audio)?
+            ));
+        }
+    }
+
+    write(name, extension, &content, overwrite)
+}
+
+/// Where the durations of the stages are written in a config file.
+#[derive(Deserialize)]
+struct TimeSpans {
+    #[serde(default, rename = "stage")]
+    stages: Vec<StageTimeSpan>,
+    repeat: Option<toml::Value>,
+    parent: Option<String>,
+    scale: Option<toml::Value>
+}
+
+#[derive(Deserialize)]
+struct StageTimeSpan {
+    time: Option<toml::Spanned<toml::Value>>,
+    include: Option<String>
+}
+
+/// Give the stages of the preset `name` new durations, in the order they
+/// are written, where `periods` has one. Everything else in the file is
+/// left as it is. Only TOML presets without `repeat` can be changed.
+pub fn set_times(name: &str, periods: &[Option<Duration>]) -> Result<PathBuf, String> {
+    let path = find(name)?;
+    if path.extension().and_then(|extension| extension.to_str()) != Some("toml") {
+        return Err(format!(
+            "Only TOML presets can be changed, edit {} by hand", path.display()
+        ));
+    }
+    let mut content = fs::read_to_string(&path).map_err(|e| format!(
+        "Could not read preset {}: {}", path.display(), e
+    ))?;
+    let spans: TimeSpans = toml::from_str(&content).map_err(|e| format!(
+        "Could not parse preset {}: {}", path.display(), e
+    ))?;
+    if let Some(parent) = spans.parent {
+        return Err(format!(
+            "The stages of {} come from the preset \"{}\", change that one instead",
+            path.display(), parent
+        ));
+    }
+    if spans.scale.is_some() {
+        return Err(format!(
+            "The stages of presets with scale can't be changed, edit {} by hand",
+            path.display()
+        ));
+    }
+    if let Some(include) = spans.stages.iter().find_map(|stage| stage.include.as_ref()) {
+        return Err(format!(
+            "The stages of presets that include others, like \"{}\", can't be \
+            changed, edit {} by hand",
+            include, path.display()
+        ));
+    }
+    if spans.repeat.is_some() {
+        return Err(format!(
+            "The stages of presets with repeat can't be changed, edit {} by hand",
+            path.display()
+        ));
+    }
+    if spans.stages.len() != periods.len() {
+        return Err(format!(
+            "{} stages are written in {} but {} were given times, edit it by hand",
+            spans.stages.len(), path.display(), periods.len()
+        ));
+    }
+
+    // from the end so the spans of earlier stages stay where they are
+    for (stage, period) in spans.stages.iter().zip(periods).rev() {
+        if let (Some(time), Some(period)) = (&stage.time, period) {
+            content.replace_range(time.span(), &config_time(*period));
+        }
+    }
+    fs::write(&path, content).map_err(|e| format!(
+        "Could not write preset {}: {}", path.display(), e
+    ))?;
+    Ok(path)
+}
+
+/// Settings `copy` can change, at the top of a config file.
+const COPY_SETTINGS: [&str; 5] = ["scale", "repeat", "stage_names", "on_complete", "options"];
+
+/// Save the preset `name` as derived from the preset `source`: it runs the
+/// stages of `source`, changes to them included, with the settings in
+/// `set`, given as `key=value`, in place of those of `source`. Values are
+/// TOML, anything else is taken as a string.
+pub fn copy(source: &str, name: &str, set: &[String], overwrite: bool) -> Result<PathBuf, String> {
+    check_name(name)?;
+    if source == name {
+        return Err(format!("The preset \"{}\" can't be derived from itself", name));
+    }
+    find(source)?;
+    let mut content = format!("parent = {}\n", toml::Value::from(source));
+    for setting in set {
+        let (key, value) = setting.split_once('=').ok_or_else(|| format!(
+            "\"{}\" is not of the form key=value, e.g. scale=1.25", setting
+        ))?;
+        let key = key.trim();
+        if !COPY_SETTINGS.contains(&key) {
+            return Err(format!(
+                "\"{}\" can't be set, only {}", key, COPY_SETTINGS.join(", ")
+            ));
+        }
+        let value = toml::from_str::<toml::Table>(&format!("value = {}", value.trim()))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| toml::Value::from(value.trim()));
+        content.push_str(&format!("{} = {}\n", key, value));
+    }
+
+    // only save presets that will load later, checked before they are
+    // written as if they were where they go
+    let dir = presets_dir().ok_or("Could not find the config directory")?;
+    let path = dir.join(format!("{}.toml", name));
+    config::load_toml(&content, &path)
+        .map_err(|message| message.replace(&path.display().to_string(), name))?;
+
+    write(name, "toml", &content, overwrite)
+}
+
+/// Whole seconds of `period`, e.g. `3:15`.
+fn plan_time(period: Duration) -> String {
+    let seconds = u32::try_from(period.as_secs()).unwrap_or(u32::MAX);
+    TimeFormat::Smart.format(seconds, seconds)
+}
+
+/// The stages of a loaded preset the way they run, cues included, one line
+/// for each stage and checkpoint.
+fn plan(loaded: &LoadedConfig) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (i, stage) in loaded.stages.iter().enumerate() {
+        let name = stage.name.clone().unwrap_or_else(|| format!("Stage {}", i + 1));
+        let time = if stage.count_up { String::from("open") } else { plan_time(stage.period) };
+        let after = stage.after.as_ref()
+            .map(|after| format!(", ending in {} {}", after.name, plan_time(after.period)))
+            .unwrap_or_default();
+        let disabled = if stage.enabled { "" } else { " (disabled)" };
+        lines.push(format!("{} {}{}{}", name, time, after, disabled));
+
+        let cued = loaded.cues.iter().flat_map(|cue| cue.checkpoints(stage));
+        let mut checkpoints: Vec<_> = stage.checkpoints.iter().cloned().chain(cued).collect();
+        checkpoints.sort_by_key(|checkpoint| checkpoint.position_s(stage.period));
+        lines.extend(checkpoints.iter().map(|checkpoint| match checkpoint.percent {
+            Some(percent) => format!("    at {}%: {}", percent, checkpoint.message),
+            None => format!(
+                "    at {}: {}",
+                plan_time(Duration::from_secs(checkpoint.at_s.into())), checkpoint.message
+            )
+        }));
+    }
+    let total = loaded.stages.iter().filter(|stage| stage.enabled).map(|stage| stage.period).sum();
+    lines.push(format!("Total {}", plan_time(total)));
+    if !loaded.options.is_empty() {
+        lines.push(format!("Options {}", loaded.options.join(" ")));
+    }
+    lines
+}
+
+/// The lines of `after` marked with `+`, `-` or nothing compared to
+/// `before`, from their longest common subsequence.
+fn plan_diff(before: &[String], after: &[String]) -> Vec<String> {
+    // common[i][j] is how many lines before[i..] and after[j..] share
+    let mut common = vec![vec![0; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            common[i][j] = if before[i] == after[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < before.len() || j < after.len() {
+        if i < before.len() && j < after.len() && before[i] == after[j] {
+            lines.push(format!("  {}", before[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if i < before.len() && (j == after.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(format!("- {}", before[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", after[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// Open the preset `name` in `$EDITOR`, check it when the editor is closed
+/// and show how its stages changed before keeping it. The editor works on a
+/// copy next to the preset, so its includes still resolve, and the copy
+/// replaces the preset once the changes are kept.
+pub fn edit(name: &str) -> Result<(), String> {
+    let path = find(name)?;
+    let original = fs::read_to_string(&path).map_err(|e| format!(
+        "Could not read preset {}: {}", path.display(), e
+    ))?;
+    let before = config::load(&path).ok().map(|loaded| plan(&loaded));
+
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("toml");
+    let copy = path.with_file_name(format!(".{}.edit.{}", name, extension));
+    fs::OpenOptions::new().write(true).create_new(true).open(&copy)
+        .and_then(|mut file| io::Write::write_all(&mut file, original.as_bytes()))
+        .and_then(|_| fs::set_permissions(&copy, fs::metadata(&path)?.permissions()))
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => format!(
+                "Preset \"{}\" is being edited already, or an edit was cut short: \
+                remove {} to edit it",
+                name, copy.display()
+            ),
+            _ => format!("Could not copy preset {} to edit it: {}", path.display(), e)
+        })?;
+
+    let kept = review(&path, &copy, before.as_deref()).and_then(|kept| if kept {
+        fs::rename(&copy, &path).map_err(|e| format!(
+            "Could not replace preset {}: {}", path.display(), e
+        ))
+    } else {
+        Err(format!("Preset \"{}\" was left unchanged", name))
+    });
+    if kept.is_err() {
+        let _ = fs::remove_file(&copy);
+    }
+    kept
+}
+
+/// Edit `copy` of the preset at `path` until it loads, showing how
+/// the stages changed from the plan `before`. Whether to keep the changes.
+fn review(path: &Path, copy: &Path, before: Option<&[String]>) -> Result<bool, String> {
+    loop {
+        config::run_editor(copy)?;
+
+        let loaded = match config::load(copy) {
+            Ok(loaded) => loaded,
+            Err(error) => {
+                eprintln!("{}", error.replace(&copy.display().to_string(), &path.display().to_string()));
+                if config::prompt("Edit again? [Y/n]")? == "n" {
+                    return Ok(false);
+                }
+                continue;
+            }
+        };
+        let after = plan(&loaded);
+        let before = match before {
+            // without a plan to compare to, a preset that loads is all
+            None => return Ok(true),
+            Some(before) if before == after => return Ok(true),
+            Some(before) => before
+        };
+        for line in plan_diff(before, &after) {
+            println!("{}", line);
+        }
+        match config::prompt("Keep the changes? [Y/n/e(dit again)]")?.as_str() {
+            "n" => return Ok(false),
+            "e" => continue,
+            _ => return Ok(true)
+        }
+    }
+}
+
+fn write(name: &str, extension: &str, content: &str, overwrite: bool) -> Result<PathBuf, String> {
+    check_name(name)?;
+    if !overwrite {
+        if let Ok(existing) = find(name) {
+            return Err(format!(
+                "A preset named \"{}\" already exists at {}, use --force to \
+                replace it",
+                name, existing.display()
+            ));
+        }
+    }
+
+    let dir = presets_dir().ok_or("Could not find the config directory")?;
+    fs::create_dir_all(&dir).map_err(|e| format!(
+        "Could not create presets directory {}: {}", dir.display(), e
+    ))?;
+    // a replaced preset may have had another format
+    for extension in EXTENSIONS {
+        let _ = fs::remove_file(dir.join(format!("{}.{}", name, extension)));
+    }
+
+    let path = dir.join(format!("{}.{}", name, extension));
+    fs::write(&path, content).map_err(|e| format!(
+        "Could not write preset {}: {}", path.display(), e
+    ))?;
+    Ok(path)
+}
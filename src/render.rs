@@ -0,0 +1,123 @@
+use std::{
+    io::{self, Write},
+    time::Duration
+};
+
+use serde_json::json;
+
+use staged_timer::{EventStream, TimeFormat, Timer, TimerEvent};
+
+use crate::{overlay, schema};
+
+/// Shows the timer somewhere. The TUI is one, the plain and headless output,
+/// the status bar and the overlay are others. Each is given the timer
+/// whenever it may have changed and works out for itself what to show, so
+/// all of them follow the same state.
+pub trait Renderer {
+    /// Bring what is shown up to date with `timer`.
+    fn render(&mut self, timer: &Timer) -> io::Result<()>;
+
+    /// Say why the timer did something by itself, e.g. ended a rest early.
+    /// Only shown where there is room for it.
+    fn notice(&mut self, _notice: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Note that the hooks, sounds and notifications of the stage that just
+    /// started were done `slack` after it started, for the next render to
+    /// show if it shows such things.
+    fn stage_slack(&mut self, _slack: Duration) {}
+}
+
+/// Every event as a line of JSON, for `--headless`.
+pub struct Headless<W: Write> {
+    out: W,
+    events: EventStream,
+    /// Slack of the stage that just started, for its `stage_start`
+    slack: Option<Duration>
+}
+
+impl<W: Write> Headless<W> {
+    /// Write to `out`, warning `warning_s` before the end of a stage.
+    pub fn new(out: W, warning_s: u32) -> Headless<W> {
+        Headless { out, events: EventStream::with_warning(warning_s), slack: None }
+    }
+}
+
+impl<W: Write> Renderer for Headless<W> {
+    fn render(&mut self, timer: &Timer) -> io::Result<()> {
+        for event in self.events.update(timer) {
+            let mut line = schema::Event::of(event, timer);
+            // the slack is that of the running stage, not of any passed on
+            // the way to it
+            if let schema::Event::StageStart { slack_ms, .. } = &mut line {
+                if event == TimerEvent::StageStart(timer.current_index()) {
+                    *slack_ms = self.slack.take().map(|slack| slack.as_millis() as u64);
+                }
+            }
+            writeln!(self.out, "{}", schema::line(&line))?;
+        }
+        Ok(())
+    }
+
+    fn stage_slack(&mut self, slack: Duration) {
+        self.slack = Some(slack);
+    }
+}
+
+/// A line of JSON whenever the running stage or its time changes, in the
+/// form status bars like waybar and i3status-rust read from a custom module,
+/// for `--statusbar`.
+pub struct Statusbar<W: Write> {
+    out: W,
+    time_format: TimeFormat,
+    shown: Option<String>
+}
+
+impl<W: Write> Statusbar<W> {
+    pub fn new(out: W, time_format: TimeFormat) -> Statusbar<W> {
+        Statusbar { out, time_format, shown: None }
+    }
+}
+
+/// What kind of state the timer is in, for status bars to style it by:
+/// running, paused, waiting, overtime or finished.
+pub fn state_class(timer: &Timer) -> &'static str {
+    match timer.current_stage() {
+        None => "finished",
+        Some(stage) if timer.is_paused() && !stage.runs_while_paused => "paused",
+        Some(_) if timer.is_waiting() => "waiting",
+        Some(stage) if stage.is_overtime() && stage.elapsed_s() >= stage.period_s() => "overtime",
+        Some(_) => "running"
+    }
+}
+
+impl<W: Write> Renderer for Statusbar<W> {
+    fn render(&mut self, timer: &Timer) -> io::Result<()> {
+        let index = timer.current_index();
+        let count = timer.stages().len();
+        let tooltip = match (timer.current_stage(), timer.stages().get(index + 1)) {
+            (None, _) => String::from("All stages are done"),
+            (Some(_), Some(next)) => format!("Stage {} of {}, next: {}", index + 1, count, next.name),
+            (Some(_), None) => format!("Stage {} of {}", index + 1, count)
+        };
+        let percentage = timer.current_stage().map_or(100, |stage| {
+            (stage.elapsed_s() * 100).checked_div(stage.period_s()).unwrap_or(0).min(100)
+        });
+        let line = json!({
+            "text": overlay::text(overlay::DEFAULT_FORMAT, timer, self.time_format),
+            "tooltip": tooltip,
+            "class": state_class(timer),
+            "percentage": percentage
+        }).to_string();
+        if self.shown.as_ref() == Some(&line) {
+            return Ok(());
+        }
+        writeln!(self.out, "{}", line)?;
+        // status bars read the line as soon as it comes, not once a buffer
+        // is full
+        self.out.flush()?;
+        self.shown = Some(line);
+        Ok(())
+    }
+}
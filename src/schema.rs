@@ -0,0 +1,289 @@
+use serde::Serialize;
+
+use staged_timer::{Timer, TimerEvent, TimerStage};
+
+use crate::history::{Environment, SessionRecord, StageRecord};
+
+/// Version of the JSON written by `--headless` and `history --json`. Every
+/// line carries it as `schema`. It goes up whenever a field changes meaning
+/// or goes away, fields may be added without.
+pub const VERSION: u32 = 1;
+
+/// JSON Schema of every line of JSON the timer writes, printed by
+/// `--schema`.
+pub const JSON_SCHEMA: &str = include_str!("schema.json");
+
+/// A line of JSON output with the schema version.
+#[derive(Serialize)]
+struct Versioned<'a, T> {
+    schema: u32,
+    #[serde(flatten)]
+    record: &'a T
+}
+
+/// `record` as a line of JSON output.
+pub fn line<T: Serialize>(record: &T) -> String {
+    serde_json::to_string(&Versioned { schema: VERSION, record })
+        .unwrap_or_else(|e| format!("{{\"schema\":{},\"error\":\"{}\"}}", VERSION, e))
+}
+
+/// Which stage an event is about.
+#[derive(Serialize)]
+pub struct StageRef {
+    pub stage: String,
+    /// Position of the stage, starting at 1
+    pub index: usize,
+    /// Number of stages of the run
+    pub count: usize
+}
+
+impl StageRef {
+    pub fn of(timer: &Timer, index: usize) -> StageRef {
+        StageRef {
+            stage: timer.stages()[index].name.clone(),
+            index: index + 1,
+            count: timer.stages().len()
+        }
+    }
+}
+
+/// Something that happened to the timer, written by `--headless`.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Tick {
+        #[serde(flatten)]
+        stage: Option<StageRef>,
+        elapsed_s: u32,
+        remaining_s: u32
+    },
+    StageStart {
+        #[serde(flatten)]
+        stage: StageRef,
+        time_s: f64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        note: Option<String>,
+        /// Milliseconds from the stage starting to its hooks, sounds and
+        /// notifications being done, written by `--headless`
+        #[serde(skip_serializing_if = "Option::is_none")]
+        slack_ms: Option<u64>
+    },
+    StageEnd {
+        #[serde(flatten)]
+        stage: StageRef,
+        aborted: bool
+    },
+    Warning {
+        #[serde(flatten)]
+        stage: StageRef,
+        remaining_s: u32
+    },
+    /// The running stage reached one of its countdown cues
+    Countdown {
+        #[serde(flatten)]
+        stage: StageRef,
+        remaining_s: u32
+    },
+    Paused {
+        #[serde(flatten)]
+        stage: Option<StageRef>
+    },
+    Resumed {
+        #[serde(flatten)]
+        stage: Option<StageRef>
+    },
+    Finished {
+        aborted: bool
+    },
+    /// How the run went, after it finished
+    Session {
+        session: Session
+    },
+    /// The timer started on its first stage, written to the event log
+    Started {
+        /// Number of stages of the run
+        count: usize
+    },
+    /// The user changed the running stage, written to the event log before
+    /// the change
+    Adjusted {
+        #[serde(flatten)]
+        stage: StageRef,
+        adjustment: Adjustment,
+        /// Seconds the stage got longer or shorter by
+        #[serde(skip_serializing_if = "Option::is_none")]
+        by_s: Option<u32>
+    },
+    /// The user quit before all stages were done, written to the event log
+    Quit {
+        #[serde(flatten)]
+        stage: Option<StageRef>
+    },
+    /// How the timer is doing, the answer to `status` on the control socket
+    Status {
+        #[serde(flatten)]
+        stage: Option<StageRef>,
+        elapsed_s: u32,
+        remaining_s: u32,
+        paused: bool,
+        /// Whether the running stage waits to be finished
+        waiting: bool,
+        finished: bool
+    }
+}
+
+/// How the user changed the running stage.
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Adjustment {
+    Extended,
+    Shortened,
+    Restarted,
+    /// Went back to the stage before
+    Previous,
+    Skipped,
+    /// Jumped to another stage by clicking it
+    Jumped,
+    /// Gave up on the stage, or on the whole run
+    Aborted,
+    /// Finished a stage counting up, in overtime or waiting with ENTER
+    Finished
+}
+
+/// An entry of the event log written with `--log`: an event and when it
+/// happened.
+#[derive(Serialize)]
+pub struct LogEntry {
+    /// Local time of the event, in RFC 3339 format
+    pub time: String,
+    #[serde(flatten)]
+    pub event: Event
+}
+
+impl Event {
+    /// The status of `timer` right now.
+    pub fn status(timer: &Timer) -> Event {
+        Event::Status {
+            stage: timer.current_stage().map(|_| StageRef::of(timer, timer.current_index())),
+            elapsed_s: timer.current_stage().map_or(0, TimerStage::elapsed_s),
+            remaining_s: timer.current_stage().map_or(0, TimerStage::remaining_s),
+            paused: timer.is_paused(),
+            waiting: timer.is_waiting()
+                || timer.current_stage().is_some_and(TimerStage::awaits_finish),
+            finished: timer.is_finished()
+        }
+    }
+
+    /// `event` of `timer` as it is written out.
+    pub fn of(event: TimerEvent, timer: &Timer) -> Event {
+        let running = || timer.current_stage().map(|_| StageRef::of(timer, timer.current_index()));
+        match event {
+            TimerEvent::Tick => Event::Tick {
+                stage: running(),
+                elapsed_s: timer.current_stage().map_or(0, TimerStage::elapsed_s),
+                remaining_s: timer.current_stage().map_or(0, TimerStage::remaining_s)
+            },
+            TimerEvent::StageStart(index) => Event::StageStart {
+                stage: StageRef::of(timer, index),
+                time_s: timer.stages()[index].period.as_secs_f64(),
+                note: timer.stages()[index].note.clone(),
+                slack_ms: None
+            },
+            TimerEvent::StageEnd(index) => Event::StageEnd {
+                stage: StageRef::of(timer, index),
+                aborted: timer.stages()[index].aborted
+            },
+            TimerEvent::Warning(index) => Event::Warning {
+                stage: StageRef::of(timer, index),
+                remaining_s: timer.stages()[index].remaining_s()
+            },
+            TimerEvent::Countdown(index) => Event::Countdown {
+                stage: StageRef::of(timer, index),
+                remaining_s: timer.stages()[index].remaining_s()
+            },
+            TimerEvent::Paused => Event::Paused { stage: running() },
+            TimerEvent::Resumed => Event::Resumed { stage: running() },
+            TimerEvent::Finished => Event::Finished { aborted: timer.is_aborted() }
+        }
+    }
+}
+
+/// A run of the timer, as recorded in the history.
+#[derive(Serialize)]
+pub struct Session {
+    /// Position in the history, starting at 1, for runs read from it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<usize>,
+    /// Local time the run started at, in RFC 3339 format
+    pub started: String,
+    pub completed: bool,
+    pub aborted: bool,
+    pub roll: Option<String>,
+    pub preset: Option<String>,
+    pub batches: Vec<String>,
+    pub notes: Vec<String>,
+    pub environment: Option<Environment>,
+    /// Whether the pauses of the run took longer than its pause budget
+    pub over_pause_budget: bool,
+    pub stages: Vec<SessionStage>
+}
+
+/// How a run went as a whole, written by `--summary-json`.
+#[derive(Serialize)]
+pub struct Summary<'a> {
+    /// Planned duration of all stages
+    pub time_s: u32,
+    /// Wall clock time the run took, pauses included
+    pub actual_s: u32,
+    /// How often the timer was paused
+    pub pauses: u32,
+    /// Wall clock time the timer spent paused
+    pub paused_s: u32,
+    #[serde(flatten)]
+    pub session: &'a Session
+}
+
+/// How a stage of a run went.
+#[derive(Serialize)]
+pub struct SessionStage {
+    pub name: String,
+    /// Planned duration
+    pub time_s: u32,
+    /// Wall clock time the stage took, pauses included
+    pub actual_s: Option<u32>,
+    /// Whether the actual time was off the plan by more than the tolerance
+    pub deviated: bool,
+    pub aborted: bool,
+    pub environment: Option<Environment>
+}
+
+impl Session {
+    pub fn of(record: &SessionRecord, id: Option<usize>) -> Session {
+        Session {
+            id,
+            started: record.started.clone(),
+            completed: record.completed,
+            aborted: record.aborted,
+            roll: record.roll.clone(),
+            preset: record.preset.clone(),
+            batches: record.batches.clone(),
+            notes: record.notes.clone(),
+            environment: record.environment,
+            over_pause_budget: record.over_pause_budget,
+            stages: record.stages.iter().map(SessionStage::of).collect()
+        }
+    }
+}
+
+impl SessionStage {
+    fn of(record: &StageRecord) -> SessionStage {
+        SessionStage {
+            name: record.name.clone(),
+            time_s: record.period_s,
+            actual_s: record.actual_s,
+            deviated: record.deviated,
+            aborted: record.aborted,
+            environment: record.environment
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::{env, fs::{self, File, OpenOptions}, io::{Read, Write}, path::PathBuf};
+#[cfg(unix)]
+use std::{io, os::unix::fs::{DirBuilderExt, MetadataExt}};
+
+/// Holds the lock of a named session for as long as it is alive. The
+/// operating system releases the lock when the process ends, so a crashed
+/// timer never leaves a stale session behind.
+pub struct SessionLock {
+    _file: File
+}
+
+/// Directory for files that only live as long as a timer is running.
+/// Without `XDG_RUNTIME_DIR` it is one of the user's own in the shared
+/// temporary directory, see [`private_temp_dir`].
+pub fn runtime_dir() -> Result<PathBuf, String> {
+    match env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => Ok(PathBuf::from(dir).join("staged-timer")),
+        #[cfg(unix)]
+        None => private_temp_dir(),
+        #[cfg(not(unix))]
+        None => Ok(env::temp_dir().join("staged-timer"))
+    }
+}
+
+/// `staged-timer-$UID` in the temporary directory, created so that only
+/// the user can get in. Anybody can create files there, so one made by
+/// another user or that others can write to is refused: its sockets and
+/// state could be theirs.
+#[cfg(unix)]
+fn private_temp_dir() -> Result<PathBuf, String> {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    let uid = unsafe { libc::geteuid() };
+    let dir = env::temp_dir().join(format!("staged-timer-{}", uid));
+    match fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(format!(
+            "Could not create runtime directory {}: {}", dir.display(), e
+        )),
+        _ => {}
+    }
+    // a link somebody put there isn't followed
+    let metadata = fs::symlink_metadata(&dir).map_err(|e| format!(
+        "Could not read runtime directory {}: {}", dir.display(), e
+    ))?;
+    if !metadata.is_dir() || metadata.uid() != uid {
+        return Err(format!(
+            "The runtime directory {} belongs to another user, not using it", dir.display()
+        ));
+    }
+    if metadata.mode() & 0o022 != 0 {
+        return Err(format!(
+            "Other users can write to the runtime directory {}, not using it", dir.display()
+        ));
+    }
+    Ok(dir)
+}
+
+/// `name` as a file name, whatever the user typed.
+pub fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// Outcome of trying to claim a session name.
+pub enum SessionClaim {
+    Claimed(SessionLock),
+    /// Another instance runs a session with this name, with its PID if known
+    Taken(Option<u32>)
+}
+
+/// Try to claim the session `name` for this process.
+pub fn claim(name: &str) -> Result<SessionClaim, String> {
+    let dir = runtime_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| format!(
+        "Could not create runtime directory {}: {}", dir.display(), e
+    ))?;
+
+    let path = dir.join(format!("{}.lock", file_name(name)));
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .map_err(|e| format!("Could not open lock file {}: {}", path.display(), e))?;
+
+    if file.try_lock().is_err() {
+        let mut pid = String::new();
+        let _ = file.read_to_string(&mut pid);
+        return Ok(SessionClaim::Taken(pid.trim().parse().ok()));
+    }
+
+    file.set_len(0)
+        .and_then(|_| write!(file, "{}", std::process::id()))
+        .and_then(|_| file.flush())
+        .map_err(|e| format!("Could not write lock file {}: {}", path.display(), e))?;
+
+    Ok(SessionClaim::Claimed(SessionLock { _file: file }))
+}
@@ -0,0 +1,158 @@
+use std::{
+    io::{self, Write},
+    panic,
+    sync::{atomic::{AtomicBool, Ordering}, Once}
+};
+#[cfg(unix)]
+use std::sync::OnceLock;
+
+use crossterm::{
+    cursor,
+    event::EnableMouseCapture,
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen}
+};
+
+/// Stops mouse capture, leaves the alternate screen and shows the cursor,
+/// written as is so it is safe to send from a signal handler.
+#[cfg(unix)]
+const RESTORE: &[u8] = b"\x1b[?1006l\x1b[?1015l\x1b[?1003l\x1b[?1002l\x1b[?1000l\
+    \x1b[?1049l\x1b[?25h";
+
+/// Whether the terminal is set up for the TUI and has to be restored.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Whether the TUI is drawn on stderr rather than stdout.
+static ON_STDERR: AtomicBool = AtomicBool::new(false);
+/// Settings of the terminal on stdin from before raw mode.
+#[cfg(unix)]
+static SAVED: OnceLock<libc::termios> = OnceLock::new();
+static HOOKS: Once = Once::new();
+
+/// The stream the TUI is drawn on.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Screen {
+    Stdout,
+    Stderr
+}
+
+impl Screen {
+    /// A new handle to the stream, e.g. to ring the bell on.
+    pub fn writer(self) -> Box<dyn Write + Send> {
+        match self {
+            Screen::Stdout => Box::new(io::stdout()),
+            Screen::Stderr => Box::new(io::stderr())
+        }
+    }
+
+    /// The screen the guard was last entered with.
+    fn current() -> Screen {
+        if ON_STDERR.load(Ordering::SeqCst) { Screen::Stderr } else { Screen::Stdout }
+    }
+}
+
+/// Sets the terminal up for the TUI: raw mode, the alternate screen, mouse
+/// capture and a hidden cursor. All of it is undone when the guard is
+/// dropped, when the program panics and when it is ended by a signal, so the
+/// terminal is never left unusable.
+pub struct TerminalGuard {
+    _private: ()
+}
+
+impl TerminalGuard {
+    /// Set up the terminal that `out`, a handle to `screen`, writes to.
+    pub fn enter(out: &mut impl Write, screen: Screen) -> io::Result<TerminalGuard> {
+        #[cfg(unix)]
+        {
+            let mut saved = std::mem::MaybeUninit::<libc::termios>::uninit();
+            // SAFETY: tcgetattr only writes to `saved`, which is only read if
+            // it succeeded
+            if unsafe { libc::tcgetattr(libc::STDIN_FILENO, saved.as_mut_ptr()) } == 0 {
+                let _ = SAVED.set(unsafe { saved.assume_init() });
+            }
+        }
+        ON_STDERR.store(screen == Screen::Stderr, Ordering::SeqCst);
+        install_hooks();
+
+        enable_raw_mode()?;
+        ACTIVE.store(true, Ordering::SeqCst);
+        let guard = TerminalGuard { _private: () };
+        execute!(out, EnterAlternateScreen, EnableMouseCapture, cursor::Hide)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+        // stdin may not be the terminal crossterm put into raw mode
+        let _ = disable_raw_mode();
+    }
+}
+
+/// Undo the terminal setup unless it already was. Only calls functions that
+/// are safe to call from a signal handler.
+#[cfg(unix)]
+fn restore() {
+    if !ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    // SAFETY: tcsetattr and write are async-signal-safe and only read from
+    // the buffers passed to them
+    unsafe {
+        if let Some(saved) = SAVED.get() {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+        }
+        let fd = match Screen::current() {
+            Screen::Stdout => libc::STDOUT_FILENO,
+            Screen::Stderr => libc::STDERR_FILENO
+        };
+        libc::write(fd, RESTORE.as_ptr().cast(), RESTORE.len());
+    }
+}
+
+/// Undo the terminal setup unless it already was, through crossterm where
+/// there are no signals to restore it from.
+#[cfg(not(unix))]
+fn restore() {
+    use crossterm::{event::DisableMouseCapture, terminal::LeaveAlternateScreen};
+
+    if !ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    let _ = disable_raw_mode();
+    let _ = execute!(
+        Screen::current().writer(), DisableMouseCapture, LeaveAlternateScreen, cursor::Show
+    );
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    restore();
+    // end the program the way the signal would have
+    // SAFETY: signal and raise are async-signal-safe
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
+/// Restore the terminal before a panic message is printed and, on Unix, when
+/// the program is ended by a signal.
+fn install_hooks() {
+    HOOKS.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore();
+            default_hook(info);
+        }));
+
+        #[cfg(unix)]
+        for signal in [libc::SIGTERM, libc::SIGHUP, libc::SIGINT, libc::SIGQUIT] {
+            // SAFETY: the handler only calls async-signal-safe functions
+            unsafe {
+                let handler: extern "C" fn(libc::c_int) = on_signal;
+                libc::signal(signal, handler as libc::sighandler_t);
+            }
+        }
+    });
+}
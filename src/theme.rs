@@ -0,0 +1,292 @@
+use std::{env, io};
+
+use serde::{Deserialize, Serialize};
+use tui::{backend::Backend, buffer::Cell, layout::Rect, style::Color};
+
+/// Names of the built-in themes, for `--theme` and the settings.
+pub const NAMES: [&str; 3] = ["dark", "light", "high-contrast"];
+
+/// Colours the timer is drawn in, as `[r, g, b]`.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Theme {
+    /// The running stage and text
+    pub active: [u8; 3],
+    /// Stages that aren't running
+    pub inactive: [u8; 3],
+    /// Stages within their warning threshold and messages
+    pub warning: [u8; 3],
+    /// The terminal's own background if not set
+    pub background: Option<[u8; 3]>,
+    /// The running stage in its last seconds
+    #[serde(default = "default_critical")]
+    pub critical: [u8; 3],
+    /// Seconds before its end from which the running stage's gauge blinks,
+    /// 0 for never
+    #[serde(default = "default_blink_s")]
+    pub blink_s: u32,
+    /// Seconds before its end from which it is drawn in `critical`, 0 for
+    /// never
+    #[serde(default = "default_critical_s")]
+    pub critical_s: u32,
+    /// The running stage with all, half and none of its time left, which
+    /// it goes from one to the next of with `--gradient`
+    #[serde(default = "default_gradient")]
+    pub gradient: [[u8; 3]; 3]
+}
+
+fn default_critical() -> [u8; 3] {
+    [0xff, 0x30, 0x30]
+}
+
+fn default_blink_s() -> u32 {
+    10
+}
+
+fn default_critical_s() -> u32 {
+    5
+}
+
+fn default_gradient() -> [[u8; 3]; 3] {
+    [[0x40, 0xc0, 0x40], [0xff, 0xe5, 0x00], default_critical()]
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            active: [0xff, 0xff, 0xff],
+            inactive: [42, 42, 42],
+            warning: [0xff, 0xe5, 0x00],
+            background: None,
+            critical: default_critical(),
+            blink_s: default_blink_s(),
+            critical_s: default_critical_s(),
+            gradient: default_gradient()
+        }
+    }
+}
+
+impl Theme {
+    /// The built-in theme called `name`.
+    pub fn named(name: &str) -> Option<Theme> {
+        match name {
+            "dark" => Some(Theme::default()),
+            "light" => Some(Theme {
+                active: [0x20, 0x20, 0x20],
+                inactive: [0xd0, 0xd0, 0xd0],
+                warning: [0xc0, 0x60, 0x00],
+                background: Some([0xfa, 0xfa, 0xfa]),
+                critical: [0xc0, 0x00, 0x00],
+                gradient: [[0x20, 0x90, 0x20], [0xc0, 0x90, 0x00], [0xc0, 0x00, 0x00]],
+                ..Theme::default()
+            }),
+            "high-contrast" => Some(Theme {
+                active: [0xff, 0xff, 0xff],
+                inactive: [0x70, 0x70, 0x70],
+                warning: [0xff, 0xff, 0x00],
+                background: Some([0x00, 0x00, 0x00]),
+                critical: [0xff, 0x00, 0x00],
+                gradient: [[0x00, 0xff, 0x00], [0xff, 0xff, 0x00], [0xff, 0x00, 0x00]],
+                ..Theme::default()
+            }),
+            _ => None
+        }
+    }
+
+    pub fn active(&self) -> Color {
+        rgb(self.active)
+    }
+
+    pub fn inactive(&self) -> Color {
+        rgb(self.inactive)
+    }
+
+    pub fn warning(&self) -> Color {
+        rgb(self.warning)
+    }
+
+    pub fn critical(&self) -> Color {
+        rgb(self.critical)
+    }
+
+    /// Halfway between active and inactive, e.g. for marks that were passed.
+    pub fn muted(&self) -> Color {
+        rgb(mix(self.active, self.inactive))
+    }
+
+    /// Between muted and inactive.
+    pub fn faint(&self) -> Color {
+        rgb(mix(mix(self.active, self.inactive), self.inactive))
+    }
+
+    /// The colour of the gradient with `left` of the time left, from 1 down
+    /// to 0. Drawn in fewer colours than `palette` has, the nearest of them
+    /// would make a mess of the colours in between, so it goes from one
+    /// colour of the gradient to the next at once instead.
+    pub fn gradient(&self, left: f64, palette: Palette) -> Color {
+        let left = left.clamp(0.0, 1.0);
+        if palette != Palette::Rgb {
+            return rgb(self.gradient[((1.0 - left) * 2.0).round() as usize]);
+        }
+        // between the first two colours in the first half, the last two in
+        // the second
+        let (from, to, t) = if left >= 0.5 {
+            (self.gradient[0], self.gradient[1], (1.0 - left) * 2.0)
+        } else {
+            (self.gradient[1], self.gradient[2], (0.5 - left) * 2.0)
+        };
+        let channel = |i: usize| {
+            (f64::from(from[i]) + (f64::from(to[i]) - f64::from(from[i])) * t).round() as u8
+        };
+        rgb([channel(0), channel(1), channel(2)])
+    }
+
+    /// `color` toned down towards the inactive colour, for parts of a stage
+    /// that are still to come.
+    pub fn dimmed(&self, color: [u8; 3]) -> Color {
+        rgb(mix(color, self.inactive))
+    }
+}
+
+/// Black or white, whichever stands out from `color`, for text drawn on it.
+pub fn text_on(color: Color) -> Color {
+    match color {
+        Color::Rgb(r, g, b) if u32::from(r) * 299 + u32::from(g) * 587 + u32::from(b) * 114 < 128_000 => {
+            Color::White
+        },
+        _ => Color::Black
+    }
+}
+
+pub fn rgb([r, g, b]: [u8; 3]) -> Color {
+    Color::Rgb(r, g, b)
+}
+
+/// Which colours the terminal is drawn in.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Palette {
+    /// Colours as they are
+    #[default]
+    Rgb,
+    /// The nearest of the 16 colours every terminal has
+    Ansi16,
+    /// None at all, only the terminal's own
+    None
+}
+
+/// The 16 ANSI colours with their usual values.
+const ANSI_COLORS: [(Color, [u8; 3]); 16] = [
+    (Color::Black, [0, 0, 0]),
+    (Color::Red, [205, 0, 0]),
+    (Color::Green, [0, 205, 0]),
+    (Color::Yellow, [205, 205, 0]),
+    (Color::Blue, [0, 0, 238]),
+    (Color::Magenta, [205, 0, 205]),
+    (Color::Cyan, [0, 205, 205]),
+    (Color::Gray, [229, 229, 229]),
+    (Color::DarkGray, [127, 127, 127]),
+    (Color::LightRed, [255, 0, 0]),
+    (Color::LightGreen, [0, 255, 0]),
+    (Color::LightYellow, [255, 255, 0]),
+    (Color::LightBlue, [92, 92, 255]),
+    (Color::LightMagenta, [255, 0, 255]),
+    (Color::LightCyan, [0, 255, 255]),
+    (Color::White, [255, 255, 255])
+];
+
+impl Palette {
+    /// The colours the terminal says it can show: none if `NO_COLOR` is
+    /// set, all of them if `COLORTERM` says so and the 16 ANSI colours
+    /// otherwise.
+    pub fn detect() -> Palette {
+        if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return Palette::None;
+        }
+        match env::var("COLORTERM").as_deref() {
+            Ok("truecolor" | "24bit") => Palette::Rgb,
+            _ => Palette::Ansi16
+        }
+    }
+
+    /// `color` as it is drawn in this palette.
+    pub fn apply(self, color: Color) -> Color {
+        match (self, color) {
+            (Palette::None, _) => Color::Reset,
+            (Palette::Ansi16, Color::Rgb(r, g, b)) => {
+                let distance = |[cr, cg, cb]: [u8; 3]| {
+                    [(r, cr), (g, cg), (b, cb)].into_iter()
+                        .map(|(a, b)| (i32::from(a) - i32::from(b)).pow(2))
+                        .sum::<i32>()
+                };
+                ANSI_COLORS.iter()
+                    .min_by_key(|(_, value)| distance(*value))
+                    .map_or(color, |(ansi, _)| *ansi)
+            },
+            _ => color
+        }
+    }
+}
+
+/// Draws to another backend in the colours of a [`Palette`].
+pub struct PaletteBackend<B: Backend> {
+    backend: B,
+    palette: Palette
+}
+
+impl<B: Backend> PaletteBackend<B> {
+    pub fn new(backend: B, palette: Palette) -> PaletteBackend<B> {
+        PaletteBackend { backend, palette }
+    }
+}
+
+impl<B: Backend> Backend for PaletteBackend<B> {
+    fn draw<'a, I>(&mut self, content: I) -> io::Result<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>
+    {
+        if self.palette == Palette::Rgb {
+            return self.backend.draw(content);
+        }
+        let cells: Vec<(u16, u16, Cell)> = content
+            .map(|(x, y, cell)| {
+                let mut cell = cell.clone();
+                cell.fg = self.palette.apply(cell.fg);
+                cell.bg = self.palette.apply(cell.bg);
+                (x, y, cell)
+            })
+            .collect();
+        self.backend.draw(cells.iter().map(|(x, y, cell)| (*x, *y, cell)))
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        self.backend.hide_cursor()
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        self.backend.show_cursor()
+    }
+
+    fn get_cursor(&mut self) -> io::Result<(u16, u16)> {
+        self.backend.get_cursor()
+    }
+
+    fn set_cursor(&mut self, x: u16, y: u16) -> io::Result<()> {
+        self.backend.set_cursor(x, y)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.backend.clear()
+    }
+
+    fn size(&self) -> io::Result<Rect> {
+        self.backend.size()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.backend.flush()
+    }
+}
+
+fn mix(a: [u8; 3], b: [u8; 3]) -> [u8; 3] {
+    let channel = |i: usize| ((u16::from(a[i]) + u16::from(b[i])) / 2) as u8;
+    [channel(0), channel(1), channel(2)]
+}
@@ -0,0 +1,435 @@
+use std::{env, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+/// Languages that write decimals with a comma, as in `7,5`.
+const COMMA_LANGUAGES: &[&str] = &[
+    "be", "bg", "ca", "cs", "da", "de", "el", "es", "et", "eu", "fi", "fr",
+    "gl", "hr", "hu", "id", "is", "it", "lt", "lv", "nb", "nl", "nn", "no",
+    "pl", "pt", "ro", "ru", "sk", "sl", "sr", "sv", "tr", "uk", "vi"
+];
+
+/// Unit suffixes in the languages most users write them in, lowercase.
+const UNITS: &[(u32, &[&str])] = &[
+    (1, &[
+        "s", "sec", "secs", "second", "seconds", "sek", "sekunde", "sekunden",
+        "seg", "segundo", "segundos", "seconde", "secondes", "secondi",
+        "secondo", "seconden", "sekund", "sekunder", "sekuntia"
+    ]),
+    (60, &[
+        "m", "min", "mins", "minute", "minutes", "minuten", "minuto",
+        "minuti", "minutos", "minuut", "minut", "minuter", "minutter",
+        "minuuttia"
+    ]),
+    (60 * 60, &[
+        "h", "hr", "hrs", "hour", "hours", "std", "stunde", "stunden",
+        "heure", "heures", "ora", "ore", "hora", "horas", "uur", "timer",
+        "timme", "timmar", "tuntia"
+    ])
+];
+
+/// The character separating the integer and fractional part of a number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecimalSeparator {
+    Point,
+    /// `7,5`; points are accepted as well
+    Comma
+}
+
+impl DecimalSeparator {
+    /// Detect the separator from the numeric locale in `LC_ALL`,
+    /// `LC_NUMERIC` or `LANG`.
+    pub fn from_env() -> DecimalSeparator {
+        let locale = ["LC_ALL", "LC_NUMERIC", "LANG"].iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        let language = locale.split(['_', '.', '@', '-']).next().unwrap_or("");
+        if COMMA_LANGUAGES.contains(&language.to_lowercase().as_str()) {
+            DecimalSeparator::Comma
+        } else {
+            DecimalSeparator::Point
+        }
+    }
+
+    fn is_separator(self, c: char) -> bool {
+        c == '.' || (self == DecimalSeparator::Comma && c == ',')
+    }
+}
+
+/// Parse a duration given in seconds, as `[[hrs:]min:]sec` or with unit
+/// suffixes like `7.5min`, `1h 30m` or `2m30`, where a number without a unit
+/// at the end is of the next smaller unit. Seconds may have a fraction, as in
+/// `0:30.5`. The decimal separator and localized units such as `7,5 Min` are
+/// accepted according to the locale.
+pub fn parse_duration(time_str: &str) -> Result<Duration, String> {
+    parse_duration_with(time_str, DecimalSeparator::from_env())
+}
+
+/// Parse a duration like [`parse_duration`] with the given decimal
+/// separator. Errors point out the position in `time_str` where parsing
+/// failed.
+pub fn parse_duration_with(
+    time_str: &str,
+    separator: DecimalSeparator
+) -> Result<Duration, String>
+{
+    if time_str.trim().is_empty() {
+        return Err(format!("Could not parse time string \"{}\": the time is empty", time_str));
+    }
+    let mut parser = TimeParser {
+        chars: time_str.chars().collect(),
+        position: 0,
+        separator
+    };
+    parser.duration().map_err(|message| format!(
+        "Could not parse time string \"{}\": {}", time_str, message
+    ))
+}
+
+/// Parse a duration like [`parse_duration`] into whole seconds.
+pub fn parse_time(time_str: &str) -> Result<u32, String> {
+    parse_time_with(time_str, DecimalSeparator::from_env())
+}
+
+/// Parse a duration like [`parse_time`] with the given decimal separator.
+pub fn parse_time_with(
+    time_str: &str,
+    separator: DecimalSeparator
+) -> Result<u32, String>
+{
+    let duration = parse_duration_with(time_str, separator)?;
+    if duration.subsec_nanos() != 0 {
+        return Err(format!(
+            "Could not parse time string \"{}\": expected whole seconds",
+            time_str
+        ));
+    }
+    Ok(duration.as_secs() as u32)
+}
+
+/// A number as written in a duration.
+struct Number {
+    whole: u64,
+    /// Fractional part and the position of its decimal separator
+    fraction: Option<(f64, usize)>
+}
+
+/// Recursive descent parser for the grammar
+///
+/// ```text
+/// duration = number [ ":" digits [ ":" digits ] ]
+///          | number unit { number unit } [ number ]
+/// number   = digits [ separator digits ]
+/// ```
+///
+/// where whitespace may only appear around units and units go from hours
+/// down to seconds, each at most once.
+struct TimeParser {
+    chars: Vec<char>,
+    position: usize,
+    separator: DecimalSeparator
+}
+
+impl TimeParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    /// Describe the character at the current position as unexpected.
+    fn unexpected(&self, expected: &str) -> String {
+        match self.peek() {
+            Some(',') if self.separator == DecimalSeparator::Point => format!(
+                "unexpected ',' at position {}, the locale uses decimal points",
+                self.position + 1
+            ),
+            Some(c) => format!(
+                "unexpected '{}' at position {}, expected {}",
+                c, self.position + 1, expected
+            ),
+            None => format!(
+                "unexpected end at position {}, expected {}",
+                self.position + 1, expected
+            )
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn digits(&mut self) -> Result<u64, String> {
+        let start = self.position;
+        let mut value: u64 = 0;
+        while let Some(digit) = self.peek().and_then(|c| c.to_digit(10)) {
+            value = value.checked_mul(10)
+                .and_then(|value| value.checked_add(u64::from(digit)))
+                .ok_or_else(|| format!("number at position {} is too large", start + 1))?;
+            self.position += 1;
+        }
+        if self.position == start {
+            return Err(self.unexpected("digits"));
+        }
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Number, String> {
+        let whole = self.digits()?;
+        let separator_position = self.position;
+        let fraction = match self.peek() {
+            Some(c) if self.separator.is_separator(c) => {
+                self.position += 1;
+                let start = self.position;
+                self.digits()?;
+                let digits: String = self.chars[start..self.position].iter().collect();
+                let fraction: f64 = format!("0.{}", digits).parse()
+                    .map_err(|_| self.unexpected("digits"))?;
+                Some((fraction, separator_position))
+            },
+            _ => None
+        };
+        Ok(Number { whole, fraction })
+    }
+
+    fn duration(&mut self) -> Result<Duration, String> {
+        let first = self.number()?;
+        match self.peek() {
+            None => seconds(first.whole as f64 + first.fraction()),
+            Some(':') => match first.fraction {
+                Some((_, position)) => {
+                    self.position = position;
+                    Err(self.unexpected("digits or ':'"))
+                },
+                None => self.clock(first.whole)
+            },
+            Some(c) if c.is_alphabetic() || c.is_whitespace() => self.with_units(first),
+            Some(_) => Err(self.unexpected("digits, ':' or a unit"))
+        }
+    }
+
+    /// The rest of a `[[hrs:]min:]sec` duration after its first segment.
+    /// Only the seconds may have a fraction, even one of zero.
+    fn clock(&mut self, first: u64) -> Result<Duration, String> {
+        let mut total = first;
+        let mut fraction = None;
+        let mut segments = 1;
+        while self.peek() == Some(':') {
+            if let Some((_, position)) = fraction {
+                self.position = position;
+                return Err(self.unexpected("digits or ':'"));
+            }
+            if segments == 3 {
+                return Err(self.unexpected("at most hrs:min:sec"));
+            }
+            self.position += 1;
+            let start = self.position;
+            let segment = self.number()?;
+            if segment.whole >= 60 {
+                return Err(format!(
+                    "{} at position {} is out of range, expected 0 to 59",
+                    segment.whole, start + 1
+                ));
+            }
+            total = total.checked_mul(60)
+                .and_then(|total| total.checked_add(segment.whole))
+                .ok_or_else(|| String::from("the time is too long"))?;
+            fraction = segment.fraction;
+            segments += 1;
+        }
+        if self.peek().is_some() {
+            return Err(self.unexpected("digits or ':'"));
+        }
+        seconds(total as f64 + fraction.map_or(0.0, |(fraction, _)| fraction))
+    }
+
+    /// A sequence of numbers followed by units, e.g. `1h 30m`. A last number
+    /// without a unit is of the unit after the one before it, as in `2m30`.
+    fn with_units(&mut self, first: Number) -> Result<Duration, String> {
+        let mut total = 0.0;
+        let mut number = first;
+        // index into UNITS of the unit before
+        let mut previous: Option<usize> = None;
+        loop {
+            self.skip_whitespace();
+            let start = self.position;
+            while self.peek().is_some_and(char::is_alphabetic) {
+                self.position += 1;
+            }
+            let unit = if self.position == start {
+                match previous {
+                    Some(previous) if previous > 0 && self.peek().is_none() => previous - 1,
+                    _ => return Err(self.unexpected("a unit like s, min or h"))
+                }
+            } else {
+                let name: String = self.chars[start..self.position].iter().collect();
+                let unit = UNITS.iter()
+                    .position(|(_, names)| names.contains(&name.to_lowercase().as_str()))
+                    .ok_or_else(|| format!(
+                        "unknown unit \"{}\" at position {}, expected e.g. s, min or h",
+                        name, start + 1
+                    ))?;
+                if previous.is_some_and(|previous| unit >= previous) {
+                    return Err(format!(
+                        "unit \"{}\" at position {} is out of order, expected hours, \
+                        minutes and seconds in that order and each at most once",
+                        name, start + 1
+                    ));
+                }
+                unit
+            };
+            total += (number.whole as f64 + number.fraction()) * f64::from(UNITS[unit].0);
+            previous = Some(unit);
+
+            self.skip_whitespace();
+            if self.peek().is_none() {
+                break;
+            }
+            number = self.number()?;
+        }
+        seconds(total)
+    }
+}
+
+impl Number {
+    fn fraction(&self) -> f64 {
+        self.fraction.map_or(0.0, |(fraction, _)| fraction)
+    }
+}
+
+/// A duration of `total` seconds, to the millisecond.
+fn seconds(total: f64) -> Result<Duration, String> {
+    if total > f64::from(u32::MAX) {
+        return Err(String::from("the time is too long"));
+    }
+    Ok(Duration::from_millis((total * 1000.0).round() as u64))
+}
+
+/// Format a duration as `hh:mm:ss`.
+pub fn format_seconds(seconds: u32) -> String {
+    let hrs = seconds / (60 * 60);
+    let min = (seconds % (60 * 60)) / 60;
+    let sec = seconds % 60;
+    format!("{:#02}:{:#02}:{:#02}", hrs, min, sec)
+}
+
+/// How durations are written on screen.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeFormat {
+    /// Adapted to the length of the stage: `45s`, `9:30` or `1:02:00`
+    Smart,
+    /// Always `hh:mm:ss`
+    Fixed
+}
+
+impl TimeFormat {
+    /// Format `seconds` as part of a stage lasting `magnitude_s` seconds, so
+    /// the remaining and total time of a stage use the same format.
+    pub fn format(self, seconds: u32, magnitude_s: u32) -> String {
+        match self {
+            TimeFormat::Fixed => format_seconds(seconds),
+            TimeFormat::Smart if magnitude_s < 60 => format!("{}s", seconds),
+            TimeFormat::Smart if magnitude_s < 60 * 60 => {
+                format!("{}:{:02}", seconds / 60, seconds % 60)
+            },
+            TimeFormat::Smart => format!(
+                "{}:{:02}:{:02}",
+                seconds / (60 * 60),
+                (seconds % (60 * 60)) / 60,
+                seconds % 60
+            )
+        }
+    }
+
+    /// Format `duration` like [`TimeFormat::format`] with tenths of a second,
+    /// e.g. `15.5s` or `9:30.2`. Rounds up, so a countdown shows zero only at
+    /// its end.
+    pub fn format_tenths(self, duration: Duration, magnitude_s: u32) -> String {
+        let tenths = duration.as_nanos().div_ceil(100_000_000);
+        let seconds = u32::try_from(tenths / 10).unwrap_or(u32::MAX);
+        let tenth = tenths % 10;
+        match self {
+            TimeFormat::Smart if magnitude_s < 60 => format!("{}.{}s", seconds, tenth),
+            _ => format!("{}.{}", self.format(seconds, magnitude_s), tenth)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(time_str: &str) -> Result<Duration, String> {
+        parse_duration_with(time_str, DecimalSeparator::Point)
+    }
+
+    #[test]
+    fn trailing_number_is_of_the_next_smaller_unit() {
+        assert_eq!(parse("2m30"), Ok(Duration::from_secs(150)));
+        assert_eq!(parse("1h 5"), Ok(Duration::from_secs(60 * 60 + 5 * 60)));
+        assert!(parse("30s5").is_err());
+    }
+
+    #[test]
+    fn clock_segments_are_in_range() {
+        assert_eq!(parse("1:30:00"), Ok(Duration::from_secs(90 * 60)));
+        let error = parse("0:99").unwrap_err();
+        assert!(error.contains("99 at position 3 is out of range"), "{}", error);
+        let error = parse("1:99:00").unwrap_err();
+        assert!(error.contains("99 at position 3 is out of range"), "{}", error);
+    }
+
+    #[test]
+    fn clock_needs_a_number_before_each_colon() {
+        let error = parse("::5").unwrap_err();
+        assert!(error.contains("unexpected ':' at position 1"), "{}", error);
+    }
+
+    #[test]
+    fn units_go_from_hours_to_seconds() {
+        let error = parse("30m2h").unwrap_err();
+        assert!(error.contains("unit \"h\" at position 5 is out of order"), "{}", error);
+        assert!(parse("1m 1m").is_err());
+    }
+
+    #[test]
+    fn only_the_seconds_have_a_fraction() {
+        let error = parse("1:30.0:00").unwrap_err();
+        assert!(error.contains("unexpected '.' at position 5"), "{}", error);
+        let error = parse("1:30.5:00").unwrap_err();
+        assert!(error.contains("unexpected '.' at position 5"), "{}", error);
+        let error = parse("1.0:30").unwrap_err();
+        assert!(error.contains("unexpected '.' at position 2"), "{}", error);
+        assert_eq!(parse("1:30:00.0"), Ok(Duration::from_secs(90 * 60)));
+    }
+
+    #[test]
+    fn clock_and_units_do_not_mix() {
+        let error = parse("1:2m").unwrap_err();
+        assert!(error.contains("unexpected 'm' at position 4"), "{}", error);
+    }
+
+    #[test]
+    fn overflow_is_rejected() {
+        let error = parse("99999999999999999999").unwrap_err();
+        assert!(error.contains("too large"), "{}", error);
+        let error = parse("4294967296").unwrap_err();
+        assert!(error.contains("too long"), "{}", error);
+        assert!(parse("1193047h").is_err());
+        assert_eq!(parse("4294967295"), Ok(Duration::from_secs(u64::from(u32::MAX))));
+    }
+
+    #[test]
+    fn fractions_follow_the_separator() {
+        assert_eq!(parse("0:30.5"), Ok(Duration::from_millis(30_500)));
+        assert!(parse("7,5min").is_err());
+        assert_eq!(
+            parse_duration_with("7,5min", DecimalSeparator::Comma),
+            Ok(Duration::from_secs(450))
+        );
+        assert_eq!(parse_time_with("7.5min", DecimalSeparator::Point), Ok(450));
+        assert!(parse_time_with("0:30.5", DecimalSeparator::Point).is_err());
+    }
+}
@@ -0,0 +1,978 @@
+use std::{ops::Range, path::PathBuf, time::{Duration, SystemTime}};
+
+use serde::{Deserialize, Serialize};
+
+/// A named point within a stage, `at_s` seconds after the stage started,
+/// or `percent` of the way through it so that it moves along when the stage
+/// gets longer or shorter.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub at_s: u32,
+    /// Where the checkpoint is in percent of the stage, in place of `at_s`
+    #[serde(default)]
+    pub percent: Option<u8>,
+    pub message: String
+}
+
+impl Checkpoint {
+    /// Seconds into a stage lasting `period` at which the checkpoint is.
+    pub fn position_s(&self, period: Duration) -> u32 {
+        match self.percent {
+            Some(percent) => (period.as_secs_f64() * f64::from(percent) / 100.0).round() as u32,
+            None => self.at_s
+        }
+    }
+}
+
+/// When to agitate during a stage, e.g. a developing tank: for the first
+/// `initial_s` seconds, then for `for_s` seconds every `every_s` seconds
+/// counted from the start of the stage.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Agitation {
+    pub initial_s: u32,
+    /// Zero for no agitation after the initial one
+    pub every_s: u32,
+    pub for_s: u32
+}
+
+impl Agitation {
+    /// Whether the stage is to be agitated `elapsed_s` seconds after it
+    /// started.
+    pub fn is_due(&self, elapsed_s: u32) -> bool {
+        elapsed_s < self.initial_s
+        || self.every_s > 0 && elapsed_s >= self.every_s && elapsed_s % self.every_s < self.for_s
+    }
+
+    /// Seconds into a stage of `period_s` at which the agitations after the
+    /// initial one start.
+    pub fn starts(&self, period_s: u32) -> impl Iterator<Item = u32> {
+        let every_s = self.every_s;
+        (1..)
+            .map_while(move |i: u32| i.checked_mul(every_s).filter(|_| every_s > 0))
+            .take_while(move |start_s| *start_s < period_s)
+    }
+}
+
+/// A point of a stage at which the time left is announced, counted back
+/// from the end of the stage.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CountdownCue {
+    /// When this many seconds are left
+    Remaining(u32),
+    /// When this percentage of the stage is left
+    Percent(u8)
+}
+
+impl CountdownCue {
+    /// Seconds left of a stage of `period_s` at which the cue is due, `None`
+    /// if that isn't within the stage.
+    pub fn remaining_s(&self, period_s: u32) -> Option<u32> {
+        let remaining_s = match *self {
+            CountdownCue::Remaining(remaining_s) => remaining_s,
+            CountdownCue::Percent(percent) => {
+                (u64::from(period_s) * u64::from(percent) / 100) as u32
+            }
+        };
+        (remaining_s > 0 && remaining_s < period_s).then_some(remaining_s)
+    }
+}
+
+/// A short part at the end of a stage with a name of its own, e.g. 5s to
+/// switch sides after a 30s hold. It is part of the stage's `period`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Phase {
+    pub name: String,
+    pub period: Duration
+}
+
+/// An entry of an exercise library, shown next to the gauges while a stage
+/// referring to it is running.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Exercise {
+    pub name: String,
+    pub instructions: String,
+    pub diagram: Option<String>,
+    pub link: Option<String>
+}
+
+/// A single stage of a timer. Stages with a `period` of zero are markers
+/// that are passed over without running, unless they count up.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TimerStage {
+    pub name: String,
+    pub period: Duration,
+    /// Time the stage has run for
+    pub elapsed: Duration,
+    /// Training intensity zone from 1 to 5
+    pub zone: Option<u8>,
+    /// Whether to chime after every full minute
+    pub minute_chime: bool,
+    pub checkpoints: Vec<Checkpoint>,
+    /// When to announce the time left
+    #[serde(default)]
+    pub countdown: Vec<CountdownCue>,
+    #[serde(default)]
+    pub agitation: Option<Agitation>,
+    /// Part at the end of the stage, after the active one
+    #[serde(default)]
+    pub after: Option<Phase>,
+    pub exercise: Option<Exercise>,
+    /// Free text shown while the stage runs, e.g. a dilution or a form cue
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Section the stage belongs to, e.g. "Washing". Stages next to each
+    /// other with the same group are shown under one header and can be
+    /// skipped together.
+    #[serde(default)]
+    pub group: Option<String>,
+    /// Played when the stage starts
+    pub audio: Option<PathBuf>,
+    /// Breathing or pacing cycles per minute to guide through
+    pub pace_cpm: Option<f64>,
+    /// Ends early or goes on longer depending on the heart rate
+    pub rest: bool,
+    /// Remaining time from which the stage is highlighted, overriding the
+    /// threshold of the whole timer
+    pub warn_s: Option<u32>,
+    /// Colour of the stage as RGB
+    pub color: Option<[u8; 3]>,
+    /// Shell command for the frontend to run when the stage starts
+    pub on_start: Option<String>,
+    /// Shell command for the frontend to run when the stage ends
+    pub on_end: Option<String>,
+    /// Whether the stage's clock keeps running while the timer is paused,
+    /// e.g. because the chemistry doesn't stop either
+    pub runs_while_paused: bool,
+    /// Whether the stage has no set duration and counts up until the user
+    /// finishes it, which sets its `period` to the time it took
+    #[serde(default)]
+    pub count_up: bool,
+    /// Whether the stage keeps running past its end, counting the time over,
+    /// until the user finishes it instead of moving on by itself
+    #[serde(default)]
+    pub overtime: bool,
+    /// Whether the timer waits at the end of the stage to be told to start
+    /// the next one
+    #[serde(default)]
+    pub manual_advance: bool,
+    /// Wall clock time the stage ends at, e.g. a time of day counted down
+    /// to, see [`Timer::follow_deadline`]
+    #[serde(default)]
+    pub ends_at: Option<SystemTime>,
+    /// How long the timer was paused while the stage was running
+    #[serde(default)]
+    pub paused: Duration,
+    /// Whether the stage was given up on because something went wrong
+    pub aborted: bool
+}
+
+impl TimerStage {
+    pub fn new(name: String, period: Duration) -> TimerStage {
+        TimerStage {
+            name,
+            period,
+            elapsed: Duration::ZERO,
+            zone: None,
+            minute_chime: false,
+            checkpoints: Vec::new(),
+            countdown: Vec::new(),
+            agitation: None,
+            after: None,
+            exercise: None,
+            note: None,
+            group: None,
+            audio: None,
+            pace_cpm: None,
+            rest: false,
+            warn_s: None,
+            color: None,
+            on_start: None,
+            on_end: None,
+            runs_while_paused: false,
+            count_up: false,
+            overtime: false,
+            manual_advance: false,
+            ends_at: None,
+            paused: Duration::ZERO,
+            aborted: false
+        }
+    }
+
+    /// Whether the stage is passed over without running.
+    pub fn is_marker(&self) -> bool {
+        self.period.is_zero() && !self.count_up
+    }
+
+    /// Whether the stage counts up and hasn't been finished yet.
+    pub fn is_open(&self) -> bool {
+        self.period.is_zero() && self.count_up
+    }
+
+    /// Whether the stage has run past its end and waits to be finished.
+    pub fn is_overtime(&self) -> bool {
+        self.overtime && !self.count_up && !self.period.is_zero() && self.elapsed >= self.period
+    }
+
+    /// Whether the stage only moves on once the user finishes it, because it
+    /// counts up or is in overtime.
+    pub fn awaits_finish(&self) -> bool {
+        self.is_open() || self.is_overtime()
+    }
+
+    /// Whether the stage is running and due to be agitated right now.
+    pub fn is_agitating(&self) -> bool {
+        let running = self.count_up || self.elapsed < self.period;
+        running && self.agitation.is_some_and(|agitation| agitation.is_due(self.elapsed_s()))
+    }
+
+    /// Time into the stage at which its part at the end starts, see
+    /// [`Phase`].
+    pub fn after_starts(&self) -> Option<Duration> {
+        self.after.as_ref()
+            .filter(|_| !self.count_up)
+            .map(|after| self.period.saturating_sub(after.period))
+    }
+
+    /// The part at the end of the stage, if it is in it.
+    pub fn current_after(&self) -> Option<&Phase> {
+        let starts = self.after_starts()?;
+        self.after.as_ref().filter(|_| starts <= self.elapsed && self.elapsed < self.period)
+    }
+
+    /// Time left, always zero for stages counting up.
+    pub fn remaining(&self) -> Duration {
+        if self.count_up {
+            Duration::ZERO
+        } else {
+            self.period.saturating_sub(self.elapsed)
+        }
+    }
+
+    /// What the stage's clock shows: the time left, the time so far for
+    /// stages counting up or the time over for stages in overtime.
+    pub fn clock(&self) -> Duration {
+        if self.count_up {
+            self.elapsed
+        } else if self.is_overtime() {
+            self.elapsed - self.period
+        } else {
+            self.remaining()
+        }
+    }
+
+    /// How long the stage ran past its end in overtime.
+    pub fn overtime_by(&self) -> Duration {
+        if self.overtime && !self.count_up {
+            self.elapsed.saturating_sub(self.period)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// Whole seconds left, rounded up so a countdown shows zero only once
+    /// the stage has ended.
+    pub fn remaining_s(&self) -> u32 {
+        ceil_s(self.remaining())
+    }
+
+    /// Seconds left at which the time left is announced, see
+    /// [`CountdownCue`]. Stages counting up have none.
+    pub fn countdown_s(&self) -> impl Iterator<Item = u32> + '_ {
+        let period_s = if self.count_up { 0 } else { self.period_s() };
+        self.countdown.iter().filter_map(move |cue| cue.remaining_s(period_s))
+    }
+
+    /// Whole seconds the stage has run for.
+    pub fn elapsed_s(&self) -> u32 {
+        u32::try_from(self.elapsed.as_secs()).unwrap_or(u32::MAX)
+    }
+
+    /// Length of the stage in seconds, rounded up.
+    pub fn period_s(&self) -> u32 {
+        ceil_s(self.period)
+    }
+
+    /// Fraction of the stage that has passed. Markers count as complete,
+    /// stages counting up as not started until they are finished.
+    pub fn completion(&self) -> f64 {
+        if self.period.is_zero() {
+            if self.is_open() { 0.0 } else { 1.0 }
+        } else {
+            (self.elapsed.as_secs_f64() / self.period.as_secs_f64()).min(1.0)
+        }
+    }
+}
+
+/// When `stage` next reaches a whole second or its end, whichever comes
+/// first. Stages counting up and stages past their end only have seconds.
+fn next_step(stage: &TimerStage) -> Duration {
+    let next_second = Duration::from_secs(stage.elapsed.as_secs() + 1);
+    if stage.count_up || stage.elapsed >= stage.period {
+        next_second
+    } else {
+        next_second.min(stage.period)
+    }
+}
+
+fn ceil_s(duration: Duration) -> u32 {
+    let seconds = duration.as_secs() + u64::from(duration.subsec_nanos() > 0);
+    u32::try_from(seconds).unwrap_or(u32::MAX)
+}
+
+/// A sequence of stages that run one after the other. The timer does not
+/// keep time itself, call [`Timer::advance`] with the time that has passed
+/// or [`Timer::tick`] once per second to advance it. Timers can be saved
+/// with serde to pick them up again later.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Timer {
+    stages: Vec<TimerStage>,
+    current_timer: usize,
+    paused: bool,
+    /// How long the timer has been paused
+    #[serde(default)]
+    paused_for: Duration,
+    /// How long all pauses took, the running one included
+    #[serde(default)]
+    paused_total: Duration,
+    /// Whether the whole timer was given up on
+    aborted: bool
+}
+
+impl Timer {
+    /// Create a timer that starts with the first stage that isn't a marker.
+    pub fn new(stages: Vec<TimerStage>) -> Timer {
+        let mut timer = Timer {
+            stages,
+            current_timer: 0,
+            paused: false,
+            paused_for: Duration::ZERO,
+            paused_total: Duration::ZERO,
+            aborted: false
+        };
+        timer.skip_markers();
+        timer
+    }
+
+    /// Move past any zero-length stages at the current position. These act
+    /// as markers or instructions in the stage list and never run.
+    fn skip_markers(&mut self) {
+        while self.stages.get(self.current_timer)
+            .is_some_and(TimerStage::is_marker)
+        {
+            self.current_timer += 1;
+        }
+    }
+
+    /// Advance the running stage by one second, moving on to the next stage
+    /// when it runs out. Does nothing while paused, unless the stage runs
+    /// while paused. Returns `false` once all stages have finished or the
+    /// timer was aborted.
+    pub fn tick(&mut self) -> bool {
+        if self.is_finished() || self.aborted {
+            return false;
+        }
+        self.advance(Duration::from_secs(1));
+        true
+    }
+
+    /// End the running stage now, making the time it ran for its duration,
+    /// and move on to the next one.
+    pub fn end_stage(&mut self) {
+        if self.aborted {
+            return;
+        }
+        if let Some(stage) = self.stages.get_mut(self.current_timer) {
+            stage.period = stage.elapsed;
+            self.current_timer += 1;
+            self.skip_markers();
+        }
+    }
+
+    /// Advance the timer by `by` of wall clock time, e.g. measured with
+    /// [`std::time::Instant`] since the last call. Time left over at the end
+    /// of a stage goes to the next one, so the timer doesn't drift no matter
+    /// how often it is called. Time passing while paused is ignored unless
+    /// the stage runs while paused.
+    ///
+    /// Returns how many times the running stage reached a whole second or
+    /// its end, which is when a countdown changes.
+    pub fn advance(&mut self, by: Duration) -> u32 {
+        if self.paused {
+            self.paused_for += by;
+            self.paused_total += by;
+            if let Some(stage) = self.stages.get_mut(self.current_timer) {
+                stage.paused += by;
+            }
+        }
+        let mut left = by;
+        let mut steps = 0;
+        while !left.is_zero() && self.is_counting() {
+            let stage = match self.stages.get_mut(self.current_timer) {
+                Some(stage) => stage,
+                None => break
+            };
+            let end = next_step(stage);
+            let step = end.saturating_sub(stage.elapsed);
+            if left < step {
+                stage.elapsed += left;
+                break;
+            }
+
+            stage.elapsed += step;
+            left -= step;
+            steps += 1;
+            // stages counting up or in overtime run until they are finished
+            let ended = !stage.count_up && !stage.overtime && stage.elapsed >= stage.period;
+            if ended && !self.is_waiting() {
+                self.current_timer += 1;
+                self.skip_markers();
+            }
+        }
+        steps
+    }
+
+    /// Whether the running stage has ended and waits to be finished before
+    /// the next one starts, see [`TimerStage::manual_advance`]. The last
+    /// stage doesn't wait.
+    pub fn is_waiting(&self) -> bool {
+        let stage = match self.current_stage() {
+            Some(stage) => stage,
+            None => return false
+        };
+        stage.manual_advance
+        && !stage.count_up
+        && !stage.overtime
+        && !stage.period.is_zero()
+        && stage.elapsed >= stage.period
+        && self.stages[self.current_timer + 1..].iter().any(|stage| !stage.is_marker())
+    }
+
+    /// Time until the running stage reaches its next whole second or its
+    /// end, `None` if the timer isn't running.
+    pub fn until_next_step(&self) -> Option<Duration> {
+        let stage = self.current_stage().filter(|_| self.is_counting())?;
+        Some(next_step(stage).saturating_sub(stage.elapsed))
+    }
+
+    pub fn pause(&mut self) {
+        if !self.paused {
+            self.paused_for = Duration::ZERO;
+        }
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.paused {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// How long the timer has been paused, as passed to
+    /// [`Timer::advance`], `None` if it isn't.
+    pub fn paused_for(&self) -> Option<Duration> {
+        Some(self.paused_for).filter(|_| self.paused)
+    }
+
+    /// How long the timer has been paused in all, as passed to
+    /// [`Timer::advance`].
+    pub fn paused_total(&self) -> Duration {
+        self.paused_total
+    }
+
+    /// Make the running stage end at its [`TimerStage::ends_at`] as the wall
+    /// clock shows `now`, so it ends on time even if the system slept or the
+    /// clock was set since it started. Call it after [`Timer::advance`].
+    /// Stages without one and stages past their end are left as they are.
+    ///
+    /// Returns whether the stage ended.
+    pub fn follow_deadline(&mut self, now: SystemTime) -> bool {
+        if self.aborted {
+            return false;
+        }
+        let stage = match self.stages.get_mut(self.current_timer) {
+            Some(stage) if !stage.count_up && stage.elapsed < stage.period => stage,
+            _ => return false
+        };
+        let ends_at = match stage.ends_at {
+            Some(ends_at) => ends_at,
+            None => return false
+        };
+        let left = ends_at.duration_since(now).unwrap_or(Duration::ZERO);
+        stage.period = stage.elapsed.saturating_add(left)
+            .min(Duration::from_secs(u32::MAX.into()));
+        // stages in overtime run on until they are finished
+        if !left.is_zero() || stage.overtime || self.is_waiting() {
+            return false;
+        }
+        self.current_timer += 1;
+        self.skip_markers();
+        true
+    }
+
+    /// Whether the next tick advances the running stage, i.e. the timer
+    /// isn't paused or the stage runs while paused, and it isn't waiting.
+    pub fn is_counting(&self) -> bool {
+        !self.aborted && !self.is_waiting() && (
+            !self.paused
+            || self.current_stage().is_some_and(|stage| stage.runs_while_paused)
+        )
+    }
+
+    /// Give the running stage `by` more time.
+    pub fn extend_stage(&mut self, by: Duration) {
+        if self.aborted {
+            return;
+        }
+        if let Some(stage) = self.stages.get_mut(self.current_timer) {
+            stage.period = stage.period.saturating_add(by)
+                .min(Duration::from_secs(u32::MAX.into()));
+        }
+    }
+
+    /// Take `by` off the running stage. The stage runs at least until its
+    /// next whole second, so it ends then if it has less time left than `by`.
+    pub fn shorten_stage(&mut self, by: Duration) {
+        if self.aborted {
+            return;
+        }
+        if let Some(stage) = self.stages.get_mut(self.current_timer) {
+            let next_second = Duration::from_secs(stage.elapsed.as_secs() + 1)
+                .min(stage.period);
+            stage.period = stage.period.saturating_sub(by).max(next_second);
+        }
+    }
+
+    /// Move on to the next stage without finishing the running one.
+    pub fn skip_stage(&mut self) {
+        if self.aborted || self.is_finished() {
+            return;
+        }
+        self.current_timer += 1;
+        self.skip_markers();
+    }
+
+    /// Move on past the rest of the running stage's group without finishing
+    /// it, see [`TimerStage::group`]. Stages outside of groups are skipped
+    /// like with [`Timer::skip_stage`].
+    pub fn skip_group(&mut self) {
+        if self.aborted || self.is_finished() {
+            return;
+        }
+        self.current_timer = self.group_range(self.current_timer).end;
+        self.skip_markers();
+    }
+
+    /// Positions of the stages in the group of the stage at `index`, i.e.
+    /// those next to it with the same group. Only `index` itself for stages
+    /// outside of groups.
+    pub fn group_range(&self, index: usize) -> Range<usize> {
+        let group = match self.stages.get(index).and_then(|stage| stage.group.as_ref()) {
+            Some(group) => group,
+            None => return index..index + 1
+        };
+        let same = |stage: &TimerStage| stage.group.as_ref() == Some(group);
+        let start = self.stages[..index].iter().rposition(|stage| !same(stage))
+            .map_or(0, |before| before + 1);
+        let end = self.stages[index..].iter().position(|stage| !same(stage))
+            .map_or(self.stages.len(), |after| index + after);
+        start..end
+    }
+
+    /// Finish the running stage if it counts up, making the time it took,
+    /// in whole seconds, its duration, or if it is in overtime or waiting,
+    /// and move on to the next one.
+    pub fn finish_stage(&mut self) {
+        if self.aborted {
+            return;
+        }
+        let waiting = self.is_waiting();
+        let stage = match self.stages.get_mut(self.current_timer) {
+            Some(stage) if stage.count_up || stage.is_overtime() || waiting => stage,
+            _ => return
+        };
+        if stage.count_up {
+            let seconds = (stage.elapsed.as_secs_f64().round() as u64).max(1);
+            stage.period = Duration::from_secs(seconds);
+            stage.elapsed = stage.period;
+        }
+        self.current_timer += 1;
+        self.skip_markers();
+    }
+
+    /// Start the running stage over.
+    pub fn restart_stage(&mut self) {
+        if self.aborted {
+            return;
+        }
+        if let Some(stage) = self.stages.get_mut(self.current_timer) {
+            stage.elapsed = Duration::ZERO;
+        }
+    }
+
+    /// Go back to the stage before the running one, passing over markers,
+    /// and start it over. The running stage starts over if it is the first.
+    pub fn previous_stage(&mut self) {
+        if self.aborted {
+            return;
+        }
+        self.restart_stage();
+        let end = self.current_timer.min(self.stages.len());
+        if let Some(previous) = self.stages[..end].iter()
+            .rposition(|stage| !stage.is_marker())
+        {
+            self.current_timer = previous;
+            let stage = &mut self.stages[previous];
+            stage.elapsed = Duration::ZERO;
+            stage.aborted = false;
+            if stage.count_up {
+                stage.period = Duration::ZERO;
+            }
+        }
+    }
+
+    /// Make the stage at `index` the running one. Going back starts it and
+    /// every stage after it over, going forward leaves the running one as
+    /// it is, like [`Timer::skip_stage`]. Markers can't be jumped to.
+    pub fn jump_to(&mut self, index: usize) {
+        if self.aborted || self.stages.get(index).is_none_or(TimerStage::is_marker) {
+            return;
+        }
+        if index < self.current_timer {
+            let end = self.current_timer.min(self.stages.len() - 1);
+            for stage in &mut self.stages[index..=end] {
+                stage.elapsed = Duration::ZERO;
+                stage.aborted = false;
+                if stage.count_up {
+                    stage.period = Duration::ZERO;
+                }
+            }
+        }
+        self.current_timer = index;
+    }
+
+    /// Mark the running stage as aborted because something went wrong and
+    /// go on with the next one.
+    pub fn abort_stage(&mut self) {
+        if self.aborted {
+            return;
+        }
+        if let Some(stage) = self.stages.get_mut(self.current_timer) {
+            stage.aborted = true;
+            self.current_timer += 1;
+            self.skip_markers();
+        }
+    }
+
+    /// Mark the running stage as aborted and stop the whole timer. The
+    /// stage stays the current one.
+    pub fn abort(&mut self) {
+        if let Some(stage) = self.stages.get_mut(self.current_timer) {
+            stage.aborted = true;
+            self.aborted = true;
+        }
+    }
+
+    /// Whether the timer was stopped with [`Timer::abort`].
+    pub fn is_aborted(&self) -> bool {
+        self.aborted
+    }
+
+    /// Whether all stages have run to the end.
+    pub fn is_finished(&self) -> bool {
+        self.current_timer >= self.stages.len()
+    }
+
+    /// Position of the running stage, or the number of stages once the timer
+    /// has finished.
+    pub fn current_index(&self) -> usize {
+        self.current_timer
+    }
+
+    /// The running stage, `None` once the timer has finished.
+    pub fn current_stage(&self) -> Option<&TimerStage> {
+        self.stages.get(self.current_timer)
+    }
+
+    /// All stages, including finished and upcoming ones.
+    pub fn stages(&self) -> &[TimerStage] {
+        &self.stages
+    }
+
+    /// The stages after the running one.
+    pub fn upcoming(&self) -> impl Iterator<Item = &TimerStage> {
+        self.stages.iter().skip(self.current_timer + 1)
+    }
+
+    /// Replace the stages after the running one with those of `stages` past
+    /// the same position, such as the stages of an edited config file. The
+    /// running stage and those before it stay as they are, and have to be
+    /// the same in `stages` by name, or the upcoming ones would be taken
+    /// from the wrong position and nothing is replaced.
+    pub fn replace_upcoming(&mut self, stages: Vec<TimerStage>) -> Result<(), String> {
+        if self.is_finished() || self.aborted {
+            return Ok(());
+        }
+        let next = self.current_timer + 1;
+        let same = stages.len() >= next && self.stages[..next].iter()
+            .zip(&stages)
+            .all(|(stage, new)| stage.name == new.name);
+        if !same {
+            return Err(String::from("the stages up to the running one changed"));
+        }
+        self.stages.truncate(next);
+        self.stages.extend(stages.into_iter().skip(next));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    /// A timer of stages named after their position, lasting `periods_s`.
+    fn timer(periods_s: &[u64]) -> Timer {
+        Timer::new(periods_s.iter()
+            .enumerate()
+            .map(|(i, period_s)| TimerStage::new(i.to_string(), secs(*period_s)))
+            .collect())
+    }
+
+    #[test]
+    fn stages_run_one_after_the_other() {
+        let mut timer = timer(&[2, 3]);
+        assert_eq!(timer.advance(secs(1)), 1);
+        assert_eq!(timer.current_index(), 0);
+        // time left over at the end of a stage goes to the next one
+        assert_eq!(timer.advance(Duration::from_millis(1500)), 1);
+        assert_eq!(timer.current_index(), 1);
+        assert_eq!(timer.current_stage().unwrap().elapsed, Duration::from_millis(500));
+        timer.advance(secs(10));
+        assert!(timer.is_finished());
+        assert_eq!(timer.stages()[1].elapsed, secs(3));
+        assert!(!timer.tick());
+    }
+
+    #[test]
+    fn markers_are_passed_over() {
+        let mut timer = timer(&[0, 1, 0, 0, 1]);
+        assert_eq!(timer.current_index(), 1);
+        timer.tick();
+        assert_eq!(timer.current_index(), 4);
+    }
+
+    #[test]
+    fn time_passing_while_paused_is_ignored() {
+        let mut timer = timer(&[5]);
+        timer.advance(secs(1));
+        timer.pause();
+        timer.advance(secs(3));
+        assert_eq!(timer.current_stage().unwrap().elapsed, secs(1));
+        assert_eq!(timer.paused_for(), Some(secs(3)));
+        timer.resume();
+        timer.advance(secs(1));
+        assert_eq!(timer.current_stage().unwrap().elapsed, secs(2));
+        assert_eq!(timer.paused_for(), None);
+        assert_eq!(timer.paused_total(), secs(3));
+        assert_eq!(timer.current_stage().unwrap().paused, secs(3));
+    }
+
+    #[test]
+    fn some_stages_run_while_paused() {
+        let mut timer = Timer::new(vec![TimerStage {
+            runs_while_paused: true,
+            ..TimerStage::new(String::from("Fix"), secs(5))
+        }]);
+        timer.pause();
+        timer.advance(secs(2));
+        assert_eq!(timer.current_stage().unwrap().elapsed, secs(2));
+    }
+
+    #[test]
+    fn skipping_leaves_the_stage_unfinished() {
+        let mut timer = timer(&[5, 0, 5]);
+        timer.advance(secs(1));
+        timer.skip_stage();
+        assert_eq!(timer.current_index(), 2);
+        assert_eq!(timer.stages()[0].elapsed, secs(1));
+        timer.skip_stage();
+        assert!(timer.is_finished());
+        // there is nothing left to skip
+        timer.skip_stage();
+        assert_eq!(timer.current_index(), 3);
+    }
+
+    #[test]
+    fn groups_are_skipped_together() {
+        let grouped = |name: &str, group: Option<&str>| TimerStage {
+            group: group.map(String::from),
+            ..TimerStage::new(String::from(name), secs(5))
+        };
+        let mut timer = Timer::new(vec![
+            grouped("Rinse", Some("Wash")),
+            grouped("Soak", Some("Wash")),
+            grouped("Dry", None)
+        ]);
+        assert_eq!(timer.group_range(1), 0..2);
+        assert_eq!(timer.group_range(2), 2..3);
+        timer.skip_group();
+        assert_eq!(timer.current_index(), 2);
+    }
+
+    #[test]
+    fn jumping_back_starts_the_stages_over() {
+        let mut timer = timer(&[2, 2, 2]);
+        timer.advance(secs(3));
+        timer.jump_to(0);
+        assert_eq!(timer.current_index(), 0);
+        assert!(timer.stages()[..2].iter().all(|stage| stage.elapsed.is_zero()));
+
+        timer.advance(secs(1));
+        timer.jump_to(2);
+        assert_eq!(timer.current_index(), 2);
+        assert_eq!(timer.stages()[0].elapsed, secs(1));
+    }
+
+    #[test]
+    fn markers_and_missing_stages_cannot_be_jumped_to() {
+        let mut timer = timer(&[2, 0, 2]);
+        timer.jump_to(1);
+        timer.jump_to(3);
+        assert_eq!(timer.current_index(), 0);
+    }
+
+    #[test]
+    fn finishing_a_stage_counting_up_sets_its_duration() {
+        let mut timer = Timer::new(vec![
+            TimerStage { count_up: true, ..TimerStage::new(String::from("Develop"), Duration::ZERO) },
+            TimerStage::new(String::from("Stop"), secs(30))
+        ]);
+        timer.advance(secs(100));
+        assert_eq!(timer.current_index(), 0);
+        timer.advance(Duration::from_millis(400));
+        timer.finish_stage();
+        assert_eq!(timer.current_index(), 1);
+        assert_eq!(timer.stages()[0].period, secs(100));
+    }
+
+    #[test]
+    fn stages_counting_down_are_not_finished() {
+        let mut timer = timer(&[5, 5]);
+        timer.advance(secs(1));
+        timer.finish_stage();
+        assert_eq!(timer.current_index(), 0);
+    }
+
+    #[test]
+    fn stages_can_be_extended_and_shortened() {
+        let mut timer = timer(&[10]);
+        timer.advance(Duration::from_millis(2500));
+        timer.extend_stage(secs(5));
+        assert_eq!(timer.current_stage().unwrap().period, secs(15));
+        timer.shorten_stage(secs(8));
+        assert_eq!(timer.current_stage().unwrap().period, secs(7));
+        // it runs at least until its next whole second
+        timer.shorten_stage(secs(60));
+        assert_eq!(timer.current_stage().unwrap().period, secs(3));
+        timer.advance(Duration::from_millis(500));
+        assert!(timer.is_finished());
+    }
+
+    #[test]
+    fn overtime_goes_on_until_finished() {
+        let mut timer = Timer::new(vec![
+            TimerStage { overtime: true, ..TimerStage::new(String::from("Fix"), secs(2)) },
+            TimerStage::new(String::from("Wash"), secs(5))
+        ]);
+        timer.advance(secs(5));
+        let stage = timer.current_stage().unwrap();
+        assert!(stage.is_overtime());
+        assert_eq!(stage.overtime_by(), secs(3));
+        assert_eq!(stage.clock(), secs(3));
+        timer.finish_stage();
+        assert_eq!(timer.current_index(), 1);
+    }
+
+    #[test]
+    fn manual_advance_waits_at_the_end() {
+        let mut timer = Timer::new(vec![
+            TimerStage { manual_advance: true, ..TimerStage::new(String::from("Fix"), secs(2)) },
+            TimerStage::new(String::from("Wash"), secs(5))
+        ]);
+        timer.advance(secs(5));
+        assert!(timer.is_waiting());
+        assert!(!timer.is_counting());
+        assert_eq!(timer.current_stage().unwrap().elapsed, secs(2));
+        timer.finish_stage();
+        assert_eq!(timer.current_index(), 1);
+    }
+
+    #[test]
+    fn deadlines_follow_the_wall_clock() {
+        let now = SystemTime::now();
+        let mut timer = Timer::new(vec![TimerStage {
+            ends_at: Some(now + secs(60)),
+            ..TimerStage::new(String::from("Alarm"), secs(60))
+        }]);
+        timer.advance(secs(1));
+        // the clock went forward, e.g. while the system slept
+        assert!(!timer.follow_deadline(now + secs(31)));
+        assert_eq!(timer.current_stage().unwrap().remaining(), secs(29));
+        assert!(timer.follow_deadline(now + secs(61)));
+        assert!(timer.is_finished());
+    }
+
+    #[test]
+    fn aborted_timers_stay_put() {
+        let mut timer = timer(&[5, 5]);
+        timer.abort();
+        assert!(timer.is_aborted());
+        assert!(!timer.tick());
+        timer.skip_stage();
+        timer.extend_stage(secs(5));
+        assert_eq!(timer.current_index(), 0);
+        assert_eq!(timer.current_stage().unwrap().period, secs(5));
+        assert!(timer.current_stage().unwrap().aborted);
+    }
+
+    #[test]
+    fn reloads_replace_only_upcoming_stages() {
+        let mut reloaded = timer(&[9, 9, 9, 9]).stages().to_vec();
+        reloaded[2].name = String::from("new");
+        let mut timer = timer(&[5, 5, 5]);
+        timer.advance(secs(6));
+        assert!(timer.replace_upcoming(reloaded).is_ok());
+        let names: Vec<&str> = timer.stages().iter().map(|stage| stage.name.as_str()).collect();
+        assert_eq!(names, ["0", "1", "new", "3"]);
+        assert_eq!(timer.current_stage().unwrap().period, secs(5));
+        assert_eq!(timer.stages()[2].period, secs(9));
+    }
+
+    #[test]
+    fn reloads_with_other_stages_so_far_are_refused() {
+        // a stage was added before the running one
+        let mut reloaded = timer(&[5, 5, 5]).stages().to_vec();
+        reloaded.insert(0, TimerStage::new(String::from("new"), secs(5)));
+        let shorter = timer(&[5]).stages().to_vec();
+        let mut timer = timer(&[5, 5, 5]);
+        timer.advance(secs(6));
+        assert!(timer.replace_upcoming(reloaded).is_err());
+        assert!(timer.replace_upcoming(shorter).is_err());
+        assert_eq!(timer.stages().len(), 3);
+        assert_eq!(timer.current_index(), 1);
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    io,
+    net::TcpStream,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant}
+};
+
+use rustls::{
+    crypto::ring,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    ServerConfig, ServerConnection, StreamOwned
+};
+
+use crate::config::TlsOptions;
+
+/// How long a client gets for the whole handshake before it is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A connection over TLS, taken with [`Acceptor::accept`].
+pub type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+/// The certificate chain in the PEM file at `path`.
+fn certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let failed = |e: rustls::pki_types::pem::Error| format!(
+        "Could not load the certificate {}: {}", path.display(), e
+    );
+    let chain = CertificateDer::pem_file_iter(path).map_err(failed)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(failed)?;
+    if chain.is_empty() {
+        return Err(format!("There is no certificate in {}", path.display()));
+    }
+    Ok(chain)
+}
+
+/// Takes TLS connections with a certificate and its key, shared by the
+/// connections of a server.
+pub struct Acceptor {
+    config: Arc<ServerConfig>
+}
+
+impl Acceptor {
+    /// Load the certificate and key of `options`. Fails if they can't be
+    /// read or don't belong together.
+    pub fn new(options: &TlsOptions) -> Result<Acceptor, String> {
+        let chain = certificates(&options.cert)?;
+        let key = PrivateKeyDer::from_pem_file(&options.key).map_err(|e| format!(
+            "Could not load the private key {}: {}", options.key.display(), e
+        ))?;
+        let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| format!("Could not set up TLS: {}", e))?
+            .with_no_client_auth()
+            .with_single_cert(chain, key)
+            .map_err(|e| format!(
+                "The private key {} doesn't belong to the certificate {}: {}",
+                options.key.display(), options.cert.display(), e
+            ))?;
+        Ok(Acceptor { config: Arc::new(config) })
+    }
+
+    /// Do the handshake with the client on the other end of `stream`, which
+    /// has to be done within [`HANDSHAKE_TIMEOUT`] however slowly the client
+    /// sends. The timeouts of `stream` are left as they were.
+    pub fn accept(&self, mut stream: TcpStream) -> io::Result<TlsStream> {
+        let timeouts = (stream.read_timeout()?, stream.write_timeout()?);
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        let mut connection = ServerConnection::new(Arc::clone(&self.config))
+            .map_err(|e| io::Error::new(io::ErrorKind::ConnectionAborted, e))?;
+        while connection.is_handshaking() || connection.wants_write() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out"));
+            }
+            stream.set_read_timeout(Some(left))?;
+            stream.set_write_timeout(Some(left))?;
+            if connection.wants_write() {
+                connection.write_tls(&mut stream)?;
+            } else if connection.read_tls(&mut stream)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            } else if let Err(e) = connection.process_new_packets() {
+                // tell the client what went wrong before hanging up
+                let _ = connection.write_tls(&mut stream);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+            }
+        }
+        stream.set_read_timeout(timeouts.0)?;
+        stream.set_write_timeout(timeouts.1)?;
+        Ok(StreamOwned::new(connection, stream))
+    }
+}
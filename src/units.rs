@@ -0,0 +1,53 @@
+use serde::Deserialize;
+
+const ML_PER_FL_OZ: f64 = 29.5735;
+
+/// Unit system for temperatures and volumes.
+#[derive(Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    /// °C and millilitres
+    Metric,
+    /// °F and US fluid ounces
+    Imperial
+}
+
+impl Units {
+    pub fn from_name(name: &str) -> Option<Units> {
+        match name {
+            "metric" => Some(Units::Metric),
+            "imperial" => Some(Units::Imperial),
+            _ => None
+        }
+    }
+
+    /// Convert a temperature given in these units to °C.
+    pub fn to_celsius(self, temperature: f64) -> f64 {
+        match self {
+            Units::Metric => temperature,
+            Units::Imperial => (temperature - 32.0) * 5.0 / 9.0
+        }
+    }
+
+    pub fn format_temperature(self, celsius: f64) -> String {
+        match self {
+            Units::Metric => format!("{:.1} °C", celsius),
+            Units::Imperial => format!("{:.1} °F", celsius * 9.0 / 5.0 + 32.0)
+        }
+    }
+
+    /// Millilitres per unit of volume when no unit is given.
+    pub fn volume_factor(self) -> f64 {
+        match self {
+            Units::Metric => 1.0,
+            Units::Imperial => ML_PER_FL_OZ
+        }
+    }
+
+    pub fn format_volume(self, ml: f64) -> String {
+        match self {
+            Units::Metric => format!("{:.1} ml", ml),
+            Units::Imperial => format!("{:.2} fl oz", ml / ML_PER_FL_OZ)
+        }
+    }
+}
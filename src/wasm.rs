@@ -0,0 +1,192 @@
+//! JavaScript bindings of the engine, built with the `wasm` feature for
+//! `wasm32-unknown-unknown`, e.g.
+//!
+//! ```sh
+//! cargo rustc --lib --release --target wasm32-unknown-unknown \
+//!     --no-default-features --features wasm --crate-type cdylib
+//! wasm-bindgen --target web --out-dir web \
+//!     target/wasm32-unknown-unknown/release/staged_timer.wasm
+//! ```
+//!
+//! The timer keeps time with `performance.now()` of the page and is
+//! advanced by calling [`WebTimer::update`], e.g. from
+//! `requestAnimationFrame`.
+
+use std::time::Duration;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{compensation, Clock, StagedTimer, TimerEvent, TimerStage};
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
+}
+
+/// The monotonic clock of the page, in milliseconds since it was loaded.
+struct PerformanceClock;
+
+impl Clock for PerformanceClock {
+    fn now(&self) -> Duration {
+        Duration::from_secs_f64(performance_now().max(0.0) / 1000.0)
+    }
+}
+
+/// Something that happened to a [`WebTimer`]. `kind` is named like the
+/// events of `--headless`: `tick`, `stage_start`, `stage_end`, `warning`,
+/// `paused`, `resumed` or `finished`.
+#[wasm_bindgen(getter_with_clone)]
+pub struct WebEvent {
+    pub kind: String,
+    /// The stage the event is about, or the running stage for events that
+    /// aren't about one
+    pub stage: usize
+}
+
+/// A timer of stages for the browser.
+#[wasm_bindgen]
+pub struct WebTimer {
+    timer: StagedTimer
+}
+
+#[wasm_bindgen]
+impl WebTimer {
+    /// A timer of stages named `names` lasting `seconds` each, that starts
+    /// running right away. Stages within `warning_s` of their end get a
+    /// `warning` event, 0 for none.
+    #[wasm_bindgen(constructor)]
+    pub fn new(names: Vec<String>, seconds: Vec<f64>, warning_s: u32) -> Result<WebTimer, JsError> {
+        if names.len() != seconds.len() {
+            return Err(JsError::new("Every stage needs a name and a duration"));
+        }
+        let stages = names.into_iter()
+            .zip(seconds)
+            .map(|(name, seconds)| {
+                Duration::try_from_secs_f64(seconds)
+                    .map(|period| TimerStage::new(name, period))
+                    .map_err(|_| JsError::new(&format!("Invalid duration: {}", seconds)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(WebTimer {
+            timer: StagedTimer::with_clock(stages, PerformanceClock).with_warning(warning_s)
+        })
+    }
+
+    /// Advance the timer by the time since the last update and return
+    /// what happened.
+    pub fn update(&mut self) -> Vec<WebEvent> {
+        let events = self.timer.update();
+        let running = self.timer.timer().current_index();
+        events.into_iter()
+            .map(|event| {
+                let (kind, stage) = match event {
+                    TimerEvent::Tick => ("tick", running),
+                    TimerEvent::StageStart(stage) => ("stage_start", stage),
+                    TimerEvent::StageEnd(stage) => ("stage_end", stage),
+                    TimerEvent::Warning(stage) => ("warning", stage),
+                    TimerEvent::Countdown(stage) => ("countdown", stage),
+                    TimerEvent::Paused => ("paused", running),
+                    TimerEvent::Resumed => ("resumed", running),
+                    TimerEvent::Finished => ("finished", running)
+                };
+                WebEvent { kind: String::from(kind), stage }
+            })
+            .collect()
+    }
+
+    /// Pause the timer, or resume it with `paused` false.
+    pub fn set_paused(&mut self, paused: bool) {
+        if paused {
+            self.timer.timer_mut().pause();
+        } else {
+            self.timer.timer_mut().resume();
+        }
+    }
+
+    /// Move on to the next stage without finishing the running one.
+    pub fn skip_stage(&mut self) {
+        self.timer.timer_mut().skip_stage();
+    }
+
+    /// Finish a stage counting up, in overtime or waiting to go on.
+    pub fn finish_stage(&mut self) {
+        self.timer.timer_mut().finish_stage();
+    }
+
+    pub fn restart_stage(&mut self) {
+        self.timer.timer_mut().restart_stage();
+    }
+
+    pub fn previous_stage(&mut self) {
+        self.timer.timer_mut().previous_stage();
+    }
+
+    /// Make the stage at `index` the running one.
+    pub fn jump_to(&mut self, index: usize) {
+        self.timer.timer_mut().jump_to(index);
+    }
+
+    pub fn extend_stage(&mut self, seconds: u32) {
+        self.timer.timer_mut().extend_stage(Duration::from_secs(seconds.into()));
+    }
+
+    pub fn shorten_stage(&mut self, seconds: u32) {
+        self.timer.timer_mut().shorten_stage(Duration::from_secs(seconds.into()));
+    }
+
+    /// Position of the running stage, or the number of stages once the
+    /// timer has finished.
+    pub fn current_stage(&self) -> usize {
+        self.timer.timer().current_index()
+    }
+
+    pub fn stage_count(&self) -> usize {
+        self.timer.timer().stages().len()
+    }
+
+    /// Name of the stage at `index`, undefined if there is none.
+    pub fn stage_name(&self, index: usize) -> Option<String> {
+        self.timer.timer().stages().get(index).map(|stage| stage.name.clone())
+    }
+
+    /// Whole seconds the running stage has run for, 0 once the timer has
+    /// finished.
+    pub fn elapsed_s(&self) -> u32 {
+        self.timer.timer().current_stage().map_or(0, TimerStage::elapsed_s)
+    }
+
+    /// Seconds left of the running stage, rounded up, 0 once the timer has
+    /// finished.
+    pub fn remaining_s(&self) -> u32 {
+        self.timer.timer().current_stage().map_or(0, TimerStage::remaining_s)
+    }
+
+    /// How far the running stage is done, from 0 to 1.
+    pub fn completion(&self) -> f64 {
+        self.timer.timer().current_stage().map_or(1.0, TimerStage::completion)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.timer.timer().is_paused()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.timer.timer().is_finished()
+    }
+}
+
+/// Factor development times are multiplied by at `temperature_c` by the
+/// usual chart for black and white developers, undefined outside of it.
+#[wasm_bindgen]
+pub fn compensation_factor(temperature_c: f64) -> Option<f64> {
+    compensation::factor(compensation::STANDARD_CURVE, temperature_c)
+}
+
+/// `seconds` scaled by `factor` the way `--temperature` scales stages.
+#[wasm_bindgen]
+pub fn compensated_seconds(seconds: f64, factor: f64) -> Result<f64, JsError> {
+    let period = Duration::try_from_secs_f64(seconds)
+        .map_err(|_| JsError::new(&format!("Invalid duration: {}", seconds)))?;
+    Ok(compensation::scale(period, &mut Vec::new(), factor).as_secs_f64())
+}